@@ -0,0 +1,142 @@
+// Tracks the size and proving time of our main circuits, so performance regressions show up.
+// Run with
+//     cargo bench --bench circuits
+//
+// For CI, save a baseline on the main branch and compare branches against it:
+//     cargo bench --bench circuits -- --save-baseline main
+//     cargo bench --bench circuits -- --baseline main
+// Criterion prints "Performance has regressed" for any benchmark that got significantly slower.
+// The constraint counts are printed before the timings. A change there is a change to the circuit
+// itself, and should also show up in tests/constraint_counts.rs.
+
+use arkworks_merkle_tree_example::{
+    constraints::PossessionCircuit,
+    constraints_showprice::PossessionShowPriceCircuit,
+    constraints_transfer::TransferCircuit,
+    hash::{LeafHash, OwnedLeafHash, TwoToOneHash},
+    util::{count_constraints, gen_test_tree, get_test_card},
+    E, F,
+};
+
+use ark_crypto_primitives::crh::{CRHScheme, TwoToOneCRHScheme};
+use ark_ff::UniformRand;
+use ark_groth16::{create_random_proof, generate_random_parameters, ProvingKey};
+use ark_relations::r1cs::ConstraintSynthesizer;
+use criterion::{criterion_group, criterion_main, Criterion};
+
+// The index of the card we show possession of
+const OUR_IDX: usize = 7;
+
+// Makes a valid instance of each circuit, all about the same card
+fn circuits() -> (
+    PossessionCircuit,
+    PossessionShowPriceCircuit,
+    TransferCircuit,
+) {
+    let mut rng = ark_std::test_rng();
+    let leaf_crh_params = <LeafHash as CRHScheme>::setup(&mut rng).unwrap();
+    let two_to_one_crh_params = <TwoToOneHash as TwoToOneCRHScheme>::setup(&mut rng).unwrap();
+    let owned_leaf_crh_params = <OwnedLeafHash as CRHScheme>::setup(&mut rng).unwrap();
+    let tree = gen_test_tree(&leaf_crh_params, &two_to_one_crh_params);
+    let (card, card_com_rand) = get_test_card(OUR_IDX);
+    let leaf = tree.leaves()[OUR_IDX].to_vec();
+    let auth_path = tree.generate_proof(OUR_IDX).unwrap();
+
+    let possession = PossessionCircuit {
+        leaf_crh_params: leaf_crh_params.clone(),
+        two_to_one_crh_params: two_to_one_crh_params.clone(),
+        enforce_nonzero_price: false,
+        root: tree.root(),
+        leaf: leaf.clone(),
+        card_serial_num: card.serial_num,
+        auth_path: auth_path.clone(),
+        card_purchase_price: card.purchase_price,
+        card_com_rand,
+    };
+    let showprice = PossessionShowPriceCircuit {
+        leaf_crh_params: leaf_crh_params.clone(),
+        two_to_one_crh_params: two_to_one_crh_params.clone(),
+        check_price_range: false,
+        root: tree.root(),
+        leaf: leaf.clone(),
+        card_serial_num: card.serial_num,
+        auth_path: auth_path.clone(),
+        card_purchase_price: card.purchase_price,
+        card_com_rand,
+    };
+    let owner_pubkey = F::rand(&mut rng);
+    let transfer = TransferCircuit {
+        root: tree.root(),
+        card_serial_num: card.serial_num,
+        owner_pubkey,
+        new_leaf: card
+            .commit_with_owner(&owned_leaf_crh_params, &card_com_rand, &owner_pubkey)
+            .to_vec(),
+        leaf,
+        card_purchase_price: card.purchase_price,
+        card_com_rand,
+        auth_path,
+        leaf_crh_params,
+        two_to_one_crh_params,
+        owned_leaf_crh_params,
+    };
+
+    (possession, showprice, transfer)
+}
+
+fn bench_constraint_counts(c: &mut Criterion) {
+    let (possession, showprice, transfer) = circuits();
+
+    for (name, (_, _, num_constraints)) in [
+        ("PossessionCircuit", count_constraints(possession.clone())),
+        (
+            "PossessionShowPriceCircuit",
+            count_constraints(showprice.clone()),
+        ),
+        ("TransferCircuit", count_constraints(transfer.clone())),
+    ] {
+        println!("{name}: {num_constraints} constraints");
+    }
+
+    let mut group = c.benchmark_group("constraint counting");
+    group.sample_size(1000);
+    group.bench_function("possession", |b| {
+        b.iter(|| count_constraints(possession.clone()))
+    });
+    group.bench_function("showprice", |b| {
+        b.iter(|| count_constraints(showprice.clone()))
+    });
+    group.bench_function("transfer", |b| {
+        b.iter(|| count_constraints(transfer.clone()))
+    });
+    group.finish();
+}
+
+// Makes a proving key for the given circuit
+fn setup<C: ConstraintSynthesizer<F> + Clone>(circuit: &C) -> ProvingKey<E> {
+    generate_random_parameters(circuit.clone(), &mut ark_std::test_rng()).unwrap()
+}
+
+fn bench_proving(c: &mut Criterion) {
+    let mut rng = ark_std::test_rng();
+    let (possession, showprice, transfer) = circuits();
+    let possession_pk = setup(&possession);
+    let showprice_pk = setup(&showprice);
+    let transfer_pk = setup(&transfer);
+
+    let mut group = c.benchmark_group("proving");
+    group.sample_size(10);
+    group.bench_function("possession", |b| {
+        b.iter(|| create_random_proof(possession.clone(), &possession_pk, &mut rng).unwrap())
+    });
+    group.bench_function("showprice", |b| {
+        b.iter(|| create_random_proof(showprice.clone(), &showprice_pk, &mut rng).unwrap())
+    });
+    group.bench_function("transfer", |b| {
+        b.iter(|| create_random_proof(transfer.clone(), &transfer_pk, &mut rng).unwrap())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_constraint_counts, bench_proving);
+criterion_main!(benches);
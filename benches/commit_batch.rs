@@ -0,0 +1,98 @@
+// Compares commit_batch against committing to each card separately. Run with
+//     cargo bench --bench commit_batch
+// After the timings, this prints how much faster the batch is, and fails if the batch gives
+// different commitments or is clearly slower than committing one card at a time. The Pedersen hash
+// dominates the cost of a commitment, so expect a speedup close to 1x.
+
+use arkworks_merkle_tree_example::{
+    card::{commit_batch, Card},
+    hash::{LeafHash, LeafHashParams},
+    merkle::Leaf,
+    F,
+};
+
+use ark_crypto_primitives::crh::CRHScheme;
+use ark_ff::UniformRand;
+use criterion::{criterion_group, criterion_main, Criterion};
+use std::time::{Duration, Instant};
+
+// The number of cards we commit to
+const BATCH_SIZE: usize = 1024;
+
+// The number of times we run each method when checking the speedup. The runs alternate between
+// the methods, and we take the fastest run of each, which is the least affected by whatever else
+// the machine is doing.
+const NUM_SPEEDUP_RUNS: usize = 10;
+
+// The lowest speedup we accept. Anything below 1x means the batch is slower, but run-to-run noise
+// is around 10%, so we only fail on a slowdown well beyond that.
+const MIN_SPEEDUP: f64 = 0.8;
+
+// Makes the hash params and the cards to commit to
+fn setup() -> (LeafHashParams, Vec<(Card, F)>) {
+    let mut rng = ark_std::test_rng();
+    let leaf_crh_params = <LeafHash as CRHScheme>::setup(&mut rng).unwrap();
+    let cards = (0..BATCH_SIZE)
+        .map(|_| (Card::rand(&mut rng), F::rand(&mut rng)))
+        .collect();
+
+    (leaf_crh_params, cards)
+}
+
+// Commits to every card separately
+fn commit_naive(leaf_crh_params: &LeafHashParams, cards: &[(Card, F)]) -> Vec<Leaf> {
+    cards
+        .iter()
+        .map(|(card, com_rand)| card.commit(leaf_crh_params, com_rand))
+        .collect()
+}
+
+// Times one run of `f`
+fn time_run(f: impl FnOnce() -> Vec<Leaf>) -> Duration {
+    let start = Instant::now();
+    criterion::black_box(f());
+    start.elapsed()
+}
+
+fn bench_commit_batch(c: &mut Criterion) {
+    let (leaf_crh_params, cards) = setup();
+
+    let mut group = c.benchmark_group("commit 1024 cards");
+    group.sample_size(10);
+    group.bench_function("naive", |b| {
+        b.iter(|| commit_naive(&leaf_crh_params, &cards))
+    });
+    group.bench_function("batch", |b| {
+        b.iter(|| commit_batch(&leaf_crh_params, &cards))
+    });
+    group.finish();
+}
+
+// Checks that the batch gives the same commitments as the naive method, and isn't clearly slower
+fn check_speedup(_: &mut Criterion) {
+    let (leaf_crh_params, cards) = setup();
+    assert_eq!(
+        commit_batch(&leaf_crh_params, &cards),
+        commit_naive(&leaf_crh_params, &cards),
+        "commit_batch disagrees with Card::commit"
+    );
+
+    let (naive, batch) = (0..NUM_SPEEDUP_RUNS)
+        .map(|_| {
+            (
+                time_run(|| commit_naive(&leaf_crh_params, &cards)),
+                time_run(|| commit_batch(&leaf_crh_params, &cards)),
+            )
+        })
+        .reduce(|(n1, b1), (n2, b2)| (n1.min(n2), b1.min(b2)))
+        .unwrap();
+    let speedup = naive.as_secs_f64() / batch.as_secs_f64();
+    println!("commit_batch speedup over naive: {speedup:.3}x ({naive:?} -> {batch:?})");
+    assert!(
+        speedup >= MIN_SPEEDUP,
+        "commit_batch is slower than committing to each card separately"
+    );
+}
+
+criterion_group!(benches, bench_commit_batch, check_speedup);
+criterion_main!(benches);
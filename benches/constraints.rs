@@ -0,0 +1,86 @@
+// Compares the Pedersen-based PossessionCircuit against the Poseidon-based
+// PoseidonPossessionCircuit. Run with
+//     cargo bench --bench constraints
+// The constraint counts are printed before the timings.
+
+use arkworks_merkle_tree_example::{
+    constraints::PossessionCircuit,
+    constraints_poseidon::PoseidonPossessionCircuit,
+    hash::{LeafHash, TwoToOneHash},
+    poseidon_params::poseidon_params,
+    util::{gen_test_poseidon_tree, gen_test_tree, get_test_card, get_test_poseidon_leaf},
+    F,
+};
+
+use ark_crypto_primitives::crh::{CRHScheme, TwoToOneCRHScheme};
+use ark_relations::r1cs::{ConstraintSynthesizer, ConstraintSystem};
+use criterion::{criterion_group, criterion_main, Criterion};
+
+// The index of the card we show possession of
+const OUR_IDX: usize = 7;
+
+fn pedersen_circuit() -> PossessionCircuit {
+    let mut rng = ark_std::test_rng();
+    let leaf_crh_params = <LeafHash as CRHScheme>::setup(&mut rng).unwrap();
+    let two_to_one_crh_params = <TwoToOneHash as TwoToOneCRHScheme>::setup(&mut rng).unwrap();
+    let tree = gen_test_tree(&leaf_crh_params, &two_to_one_crh_params);
+    let (card, card_com_rand) = get_test_card(OUR_IDX);
+
+    PossessionCircuit {
+        leaf_crh_params,
+        two_to_one_crh_params,
+        enforce_nonzero_price: false,
+        root: tree.root(),
+        leaf: tree.leaves()[OUR_IDX].to_vec(),
+        card_serial_num: card.serial_num,
+        auth_path: tree.generate_proof(OUR_IDX).unwrap(),
+        card_purchase_price: card.purchase_price,
+        card_com_rand,
+    }
+}
+
+fn poseidon_circuit() -> PoseidonPossessionCircuit {
+    let poseidon_params = poseidon_params();
+    let tree = gen_test_poseidon_tree(&poseidon_params);
+    let (card, card_com_rand) = get_test_card(OUR_IDX);
+
+    PoseidonPossessionCircuit {
+        root: tree.root(),
+        leaf: get_test_poseidon_leaf(&poseidon_params, OUR_IDX),
+        card_serial_num: card.serial_num,
+        auth_path: tree.generate_proof(OUR_IDX).unwrap(),
+        card_purchase_price: card.purchase_price,
+        card_com_rand,
+        poseidon_params,
+    }
+}
+
+// Runs the circuit on a fresh constraint system and returns the number of constraints
+fn synthesize<C: ConstraintSynthesizer<F>>(circuit: C) -> usize {
+    let cs = ConstraintSystem::new_ref();
+    circuit.generate_constraints(cs.clone()).unwrap();
+    cs.num_constraints()
+}
+
+fn bench_possession(c: &mut Criterion) {
+    let pedersen = pedersen_circuit();
+    let poseidon = poseidon_circuit();
+
+    println!(
+        "PossessionCircuit (Pedersen): {} constraints",
+        synthesize(pedersen.clone())
+    );
+    println!(
+        "PoseidonPossessionCircuit: {} constraints",
+        synthesize(poseidon.clone())
+    );
+
+    let mut group = c.benchmark_group("possession synthesis");
+    group.sample_size(10);
+    group.bench_function("pedersen", |b| b.iter(|| synthesize(pedersen.clone())));
+    group.bench_function("poseidon", |b| b.iter(|| synthesize(poseidon.clone())));
+    group.finish();
+}
+
+criterion_group!(benches, bench_possession);
+criterion_main!(benches);
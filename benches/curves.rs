@@ -0,0 +1,78 @@
+// Compares proving PossessionCircuit over BLS12-381 and over BN254. Run with
+//     cargo bench --bench curves
+// Both circuits have the same shape, so the difference is down to the curves.
+
+use arkworks_merkle_tree_example::{
+    bn254,
+    constraints::PossessionCircuit,
+    hash::{LeafHash, TwoToOneHash},
+    util::{gen_test_tree, get_test_card},
+    Bls12Config, Bn254Config, CryptoConfig,
+};
+
+use ark_crypto_primitives::crh::{CRHScheme, TwoToOneCRHScheme};
+use ark_groth16::{create_random_proof, generate_random_parameters, ProvingKey};
+use ark_relations::r1cs::ConstraintSynthesizer;
+use criterion::{criterion_group, criterion_main, Criterion};
+
+// The index of the card we show possession of
+const OUR_IDX: usize = 7;
+
+// Makes a valid possession circuit over BLS12-381
+fn bls12_circuit() -> PossessionCircuit<Bls12Config> {
+    let mut rng = ark_std::test_rng();
+    let leaf_crh_params = <LeafHash as CRHScheme>::setup(&mut rng).unwrap();
+    let two_to_one_crh_params = <TwoToOneHash as TwoToOneCRHScheme>::setup(&mut rng).unwrap();
+    let tree = gen_test_tree(&leaf_crh_params, &two_to_one_crh_params);
+    let (card, card_com_rand) = get_test_card(OUR_IDX);
+
+    PossessionCircuit {
+        leaf: tree.leaves()[OUR_IDX].to_vec(),
+        root: tree.root(),
+        auth_path: tree.generate_proof(OUR_IDX).unwrap(),
+        leaf_crh_params,
+        two_to_one_crh_params,
+        enforce_nonzero_price: false,
+        card_serial_num: card.serial_num,
+        card_purchase_price: card.purchase_price,
+        card_com_rand,
+    }
+}
+
+// Makes a valid possession circuit over BN254
+fn bn254_circuit() -> PossessionCircuit<Bn254Config> {
+    let mut rng = ark_std::test_rng();
+    let leaf_crh_params = <bn254::LeafHash as CRHScheme>::setup(&mut rng).unwrap();
+    let two_to_one_crh_params =
+        <bn254::TwoToOneHash as TwoToOneCRHScheme>::setup(&mut rng).unwrap();
+    bn254::possession_circuit(leaf_crh_params, two_to_one_crh_params, OUR_IDX)
+}
+
+// Makes a proving key for the given circuit
+fn setup<C: CryptoConfig>(circuit: &PossessionCircuit<C>) -> ProvingKey<C::E>
+where
+    PossessionCircuit<C>: ConstraintSynthesizer<C::F> + Clone,
+{
+    generate_random_parameters(circuit.clone(), &mut ark_std::test_rng()).unwrap()
+}
+
+fn bench_proving(c: &mut Criterion) {
+    let mut rng = ark_std::test_rng();
+    let bls12 = bls12_circuit();
+    let bn254 = bn254_circuit();
+    let bls12_pk = setup(&bls12);
+    let bn254_pk = setup(&bn254);
+
+    let mut group = c.benchmark_group("possession proving");
+    group.sample_size(10);
+    group.bench_function("BLS12-381", |b| {
+        b.iter(|| create_random_proof(bls12.clone(), &bls12_pk, &mut rng).unwrap())
+    });
+    group.bench_function("BN254", |b| {
+        b.iter(|| create_random_proof(bn254.clone(), &bn254_pk, &mut rng).unwrap())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_proving);
+criterion_main!(benches);
@@ -0,0 +1,35 @@
+// Measures how commit_cards_parallel scales with the number of cards. Run with
+//     cargo bench --bench parallel_commit --features parallel
+// On a multi-core machine, the time per card should stay roughly flat as the batch grows.
+
+use arkworks_merkle_tree_example::{card::Card, hash::LeafHash, util::commit_cards_parallel, F};
+
+use ark_crypto_primitives::crh::CRHScheme;
+use ark_ff::UniformRand;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+fn bench_parallel_commit(c: &mut Criterion) {
+    let mut rng = ark_std::test_rng();
+    let leaf_crh_params = <LeafHash as CRHScheme>::setup(&mut rng).unwrap();
+
+    let mut group = c.benchmark_group("parallel commit");
+    group.sample_size(10);
+    for num_cards in [64, 256, 1024] {
+        let cards: Vec<(Card, F)> = (0..num_cards)
+            .map(|_| (Card::rand(&mut rng), F::rand(&mut rng)))
+            .collect();
+
+        // Reporting throughput makes criterion print cards/sec, which should be about the same
+        // for every batch size
+        group.throughput(Throughput::Elements(num_cards as u64));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(num_cards),
+            &cards,
+            |b, cards| b.iter(|| commit_cards_parallel(&leaf_crh_params, cards)),
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_parallel_commit);
+criterion_main!(benches);
@@ -0,0 +1,215 @@
+use crate::util::VkExt;
+
+use ark_ec::pairing::Pairing;
+use ark_ff::{Field, PrimeField, UniformRand, Zero};
+use ark_groth16::{PreparedVerifyingKey, Proof};
+use rand::RngCore;
+
+/// Verifies many Groth16 proofs under the same verifying key at once.
+///
+/// A single Groth16 verification checks `e(A, B) = e(α, β) · e(X, γ) · e(C, δ)`, where `X` is
+/// computed from the public inputs. To check many of these at once, we pick a random `r_i` for
+/// every proof and check the product of the `r_i`-th powers of all the equations. This takes one
+/// pairing per proof plus two, rather than three per proof, and it's sound except with negligible
+/// probability over the choice of the `r_i`.
+pub struct BatchVerifier<E: Pairing> {
+    proofs: Vec<(Proof<E>, Vec<E::ScalarField>)>,
+}
+
+impl<E: Pairing> Default for BatchVerifier<E> {
+    fn default() -> Self {
+        BatchVerifier { proofs: Vec::new() }
+    }
+}
+
+impl<E: Pairing> BatchVerifier<E> {
+    /// Makes an empty batch
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a proof and its public inputs to the batch
+    pub fn add(&mut self, proof: Proof<E>, public_inputs: Vec<E::ScalarField>) {
+        self.proofs.push((proof, public_inputs));
+    }
+
+    /// Returns the number of proofs in the batch
+    pub fn len(&self) -> usize {
+        self.proofs.len()
+    }
+
+    /// Returns whether the batch is empty
+    pub fn is_empty(&self) -> bool {
+        self.proofs.is_empty()
+    }
+
+    /// Returns whether every proof in the batch is valid with respect to `vk`. An empty batch is
+    /// valid.
+    pub fn verify_batch(&self, vk: &PreparedVerifyingKey<E>, rng: &mut impl RngCore) -> bool {
+        let gamma_abc_g1 = &vk.vk.gamma_abc_g1;
+
+        // The left-hand sides of the pairings we'll compute
+        let mut g1s: Vec<E::G1Prepared> = Vec::with_capacity(self.proofs.len() + 2);
+        // The right-hand sides of the pairings we'll compute
+        let mut g2s: Vec<E::G2Prepared> = Vec::with_capacity(self.proofs.len() + 2);
+
+        // Accumulators for Σ r_i, Σ r_i X_i, and Σ r_i C_i
+        let mut r_sum = E::ScalarField::zero();
+        let mut x_acc = E::G1::zero();
+        let mut c_acc = E::G1::zero();
+
+        for (proof, public_inputs) in &self.proofs {
+            // Wrong-length inputs can't possibly verify
+            if public_inputs.len() != vk.num_public_inputs() {
+                return false;
+            }
+
+            // Compute X_i = γ_0 + Σ_j x_j γ_j from the public inputs
+            let mut x = E::G1::from(gamma_abc_g1[0]);
+            for (input, base) in public_inputs.iter().zip(gamma_abc_g1.iter().skip(1)) {
+                x += *base * *input;
+            }
+
+            // Randomize this proof's equation and add it to the batch
+            let r = E::ScalarField::rand(rng);
+            g1s.push(E::G1Prepared::from(proof.a * r));
+            g2s.push(proof.b.into());
+            r_sum += r;
+            x_acc += x * r;
+            c_acc += proof.c * r;
+        }
+
+        // The prepared key has -γ and -δ, so these pairings go on the same side as e(A_i, B_i)
+        g1s.push(E::G1Prepared::from(x_acc));
+        g2s.push(vk.gamma_g2_neg_pc.clone());
+        g1s.push(E::G1Prepared::from(c_acc));
+        g2s.push(vk.delta_g2_neg_pc.clone());
+
+        // Check Π e(r_i A_i, B_i) · e(Σ r_i X_i, -γ) · e(Σ r_i C_i, -δ) = e(α, β)^(Σ r_i)
+        let lhs = E::multi_pairing(g1s, g2s).0;
+        let rhs = vk.alpha_g1_beta_g2.pow(r_sum.into_bigint());
+        lhs == rhs
+    }
+}
+
+//
+// TESTS
+//
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        util::{MockCircuit, SquareCircuit},
+        E, F,
+    };
+
+    use ark_groth16::{
+        create_random_proof, generate_random_parameters, prepare_verifying_key, verify_proof,
+        ProvingKey,
+    };
+
+    // A list of proofs, each with its public inputs
+    type ProofsWithInputs = Vec<(Proof<E>, Vec<F>)>;
+
+    // Makes `n` valid proofs along with their public inputs
+    fn setup(n: usize) -> (PreparedVerifyingKey<E>, ProofsWithInputs) {
+        let mut rng = ark_std::test_rng();
+
+        let placeholder = SquareCircuit {
+            root: Some(F::zero()),
+            square: F::zero(),
+        };
+        let pk: ProvingKey<E> = generate_random_parameters(placeholder, &mut rng).unwrap();
+        let vk = prepare_verifying_key(&pk.vk);
+
+        let proofs = (0..n)
+            .map(|_| {
+                let root = F::rand(&mut rng);
+                let square = root * root;
+                let proof = create_random_proof(
+                    SquareCircuit {
+                        root: Some(root),
+                        square,
+                    },
+                    &pk,
+                    &mut rng,
+                )
+                .unwrap();
+                (proof, vec![square])
+            })
+            .collect();
+
+        (vk, proofs)
+    }
+
+    // A batch of valid proofs should verify, just like each proof individually
+    #[test]
+    fn batch_matches_individual() {
+        let mut rng = ark_std::test_rng();
+        let (vk, proofs) = setup(4);
+
+        let mut batch = BatchVerifier::new();
+        for (proof, inputs) in proofs {
+            assert!(verify_proof(&vk, &proof, &inputs).unwrap());
+            batch.add(proof, inputs);
+        }
+        assert!(batch.verify_batch(&vk, &mut rng));
+
+        // The empty batch is also valid
+        assert!(BatchVerifier::<E>::new().verify_batch(&vk, &mut rng));
+    }
+
+    // If one proof in the batch is bad, the whole batch should fail
+    #[test]
+    fn batch_soundness() {
+        let mut rng = ark_std::test_rng();
+        let (vk, mut proofs) = setup(4);
+
+        // Change the public input of the third proof
+        proofs[2].1[0] += F::from(1u64);
+
+        let mut batch = BatchVerifier::new();
+        for (proof, inputs) in proofs {
+            batch.add(proof, inputs);
+        }
+        assert!(!verify_proof(&vk, &batch.proofs[2].0, &batch.proofs[2].1).unwrap());
+        assert!(!batch.verify_batch(&vk, &mut rng));
+
+        // Wrong-length public inputs also fail
+        let (vk, mut proofs) = setup(1);
+        proofs[0].1.push(F::zero());
+        let mut batch = BatchVerifier::new();
+        batch.add(proofs[0].0.clone(), proofs[0].1.clone());
+        assert!(!batch.verify_batch(&vk, &mut rng));
+    }
+
+    // The batch verifier doesn't care what the circuit is, so mock circuits with no public inputs
+    // are enough to check it. A batch with a mangled proof in it should fail.
+    #[test]
+    fn batch_mock_proofs() {
+        let mut rng = ark_std::test_rng();
+        let circuit = MockCircuit {
+            num_constraints: 8,
+            should_satisfy: true,
+        };
+        let pk: ProvingKey<E> = generate_random_parameters(circuit, &mut rng).unwrap();
+        let vk = prepare_verifying_key(&pk.vk);
+
+        let proofs: Vec<Proof<E>> = (0..3)
+            .map(|_| create_random_proof(circuit, &pk, &mut rng).unwrap())
+            .collect();
+        let mut batch = BatchVerifier::new();
+        for proof in &proofs {
+            batch.add(proof.clone(), Vec::new());
+        }
+        assert!(batch.verify_batch(&vk, &mut rng));
+
+        // Mix the pieces of two proofs together
+        let mut bad_proof = proofs[0].clone();
+        bad_proof.c = proofs[1].c;
+        assert!(!verify_proof(&vk, &bad_proof, &[]).unwrap());
+        batch.add(bad_proof, Vec::new());
+        assert!(!batch.verify_batch(&vk, &mut rng));
+    }
+}
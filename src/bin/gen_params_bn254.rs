@@ -0,0 +1,57 @@
+use arkworks_merkle_tree_example::{
+    bn254::{self, root_to_zbase32},
+    constraints::PossessionCircuit,
+    util::{
+        write_to_file_atomic, PEDERSEN_PARAMS_BN254_FILENAME, POSSESSION_BN254_PK_FILENAME,
+        POSSESSION_BN254_VK_FILENAME,
+    },
+    Bn254Config, CryptoConfig,
+};
+
+use ark_crypto_primitives::crh::{CRHScheme, TwoToOneCRHScheme};
+use ark_groth16::{generate_random_parameters, prepare_verifying_key, ProvingKey};
+
+// The pairing engine for BN254
+type E = <Bn254Config as CryptoConfig>::E;
+
+fn main() {
+    // Use a deterministic RNG
+    let mut rng = ark_std::test_rng();
+
+    //
+    // First step is to generate the Pedersen hashing parameters. These are over Baby Jubjub, so
+    // they're different from the ones in pedersen_params.bin
+    //
+
+    // Sample the Pedersen params randomly
+    let two_to_one_crh_params =
+        <bn254::TwoToOneHash as TwoToOneCRHScheme>::setup(&mut rng).unwrap();
+    let leaf_crh_params = <bn254::LeafHash as CRHScheme>::setup(&mut rng).unwrap();
+
+    // Write the CRH params to a file
+    write_to_file_atomic(
+        PEDERSEN_PARAMS_BN254_FILENAME,
+        &(leaf_crh_params.clone(), two_to_one_crh_params.clone()),
+    )
+    .expect("could not write file");
+    println!("Wrote {PEDERSEN_PARAMS_BN254_FILENAME}");
+
+    //
+    // Now we generate the Groth16 CRS for PossessionCircuit<Bn254Config>. To do so, we have to
+    // make a placeholder circuit of the right shape
+    //
+
+    // The prover needs the root of the test tree
+    let (tree, _) = bn254::gen_test_tree(&leaf_crh_params, &two_to_one_crh_params);
+    let root = tree.root();
+    let circuit = PossessionCircuit::<Bn254Config>::stub(leaf_crh_params, two_to_one_crh_params);
+
+    // Generate the Groth16 proving and verifying key and write to files
+    let pk: ProvingKey<E> = generate_random_parameters(circuit, &mut rng).unwrap();
+    let vk = prepare_verifying_key(&pk.vk);
+    write_to_file_atomic(POSSESSION_BN254_PK_FILENAME, &pk).expect("could not write file");
+    write_to_file_atomic(POSSESSION_BN254_VK_FILENAME, &vk).expect("could not write file");
+    println!("Wrote {POSSESSION_BN254_PK_FILENAME}");
+    println!("Wrote {POSSESSION_BN254_VK_FILENAME}");
+    println!("Test tree root is {}", root_to_zbase32(&root));
+}
@@ -7,8 +7,8 @@ use arkworks_merkle_tree_example::{
     hash::{LeafHash, TwoToOneHash},
     merkle::{Leaf, MerkleRoot},
     util::{
-        gen_test_tree, write_to_file, PEDERSEN_PARAMS_FILENAME, POSSESSION_SHOWPRICE_PK_FILENAME,
-        POSSESSION_SHOWPRICE_VK_FILENAME,
+        gen_test_tree, write_to_file_atomic, PEDERSEN_PARAMS_FILENAME,
+        POSSESSION_SHOWPRICE_PK_FILENAME, POSSESSION_SHOWPRICE_VK_FILENAME,
     },
     E, F,
 };
@@ -30,10 +30,11 @@ fn main() {
     let leaf_crh_params = <LeafHash as CRHScheme>::setup(&mut rng).unwrap();
 
     // Write the CRH params to a file
-    write_to_file(
+    write_to_file_atomic(
         PEDERSEN_PARAMS_FILENAME,
         &(leaf_crh_params.clone(), two_to_one_crh_params.clone()),
-    );
+    )
+    .expect("could not write file");
     println!("Wrote {PEDERSEN_PARAMS_FILENAME}");
 
     //
@@ -55,6 +56,8 @@ fn main() {
         // Constants that the circuit needs
         leaf_crh_params,
         two_to_one_crh_params,
+        // Our test cards have random prices, which don't fit in a u64
+        check_price_range: false,
 
         // Public inputs to the circuit
         root: MerkleRoot::rand(&mut rng),
@@ -71,8 +74,8 @@ fn main() {
     // Generate the Groth16 proving and verifying key and write to files
     let pk: ProvingKey<E> = generate_random_parameters(circuit.clone(), &mut rng).unwrap();
     let vk = prepare_verifying_key(&pk.vk);
-    write_to_file(POSSESSION_SHOWPRICE_PK_FILENAME, &pk);
-    write_to_file(POSSESSION_SHOWPRICE_VK_FILENAME, &vk);
+    write_to_file_atomic(POSSESSION_SHOWPRICE_PK_FILENAME, &pk).expect("could not write file");
+    write_to_file_atomic(POSSESSION_SHOWPRICE_VK_FILENAME, &vk).expect("could not write file");
     println!("Wrote {POSSESSION_SHOWPRICE_PK_FILENAME}");
     println!("Wrote {POSSESSION_SHOWPRICE_VK_FILENAME}");
 }
@@ -0,0 +1,65 @@
+use arkworks_merkle_tree_example::{
+    constraints_voting::VotingCircuit,
+    merkle::{Leaf, MerkleRoot},
+    util::{
+        gen_test_tree, read_from_file, write_to_file_atomic, PEDERSEN_PARAMS_FILENAME,
+        VOTING_PK_FILENAME, VOTING_VK_FILENAME,
+    },
+    E, F,
+};
+
+use ark_ff::UniformRand;
+use ark_groth16::{generate_random_parameters, prepare_verifying_key, ProvingKey};
+
+fn main() {
+    // Use a deterministic RNG
+    let mut rng = ark_std::test_rng();
+
+    // Read the hashing params from a file. These are made by gen_params, and the voter registry
+    // uses the same ones.
+    let (leaf_crh_params, two_to_one_crh_params) = read_from_file(PEDERSEN_PARAMS_FILENAME);
+
+    //
+    // Now we generate the Groth16 CRS for VotingCircuit. To do so, we have to make a placeholder
+    // circuit. We will just fill in everything with random values
+    //
+
+    // Make a uniform leaf
+    let zero_leaf: Leaf = [0u8; 64];
+    // To make a correctly sized auth path, we make a Merkle tree of the same size as our test
+    // tree, and create an auth path for any arbitrary index
+    let random_auth_path = {
+        let tree = gen_test_tree(&leaf_crh_params, &two_to_one_crh_params);
+        tree.generate_proof(0).unwrap()
+    };
+
+    // Now construct the circuit with all the random values
+    let circuit = VotingCircuit {
+        // Constants that the circuit needs
+        leaf_crh_params,
+        two_to_one_crh_params,
+
+        // Public inputs to the circuit
+        root: MerkleRoot::rand(&mut rng),
+        credential_nullifier: F::rand(&mut rng),
+        ballot_com: zero_leaf.to_vec(),
+
+        // Witness to membership
+        leaf: zero_leaf.to_vec(),
+        auth_path: random_auth_path,
+        // Credential opening details
+        credential_secret: F::rand(&mut rng),
+        credential_com_rand: F::rand(&mut rng),
+        // Ballot opening details
+        choice: false,
+        ballot_com_rand: F::rand(&mut rng),
+    };
+
+    // Generate the Groth16 proving and verifying key and write to files
+    let pk: ProvingKey<E> = generate_random_parameters(circuit, &mut rng).unwrap();
+    let vk = prepare_verifying_key(&pk.vk);
+    write_to_file_atomic(VOTING_PK_FILENAME, &pk).expect("could not write file");
+    write_to_file_atomic(VOTING_VK_FILENAME, &vk).expect("could not write file");
+    println!("Wrote {VOTING_PK_FILENAME}");
+    println!("Wrote {VOTING_VK_FILENAME}");
+}
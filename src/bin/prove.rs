@@ -1,9 +1,11 @@
 use arkworks_merkle_tree_example::{
     constraints::PossessionCircuit,
-    merkle::MerkleRoot,
+    proof_package::ProofPackage,
     util::{
-        gen_test_tree, get_test_card, get_test_leaf, read_from_file, write_to_file,
-        POSSESSION_PROOF_FILENAME, POSSESSION_REVEALED_SERIAL_FILENAME, POSSESSION_VK_FILENAME,
+        gen_test_tree, get_test_card, leaf_from_zbase32, leaf_to_zbase32, read_from_file,
+        root_from_zbase32, write_to_file_atomic, ProofCache, POSSESSION_PROOF_CACHE_FILENAME,
+        POSSESSION_PROOF_FILENAME, POSSESSION_PROOF_PACKAGE_FILENAME,
+        POSSESSION_REVEALED_SERIAL_FILENAME, POSSESSION_VK_FILENAME,
     },
     E,
 };
@@ -11,36 +13,37 @@ use arkworks_merkle_tree_example::{
 use std::env;
 
 use ark_ff::ToConstraintField;
-use ark_groth16::{create_random_proof, verify_proof, ProvingKey};
-use ark_serialize::CanonicalDeserialize;
+use ark_groth16::ProvingKey;
 
 const HELP_STR: &str = "\
 Error: bad command line arguments
 
 Usage:
-    cargo run --release --bin prove -- PEDERSEN_PARAM_FILE PROVING_KEY_FILE MERKLE_ROOT
+    cargo run --release --bin prove -- PEDERSEN_PARAM_FILE PROVING_KEY_FILE MERKLE_ROOT [LEAF]
 Example:
     cargo run --release --bin prove -- \\
         pedersen_params.bin \\
         possession_proving_key.bin \\
         f5pj64oh3m6anguhjb5rhfugwe44ximao17ya3wgx1fbmg1iobmo
+
+LEAF is the zbase32-encoded leaf of the card to prove possession of. It must be one of the cards in
+the test tree. If it's not given, this proves possession of card #7.
 ";
 
 fn main() {
     let args: Vec<String> = env::args().collect();
-    if args.len() != 4 {
+    if args.len() != 4 && args.len() != 5 {
         println!("{}", HELP_STR);
         panic!("bad command line input");
     }
     // Unpack command line args
     let pedersen_params_filename = &args[1];
     let possession_pk_filename = &args[2];
-    let given_merkle_root = {
-        let bytes = zbase32::decode_full_bytes(args[3].as_bytes())
-            .expect("could not decode Merkle root string");
-        MerkleRoot::deserialize_compressed(bytes.as_slice())
-            .expect("Merkle root string is an invalid hash")
-    };
+    let given_merkle_root =
+        root_from_zbase32(&args[3]).expect("could not decode Merkle root string");
+    let given_leaf = args
+        .get(4)
+        .map(|s| leaf_from_zbase32(s).expect("could not decode leaf string"));
 
     //
     // Setup
@@ -50,9 +53,9 @@ fn main() {
 
     println!("Reading params and proving key...");
     // Read the hashing params from a file
-    let (leaf_crh_params, two_to_one_crh_params) = read_from_file(&pedersen_params_filename);
+    let (leaf_crh_params, two_to_one_crh_params) = read_from_file(pedersen_params_filename);
     // Read the Groth16 CRS from a file
-    let pk: ProvingKey<E> = read_from_file(&possession_pk_filename);
+    let pk: ProvingKey<E> = read_from_file(possession_pk_filename);
 
     // Generate a test tree and compute its root
     let tree = gen_test_tree(&leaf_crh_params, &two_to_one_crh_params);
@@ -62,26 +65,46 @@ fn main() {
         root, given_merkle_root,
         "The Merkle root I'm trying to use is different than the one you gave me"
     );
-    // Also imagine we possess the card that appears at index 7 in the tree
-    let our_idx = 7;
+    // Also imagine we possess the card with the given leaf. If no leaf was given, it's the card
+    // that appears at index 7 in the tree.
+    let our_idx = match given_leaf {
+        Some(leaf) => tree
+            .leaves()
+            .iter()
+            .position(|l| l == &leaf)
+            .expect("the given leaf is not in the tree"),
+        None => 7,
+    };
     let (card, card_com_rand) = get_test_card(our_idx);
+    println!(
+        "Proving possession of card #{our_idx}, with leaf {}",
+        leaf_to_zbase32(&tree.leaves()[our_idx])
+    );
 
     //
     // Now generate a proof
     //
 
-    // We'll prove membership of our card, i.e., the 7th item in the tree
+    // We'll prove membership of our card
     let idx_to_prove = our_idx;
-    let claimed_leaf = &get_test_leaf(&leaf_crh_params, idx_to_prove);
+    let claimed_leaf = &tree.leaves()[idx_to_prove];
+
+    // Make sure our card actually opens the leaf before spending time on a proof
+    if !card.verify_commitment(&leaf_crh_params, &card_com_rand, claimed_leaf) {
+        eprintln!("Error: our card is not committed to by leaf #{idx_to_prove}");
+        std::process::exit(1);
+    }
 
     // Generate an authentication path for our leaf
     let auth_path = tree.generate_proof(idx_to_prove).unwrap();
 
     // We now have everything we need to build the PossessionCircuit
-    let circuit = PossessionCircuit {
+    let circuit: PossessionCircuit = PossessionCircuit {
         // Constants that the circuit needs
         leaf_crh_params,
         two_to_one_crh_params,
+        // We don't require a nonzero price
+        enforce_nonzero_price: false,
 
         // Public inputs to the circuit
         root,
@@ -95,29 +118,50 @@ fn main() {
         card_purchase_price: card.purchase_price,
     };
 
-    // Create the proof
-    println!("Proving...");
-    let proof = create_random_proof(circuit.clone(), &pk, &mut rng).unwrap();
+    // These are the public inputs of the circuit
+    let public_inputs = [
+        root.to_field_elements().unwrap(),
+        card.serial_num.to_field_elements().unwrap(),
+    ]
+    .concat();
+    assert_eq!(public_inputs.len(), <PossessionCircuit>::NUM_PUBLIC_INPUTS);
+
+    // Create the proof, unless we've proven this exact statement with this proving key before.
+    // Cached proofs are keyed by the proving key too, so regenerating the params is fine.
+    let mut cache = ProofCache::load(POSSESSION_PROOF_CACHE_FILENAME);
+    if cache.get(&pk, &public_inputs).is_some() {
+        println!("Using cached proof from {POSSESSION_PROOF_CACHE_FILENAME}");
+    } else {
+        println!("Proving...");
+    }
+    let proof = cache
+        .get_or_prove(circuit.clone(), &public_inputs, &pk, &mut rng)
+        .expect("could not create proof");
+    cache.save(POSSESSION_PROOF_CACHE_FILENAME);
 
     //
     // Wrap-up
     //
 
     // Verify the proof package. This should succeed
-    let vk = read_from_file(POSSESSION_VK_FILENAME);
-    let public_inputs = [
-        root.to_field_elements().unwrap(),
-        card.serial_num.to_field_elements().unwrap(),
-    ]
-    .concat();
+    let package: ProofPackage<E> = ProofPackage {
+        proof,
+        vk: read_from_file(POSSESSION_VK_FILENAME),
+        public_inputs,
+    };
     assert!(
-        verify_proof(&vk, &proof, &public_inputs).unwrap(),
+        package.verify().unwrap(),
         "honest proof failed to verify with supplied verifying key"
     );
 
-    // Write the proof and serial to a file
-    write_to_file(POSSESSION_PROOF_FILENAME, &proof);
-    write_to_file(POSSESSION_REVEALED_SERIAL_FILENAME, &card.serial_num);
+    // Write the proof and serial to a file. Also write the whole package, for anyone who wants
+    // everything in one place.
+    write_to_file_atomic(POSSESSION_PROOF_FILENAME, &package.proof).expect("could not write file");
+    write_to_file_atomic(POSSESSION_REVEALED_SERIAL_FILENAME, &card.serial_num)
+        .expect("could not write file");
+    write_to_file_atomic(POSSESSION_PROOF_PACKAGE_FILENAME, &package)
+        .expect("could not write file");
     println!("Wrote {POSSESSION_PROOF_FILENAME}");
     println!("Wrote {POSSESSION_REVEALED_SERIAL_FILENAME}");
+    println!("Wrote {POSSESSION_PROOF_PACKAGE_FILENAME}");
 }
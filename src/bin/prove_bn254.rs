@@ -0,0 +1,108 @@
+use arkworks_merkle_tree_example::{
+    bn254::{possession_circuit, root_from_zbase32},
+    constraints::PossessionCircuit,
+    prover::{Groth16Prover, Groth16Verifier},
+    util::{
+        read_from_file, write_to_file_atomic, POSSESSION_BN254_PROOF_FILENAME,
+        POSSESSION_BN254_REVEALED_SERIAL_FILENAME,
+    },
+    Bn254Config, CryptoConfig,
+};
+
+use std::env;
+
+use ark_ff::ToConstraintField;
+use ark_groth16::ProvingKey;
+
+// The pairing engine for BN254
+type E = <Bn254Config as CryptoConfig>::E;
+
+const HELP_STR: &str = "\
+Error: bad command line arguments
+
+Usage:
+    cargo run --release --bin prove_bn254 -- PEDERSEN_PARAM_FILE PROVING_KEY_FILE MERKLE_ROOT
+Example:
+    cargo run --release --bin prove_bn254 -- \\
+        pedersen_params_bn254.bin \\
+        possession_bn254_proving_key.bin \\
+        <the root printed by gen_params_bn254>
+
+This proves possession of card #7 of the BN254 test tree.
+";
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() != 4 {
+        println!("{}", HELP_STR);
+        panic!("bad command line input");
+    }
+    // Unpack command line args
+    let pedersen_params_filename = &args[1];
+    let possession_pk_filename = &args[2];
+    let given_merkle_root =
+        root_from_zbase32(&args[3]).expect("could not decode Merkle root string");
+
+    //
+    // Setup
+    //
+
+    let mut rng = rand::thread_rng();
+
+    println!("Reading params and proving key...");
+    // Read the hashing params from a file
+    let (leaf_crh_params, two_to_one_crh_params) = read_from_file(pedersen_params_filename);
+    // Read the Groth16 CRS from a file
+    let pk: ProvingKey<E> = read_from_file(possession_pk_filename);
+
+    // Imagine we possess the card at index 7 of the test tree
+    let our_idx = 7;
+    let circuit = possession_circuit(leaf_crh_params, two_to_one_crh_params, our_idx);
+    // Check that the root of the test tree is equal to the root that was given
+    assert_eq!(
+        circuit.root, given_merkle_root,
+        "The Merkle root I'm trying to use is different than the one you gave me"
+    );
+
+    // These are the public inputs of the circuit
+    let public_inputs = [
+        circuit.root.to_field_elements().unwrap(),
+        circuit.card_serial_num.to_field_elements().unwrap(),
+    ]
+    .concat();
+    assert_eq!(
+        public_inputs.len(),
+        PossessionCircuit::<Bn254Config>::NUM_PUBLIC_INPUTS
+    );
+
+    //
+    // Now generate a proof
+    //
+
+    println!("Proving...");
+    let card_serial_num = circuit.card_serial_num;
+    let prover = Groth16Prover::new(pk);
+    let proof = prover
+        .prove(circuit, &mut rng)
+        .expect("could not create proof");
+
+    //
+    // Wrap-up
+    //
+
+    // Verify the proof. This should succeed
+    let verifier = Groth16Verifier::from_vk(prover.vk());
+    assert!(
+        verifier
+            .verify(&proof, &public_inputs)
+            .expect("could not verify proof"),
+        "honest proof failed to verify with supplied verifying key"
+    );
+
+    // Write the proof and serial to a file
+    write_to_file_atomic(POSSESSION_BN254_PROOF_FILENAME, &proof).expect("could not write file");
+    write_to_file_atomic(POSSESSION_BN254_REVEALED_SERIAL_FILENAME, &card_serial_num)
+        .expect("could not write file");
+    println!("Wrote {POSSESSION_BN254_PROOF_FILENAME}");
+    println!("Wrote {POSSESSION_BN254_REVEALED_SERIAL_FILENAME}");
+}
@@ -4,35 +4,50 @@
 
 use arkworks_merkle_tree_example::{
     constraints_showprice::PossessionShowPriceCircuit,
-    merkle::MerkleRoot,
+    prover::{Groth16Prover, Groth16Verifier},
     util::{
-        gen_test_tree, get_test_card, get_test_leaf, read_from_file, write_to_file,
-        POSSESSION_REVEALED_PRICE_FILENAME, POSSESSION_REVEALED_SERIAL_FILENAME,
-        POSSESSION_SHOWPRICE_PROOF_FILENAME, POSSESSION_SHOWPRICE_VK_FILENAME,
+        gen_test_tree_with_card, get_test_card, read_from_file, root_from_zbase32,
+        write_to_file_atomic, CardInputJson, POSSESSION_REVEALED_PRICE_FILENAME,
+        POSSESSION_REVEALED_SERIAL_FILENAME, POSSESSION_SHOWPRICE_PROOF_FILENAME,
+        POSSESSION_SHOWPRICE_VK_FILENAME,
     },
     E,
 };
 
-use std::env;
+use std::{
+    env,
+    io::{self, Read},
+    process,
+};
 
 use ark_ff::ToConstraintField;
-use ark_groth16::{create_random_proof, verify_proof, ProvingKey};
-use ark_serialize::CanonicalDeserialize;
+use ark_groth16::ProvingKey;
 
 const HELP_STR: &str = "\
 Error: bad command line arguments
 
 Usage:
-    cargo run --release --bin prove_showprice -- PEDERSEN_PARAM_FILE PROVING_KEY_FILE MERKLE_ROOT
+    cargo run --release --bin prove_showprice -- [--stdin] PEDERSEN_PARAM_FILE PROVING_KEY_FILE MERKLE_ROOT
 Example:
     cargo run --release --bin prove_showprice -- \\
         pedersen_params.bin \\
         possession_showprice_proving_key.bin \\
         f5pj64oh3m6anguhjb5rhfugwe44ximao17ya3wgx1fbmg1iobmo
+
+By default, this proves possession of card #7 of the test tree. With --stdin, it instead reads a
+card from STDIN as JSON, e.g.,
+    { \"card_purchase_price\": \"100\", \"card_serial_num\": \"5678\", \"card_com_rand\": \"1234\" }
+and puts that card in place of card #7.
 ";
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
+    let mut args: Vec<String> = env::args().collect();
+    // Check for the --stdin flag, and remove it so the rest of the args are in the same place
+    // either way
+    let read_stdin = args.get(1).map(String::as_str) == Some("--stdin");
+    if read_stdin {
+        args.remove(1);
+    }
     if args.len() != 4 {
         println!("{}", HELP_STR);
         panic!("bad command line input");
@@ -40,11 +55,25 @@ fn main() {
     // Unpack command line args
     let pedersen_params_filename = &args[1];
     let possession_pk_filename = &args[2];
-    let given_merkle_root = {
-        let bytes = zbase32::decode_full_bytes(args[3].as_bytes())
-            .expect("could not decode Merkle root string");
-        MerkleRoot::deserialize_compressed(bytes.as_slice())
-            .expect("Merkle root string is an invalid hash")
+    let given_merkle_root =
+        root_from_zbase32(&args[3]).expect("could not decode Merkle root string");
+
+    // Our card appears at index 7 in the tree
+    let our_idx = 7;
+    // If we were asked to, read the card from STDIN. Otherwise use the test card.
+    let (card, card_com_rand) = if read_stdin {
+        let mut json = String::new();
+        io::stdin()
+            .read_to_string(&mut json)
+            .expect("could not read from STDIN");
+        CardInputJson::from_json(&json)
+            .and_then(|input| input.to_card())
+            .unwrap_or_else(|e| {
+                eprintln!("Error: {e}");
+                process::exit(1);
+            })
+    } else {
+        get_test_card(our_idx)
     };
 
     //
@@ -55,21 +84,25 @@ fn main() {
 
     println!("Reading params and proving key...");
     // Read the hashing params from a file
-    let (leaf_crh_params, two_to_one_crh_params) = read_from_file(&pedersen_params_filename);
+    let (leaf_crh_params, two_to_one_crh_params) = read_from_file(pedersen_params_filename);
     // Read the Groth16 CRS from a file
-    let pk: ProvingKey<E> = read_from_file(&possession_pk_filename);
-
-    // Generate a test tree and compute its root
-    let tree = gen_test_tree(&leaf_crh_params, &two_to_one_crh_params);
+    let pk: ProvingKey<E> = read_from_file(possession_pk_filename);
+
+    // Generate a test tree with our card in it, and compute its root. If our card is the test
+    // card, this is just the test tree.
+    let (tree, _) = gen_test_tree_with_card(
+        &leaf_crh_params,
+        &two_to_one_crh_params,
+        our_idx,
+        &card,
+        &card_com_rand,
+    );
     let root = tree.root();
     // Check that the root we generated is equal to the root that was given
     assert_eq!(
         root, given_merkle_root,
         "The Merkle root I'm trying to use is different than the one you gave me"
     );
-    // Also imagine we possess the card that appears at index 7 in the tree
-    let our_idx = 7;
-    let (card, card_com_rand) = get_test_card(our_idx);
 
     //
     // Now generate a proof
@@ -77,7 +110,13 @@ fn main() {
 
     // We'll prove membership of our card, i.e., the 7th item in the tree
     let idx_to_prove = our_idx;
-    let claimed_leaf = &get_test_leaf(&leaf_crh_params, idx_to_prove);
+    let claimed_leaf = &tree.leaves()[idx_to_prove];
+
+    // Make sure our card actually opens the leaf before spending time on a proof
+    if !card.verify_commitment(&leaf_crh_params, &card_com_rand, claimed_leaf) {
+        eprintln!("Error: our card is not committed to by leaf #{idx_to_prove}");
+        process::exit(1);
+    }
 
     // Generate an authentication path for our leaf
     let auth_path = tree.generate_proof(idx_to_prove).unwrap();
@@ -87,6 +126,8 @@ fn main() {
         // Constants that the circuit needs
         leaf_crh_params,
         two_to_one_crh_params,
+        // Our test cards have random prices, which don't fit in a u64
+        check_price_range: false,
 
         // Public inputs to the circuit
         root,
@@ -102,29 +143,39 @@ fn main() {
 
     // Create the proof
     println!("Proving...");
-    let proof = create_random_proof(circuit.clone(), &pk, &mut rng).unwrap();
+    let prover = Groth16Prover::new(pk);
+    let proof = prover.prove(circuit, &mut rng).expect("could not prove");
 
     //
     // Wrap-up
     //
 
     // Verify the proof package. This should succeed
-    let vk = read_from_file(POSSESSION_SHOWPRICE_VK_FILENAME);
+    let verifier = Groth16Verifier::new(read_from_file(POSSESSION_SHOWPRICE_VK_FILENAME));
     let public_inputs = [
         root.to_field_elements().unwrap(),
         card.serial_num.to_field_elements().unwrap(),
         card.purchase_price.to_field_elements().unwrap(),
     ]
     .concat();
+    assert_eq!(
+        public_inputs.len(),
+        PossessionShowPriceCircuit::NUM_PUBLIC_INPUTS
+    );
     assert!(
-        verify_proof(&vk, &proof, &public_inputs).unwrap(),
+        verifier
+            .verify(&proof, &public_inputs)
+            .expect("could not verify proof"),
         "honest proof failed to verify with supplied verifying key"
     );
 
     // Write the proof, serial, and purchase to files
-    write_to_file(POSSESSION_SHOWPRICE_PROOF_FILENAME, &proof);
-    write_to_file(POSSESSION_REVEALED_SERIAL_FILENAME, &card.serial_num);
-    write_to_file(POSSESSION_REVEALED_PRICE_FILENAME, &card.purchase_price);
+    write_to_file_atomic(POSSESSION_SHOWPRICE_PROOF_FILENAME, &proof)
+        .expect("could not write file");
+    write_to_file_atomic(POSSESSION_REVEALED_SERIAL_FILENAME, &card.serial_num)
+        .expect("could not write file");
+    write_to_file_atomic(POSSESSION_REVEALED_PRICE_FILENAME, &card.purchase_price)
+        .expect("could not write file");
     println!("Wrote {POSSESSION_SHOWPRICE_PROOF_FILENAME}");
     println!("Wrote {POSSESSION_REVEALED_SERIAL_FILENAME}");
     println!("Wrote {POSSESSION_REVEALED_PRICE_FILENAME}");
@@ -0,0 +1,137 @@
+use arkworks_merkle_tree_example::{
+    constraints_voting::{commit_ballot, VotingCircuit},
+    prover::{Groth16Prover, Groth16Verifier},
+    util::{
+        gen_test_tree, get_test_card, read_from_file, root_from_zbase32, write_to_file_atomic,
+        VOTING_BALLOT_FILENAME, VOTING_PROOF_FILENAME, VOTING_REVEALED_NULLIFIER_FILENAME,
+        VOTING_VK_FILENAME,
+    },
+    E, F,
+};
+
+use std::env;
+
+use ark_ff::{ToConstraintField, UniformRand};
+use ark_groth16::ProvingKey;
+
+const HELP_STR: &str = "\
+Error: bad command line arguments
+
+Usage:
+    cargo run --release --bin prove_voting -- PEDERSEN_PARAM_FILE PROVING_KEY_FILE MERKLE_ROOT CHOICE
+Example:
+    cargo run --release --bin prove_voting -- \\
+        pedersen_params.bin \\
+        voting_proving_key.bin \\
+        f5pj64oh3m6anguhjb5rhfugwe44ximao17ya3wgx1fbmg1iobmo \\
+        1
+";
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() != 5 {
+        println!("{}", HELP_STR);
+        panic!("bad command line input");
+    }
+    // Unpack command line args
+    let pedersen_params_filename = &args[1];
+    let voting_pk_filename = &args[2];
+    let given_merkle_root =
+        root_from_zbase32(&args[3]).expect("could not decode Merkle root string");
+    let choice = match args[4].as_str() {
+        "0" => false,
+        "1" => true,
+        _ => panic!("choice must be 0 or 1"),
+    };
+
+    //
+    // Setup
+    //
+
+    let mut rng = rand::thread_rng();
+
+    println!("Reading params and proving key...");
+    // Read the hashing params from a file
+    let (leaf_crh_params, two_to_one_crh_params) = read_from_file(pedersen_params_filename);
+    // Read the Groth16 CRS from a file
+    let pk: ProvingKey<E> = read_from_file(voting_pk_filename);
+
+    // Our test tree doubles as the voter registry
+    let tree = gen_test_tree(&leaf_crh_params, &two_to_one_crh_params);
+    let root = tree.root();
+    // Check that the root we generated is equal to the root that was given
+    assert_eq!(
+        root, given_merkle_root,
+        "The Merkle root I'm trying to use is different than the one you gave me"
+    );
+    // Imagine our credential is the one at index 7 in the tree
+    let our_idx = 7;
+    let (credential, credential_com_rand) = get_test_card(our_idx);
+
+    //
+    // Now generate a proof
+    //
+
+    // Fill out the ballot
+    let ballot_com_rand = F::rand(&mut rng);
+    let ballot_com = commit_ballot(
+        &leaf_crh_params,
+        &credential.serial_num,
+        choice,
+        &ballot_com_rand,
+    );
+
+    let circuit = VotingCircuit {
+        // Constants that the circuit needs
+        leaf_crh_params,
+        two_to_one_crh_params,
+
+        // Public inputs to the circuit
+        root,
+        credential_nullifier: credential.serial_num,
+        ballot_com: ballot_com.to_vec(),
+
+        // Witness to membership
+        leaf: tree.leaves()[our_idx].to_vec(),
+        auth_path: tree.generate_proof(our_idx).unwrap(),
+        // Credential opening details
+        credential_secret: credential.purchase_price,
+        credential_com_rand,
+        // Ballot opening details
+        choice,
+        ballot_com_rand,
+    };
+
+    // Create the proof
+    println!("Proving...");
+    let prover = Groth16Prover::new(pk);
+    let proof = prover.prove(circuit, &mut rng).expect("could not prove");
+
+    //
+    // Wrap-up
+    //
+
+    // Verify the proof. This should succeed
+    let verifier = Groth16Verifier::new(read_from_file(VOTING_VK_FILENAME));
+    let public_inputs = [
+        root.to_field_elements().unwrap(),
+        credential.serial_num.to_field_elements().unwrap(),
+        ballot_com.as_slice().to_field_elements().unwrap(),
+    ]
+    .concat();
+    assert!(
+        verifier
+            .verify(&proof, &public_inputs)
+            .expect("could not verify proof"),
+        "honest proof failed to verify with supplied verifying key"
+    );
+
+    // Write the proof, nullifier, and ballot to files
+    write_to_file_atomic(VOTING_PROOF_FILENAME, &proof).expect("could not write file");
+    write_to_file_atomic(VOTING_REVEALED_NULLIFIER_FILENAME, &credential.serial_num)
+        .expect("could not write file");
+    write_to_file_atomic(VOTING_BALLOT_FILENAME, &ballot_com).expect("could not write file");
+    println!("Wrote {VOTING_PROOF_FILENAME}");
+    println!("Wrote {VOTING_REVEALED_NULLIFIER_FILENAME}");
+    println!("Wrote {VOTING_BALLOT_FILENAME}");
+}
@@ -1,8 +1,11 @@
-use arkworks_merkle_tree_example::{merkle::MerkleRoot, util::read_from_file, E, F};
+use arkworks_merkle_tree_example::{
+    prover::Groth16Verifier,
+    util::{fingerprint, is_canonical_proof, read_from_file, root_from_zbase32},
+    E, F,
+};
 
 use ark_ff::ToConstraintField;
-use ark_groth16::{verify_proof, PreparedVerifyingKey, Proof};
-use ark_serialize::CanonicalDeserialize;
+use ark_groth16::{PreparedVerifyingKey, Proof};
 
 const HELP_STR: &str = "\
 Error: bad command line arguments
@@ -27,12 +30,8 @@ fn main() {
     let possession_vk_filename = &args[1];
     let possession_proof_filename = &args[2];
     let possession_revealed_serial_filename = &args[3];
-    let given_merkle_root = {
-        let bytes = zbase32::decode_full_bytes(args[4].as_bytes())
-            .expect("could not decode Merkle root string");
-        MerkleRoot::deserialize_compressed(bytes.as_slice())
-            .expect("Merkle root string is an invalid hash")
-    };
+    let given_merkle_root =
+        root_from_zbase32(&args[4]).expect("could not decode Merkle root string");
 
     //
     // Setup
@@ -43,6 +42,15 @@ fn main() {
     let vk: PreparedVerifyingKey<E> = read_from_file(possession_vk_filename);
     let proof: Proof<E> = read_from_file(possession_proof_filename);
     let card_serial: F = read_from_file(possession_revealed_serial_filename);
+    // Don't bother verifying a proof whose points aren't in the right group
+    if !is_canonical_proof(&proof) {
+        eprintln!(
+            "Error: proof is not canonical; its points are not all in the prime-order subgroup"
+        );
+        std::process::exit(1);
+    }
+    // Say which key we're using, so it can be checked against the one the prover used
+    println!("Verifying key fingerprint: {}", fingerprint(&vk));
 
     //
     // Compute the public inputs for the circuit. We know the Merkle root, and we were given the
@@ -60,9 +68,12 @@ fn main() {
     // Verify the proof
     //
 
-    // Prepare the verifying key and verify
+    // Verify with the prepared verifying key
+    let verifier = Groth16Verifier::new(vk);
     assert!(
-        verify_proof(&vk, &proof, &public_inputs).unwrap(),
+        verifier
+            .verify(&proof, &public_inputs)
+            .expect("could not verify proof"),
         "proof failed to verify"
     );
 
@@ -0,0 +1,83 @@
+use arkworks_merkle_tree_example::{
+    bn254::{root_from_zbase32, Fr},
+    prover::Groth16Verifier,
+    util::{is_canonical_proof, read_from_file},
+    Bn254Config, CryptoConfig,
+};
+
+use ark_ff::ToConstraintField;
+use ark_groth16::{PreparedVerifyingKey, Proof};
+
+// The pairing engine for BN254
+type E = <Bn254Config as CryptoConfig>::E;
+
+const HELP_STR: &str = "\
+Error: bad command line arguments
+
+Usage:
+    cargo run --release --bin verify_bn254 -- VERIFYING_KEY_FILE PROOF_FILE PUBLIC_INPUTS_FILE MERKLE_ROOT
+Example:
+    cargo run --release --bin verify_bn254 -- \\
+        possession_bn254_verifying_key.bin \\
+        possession_bn254_proof.bin \\
+        possession_bn254_revealed_serial.bin \\
+        <the root printed by gen_params_bn254>
+";
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() != 5 {
+        println!("{}", HELP_STR);
+        panic!("bad command line input");
+    }
+    // Unpack command line args
+    let possession_vk_filename = &args[1];
+    let possession_proof_filename = &args[2];
+    let possession_revealed_serial_filename = &args[3];
+    let given_merkle_root =
+        root_from_zbase32(&args[4]).expect("could not decode Merkle root string");
+
+    //
+    // Setup
+    //
+
+    println!("Reading verifying key, proof, and public inputs...");
+    // Read the Groth16 CRS, proof, and serial from a file
+    let vk: PreparedVerifyingKey<E> = read_from_file(possession_vk_filename);
+    let proof: Proof<E> = read_from_file(possession_proof_filename);
+    let card_serial: Fr = read_from_file(possession_revealed_serial_filename);
+    // Don't bother verifying a proof whose points aren't in the right group
+    if !is_canonical_proof(&proof) {
+        eprintln!(
+            "Error: proof is not canonical; its points are not all in the prime-order subgroup"
+        );
+        std::process::exit(1);
+    }
+
+    //
+    // Compute the public inputs for the circuit. We know the Merkle root, and we were given the
+    // card serial
+    //
+
+    // Serialize everything to field elements
+    let public_inputs = [
+        given_merkle_root.to_field_elements().unwrap(),
+        card_serial.to_field_elements().unwrap(),
+    ]
+    .concat();
+
+    //
+    // Verify the proof
+    //
+
+    // Verify with the prepared verifying key
+    let verifier = Groth16Verifier::new(vk);
+    assert!(
+        verifier
+            .verify(&proof, &public_inputs)
+            .expect("could not verify proof"),
+        "proof failed to verify"
+    );
+
+    println!("Proof verified successfully");
+}
@@ -2,11 +2,14 @@
 // EXTRA CREDIT SOLUTION
 //
 
-use arkworks_merkle_tree_example::{merkle::MerkleRoot, util::read_from_file, E, F};
+use arkworks_merkle_tree_example::{
+    prover::Groth16Verifier,
+    util::{read_from_file, root_from_zbase32},
+    E, F,
+};
 
 use ark_ff::ToConstraintField;
-use ark_groth16::{verify_proof, PreparedVerifyingKey, Proof};
-use ark_serialize::CanonicalDeserialize;
+use ark_groth16::{PreparedVerifyingKey, Proof};
 
 const HELP_STR: &str = "\
 Error: bad command line arguments
@@ -33,12 +36,8 @@ fn main() {
     let possession_proof_filename = &args[2];
     let possession_revealed_serial_filename = &args[3];
     let possession_revealed_price_filename = &args[4];
-    let given_merkle_root = {
-        let bytes = zbase32::decode_full_bytes(args[5].as_bytes())
-            .expect("could not decode Merkle root string");
-        MerkleRoot::deserialize_compressed(bytes.as_slice())
-            .expect("Merkle root string is an invalid hash")
-    };
+    let given_merkle_root =
+        root_from_zbase32(&args[5]).expect("could not decode Merkle root string");
 
     //
     // Setup
@@ -68,11 +67,20 @@ fn main() {
     // Verify the proof
     //
 
-    // Prepare the verifying key and verify
-    assert!(
-        verify_proof(&vk, &proof, &public_inputs).unwrap(),
-        "proof failed to verify"
-    );
-
-    println!("Proof verified successfully");
+    // Verify with the prepared verifying key
+    let verifier = Groth16Verifier::new(vk);
+    match verifier.verify(&proof, &public_inputs) {
+        Ok(true) => println!("Proof verified successfully"),
+        Ok(false) => {
+            eprintln!(
+                "Error: proof failed to verify. Check that the Merkle root, serial, and price are \
+                 the ones the proof was made with."
+            );
+            std::process::exit(1);
+        }
+        Err(e) => {
+            eprintln!("Error: {e}");
+            std::process::exit(1);
+        }
+    }
 }
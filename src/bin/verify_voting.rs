@@ -0,0 +1,77 @@
+use arkworks_merkle_tree_example::{
+    merkle::Leaf,
+    prover::Groth16Verifier,
+    util::{read_from_file, root_from_zbase32},
+    E, F,
+};
+
+use ark_ff::ToConstraintField;
+use ark_groth16::{PreparedVerifyingKey, Proof};
+
+const HELP_STR: &str = "\
+Error: bad command line arguments
+
+Usage:
+    cargo run --release --bin verify_voting -- VERIFYING_KEY_FILE PROOF_FILE NULLIFIER_FILE BALLOT_FILE MERKLE_ROOT
+Example:
+    cargo run --release --bin verify_voting -- \\
+        voting_verifying_key.bin \\
+        voting_proof.bin \\
+        voting_revealed_nullifier.bin \\
+        voting_ballot.bin \\
+        f5pj64oh3m6anguhjb5rhfugwe44ximao17ya3wgx1fbmg1iobmo
+";
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() != 6 {
+        println!("{}", HELP_STR);
+        panic!("bad command line input");
+    }
+    // Unpack command line args
+    let voting_vk_filename = &args[1];
+    let voting_proof_filename = &args[2];
+    let voting_revealed_nullifier_filename = &args[3];
+    let voting_ballot_filename = &args[4];
+    let given_merkle_root =
+        root_from_zbase32(&args[5]).expect("could not decode Merkle root string");
+
+    //
+    // Setup
+    //
+
+    println!("Reading verifying key, proof, and public inputs...");
+    // Read the Groth16 CRS, proof, nullifier, and ballot from files
+    let vk: PreparedVerifyingKey<E> = read_from_file(voting_vk_filename);
+    let proof: Proof<E> = read_from_file(voting_proof_filename);
+    let credential_nullifier: F = read_from_file(voting_revealed_nullifier_filename);
+    let ballot_com: Leaf = read_from_file(voting_ballot_filename);
+
+    //
+    // Compute the public inputs for the circuit. We know the Merkle root, and we were given the
+    // nullifier and the ballot
+    //
+
+    // Serialize everything to field elements
+    let public_inputs = [
+        given_merkle_root.to_field_elements().unwrap(),
+        credential_nullifier.to_field_elements().unwrap(),
+        ballot_com.as_slice().to_field_elements().unwrap(),
+    ]
+    .concat();
+
+    //
+    // Verify the proof
+    //
+
+    // Verify with the prepared verifying key
+    let verifier = Groth16Verifier::new(vk);
+    assert!(
+        verifier
+            .verify(&proof, &public_inputs)
+            .expect("could not verify proof"),
+        "proof failed to verify"
+    );
+
+    println!("Vote verified successfully");
+}
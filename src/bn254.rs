@@ -0,0 +1,184 @@
+use crate::{
+    card::commit_card,
+    constraints::PossessionCircuit,
+    hash::{LeafWindow, TwoToOneWindow},
+    util::DecodeError,
+    Bn254Config,
+};
+
+use ark_crypto_primitives::{
+    crh::{
+        constraints::CRHSchemeGadget, pedersen, CRHScheme, TwoToOneCRHScheme,
+        TwoToOneCRHSchemeGadget,
+    },
+    merkle_tree::{
+        constraints::{BytesVarDigestConverter, ConfigGadget},
+        ByteDigestConverter, Config, MerkleTree, Path,
+    },
+};
+use ark_ed_on_bn254::{constraints::EdwardsVar as BabyJubjubVar, EdwardsProjective as BabyJubjub};
+use ark_ff::UniformRand;
+use ark_r1cs_std::uint8::UInt8;
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+
+// The hashes and Merkle tree for `Bn254Config`. These mirror the ones in `hash` and `merkle`,
+// except the Pedersen hashes are over Baby Jubjub, the twisted Edwards curve whose base field is
+// the BN254 scalar field.
+
+/// The BN254 scalar field. Circuits over BN254 are defined over this field.
+pub type Fr = ark_bn254::Fr;
+
+// Field elements and curve points serialize to the same number of bytes as they do for
+// BLS12-381 and Jubjub, namely 32 and 64. So the windows in `hash` are big enough for our inputs
+// here too.
+pub type LeafHash = pedersen::CRH<BabyJubjub, LeafWindow>;
+pub type TwoToOneHash = pedersen::TwoToOneCRH<BabyJubjub, TwoToOneWindow>;
+pub type LeafHashParams = <LeafHash as CRHScheme>::Parameters;
+pub type TwoToOneHashParams = <TwoToOneHash as TwoToOneCRHScheme>::Parameters;
+
+pub type LeafHashGadget = pedersen::constraints::CRHGadget<BabyJubjub, BabyJubjubVar, LeafWindow>;
+pub type TwoToOneHashGadget =
+    pedersen::constraints::TwoToOneCRHGadget<BabyJubjub, BabyJubjubVar, TwoToOneWindow>;
+
+/// Defines how leaves are hashed alone and together. This is the same as `merkle::MerkleConfig`,
+/// except for the curve.
+#[derive(Clone)]
+pub struct MerkleConfig;
+
+impl Config for MerkleConfig {
+    type Leaf = [u8];
+
+    // This is an elliptic curve point
+    type LeafDigest = <LeafHash as CRHScheme>::Output;
+    // This just serializes the elliptic curve point into bytes, uncompressed
+    type LeafInnerDigestConverter = ByteDigestConverter<Self::LeafDigest>;
+    // Also an elliptic curve point
+    type InnerDigest = <TwoToOneHash as TwoToOneCRHScheme>::Output;
+
+    type LeafHash = LeafHash;
+    type TwoToOneHash = TwoToOneHash;
+}
+
+/// A Merkle tree over BN254
+pub type Bn254MerkleTree = MerkleTree<MerkleConfig>;
+
+/// The root of a Merkle tree over BN254
+pub type MerkleRoot = <TwoToOneHash as TwoToOneCRHScheme>::Output;
+
+/// A membership proof in a Merkle tree over BN254
+pub type Bn254Path = Path<MerkleConfig>;
+
+/// Merkle tree params for R1CS. This is analogous to our MerkleConfig implementation
+pub struct MerkleConfigGadget;
+impl ConfigGadget<MerkleConfig, Fr> for MerkleConfigGadget {
+    type Leaf = [UInt8<Fr>];
+    type LeafDigest = <LeafHashGadget as CRHSchemeGadget<LeafHash, Fr>>::OutputVar;
+    type LeafInnerConverter = BytesVarDigestConverter<Self::LeafDigest, Fr>;
+    type InnerDigest = <TwoToOneHashGadget as TwoToOneCRHSchemeGadget<TwoToOneHash, Fr>>::OutputVar;
+    type LeafHash = LeafHashGadget;
+    type TwoToOneHash = TwoToOneHashGadget;
+}
+
+//
+// TEST TREE
+//
+
+// The BN254 binaries prove possession of a card in a test tree, same as the BLS12-381 ones. These
+// are the BN254 versions of the test tree helpers in `util`. Cards here are just
+// `(purchase_price, serial_num)` pairs, since `Card` is over the BLS12-381 scalar field.
+
+/// Deterministically creates 16 cards, as `(purchase_price, serial_num, com_rand)`
+pub fn test_cards() -> Vec<(Fr, Fr, Fr)> {
+    // Use a deterministic RNG
+    let mut rng = ark_std::test_rng();
+
+    core::iter::repeat_with(|| (Fr::rand(&mut rng), Fr::rand(&mut rng), Fr::rand(&mut rng)))
+        .take(16)
+        .collect()
+}
+
+/// Returns a Merkle tree of the cards in `test_cards`, along with its leaves
+pub fn gen_test_tree(
+    leaf_crh_params: &LeafHashParams,
+    two_to_one_crh_params: &TwoToOneHashParams,
+) -> (Bn254MerkleTree, Vec<Vec<u8>>) {
+    let leaves: Vec<Vec<u8>> = test_cards()
+        .iter()
+        .map(|(price, serial_num, com_rand)| {
+            commit_card::<Bn254Config>(leaf_crh_params, price, serial_num, com_rand)
+        })
+        .collect();
+    let tree = Bn254MerkleTree::new(
+        leaf_crh_params,
+        two_to_one_crh_params,
+        leaves.iter().map(|l| l.as_slice()),
+    )
+    .unwrap();
+
+    (tree, leaves)
+}
+
+/// Makes a valid possession circuit for the `i`-th card of the test tree
+pub fn possession_circuit(
+    leaf_crh_params: LeafHashParams,
+    two_to_one_crh_params: TwoToOneHashParams,
+    i: usize,
+) -> PossessionCircuit<Bn254Config> {
+    let (tree, leaves) = gen_test_tree(&leaf_crh_params, &two_to_one_crh_params);
+    let (purchase_price, serial_num, com_rand) = test_cards()[i];
+
+    PossessionCircuit {
+        // Constants for hashing
+        leaf_crh_params,
+        two_to_one_crh_params,
+
+        // Circuit configuration
+        enforce_nonzero_price: false,
+
+        // Public inputs
+        root: tree.root(),
+        leaf: leaves[i].clone(),
+        card_serial_num: serial_num,
+
+        // Private inputs
+        auth_path: tree.generate_proof(i).unwrap(),
+        card_purchase_price: purchase_price,
+        card_com_rand: com_rand,
+    }
+}
+
+/// Encodes a Merkle root as a zbase32 string. This is `util::root_to_zbase32` over BN254.
+pub fn root_to_zbase32(root: &MerkleRoot) -> String {
+    let mut buf = Vec::new();
+    root.serialize_compressed(&mut buf)
+        .expect("failed to serialize Merkle root");
+    zbase32::encode_full_bytes(&buf)
+}
+
+/// Decodes a Merkle root from a zbase32 string, as output by `root_to_zbase32`
+pub fn root_from_zbase32(s: &str) -> Result<MerkleRoot, DecodeError> {
+    let bytes =
+        zbase32::decode_full_bytes(s.as_bytes()).map_err(|_| DecodeError::InvalidEncoding)?;
+    MerkleRoot::deserialize_compressed(bytes.as_slice()).map_err(DecodeError::DeserializationFailed)
+}
+
+//
+// TESTS
+//
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // A root should survive a round trip through zbase32
+    #[test]
+    fn root_zbase32_roundtrip() {
+        let mut rng = ark_std::test_rng();
+        let leaf_crh_params = <LeafHash as CRHScheme>::setup(&mut rng).unwrap();
+        let two_to_one_crh_params = <TwoToOneHash as TwoToOneCRHScheme>::setup(&mut rng).unwrap();
+
+        let (tree, _) = gen_test_tree(&leaf_crh_params, &two_to_one_crh_params);
+        let root = tree.root();
+        assert_eq!(root_from_zbase32(&root_to_zbase32(&root)).unwrap(), root);
+    }
+}
@@ -1,25 +1,65 @@
 use crate::{
-    hash::{LeafHash, LeafHashGadget, LeafHashParamsVar},
-    merkle::{Leaf, MerkleConfig},
-    F, FV,
+    hash::{
+        commit, commit_var, LeafHash, LeafHashParams, LeafHashParamsVar, OwnedLeafHashParams,
+        OwnedLeafHashParamsVar, PedersenCommitmentGadget, PoseidonLeafHash, PoseidonLeafHashGadget,
+        PoseidonParams, PoseidonParamsVar, TreeHasher, TwoToOneHash, TwoToOneHashGadget,
+        TwoToOneHashParams, TwoToOneHashParamsVar,
+    },
+    merkle::{subtree_root_var, Leaf, MerkleRoot, RootVar, SimpleMerkleTree, EMPTY_LEAF},
+    poseidon_params::poseidon_params,
+    Bls12Config, CryptoConfig, LeafHashParamsOf, LeafHashParamsVarOf, F, FV,
 };
 
-use ark_crypto_primitives::{
-    crh::{constraints::CRHSchemeGadget, CRHScheme},
-    merkle_tree::{Config, DigestConverter},
+use ark_crypto_primitives::crh::{
+    constraints::CRHSchemeGadget, CRHScheme, TwoToOneCRHScheme, TwoToOneCRHSchemeGadget,
 };
-use ark_ff::UniformRand;
-use ark_r1cs_std::{uint8::UInt8, ToBytesGadget};
-use ark_relations::r1cs::SynthesisError;
-use ark_serialize::CanonicalSerialize;
+use ark_ff::{BigInteger, PrimeField, ToConstraintField, UniformRand};
+use ark_r1cs_std::{
+    alloc::AllocVar, boolean::Boolean, eq::EqGadget, fields::fp::FpVar, fields::FieldVar,
+    uint8::UInt8, R1CSVar, ToBytesGadget,
+};
+use ark_relations::{
+    ns,
+    r1cs::{ConstraintSystemRef, SynthesisError},
+};
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+use core::fmt;
 use rand::Rng;
+use subtle::ConstantTimeEq;
 
 //
 // NATIVE IMPLEMENTATIONS
 //
 
+/// An error from manipulating cards natively
+#[derive(Debug, PartialEq, Eq)]
+pub enum CardError {
+    /// The output prices of a split don't add up to the price of the card being split
+    ValueNotConserved,
+    /// A price doesn't fit in a `u64`, so `SplitCircuit` would reject it
+    PriceOutOfRange,
+}
+
+impl fmt::Display for CardError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CardError::ValueNotConserved => {
+                write!(f, "output prices don't sum to the input price")
+            }
+            CardError::PriceOutOfRange => write!(f, "price doesn't fit in a u64"),
+        }
+    }
+}
+
+impl std::error::Error for CardError {}
+
+/// Returns whether `x` fits in a `u64`. This is the native version of `enforce_fits_in_u64`.
+pub fn fits_in_u64(x: &F) -> bool {
+    x.into_bigint().num_bits() <= 64
+}
+
 /// A baseball card. The leaves in our tree are card commitments.
-#[derive(Clone, CanonicalSerialize)]
+#[derive(Clone, CanonicalSerialize, CanonicalDeserialize)]
 pub struct Card {
     pub purchase_price: F,
     pub serial_num: F,
@@ -33,22 +73,282 @@ impl Card {
         leaf_crh_params: &<LeafHash as CRHScheme>::Parameters,
         com_rand: &F,
     ) -> Leaf {
-        // This will be the buffer we feed into the hash function
-        let mut buf = Vec::new();
+        // Compute Hash(com_rand || amount || nulifier)
+        let mut hasher = TreeHasher::new(leaf_crh_params);
+        hasher.update_serialized(com_rand);
+        hasher.update_serialized(self);
+        hasher.finalize().expect("a card fits in the leaf hash")
+    }
+
+    /// Commits to this card along with the public key of its owner. This is what a card becomes
+    /// when it's transferred. Concretely, this computes
+    /// `OwnedLeafHash(com_rand || amount || serial_num || owner_pubkey)`
+    pub fn commit_with_owner(
+        &self,
+        owned_leaf_crh_params: &OwnedLeafHashParams,
+        com_rand: &F,
+        owner_pubkey: &F,
+    ) -> Leaf {
+        let mut hasher = TreeHasher::new_owned(owned_leaf_crh_params);
+        hasher.update_serialized(com_rand);
+        hasher.update_serialized(self);
+        hasher.update_serialized(owner_pubkey);
+        hasher
+            .finalize()
+            .expect("a card and its owner fit in the leaf hash")
+    }
+
+    /// Checks that `expected` is the commitment to this card with commitment randomness
+    /// `com_rand`. The comparison is constant-time, so timing doesn't leak how many bytes of
+    /// `expected` were correct.
+    pub fn verify_commitment(
+        &self,
+        leaf_crh_params: &LeafHashParams,
+        com_rand: &F,
+        expected: &Leaf,
+    ) -> bool {
+        let computed = self.commit(leaf_crh_params, com_rand);
+        computed.as_slice().ct_eq(expected.as_slice()).into()
+    }
+
+    /// Commits to `(self.purchase_price, self.serial_num)` using Poseidon rather than Pedersen.
+    /// Concretely, this computes `PoseidonHash(com_rand, purchase_price, serial_num)`
+    pub fn commit_poseidon(&self, poseidon_params: &PoseidonParams, com_rand: &F) -> F {
+        let input = [*com_rand, self.purchase_price, self.serial_num];
+        PoseidonLeafHash::evaluate(poseidon_params, input.as_slice()).unwrap()
+    }
 
-        // Serialize the randomness
-        com_rand.serialize_uncompressed(&mut buf).unwrap();
+    /// Splits this card into two with the given prices, and fresh random serial numbers. This is
+    /// the native counterpart of `SplitCircuit`. Like the circuit, this requires this card's price
+    /// and both new prices to fit in a `u64`, and the new prices to sum to this card's price.
+    pub fn split<R: Rng + ?Sized>(
+        &self,
+        amount_a: F,
+        amount_b: F,
+        rng: &mut R,
+    ) -> Result<(Card, Card), CardError> {
+        // Without the range checks, the sum could wrap around the modulus
+        if ![self.purchase_price, amount_a, amount_b]
+            .iter()
+            .all(fits_in_u64)
+        {
+            return Err(CardError::PriceOutOfRange);
+        }
+        if amount_a + amount_b != self.purchase_price {
+            return Err(CardError::ValueNotConserved);
+        }
+
+        let card_a = Card {
+            purchase_price: amount_a,
+            serial_num: F::rand(rng),
+        };
+        let card_b = Card {
+            purchase_price: amount_b,
+            serial_num: F::rand(rng),
+        };
+        Ok((card_a, card_b))
+    }
 
-        // Now serialize the card
-        self.serialize_uncompressed(&mut buf).unwrap();
+    /// Derives commitment randomness for this card from `secret_key`. Concretely, this computes
+    /// `PoseidonHash(secret_key, serial_num, purchase_price, DERIVED_NONCE_TAG)`. The tag keeps
+    /// this from being the Poseidon commitment to the card with `secret_key` as the randomness.
+    pub fn derive_nonce(&self, poseidon_params: &PoseidonParams, secret_key: &F) -> F {
+        let input = [
+            *secret_key,
+            self.serial_num,
+            self.purchase_price,
+            F::from(DERIVED_NONCE_TAG),
+        ];
+        PoseidonLeafHash::evaluate(poseidon_params, input.as_slice()).unwrap()
+    }
+
+    /// Commits to this card like `commit`, except the commitment randomness is derived from
+    /// `secret_key` rather than sampled. See `derive_nonce`. Returns the commitment and the
+    /// derived randomness.
+    pub fn commit_with_derived_nonce(
+        &self,
+        leaf_crh_params: &LeafHashParams,
+        poseidon_params: &PoseidonParams,
+        secret_key: &F,
+    ) -> (Leaf, F) {
+        let com_rand = self.derive_nonce(poseidon_params, secret_key);
+        (self.commit(leaf_crh_params, &com_rand), com_rand)
+    }
+}
+
+/// Commits to a pair of cards at once. Each card is committed to as usual, and then the two
+/// commitments are combined with the two-to-one hash, the same way sibling nodes are combined in
+/// our Merkle tree. Concretely, this computes `TwoToOneHash(a.commit(nonce_a), b.commit(nonce_b))`.
+pub fn commit_card_pair(
+    params: &TwoToOneHashParams,
+    a: &Card,
+    leaf_crh_params: &LeafHashParams,
+    nonce_a: &F,
+    b: &Card,
+    nonce_b: &F,
+) -> MerkleRoot {
+    let com_a = a.commit(leaf_crh_params, nonce_a);
+    let com_b = b.commit(leaf_crh_params, nonce_b);
+    TwoToOneHash::evaluate(params, com_a.as_slice(), com_b.as_slice()).unwrap()
+}
+
+/// The domain-separation tag for derived commitment randomness. It's the last Poseidon input, so a
+/// derivation never absorbs the same elements as a 3-input Poseidon commitment.
+///
+/// Adding the tag changed the output of `derive_com_rand`. Randomness derived before the change is
+/// `PoseidonHash(master_key, serial_num, purchase_price)`, which `derive_untagged_com_rand` still
+/// computes, so cards committed that way can be opened and re-committed.
+pub const DERIVED_NONCE_TAG: u64 = u64::from_le_bytes(*b"comrand!");
+
+/// The most cards a `CardGroup` can hold
+pub const MAX_GROUP_SIZE: usize = 4;
+
+/// A fixed-size group of up to `MAX_GROUP_SIZE` cards that are committed to together. The
+/// commitment to the group is the root of a 4-leaf Merkle tree whose leaves are the commitments to
+/// the cards, padded with `EMPTY_LEAF`.
+#[derive(Clone)]
+pub struct CardGroup<const N: usize>(pub [Card; N]);
+
+impl<const N: usize> CardGroup<N> {
+    // The group's tree only has room for MAX_GROUP_SIZE cards. Evaluating this fails the build.
+    const FITS: () = assert!(
+        N <= MAX_GROUP_SIZE,
+        "a card group holds at most MAX_GROUP_SIZE cards"
+    );
+
+    /// Commits to every card in the group, the `i`-th card with `nonces[i]` as its commitment
+    /// randomness
+    pub fn commit_group(&self, leaf_crh_params: &LeafHashParams, nonces: &[F; N]) -> Vec<Leaf> {
+        self.0
+            .iter()
+            .zip(nonces)
+            .map(|(card, nonce)| card.commit(leaf_crh_params, nonce))
+            .collect()
+    }
+
+    /// Builds the 4-leaf tree whose leaves are the commitments to the cards in this group. Its
+    /// root is the commitment to the whole group. `N > MAX_GROUP_SIZE` is a compile error.
+    pub fn to_merkle_subtree(
+        &self,
+        leaf_crh_params: &LeafHashParams,
+        two_to_one_crh_params: &TwoToOneHashParams,
+        nonces: &[F; N],
+    ) -> SimpleMerkleTree {
+        let () = Self::FITS;
+
+        let mut leaves = self.commit_group(leaf_crh_params, nonces);
+        leaves.resize(MAX_GROUP_SIZE, EMPTY_LEAF);
+        SimpleMerkleTree::new(leaf_crh_params, two_to_one_crh_params, leaves).unwrap()
+    }
+}
+
+/// Derives the commitment randomness for `card` from `master_key`, so it never has to be stored. As
+/// long as the owner remembers their master key, they can always recompute the randomness and prove
+/// possession. Concretely, this computes
+/// `PoseidonHash(master_key, serial_num, purchase_price, DERIVED_NONCE_TAG)`, using the default
+/// Poseidon parameters. This is `card.derive_nonce(master_key)`.
+pub fn derive_com_rand(card: &Card, master_key: &F) -> F {
+    card.derive_nonce(&poseidon_params(), master_key)
+}
+
+/// Derives commitment randomness the way `derive_com_rand` did before `DERIVED_NONCE_TAG` was
+/// added, i.e., `PoseidonHash(master_key, serial_num, purchase_price)` with the default Poseidon
+/// parameters. This is only for opening cards committed with that randomness, so they can be
+/// re-committed with `derive_com_rand`. Don't commit to new cards with it.
+pub fn derive_untagged_com_rand(card: &Card, master_key: &F) -> F {
+    let input = [*master_key, card.serial_num, card.purchase_price];
+    PoseidonLeafHash::evaluate(&poseidon_params(), input.as_slice()).unwrap()
+}
+
+/// Commits to every `(card, com_rand)` pair, like calling `card.commit(leaf_crh_params, com_rand)`
+/// on each. All the commitments go through one `TreeHasher`, which keeps its input buffer between
+/// hashes, so there's one allocation for the whole batch rather than one per card. The hash itself
+/// is most of the work, so this is only slightly faster. `benches/commit_batch.rs` measures by how
+/// much.
+pub fn commit_batch(leaf_crh_params: &LeafHashParams, cards: &[(Card, F)]) -> Vec<Leaf> {
+    let mut hasher = TreeHasher::new(leaf_crh_params);
+
+    cards
+        .iter()
+        .map(|(card, com_rand)| {
+            // Compute Hash(com_rand || amount || serial_num), same as Card::commit
+            hasher.update_serialized(com_rand);
+            hasher.update_serialized(card);
+            hasher
+                .finalize_reset()
+                .expect("a card fits in the leaf hash")
+        })
+        .collect()
+}
+
+// Compares the serialized cards in constant time, so timing doesn't leak which field differed
+impl PartialEq for Card {
+    fn eq(&self, other: &Self) -> bool {
+        let mut self_bytes = Vec::new();
+        let mut other_bytes = Vec::new();
+        self.serialize_uncompressed(&mut self_bytes).unwrap();
+        other.serialize_uncompressed(&mut other_bytes).unwrap();
+
+        self_bytes.ct_eq(&other_bytes).into()
+    }
+}
 
-        // Now compute Hash(com_rand || amount || nulifier)
-        let claimed_leaf_hash = LeafHash::evaluate(&leaf_crh_params, buf.as_slice()).unwrap();
+impl Eq for Card {}
 
-        <MerkleConfig as Config>::LeafInnerDigestConverter::convert(claimed_leaf_hash)
-            .unwrap()
-            .try_into()
-            .unwrap()
+// Hashes the same serialized bytes that PartialEq compares, so equal cards hash equally
+impl core::hash::Hash for Card {
+    fn hash<H: core::hash::Hasher>(&self, state: &mut H) {
+        let mut bytes = Vec::new();
+        self.serialize_uncompressed(&mut bytes).unwrap();
+        bytes.hash(state);
+    }
+}
+
+/// Lets a card be used as a Groth16 public input. The order matches the serialization order, i.e.,
+/// `[purchase_price, serial_num]`.
+impl ToConstraintField<F> for Card {
+    fn to_field_elements(&self) -> Option<Vec<F>> {
+        Some(vec![self.purchase_price, self.serial_num])
+    }
+}
+
+/// Commits to the card with the given price and serial number, using `C`'s leaf hash. For
+/// `Bls12Config`, this is `Card::commit`. `Card` is over the BLS12-381 scalar field, so other
+/// configs pass the card's parts directly.
+pub fn commit_card<C: CryptoConfig>(
+    leaf_crh_params: &LeafHashParamsOf<C>,
+    purchase_price: &C::F,
+    serial_num: &C::F,
+    com_rand: &C::F,
+) -> Vec<u8> {
+    let mut card_bytes = Vec::new();
+    purchase_price
+        .serialize_uncompressed(&mut card_bytes)
+        .unwrap();
+    serial_num.serialize_uncompressed(&mut card_bytes).unwrap();
+    commit::<C>(leaf_crh_params, &card_bytes, com_rand)
+}
+
+/// Returns the canonical bytes of the given field element as a lowercase hex string
+fn field_hex(x: &impl CanonicalSerialize) -> String {
+    let mut buf = Vec::new();
+    x.serialize_compressed(&mut buf).unwrap();
+    buf.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+// Prints the fields in hex, so that test failures are readable
+impl fmt::Debug for Card {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Card")
+            .field(
+                "purchase_price",
+                &format_args!("{}", field_hex(&self.purchase_price)),
+            )
+            .field(
+                "serial_num",
+                &format_args!("{}", field_hex(&self.serial_num)),
+            )
+            .finish()
     }
 }
 
@@ -66,32 +366,778 @@ impl UniformRand for Card {
 // R1CS IMPLEMENTATIONS
 //
 
-/// R1CS representation of Card
-pub struct CardVar {
-    pub amount: FV,
-    pub serial_num: FV,
+/// Enforces that `x` fits in a `u64`. Concretely, we witness 64 bits, and enforce that they are
+/// the little-endian bit decomposition of `x`. If `x` is 2^64 or bigger, no such bits exist, and
+/// the constraint system is unsatisfiable.
+pub fn enforce_fits_in_u64(cs: ConstraintSystemRef<F>, x: &FV) -> Result<(), SynthesisError> {
+    // Witness the bits one at a time. We can't witness them all at once, since x has no value
+    // during CRS generation, and we still need to make exactly 64 variables.
+    let bits = (0..64)
+        .map(|i| {
+            Boolean::new_witness(ns!(cs, "bit"), || {
+                x.value().map(|a| a.into_bigint().get_bit(i))
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // Now recompose the bits and check that we get x back
+    Boolean::le_bits_to_fp_var(&bits)?.enforce_equal(x)
+}
+
+// Returns whether `var` is a constant or belongs to `cs`. We compare the underlying constraint
+// systems by address, since two refs to the same system are just two pointers to it.
+fn is_in_cs<G: PrimeField>(cs: &ConstraintSystemRef<G>, var: &FpVar<G>) -> bool {
+    match (cs.borrow(), var.cs().borrow()) {
+        (_, None) => true,
+        (Some(a), Some(b)) => core::ptr::eq(&*a, &*b),
+        (None, Some(_)) => false,
+    }
+}
+
+/// R1CS representation of Card. The field comes from `C`, which is `Bls12Config` unless otherwise
+/// specified.
+pub struct CardVar<C: CryptoConfig = Bls12Config> {
+    pub amount: FpVar<C::F>,
+    pub serial_num: FpVar<C::F>,
+}
+
+// Variables only have a value we can print if they're constants. Otherwise we'd be printing a
+// witness, which we might not even know if we're in setup mode.
+impl<C: CryptoConfig> fmt::Debug for CardVar<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let fmt_var = |v: &FpVar<C::F>| match v {
+            FpVar::Constant(c) => field_hex(c),
+            _ => "<witness>".to_string(),
+        };
+
+        f.debug_struct("CardVar")
+            .field("amount", &format_args!("{}", fmt_var(&self.amount)))
+            .field("serial_num", &format_args!("{}", fmt_var(&self.serial_num)))
+            .finish()
+    }
 }
 
 /// Defines a way to serialize a CardVar to bytes. This is only works if it is identical to the
 /// `impl CanonicalSerialize for Card` serialization.
-impl ToBytesGadget<F> for CardVar {
-    fn to_bytes(&self) -> Result<Vec<UInt8<F>>, SynthesisError> {
+impl<C: CryptoConfig> ToBytesGadget<C::F> for CardVar<C> {
+    fn to_bytes(&self) -> Result<Vec<UInt8<C::F>>, SynthesisError> {
         // Serialize self.amount then self.serial_num
         Ok([self.amount.to_bytes()?, self.serial_num.to_bytes()?].concat())
     }
 }
 
-impl CardVar {
+/// Two cards are equal iff their amounts and serial numbers are equal
+impl<C: CryptoConfig> EqGadget<C::F> for CardVar<C> {
+    fn is_eq(&self, other: &Self) -> Result<Boolean<C::F>, SynthesisError> {
+        self.amount
+            .is_eq(&other.amount)?
+            .and(&self.serial_num.is_eq(&other.serial_num)?)
+    }
+
+    // Enforcing field by field is cheaper than the default, which goes through is_eq
+    fn conditional_enforce_equal(
+        &self,
+        other: &Self,
+        should_enforce: &Boolean<C::F>,
+    ) -> Result<(), SynthesisError> {
+        self.amount
+            .conditional_enforce_equal(&other.amount, should_enforce)?;
+        self.serial_num
+            .conditional_enforce_equal(&other.serial_num, should_enforce)
+    }
+}
+
+impl<C: CryptoConfig> CardVar<C> {
+    /// Puts the given variables together into a `CardVar`. Returns an error if either variable
+    /// belongs to a constraint system other than `cs`. Constants don't belong to any constraint
+    /// system, so they're always allowed.
+    pub fn from_parts(
+        cs: ConstraintSystemRef<C::F>,
+        amount: FpVar<C::F>,
+        serial_num: FpVar<C::F>,
+    ) -> Result<Self, SynthesisError> {
+        if !is_in_cs(&cs, &amount) || !is_in_cs(&cs, &serial_num) {
+            return Err(SynthesisError::Unsatisfiable);
+        }
+
+        Ok(CardVar { amount, serial_num })
+    }
+
     /// Commits to this card using the given commitment randomness. Concretely, this computes
-    /// `Hash(com_rand || self.amount || self.serial_num)`.
+    /// `Hash(com_rand || self.amount || self.serial_num)` with `C`'s leaf hash. This is the
+    /// gadget version of `Card::commit`.
     pub fn commit(
         &self,
-        hash_params: &LeafHashParamsVar,
+        hash_params: &LeafHashParamsVarOf<C>,
+        com_rand: &FpVar<C::F>,
+    ) -> Result<Vec<UInt8<C::F>>, SynthesisError> {
+        let card_bytes = self.to_bytes()?;
+        commit_var::<C>(hash_params, &card_bytes, com_rand)
+    }
+}
+
+impl CardVar {
+    /// The gadget version of `Card::commit_with_owner`. Concretely, this computes
+    /// `OwnedLeafHash(com_rand || self.amount || self.serial_num || owner_pubkey)`.
+    pub fn commit_with_owner(
+        &self,
+        hash_params: &OwnedLeafHashParamsVar,
         com_rand: &FV,
+        owner_pubkey: &FV,
     ) -> Result<Vec<UInt8<F>>, SynthesisError> {
-        let com_rand_bytes = com_rand.to_bytes()?;
-        let card_bytes = self.to_bytes()?;
-        let hash = LeafHashGadget::evaluate(&hash_params, &[com_rand_bytes, card_bytes].concat())?;
-        hash.to_bytes()
+        let bytes = [self.to_bytes()?, owner_pubkey.to_bytes()?].concat();
+        PedersenCommitmentGadget::commit_owned(hash_params, &bytes, com_rand)
+    }
+
+    /// Enforces that `self.amount` fits in a `u64`. See `enforce_fits_in_u64`.
+    pub fn range_check(&self, cs: ConstraintSystemRef<F>) -> Result<(), SynthesisError> {
+        enforce_fits_in_u64(cs, &self.amount)
+    }
+
+    /// Commits to this card using Poseidon. Concretely, this computes
+    /// `PoseidonHash(com_rand, self.amount, self.serial_num)`.
+    pub fn commit_poseidon(
+        &self,
+        poseidon_params: &PoseidonParamsVar,
+        com_rand: &FV,
+    ) -> Result<FV, SynthesisError> {
+        let input = [vec![com_rand.clone()], self.to_field_elements()?].concat();
+        PoseidonLeafHashGadget::evaluate(poseidon_params, &input)
+    }
+
+    /// Returns the components of this card as field elements, in the order `[amount, serial_num]`.
+    /// This matches `Card::to_field_elements`, and is what gets fed into Poseidon.
+    pub fn to_field_elements(&self) -> Result<Vec<FV>, SynthesisError> {
+        Ok(vec![self.amount.clone(), self.serial_num.clone()])
+    }
+
+    /// The gadget version of `Card::derive_nonce`
+    pub fn derive_nonce(
+        &self,
+        poseidon_params: &PoseidonParamsVar,
+        secret_key: &FV,
+    ) -> Result<FV, SynthesisError> {
+        let input = [
+            secret_key.clone(),
+            self.serial_num.clone(),
+            self.amount.clone(),
+            FV::constant(F::from(DERIVED_NONCE_TAG)),
+        ];
+        PoseidonLeafHashGadget::evaluate(poseidon_params, &input)
+    }
+
+    /// The gadget version of `Card::commit_with_derived_nonce`. Returns the commitment and the
+    /// derived randomness.
+    pub fn commit_with_derived_nonce(
+        &self,
+        leaf_crh_params: &LeafHashParamsVar,
+        poseidon_params: &PoseidonParamsVar,
+        secret_key: &FV,
+    ) -> Result<(Vec<UInt8<F>>, FV), SynthesisError> {
+        let com_rand = self.derive_nonce(poseidon_params, secret_key)?;
+        Ok((self.commit(leaf_crh_params, &com_rand)?, com_rand))
+    }
+}
+
+/// An error from building a variable with a builder
+#[derive(Debug, PartialEq, Eq)]
+pub enum BuilderError {
+    /// The named field was never set
+    MissingField { which: &'static str },
+}
+
+impl fmt::Display for BuilderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BuilderError::MissingField { which } => {
+                write!(f, "cannot build without setting the {which} field")
+            }
+        }
+    }
+}
+
+impl std::error::Error for BuilderError {}
+
+/// A builder for `CardVar`. Both fields are `FV`s, so it's easy to swap them by accident when
+/// writing a struct literal. Here every field is set by name. Both fields must be set before
+/// building.
+#[derive(Default)]
+pub struct CardVarBuilder {
+    amount: Option<FV>,
+    serial_num: Option<FV>,
+}
+
+impl CardVarBuilder {
+    /// Makes a builder with no fields set
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the amount the card was purchased for
+    pub fn amount(mut self, a: FV) -> Self {
+        self.amount = Some(a);
+        self
+    }
+
+    /// Sets the card's serial number
+    pub fn serial_num(mut self, n: FV) -> Self {
+        self.serial_num = Some(n);
+        self
+    }
+
+    /// Builds the card
+    pub fn build(self) -> Result<CardVar, BuilderError> {
+        let amount = self
+            .amount
+            .ok_or(BuilderError::MissingField { which: "amount" })?;
+        let serial_num = self.serial_num.ok_or(BuilderError::MissingField {
+            which: "serial_num",
+        })?;
+
+        Ok(CardVar { amount, serial_num })
+    }
+}
+
+/// R1CS representation of a pair of cards
+pub struct CardPairVar {
+    pub a: CardVar,
+    pub b: CardVar,
+}
+
+impl CardPairVar {
+    /// The gadget version of `commit_card_pair`. Concretely, this computes
+    /// `TwoToOneHash(self.a.commit(nonce_a), self.b.commit(nonce_b))`.
+    pub fn commit(
+        &self,
+        two_to_one_crh_params: &TwoToOneHashParamsVar,
+        leaf_crh_params: &LeafHashParamsVar,
+        nonce_a: &FV,
+        nonce_b: &FV,
+    ) -> Result<RootVar, SynthesisError> {
+        let com_a = self.a.commit(leaf_crh_params, nonce_a)?;
+        let com_b = self.b.commit(leaf_crh_params, nonce_b)?;
+        TwoToOneHashGadget::evaluate(two_to_one_crh_params, &com_a, &com_b)
+    }
+}
+
+/// R1CS representation of a `CardGroup`
+pub struct CardGroupVar<const N: usize>(pub [CardVar; N]);
+
+impl<const N: usize> CardGroupVar<N> {
+    /// The gadget version of `CardGroup::commit_group`
+    pub fn commit_group(
+        &self,
+        leaf_crh_params: &LeafHashParamsVar,
+        nonces: &[FV; N],
+    ) -> Result<Vec<Vec<UInt8<F>>>, SynthesisError> {
+        self.0
+            .iter()
+            .zip(nonces)
+            .map(|(card, nonce)| card.commit(leaf_crh_params, nonce))
+            .collect()
+    }
+
+    /// Computes the root of the tree made by `CardGroup::to_merkle_subtree`. `N > MAX_GROUP_SIZE`
+    /// is a compile error.
+    pub fn subtree_root(
+        &self,
+        leaf_crh_params: &LeafHashParamsVar,
+        two_to_one_crh_params: &TwoToOneHashParamsVar,
+        nonces: &[FV; N],
+    ) -> Result<RootVar, SynthesisError> {
+        let () = CardGroup::<N>::FITS;
+
+        let mut leaves = self.commit_group(leaf_crh_params, nonces)?;
+        leaves.resize(MAX_GROUP_SIZE, UInt8::constant_vec(&EMPTY_LEAF));
+        subtree_root_var(leaf_crh_params, two_to_one_crh_params, &leaves)
+    }
+}
+
+//
+// TESTS
+//
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use ark_relations::r1cs::ConstraintSynthesizer;
+
+    // Checks that verify_commitment accepts the right opening and rejects wrong ones
+    #[test]
+    fn verify_commitment() {
+        let mut rng = ark_std::test_rng();
+        let leaf_crh_params = <LeafHash as CRHScheme>::setup(&mut rng).unwrap();
+
+        let card = Card::rand(&mut rng);
+        let com_rand = F::rand(&mut rng);
+        let leaf = card.commit(&leaf_crh_params, &com_rand);
+
+        // Correct commitment randomness
+        assert!(card.verify_commitment(&leaf_crh_params, &com_rand, &leaf));
+
+        // Wrong commitment randomness
+        let bad_com_rand = F::rand(&mut rng);
+        assert!(!card.verify_commitment(&leaf_crh_params, &bad_com_rand, &leaf));
+
+        // Corrupted leaf
+        let mut bad_leaf = leaf;
+        bad_leaf[10] ^= 1;
+        assert!(!card.verify_commitment(&leaf_crh_params, &com_rand, &bad_leaf));
+    }
+
+    // A split must conserve the price, and gives the outputs new serials
+    #[test]
+    fn card_split() {
+        let mut rng = ark_std::test_rng();
+        let card = Card {
+            purchase_price: F::from(1000u64),
+            serial_num: F::rand(&mut rng),
+        };
+        let amount_a = F::from(300u64);
+        let amount_b = F::from(700u64);
+
+        let (card_a, card_b) = card.split(amount_a, amount_b, &mut rng).unwrap();
+        assert_eq!(card_a.purchase_price, amount_a);
+        assert_eq!(card_b.purchase_price, amount_b);
+        assert_ne!(card_a.serial_num, card_b.serial_num);
+        assert_ne!(card_a.serial_num, card.serial_num);
+
+        // Off by one
+        assert_eq!(
+            card.split(amount_a, amount_b + F::from(1u64), &mut rng),
+            Err(CardError::ValueNotConserved)
+        );
+    }
+
+    // A split rejects prices that don't fit in a u64, the same as SplitCircuit does. Otherwise a
+    // "negative" price could pay for a huge one.
+    #[test]
+    fn card_split_range() {
+        let mut rng = ark_std::test_rng();
+        let card = Card {
+            purchase_price: F::from(1000u64),
+            serial_num: F::rand(&mut rng),
+        };
+
+        // -1 and 1001 sum to 1000 in the field
+        let minus_one = -F::from(1u64);
+        assert_eq!(
+            card.split(minus_one, F::from(1001u64), &mut rng),
+            Err(CardError::PriceOutOfRange)
+        );
+
+        // u64::MAX fits, 2^64 doesn't
+        let max = F::from(u64::MAX);
+        let big_card = Card {
+            purchase_price: max,
+            serial_num: F::rand(&mut rng),
+        };
+        assert!(big_card.split(max, F::from(0u64), &mut rng).is_ok());
+        let too_big_card = Card {
+            purchase_price: max + F::from(1u64),
+            serial_num: F::rand(&mut rng),
+        };
+        assert_eq!(
+            too_big_card.split(max, F::from(1u64), &mut rng),
+            Err(CardError::PriceOutOfRange)
+        );
+    }
+
+    // Cards are equal iff both fields are equal
+    #[test]
+    fn card_eq() {
+        let mut rng = ark_std::test_rng();
+
+        let card = Card::rand(&mut rng);
+        assert_eq!(card, card.clone());
+
+        let mut different_price = card.clone();
+        different_price.purchase_price += F::from(1u64);
+        assert_ne!(card, different_price);
+
+        let mut different_serial = card.clone();
+        different_serial.serial_num += F::from(1u64);
+        assert_ne!(card, different_serial);
+    }
+
+    // Equal cards hash the same, and different cards (almost certainly) don't. So cards work as
+    // set elements.
+    #[test]
+    fn card_hash() {
+        use std::collections::{hash_map::DefaultHasher, HashSet};
+        use std::hash::{Hash, Hasher};
+
+        let hash_of = |card: &Card| {
+            let mut hasher = DefaultHasher::new();
+            card.hash(&mut hasher);
+            hasher.finish()
+        };
+
+        let mut rng = ark_std::test_rng();
+        let card = Card::rand(&mut rng);
+        let other_card = Card::rand(&mut rng);
+        assert_eq!(hash_of(&card), hash_of(&card.clone()));
+        assert_ne!(hash_of(&card), hash_of(&other_card));
+
+        let set: HashSet<Card> = [card.clone(), other_card, card].into_iter().collect();
+        assert_eq!(set.len(), 2);
+    }
+
+    // The gadget should give the same field elements as the native card
+    #[test]
+    fn card_var_field_elements() {
+        use ark_relations::r1cs::ConstraintSystem;
+
+        let mut rng = ark_std::test_rng();
+        let card = Card::rand(&mut rng);
+
+        let cs = ConstraintSystem::<F>::new_ref();
+        let card_var: CardVar = CardVar {
+            amount: FV::new_witness(cs.clone(), || Ok(card.purchase_price)).unwrap(),
+            serial_num: FV::new_witness(cs, || Ok(card.serial_num)).unwrap(),
+        };
+        let elems = card_var.to_field_elements().unwrap();
+        assert_eq!(elems.len(), 2);
+        assert_eq!(elems.value().unwrap(), card.to_field_elements().unwrap());
+    }
+
+    // A CardVar can only be assembled from variables in the given constraint system
+    #[test]
+    fn card_var_from_parts() {
+        use ark_r1cs_std::fields::FieldVar;
+        use ark_relations::r1cs::ConstraintSystem;
+
+        let mut rng = ark_std::test_rng();
+        let card = Card::rand(&mut rng);
+
+        // Both witnesses in the same system, and a constant, are fine
+        let cs = ConstraintSystem::<F>::new_ref();
+        let amount = FV::new_witness(cs.clone(), || Ok(card.purchase_price)).unwrap();
+        let serial_num = FV::new_witness(cs.clone(), || Ok(card.serial_num)).unwrap();
+        let card_var = CardVar::from_parts(cs.clone(), amount.clone(), serial_num).unwrap();
+        assert_eq!(
+            card_var.to_field_elements().unwrap().value().unwrap(),
+            card.to_field_elements().unwrap()
+        );
+        let constant_serial = FV::constant(card.serial_num);
+        assert!(<CardVar>::from_parts(cs.clone(), amount.clone(), constant_serial).is_ok());
+
+        // A serial number from some other constraint system is rejected
+        let other_cs = ConstraintSystem::<F>::new_ref();
+        let other_serial_num = FV::new_witness(other_cs, || Ok(card.serial_num)).unwrap();
+        assert!(matches!(
+            <CardVar>::from_parts(cs, amount, other_serial_num),
+            Err(SynthesisError::Unsatisfiable)
+        ));
+    }
+
+    // Card equality is only enforced when the condition is true
+    #[test]
+    fn card_var_conditional_eq() {
+        use ark_relations::r1cs::ConstraintSystem;
+
+        let mut rng = ark_std::test_rng();
+        let card = Card::rand(&mut rng);
+        let other_card = Card::rand(&mut rng);
+
+        // Runs conditional_enforce_equal on the given cards and returns whether the constraints
+        // are satisfied
+        let check = |a: &Card, b: &Card, condition: bool| {
+            let cs = ConstraintSystem::<F>::new_ref();
+            let alloc_card = |c: &Card| -> CardVar {
+                CardVar {
+                    amount: FV::new_witness(cs.clone(), || Ok(c.purchase_price)).unwrap(),
+                    serial_num: FV::new_witness(cs.clone(), || Ok(c.serial_num)).unwrap(),
+                }
+            };
+            let (a_var, b_var) = (alloc_card(a), alloc_card(b));
+            let condition_var = Boolean::new_witness(cs.clone(), || Ok(condition)).unwrap();
+
+            assert_eq!(a_var.is_eq(&b_var).unwrap().value().unwrap(), a == b);
+            a_var
+                .conditional_enforce_equal(&b_var, &condition_var)
+                .unwrap();
+            cs.is_satisfied().unwrap()
+        };
+
+        // Equal cards are fine either way
+        assert!(check(&card, &card, true));
+        assert!(check(&card, &card, false));
+
+        // Unequal cards are only caught when the condition is true
+        assert!(!check(&card, &other_card, true));
+        assert!(check(&card, &other_card, false));
+    }
+
+    // Batch commitments should be the same as committing one at a time
+    #[test]
+    fn commit_batch_matches_commit() {
+        let mut rng = ark_std::test_rng();
+        let leaf_crh_params = <LeafHash as CRHScheme>::setup(&mut rng).unwrap();
+
+        let cards: Vec<(Card, F)> = (0..8)
+            .map(|_| (Card::rand(&mut rng), F::rand(&mut rng)))
+            .collect();
+        let expected: Vec<Leaf> = cards
+            .iter()
+            .map(|(card, com_rand)| card.commit(&leaf_crh_params, com_rand))
+            .collect();
+        assert_eq!(commit_batch(&leaf_crh_params, &cards), expected);
+    }
+
+    // Checks that the derived-nonce commitment gadget computes the same thing as the native
+    // version, and that the result is a normal commitment under the derived randomness
+    #[test]
+    fn derived_nonce_round_trip() {
+        use crate::poseidon_params::poseidon_params;
+        use ark_relations::r1cs::ConstraintSystem;
+
+        let mut rng = ark_std::test_rng();
+        let leaf_crh_params = <LeafHash as CRHScheme>::setup(&mut rng).unwrap();
+        let poseidon_params = poseidon_params();
+
+        let card = Card::rand(&mut rng);
+        let secret_key = F::rand(&mut rng);
+        let (leaf, com_rand) =
+            card.commit_with_derived_nonce(&leaf_crh_params, &poseidon_params, &secret_key);
+        assert!(card.verify_commitment(&leaf_crh_params, &com_rand, &leaf));
+
+        // The commitment is deterministic given the secret key
+        let (leaf2, _) =
+            card.commit_with_derived_nonce(&leaf_crh_params, &poseidon_params, &secret_key);
+        assert_eq!(leaf, leaf2);
+
+        // It's the same derivation as derive_com_rand, and it isn't the Poseidon commitment keyed
+        // by the secret key
+        assert_eq!(com_rand, derive_com_rand(&card, &secret_key));
+        assert_ne!(
+            com_rand,
+            card.commit_poseidon(&poseidon_params, &secret_key)
+        );
+
+        // Now compute the same thing in the circuit
+        let cs = ConstraintSystem::new_ref();
+        let leaf_crh_params_var =
+            LeafHashParamsVar::new_constant(cs.clone(), &leaf_crh_params).unwrap();
+        let poseidon_params_var =
+            PoseidonParamsVar::new_constant(cs.clone(), &poseidon_params).unwrap();
+        let card_var: CardVar = CardVar {
+            amount: FV::new_witness(cs.clone(), || Ok(card.purchase_price)).unwrap(),
+            serial_num: FV::new_witness(cs.clone(), || Ok(card.serial_num)).unwrap(),
+        };
+        let secret_key_var = FV::new_witness(cs.clone(), || Ok(secret_key)).unwrap();
+
+        let (leaf_var, com_rand_var) = card_var
+            .commit_with_derived_nonce(&leaf_crh_params_var, &poseidon_params_var, &secret_key_var)
+            .unwrap();
+        assert_eq!(leaf_var.value().unwrap(), leaf.to_vec());
+        assert_eq!(com_rand_var.value().unwrap(), com_rand);
+        assert!(cs.is_satisfied().unwrap());
+    }
+
+    // derive_com_rand hashes the master key, then the serial number, then the price, then the
+    // tag. Randomness derived before the tag was added can still be recomputed, and it differs.
+    #[test]
+    fn derive_com_rand_input_order() {
+        use crate::poseidon_params::poseidon_params;
+
+        let mut rng = ark_std::test_rng();
+        let card = Card::rand(&mut rng);
+        let master_key = F::rand(&mut rng);
+        let poseidon = |input: &[F]| PoseidonLeafHash::evaluate(&poseidon_params(), input).unwrap();
+
+        let tag = F::from(DERIVED_NONCE_TAG);
+        assert_eq!(
+            derive_com_rand(&card, &master_key),
+            poseidon(&[master_key, card.serial_num, card.purchase_price, tag])
+        );
+        assert_eq!(
+            derive_untagged_com_rand(&card, &master_key),
+            poseidon(&[master_key, card.serial_num, card.purchase_price])
+        );
+        assert_ne!(
+            derive_com_rand(&card, &master_key),
+            derive_untagged_com_rand(&card, &master_key)
+        );
+    }
+
+    // The pair commitment should bind both cards and both nonces, and the gadget should agree
+    // with the native version
+    #[test]
+    fn card_pair_commitment() {
+        use ark_relations::r1cs::ConstraintSystem;
+
+        let mut rng = ark_std::test_rng();
+        let leaf_crh_params = <LeafHash as CRHScheme>::setup(&mut rng).unwrap();
+        let two_to_one_crh_params = <TwoToOneHash as TwoToOneCRHScheme>::setup(&mut rng).unwrap();
+
+        let (a, b) = (Card::rand(&mut rng), Card::rand(&mut rng));
+        let (nonce_a, nonce_b) = (F::rand(&mut rng), F::rand(&mut rng));
+        let com = commit_card_pair(
+            &two_to_one_crh_params,
+            &a,
+            &leaf_crh_params,
+            &nonce_a,
+            &b,
+            &nonce_b,
+        );
+
+        // Swapping the cards, or changing a nonce, changes the commitment
+        let swapped = commit_card_pair(
+            &two_to_one_crh_params,
+            &b,
+            &leaf_crh_params,
+            &nonce_b,
+            &a,
+            &nonce_a,
+        );
+        assert!(com != swapped);
+        let other_nonce = commit_card_pair(
+            &two_to_one_crh_params,
+            &a,
+            &leaf_crh_params,
+            &F::rand(&mut rng),
+            &b,
+            &nonce_b,
+        );
+        assert!(com != other_nonce);
+
+        // Now compute the same thing in the circuit
+        let cs = ConstraintSystem::new_ref();
+        let leaf_crh_params_var =
+            LeafHashParamsVar::new_constant(cs.clone(), &leaf_crh_params).unwrap();
+        let two_to_one_crh_params_var =
+            TwoToOneHashParamsVar::new_constant(cs.clone(), &two_to_one_crh_params).unwrap();
+        let card_var = |card: &Card| CardVar {
+            amount: FV::new_witness(cs.clone(), || Ok(card.purchase_price)).unwrap(),
+            serial_num: FV::new_witness(cs.clone(), || Ok(card.serial_num)).unwrap(),
+        };
+        let pair_var = CardPairVar {
+            a: card_var(&a),
+            b: card_var(&b),
+        };
+        let nonce_a_var = FV::new_witness(cs.clone(), || Ok(nonce_a)).unwrap();
+        let nonce_b_var = FV::new_witness(cs.clone(), || Ok(nonce_b)).unwrap();
+
+        let com_var = pair_var
+            .commit(
+                &two_to_one_crh_params_var,
+                &leaf_crh_params_var,
+                &nonce_a_var,
+                &nonce_b_var,
+            )
+            .unwrap();
+        assert!(com_var.value().unwrap() == com);
+        assert!(cs.is_satisfied().unwrap());
+    }
+
+    // The builder should put every field where it was named, and complain about missing ones
+    #[test]
+    fn card_var_builder() {
+        let mut rng = ark_std::test_rng();
+        let (amount, serial_num) = (F::rand(&mut rng), F::rand(&mut rng));
+
+        // Set the fields in the opposite order from the struct
+        let card_var = CardVarBuilder::new()
+            .serial_num(FV::Constant(serial_num))
+            .amount(FV::Constant(amount))
+            .build()
+            .unwrap();
+        assert_eq!(card_var.amount.value().unwrap(), amount);
+        assert_eq!(card_var.serial_num.value().unwrap(), serial_num);
+
+        assert!(matches!(
+            CardVarBuilder::new()
+                .serial_num(FV::Constant(serial_num))
+                .build(),
+            Err(BuilderError::MissingField { which: "amount" })
+        ));
+        assert!(matches!(
+            CardVarBuilder::new().amount(FV::Constant(amount)).build(),
+            Err(BuilderError::MissingField {
+                which: "serial_num"
+            })
+        ));
+    }
+
+    // Proves that the cards are exactly the ones in a group with a known commitment
+    struct CardGroupCircuit<const N: usize> {
+        leaf_crh_params: LeafHashParams,
+        two_to_one_crh_params: TwoToOneHashParams,
+        // Public input
+        group_com: MerkleRoot,
+        // Witnesses
+        group: CardGroup<N>,
+        nonces: [F; N],
+    }
+
+    impl<const N: usize> ConstraintSynthesizer<F> for CardGroupCircuit<N> {
+        fn generate_constraints(self, cs: ConstraintSystemRef<F>) -> Result<(), SynthesisError> {
+            let leaf_crh_params =
+                LeafHashParamsVar::new_constant(cs.clone(), &self.leaf_crh_params)?;
+            let two_to_one_crh_params =
+                TwoToOneHashParamsVar::new_constant(cs.clone(), &self.two_to_one_crh_params)?;
+
+            let group_com =
+                <RootVar as AllocVar<MerkleRoot, _>>::new_input(ns!(cs, "group com"), || {
+                    Ok(&self.group_com)
+                })?;
+
+            let mut card_vars = Vec::new();
+            let mut nonce_vars = Vec::new();
+            for (card, nonce) in self.group.0.iter().zip(&self.nonces) {
+                card_vars.push(CardVar {
+                    amount: FV::new_witness(ns!(cs, "amount"), || Ok(card.purchase_price))?,
+                    serial_num: FV::new_witness(ns!(cs, "serial"), || Ok(card.serial_num))?,
+                });
+                nonce_vars.push(FV::new_witness(ns!(cs, "nonce"), || Ok(nonce))?);
+            }
+            let group_var = CardGroupVar::<N>(card_vars.try_into().unwrap());
+            let nonce_vars: [FV; N] = nonce_vars.try_into().unwrap();
+
+            group_var
+                .subtree_root(&leaf_crh_params, &two_to_one_crh_params, &nonce_vars)?
+                .enforce_equal(&group_com)
+        }
+    }
+
+    // A group of 3 cards should commit to a padded 4-leaf tree, and the circuit should prove
+    // exactly those cards are in it
+    #[test]
+    fn card_group() {
+        use ark_relations::r1cs::ConstraintSystem;
+
+        let mut rng = ark_std::test_rng();
+        let leaf_crh_params = <LeafHash as CRHScheme>::setup(&mut rng).unwrap();
+        let two_to_one_crh_params = <TwoToOneHash as TwoToOneCRHScheme>::setup(&mut rng).unwrap();
+
+        let group = CardGroup([(); 3].map(|_| Card::rand(&mut rng)));
+        let nonces = [(); 3].map(|_| F::rand(&mut rng));
+        let leaves = group.commit_group(&leaf_crh_params, &nonces);
+        let tree = group.to_merkle_subtree(&leaf_crh_params, &two_to_one_crh_params, &nonces);
+        assert_eq!(&tree.leaves()[..3], leaves.as_slice());
+        assert_eq!(tree.leaves()[3], EMPTY_LEAF);
+
+        let circuit = |group: CardGroup<3>| CardGroupCircuit {
+            leaf_crh_params: leaf_crh_params.clone(),
+            two_to_one_crh_params: two_to_one_crh_params.clone(),
+            group_com: tree.root(),
+            group,
+            nonces,
+        };
+
+        let cs = ConstraintSystem::new_ref();
+        circuit(group.clone())
+            .generate_constraints(cs.clone())
+            .unwrap();
+        assert!(cs.is_satisfied().unwrap());
+
+        // Swapping two cards changes the commitment
+        let mut swapped = group;
+        swapped.0.swap(0, 1);
+        let cs = ConstraintSystem::new_ref();
+        circuit(swapped).generate_constraints(cs.clone()).unwrap();
+        assert!(!cs.is_satisfied().unwrap());
     }
 }
@@ -1,56 +1,154 @@
 use crate::{
-    card::CardVar,
-    hash::{LeafHash, LeafHashParamsVar, TwoToOneHash, TwoToOneHashParamsVar},
-    merkle::{MerkleRoot, RootVar, SimplePath, SimplePathVar},
-    F, FV,
+    card::{commit_card, CardVar},
+    merkle::EMPTY_LEAF,
+    util::NUM_TEST_CARDS,
+    Bls12Config, CryptoConfig, LeafHashParamsOf, LeafHashParamsVarOf, MerkleRootOf,
+    MerkleRootVarOf, TwoToOneHashParamsOf, TwoToOneHashParamsVarOf,
 };
 
-use ark_crypto_primitives::crh::{CRHScheme, TwoToOneCRHScheme};
-use ark_r1cs_std::{alloc::AllocVar, eq::EqGadget, uint8::UInt8};
+use ark_crypto_primitives::merkle_tree::{constraints::PathVar, MerkleTree, Path};
+use ark_ff::{PrimeField, Zero};
+use ark_r1cs_std::{
+    alloc::AllocVar, boolean::Boolean, eq::EqGadget, fields::fp::FpVar, fields::FieldVar,
+    uint8::UInt8,
+};
 use ark_relations::{
     ns,
     r1cs::{ConstraintSynthesizer, ConstraintSystemRef, SynthesisError},
 };
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+use subtle::ConstantTimeEq;
 
 /// Our ZK circuit. This is what we will create and pass to the Groth16 prover in order to do a ZK
-/// proof of possession
-#[derive(Clone)]
-pub struct PossessionCircuit {
+/// proof of possession. This can be serialized, so a full set of prover inputs can be saved to disk
+/// and loaded back later. The curve and hashes come from `C`, which is `Bls12Config` unless
+/// otherwise specified.
+#[derive(Clone, CanonicalSerialize, CanonicalDeserialize)]
+pub struct PossessionCircuit<C: CryptoConfig = Bls12Config> {
     // These are constants that will be embedded into the circuit. They describe how the hash
     // function works. Don't worry about this.
-    pub leaf_crh_params: <LeafHash as CRHScheme>::Parameters,
-    pub two_to_one_crh_params: <TwoToOneHash as TwoToOneCRHScheme>::Parameters,
+    pub leaf_crh_params: LeafHashParamsOf<C>,
+    pub two_to_one_crh_params: TwoToOneHashParamsOf<C>,
+
+    // Circuit configuration
+    /// Whether to enforce that the card's purchase price is nonzero. Without this, someone can
+    /// show possession of a worthless card. Turning this on adds a zero test to the circuit, so a
+    /// CRS made with it off won't verify proofs made with it on.
+    pub enforce_nonzero_price: bool,
 
     // Public inputs to the circuit
     /// The root of the merkle tree we're proving membership in
-    pub root: MerkleRoot,
+    pub root: MerkleRootOf<C>,
     /// The leaf in that tree. In our case, the leaf is also a commitment to the card we're showing
     pub leaf: Vec<u8>,
     /// The serial number of this card. This is a random value unique to every card. If we show
     /// possession of a card, revealing its serial, then any future possession shows of the same
     /// card will clearly be duplicates, because an observer can check for a repeated serial.
-    pub card_serial_num: F,
+    pub card_serial_num: C::F,
 
     // Private inputs (aka "witnesses") for the circuit
     /// The amount the card was purchased for
-    pub card_purchase_price: F,
+    pub card_purchase_price: C::F,
     /// The private randomness used to commit to the card
-    pub card_com_rand: F,
+    pub card_com_rand: C::F,
     /// The merkle authentication path. Assuming the hash we use is secure, this path is proof that
     /// the committed leaf is in the tree.
-    pub auth_path: SimplePath,
+    pub auth_path: Path<C::MerkleConfig>,
+}
+
+impl<C: CryptoConfig> PossessionCircuit<C> {
+    /// The number of field elements in this circuit's public input: the Merkle root, which is a
+    /// twisted Edwards point (2 field elements), and the card serial number (1)
+    pub const NUM_PUBLIC_INPUTS: usize = 2 + 1;
+
+    /// Makes a placeholder circuit for generating Groth16 parameters. Parameter generation only
+    /// looks at the shape of the circuit, so the values here are all zero. The one thing that
+    /// affects the shape is the length of the auth path, which must match the tree the prover
+    /// uses. So the path comes from a tree of empty leaves the size of the test tree, which is
+    /// what the binaries prove membership in.
+    pub fn stub(
+        leaf_crh_params: LeafHashParamsOf<C>,
+        two_to_one_crh_params: TwoToOneHashParamsOf<C>,
+    ) -> Self {
+        let tree = MerkleTree::<C::MerkleConfig>::new(
+            &leaf_crh_params,
+            &two_to_one_crh_params,
+            [EMPTY_LEAF; NUM_TEST_CARDS]
+                .iter()
+                .map(|leaf| leaf.as_slice()),
+        )
+        .unwrap();
+        let auth_path = tree.generate_proof(0).unwrap();
+
+        PossessionCircuit {
+            leaf_crh_params,
+            two_to_one_crh_params,
+            enforce_nonzero_price: false,
+            root: tree.root(),
+            leaf: EMPTY_LEAF.to_vec(),
+            card_serial_num: C::F::zero(),
+            card_purchase_price: C::F::zero(),
+            card_com_rand: C::F::zero(),
+            auth_path,
+        }
+    }
+
+    /// Checks natively that this circuit is satisfied, i.e., that (1) the auth path proves the
+    /// leaf is in the tree with the given root, (2) the leaf is a commitment to the card with the
+    /// given price and serial number, and (3) the price is nonzero, if that's enforced. This is
+    /// much cheaper than finding out from the prover, so it's worth doing before proving.
+    pub fn is_valid_witness(&self) -> bool {
+        // CHECK #1: Membership
+        let is_member = self
+            .auth_path
+            .verify(
+                &self.leaf_crh_params,
+                &self.two_to_one_crh_params,
+                &self.root,
+                self.leaf.as_slice(),
+            )
+            .unwrap_or(false);
+
+        // CHECK #2: Card opening. The serial number is part of the card, so this also checks
+        // that the revealed serial number is the card's.
+        let computed_leaf = commit_card::<C>(
+            &self.leaf_crh_params,
+            &self.card_purchase_price,
+            &self.card_serial_num,
+            &self.card_com_rand,
+        );
+        let is_opening: bool = computed_leaf.ct_eq(&self.leaf).into();
+
+        // CHECK #3 (optional): Nonzero price
+        let is_priced = !self.enforce_nonzero_price || !self.card_purchase_price.is_zero();
+
+        is_member && is_opening && is_priced
+    }
 }
 
 /// generate_constraints is where the circuit functionality is defined. It doesn't return any
 /// value. Rather, it takes in a constraint system, and adds a bunch of constraints to that system
 /// (implicitly or explicitly). A proof is valid if and only if the final constraint system is
 /// satisfied.
-impl ConstraintSynthesizer<F> for PossessionCircuit {
-    fn generate_constraints(self, cs: ConstraintSystemRef<F>) -> Result<(), SynthesisError> {
+impl<C: CryptoConfig> ConstraintSynthesizer<C::F> for PossessionCircuit<C> {
+    fn generate_constraints(self, cs: ConstraintSystemRef<C::F>) -> Result<(), SynthesisError> {
+        self.synthesize(cs, None).map(|_| ())
+    }
+}
+
+impl<C: CryptoConfig> PossessionCircuit<C> {
+    /// Generates the constraints of this circuit and returns the card serial number variable. If
+    /// `leaf` is given, it is used as the card commitment instead of witnessing `self.leaf`.
+    fn synthesize(
+        self,
+        cs: ConstraintSystemRef<C::F>,
+        leaf: Option<Vec<UInt8<C::F>>>,
+    ) -> Result<FpVar<C::F>, SynthesisError> {
         // First, allocate the public parameters as constants
-        let leaf_crh_params = LeafHashParamsVar::new_constant(cs.clone(), &self.leaf_crh_params)?;
+        let leaf_crh_params =
+            LeafHashParamsVarOf::<C>::new_constant(cs.clone(), &self.leaf_crh_params)?;
         let two_to_one_crh_params =
-            TwoToOneHashParamsVar::new_constant(cs.clone(), &self.two_to_one_crh_params)?;
+            TwoToOneHashParamsVarOf::<C>::new_constant(cs.clone(), &self.two_to_one_crh_params)?;
 
         //
         // Next, allocate the public inputs. Note the ns! macros are just to create name spaces for
@@ -59,12 +157,16 @@ impl ConstraintSynthesizer<F> for PossessionCircuit {
         //
 
         // Merkle root
-        let claimed_root_var =
-            <RootVar as AllocVar<MerkleRoot, _>>::new_input(ns!(cs, "root"), || Ok(&self.root))?;
+        let claimed_root_var = MerkleRootVarOf::<C>::new_input(ns!(cs, "root"), || Ok(&self.root))?;
         // Card's serial number. This is public so you can only show possession once
-        let card_serial_num = FV::new_input(ns!(cs, "card serial"), || Ok(&self.card_serial_num))?;
-        // Card commitment. This is also the leaf in our tree.
-        let claimed_card_com_var = UInt8::new_witness_vec(ns!(cs, "card com"), &self.leaf)?;
+        let card_serial_num =
+            FpVar::new_input(ns!(cs, "card serial"), || Ok(&self.card_serial_num))?;
+        // Card commitment. This is also the leaf in our tree. In a chain, it's the previous
+        // circuit's output, so we use that variable rather than witnessing a fresh one.
+        let claimed_card_com_var = match leaf {
+            Some(leaf) => leaf,
+            None => UInt8::new_witness_vec(ns!(cs, "card com"), &self.leaf)?,
+        };
 
         //
         // Now we witness our private inputs
@@ -72,27 +174,25 @@ impl ConstraintSynthesizer<F> for PossessionCircuit {
 
         // The amount the card was purchase for
         let card_purchase_price =
-            FV::new_witness(ns!(cs, "purchase price"), || Ok(&self.card_purchase_price))?;
+            FpVar::new_witness(ns!(cs, "purchase price"), || Ok(&self.card_purchase_price))?;
         // Commitment randomness
-        let com_rand_var = FV::new_witness(ns!(cs, "card com_rand"), || Ok(&self.card_com_rand))?;
+        let com_rand_var =
+            FpVar::new_witness(ns!(cs, "card com_rand"), || Ok(&self.card_com_rand))?;
         // Merkle authentication path
-        let auth_path_var =
-            SimplePathVar::new_witness(ns!(cs, "merkle path"), || Ok(&self.auth_path))?;
+        let auth_path_var = PathVar::<C::MerkleConfig, C::F, C::MerkleConfigGadget>::new_witness(
+            ns!(cs, "merkle path"),
+            || Ok(&self.auth_path),
+        )?;
 
         //
         // Ok everything has been inputted. Now we do the logic of the circuit.
         //
 
-        // Put the pieces of our card together into a CardVar
-        let card_var = CardVar {
-            amount: card_purchase_price,
-            serial_num: card_serial_num,
-        };
-
         // CHECK #1: Card opening.
         // We "open" the card commitment here. Concretely, we compute the commitment of our
-        // card_var using com_rand_var. We then assert that this value is equal to the publicly
+        // card using com_rand_var. We then assert that this value is equal to the publicly
         // known commitment.
+        let card_var = CardVar::<C>::from_parts(cs, card_purchase_price, card_serial_num)?;
         let computed_card_com_var = card_var.commit(&leaf_crh_params, &com_rand_var)?;
         computed_card_com_var.enforce_equal(&claimed_card_com_var)?;
 
@@ -105,8 +205,119 @@ impl ConstraintSynthesizer<F> for PossessionCircuit {
             auth_path_var.calculate_root(&leaf_crh_params, &two_to_one_crh_params, &leaf_var)?;
         computed_root_var.enforce_equal(&claimed_root_var)?;
 
+        // CHECK #3 (optional): Nonzero price.
+        // is_zero() gives us a Boolean that is true iff the price is 0. We assert it's false.
+        if self.enforce_nonzero_price {
+            card_var.amount.is_zero()?.enforce_equal(&Boolean::FALSE)?;
+        }
+
         // All done with the checks
-        Ok(())
+        Ok(card_var.serial_num)
+    }
+}
+
+/// A circuit whose result can be handed to another circuit. `export` returns the part of this
+/// circuit's statement that the next circuit in a chain should consume, e.g., a commitment it
+/// creates. In a chain, the next circuit is given the variable holding that value, so both
+/// statements are about the same value.
+pub trait ChainableCircuit<ConstraintF: PrimeField>: ConstraintSynthesizer<ConstraintF> {
+    type Output;
+    /// The variable holding `Output` in the constraint system
+    type OutputVar;
+    /// The variable this circuit consumes from the previous circuit in a chain
+    type InputVar;
+
+    /// Returns the value this circuit passes along to the next circuit in a chain
+    fn export(&self) -> Self::Output;
+
+    /// Generates the constraints of this circuit and returns the variable holding `export()`. If
+    /// `input` is given, the circuit uses it in place of witnessing the value it consumes.
+    fn generate_chained_constraints(
+        self,
+        cs: ConstraintSystemRef<ConstraintF>,
+        input: Option<Self::InputVar>,
+    ) -> Result<Self::OutputVar, SynthesisError>;
+
+    /// Makes a circuit that proves `self` and then `next`, in the same constraint system, where
+    /// `next` consumes what `self` exports
+    fn chain<C2>(self, next: C2) -> ChainedCircuit<Self, C2>
+    where
+        Self: Sized,
+        C2: ChainableCircuit<ConstraintF, InputVar = Self::OutputVar>,
+    {
+        ChainedCircuit { first: self, next }
+    }
+}
+
+/// Two circuits proven together. The constraints of both go in the same constraint system, so a
+/// single proof covers both statements. Its public inputs are the first circuit's followed by the
+/// second circuit's. The second circuit consumes the variable the first exports, so a proof only
+/// exists if the two agree on it.
+#[derive(Clone)]
+pub struct ChainedCircuit<C1, C2> {
+    pub first: C1,
+    pub next: C2,
+}
+
+impl<ConstraintF, C1, C2> ConstraintSynthesizer<ConstraintF> for ChainedCircuit<C1, C2>
+where
+    ConstraintF: PrimeField,
+    C1: ChainableCircuit<ConstraintF>,
+    C2: ChainableCircuit<ConstraintF, InputVar = C1::OutputVar>,
+{
+    fn generate_constraints(
+        self,
+        cs: ConstraintSystemRef<ConstraintF>,
+    ) -> Result<(), SynthesisError> {
+        self.generate_chained_constraints(cs, None).map(|_| ())
+    }
+}
+
+// A chain exports whatever its last circuit exports, and consumes whatever its first circuit
+// consumes, so chains can be extended
+impl<ConstraintF, C1, C2> ChainableCircuit<ConstraintF> for ChainedCircuit<C1, C2>
+where
+    ConstraintF: PrimeField,
+    C1: ChainableCircuit<ConstraintF>,
+    C2: ChainableCircuit<ConstraintF, InputVar = C1::OutputVar>,
+{
+    type Output = C2::Output;
+    type OutputVar = C2::OutputVar;
+    type InputVar = C1::InputVar;
+
+    fn export(&self) -> Self::Output {
+        self.next.export()
+    }
+
+    fn generate_chained_constraints(
+        self,
+        cs: ConstraintSystemRef<ConstraintF>,
+        input: Option<Self::InputVar>,
+    ) -> Result<Self::OutputVar, SynthesisError> {
+        let exported = self
+            .first
+            .generate_chained_constraints(ns!(cs, "first").cs(), input)?;
+        self.next
+            .generate_chained_constraints(ns!(cs, "next").cs(), Some(exported))
+    }
+}
+
+// A possession proof consumes a card commitment and reveals the card's serial number
+impl<C: CryptoConfig> ChainableCircuit<C::F> for PossessionCircuit<C> {
+    type Output = C::F;
+    type OutputVar = FpVar<C::F>;
+    type InputVar = Vec<UInt8<C::F>>;
+
+    fn export(&self) -> C::F {
+        self.card_serial_num
+    }
+
+    fn generate_chained_constraints(
+        self,
+        cs: ConstraintSystemRef<C::F>,
+        input: Option<Vec<UInt8<C::F>>>,
+    ) -> Result<FpVar<C::F>, SynthesisError> {
+        self.synthesize(cs, input)
     }
 }
 
@@ -117,9 +328,20 @@ impl ConstraintSynthesizer<F> for PossessionCircuit {
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::util::{gen_test_tree, get_test_card, get_test_leaf};
+    use crate::{
+        bn254,
+        card::Card,
+        merkle::MerkleRoot,
+        util::{
+            count_constraints, gen_test_cards_with_seed, gen_test_tree, gen_test_tree_with_card,
+            gen_test_tree_with_seed, get_test_card, is_satisfied, sample_crh_params,
+            ConstraintSystemInspector,
+        },
+        Bn254Config,
+    };
 
     use ark_bls12_381::Fr as F;
+    use ark_crypto_primitives::crh::{CRHScheme, TwoToOneCRHScheme};
     use ark_ff::UniformRand;
     use ark_relations::r1cs::ConstraintSystem;
     use rand::RngCore;
@@ -129,9 +351,7 @@ mod test {
         // Let's set up an RNG for use within tests. Note that this is NOT safe for any production
         // use
 
-        // First, let's sample the public parameters for the hash functions
-        let leaf_crh_params = <LeafHash as CRHScheme>::setup(&mut rng).unwrap();
-        let two_to_one_crh_params = <TwoToOneHash as TwoToOneCRHScheme>::setup(&mut rng).unwrap();
+        let (leaf_crh_params, two_to_one_crh_params) = sample_crh_params(&mut rng);
 
         // Generate a test tree and the root
         let tree = gen_test_tree(&leaf_crh_params, &two_to_one_crh_params);
@@ -147,7 +367,7 @@ mod test {
         // We'll reveal and prove membership of the 8th leaf in the tree, i.e., the card com we
         // just created.
         let idx_to_prove = our_idx;
-        let claimed_leaf = get_test_leaf(&leaf_crh_params, idx_to_prove);
+        let claimed_leaf = tree.leaves()[idx_to_prove];
 
         // Generate a Merkle authentication path that proves the membership of the 8th leaf
         let auth_path = tree.generate_proof(idx_to_prove).unwrap();
@@ -158,6 +378,9 @@ mod test {
             leaf_crh_params,
             two_to_one_crh_params,
 
+            // Circuit configuration
+            enforce_nonzero_price: false,
+
             // Public inputs
             root: correct_root,
             leaf: claimed_leaf.to_vec(),
@@ -170,6 +393,42 @@ mod test {
         }
     }
 
+    // Sets up a legitimate possession circuit, except the card we possess was bought for 0
+    fn setup_zero_price(mut rng: impl RngCore) -> PossessionCircuit {
+        let (leaf_crh_params, two_to_one_crh_params) = sample_crh_params(&mut rng);
+
+        // Make a free card and put its commitment at index 7 of an otherwise normal test tree
+        let our_idx = 7;
+        let card = Card {
+            purchase_price: F::zero(),
+            serial_num: F::rand(&mut rng),
+        };
+        let card_com_rand = F::rand(&mut rng);
+        let (tree, our_leaf) = gen_test_tree_with_card(
+            &leaf_crh_params,
+            &two_to_one_crh_params,
+            our_idx,
+            &card,
+            &card_com_rand,
+        );
+        let auth_path = tree.generate_proof(our_idx).unwrap();
+
+        PossessionCircuit {
+            leaf_crh_params,
+            two_to_one_crh_params,
+
+            enforce_nonzero_price: false,
+
+            root: tree.root(),
+            leaf: our_leaf.to_vec(),
+            card_serial_num: card.serial_num,
+
+            auth_path,
+            card_purchase_price: card.purchase_price,
+            card_com_rand,
+        }
+    }
+
     // Correctness test: Make a fresh constraint system and run the circuit.
     #[test]
     fn correctness() {
@@ -187,6 +446,36 @@ mod test {
         );
     }
 
+    // Correctness test over many different trees: for a handful of seeds, prove possession of a
+    // different index each time. This catches bugs that only show up for certain leaf positions.
+    #[test]
+    fn correctness_across_seeds() {
+        let mut rng = ark_std::test_rng();
+        let (leaf_crh_params, two_to_one_crh_params) = sample_crh_params(&mut rng);
+
+        for (seed, our_idx) in [(0, 0), (1, 5), (2, 10), (3, 15)] {
+            let tree = gen_test_tree_with_seed(&leaf_crh_params, &two_to_one_crh_params, seed);
+            let (card, card_com_rand) = gen_test_cards_with_seed(seed)[our_idx].clone();
+
+            let circuit: PossessionCircuit = PossessionCircuit {
+                leaf_crh_params: leaf_crh_params.clone(),
+                two_to_one_crh_params: two_to_one_crh_params.clone(),
+                enforce_nonzero_price: false,
+                root: tree.root(),
+                leaf: card.commit(&leaf_crh_params, &card_com_rand).to_vec(),
+                card_serial_num: card.serial_num,
+                auth_path: tree.generate_proof(our_idx).unwrap(),
+                card_purchase_price: card.purchase_price,
+                card_com_rand,
+            };
+
+            assert!(
+                is_satisfied(circuit),
+                "circuit correctness check failed for seed {seed}, index {our_idx}"
+            );
+        }
+    }
+
     // Card soundness test: Modify the circuit to have a random amount. This should make the
     // proof fail, since the computed commitment up longer matches up with the claimed commitment.
     #[test]
@@ -196,14 +485,13 @@ mod test {
         let mut bad_card_circuit = setup(&mut rng);
         bad_card_circuit.card_purchase_price = F::rand(&mut rng);
 
-        // Run the circuit on a fresh constraint system
-        let cs = ConstraintSystem::new_ref();
-        bad_card_circuit.generate_constraints(cs.clone()).unwrap();
+        // Run the circuit on a fresh constraint system, keeping track of which constraints fail
+        let inspector = ConstraintSystemInspector::new(bad_card_circuit);
 
         // At least one constraint should not be satisfied. That is, the invalid circuit should
         // fail to verify.
         assert!(
-            !cs.is_satisfied().unwrap(),
+            !inspector.unsatisfied_constraint_labels().is_empty(),
             "circuit should not be satisfied after changing the card purchase price"
         );
     }
@@ -217,15 +505,205 @@ mod test {
         let mut bad_root_circuit = setup(&mut rng);
         bad_root_circuit.root = MerkleRoot::rand(&mut rng);
 
-        // Run the circuit on a fresh constraint system
-        let cs = ConstraintSystem::new_ref();
-        bad_root_circuit.generate_constraints(cs.clone()).unwrap();
+        // Run the circuit on a fresh constraint system, keeping track of which constraints fail
+        let inspector = ConstraintSystemInspector::new(bad_root_circuit);
 
         // At least one constraint should not be satisfied. That is, the invalid circuit should
         // fail to verify.
         assert!(
-            !cs.is_satisfied().unwrap(),
+            !inspector.unsatisfied_constraint_labels().is_empty(),
             "circuit should not be satisfied after changing the Merkle root"
         );
     }
+
+    // Nonzero price correctness test: A card with a nonzero price should still be accepted when
+    // the nonzero check is turned on.
+    #[test]
+    fn nonzero_price_correctness() {
+        let mut rng = ark_std::test_rng();
+        let mut circuit = setup(&mut rng);
+        circuit.enforce_nonzero_price = true;
+
+        assert!(
+            is_satisfied(circuit),
+            "circuit with nonzero price check failed on a card with a nonzero price"
+        );
+    }
+
+    // Zero price soundness test: A card that was bought for 0 is a perfectly valid card, so the
+    // circuit accepts it by default. Once the nonzero check is turned on, it should be rejected.
+    #[test]
+    fn zero_price_soundness() {
+        let mut rng = ark_std::test_rng();
+
+        // Without the check, the free card is fine
+        let circuit = setup_zero_price(&mut rng);
+        let inspector = ConstraintSystemInspector::new(circuit.clone());
+        let unsatisfied = inspector.unsatisfied_constraint_labels();
+        assert!(
+            unsatisfied.is_empty(),
+            "circuit should be satisfied by a zero-price card if the nonzero check is off, but \
+             these constraints failed: {unsatisfied:?}"
+        );
+
+        // With the check, it isn't. Only the nonzero check should fail.
+        let mut zero_price_circuit = circuit;
+        zero_price_circuit.enforce_nonzero_price = true;
+        let inspector = ConstraintSystemInspector::new(zero_price_circuit);
+        let unsatisfied = inspector.unsatisfied_constraint_labels();
+        assert_eq!(
+            unsatisfied.len(),
+            1,
+            "circuit should fail exactly the nonzero check on a zero-price card, but these \
+             constraints failed: {unsatisfied:?}"
+        );
+    }
+
+    // Serialization test: Round-trip a circuit through bytes and make sure it's still valid
+    #[test]
+    fn serialization_round_trip() {
+        let mut rng = ark_std::test_rng();
+        let circuit = setup(&mut rng);
+
+        // Serialize and deserialize
+        let mut buf = Vec::new();
+        circuit.serialize_compressed(&mut buf).unwrap();
+        let deserialized_circuit: PossessionCircuit =
+            PossessionCircuit::deserialize_compressed(buf.as_slice()).unwrap();
+
+        // The deserialized circuit should serialize to exactly the same bytes
+        let mut buf2 = Vec::new();
+        deserialized_circuit
+            .serialize_compressed(&mut buf2)
+            .unwrap();
+        assert_eq!(buf, buf2);
+
+        // And it should still be satisfied
+        let cs = ConstraintSystem::new_ref();
+        deserialized_circuit
+            .generate_constraints(cs.clone())
+            .unwrap();
+        assert!(
+            cs.is_satisfied().unwrap(),
+            "round-tripped circuit did not satisfy the constraint system"
+        );
+    }
+
+    // The stub circuit has the same shape as a real one, so it makes parameters the real one can
+    // be proven with
+    #[test]
+    fn stub_matches_shape() {
+        let mut rng = ark_std::test_rng();
+        let circuit = setup(&mut rng);
+        let stub = <PossessionCircuit>::stub(
+            circuit.leaf_crh_params.clone(),
+            circuit.two_to_one_crh_params.clone(),
+        );
+
+        assert_eq!(count_constraints(stub), count_constraints(circuit));
+    }
+
+    // The pre-flight check should accept exactly the circuits that are satisfied
+    #[test]
+    fn is_valid_witness() {
+        let mut rng = ark_std::test_rng();
+        let circuit = setup(&mut rng);
+        assert!(circuit.is_valid_witness());
+        let tree = gen_test_tree(&circuit.leaf_crh_params, &circuit.two_to_one_crh_params);
+
+        // Wrong root
+        let mut bad_root = circuit.clone();
+        bad_root.root = MerkleRoot::rand(&mut rng);
+        assert!(!bad_root.is_valid_witness());
+
+        // A leaf that isn't our card's commitment, but is in the tree
+        let mut bad_leaf = circuit.clone();
+        bad_leaf.leaf = tree.leaves()[3].to_vec();
+        bad_leaf.auth_path = tree.generate_proof(3).unwrap();
+        assert!(!bad_leaf.is_valid_witness());
+
+        // A leaf of the wrong length
+        let mut short_leaf = circuit.clone();
+        short_leaf.leaf.pop();
+        assert!(!short_leaf.is_valid_witness());
+
+        // Someone else's auth path
+        let mut bad_path = circuit.clone();
+        bad_path.auth_path = tree.generate_proof(3).unwrap();
+        assert!(!bad_path.is_valid_witness());
+
+        // A serial number that isn't the card's
+        let mut bad_serial = circuit.clone();
+        bad_serial.card_serial_num = F::rand(&mut rng);
+        assert!(!bad_serial.is_valid_witness());
+
+        // A free card, when we require a price
+        let mut zero_price = setup_zero_price(&mut rng);
+        assert!(zero_price.is_valid_witness());
+        zero_price.enforce_nonzero_price = true;
+        assert!(!zero_price.is_valid_witness());
+    }
+
+    // Sets up a legitimate possession circuit over BN254, for card 7 of the BN254 test tree
+    fn setup_bn254(mut rng: impl RngCore) -> PossessionCircuit<Bn254Config> {
+        let leaf_crh_params = <bn254::LeafHash as CRHScheme>::setup(&mut rng).unwrap();
+        let two_to_one_crh_params =
+            <bn254::TwoToOneHash as TwoToOneCRHScheme>::setup(&mut rng).unwrap();
+        bn254::possession_circuit(leaf_crh_params, two_to_one_crh_params, 7)
+    }
+
+    // The circuit works the same over BN254. It has the same public inputs, since the Merkle root
+    // is still a twisted Edwards point.
+    #[test]
+    fn bn254_correctness() {
+        let mut rng = ark_std::test_rng();
+        let circuit = setup_bn254(&mut rng);
+
+        // Run the circuit on a fresh constraint system
+        let cs = ConstraintSystem::new_ref();
+        circuit.generate_constraints(cs.clone()).unwrap();
+
+        assert!(
+            cs.is_satisfied().unwrap(),
+            "BN254 circuit correctness check failed; a valid circuit did not succeed"
+        );
+        assert_eq!(
+            cs.num_instance_variables() - 1,
+            PossessionCircuit::<Bn254Config>::NUM_PUBLIC_INPUTS
+        );
+    }
+
+    // Soundness test over BN254: Reveal a serial number that isn't the card's
+    #[test]
+    fn bn254_serial_soundness() {
+        let mut rng = ark_std::test_rng();
+        let mut bad_circuit = setup_bn254(&mut rng);
+        bad_circuit.card_serial_num = bn254::Fr::rand(&mut rng);
+
+        assert!(
+            !is_satisfied(bad_circuit),
+            "BN254 circuit should not be satisfied after changing the serial number"
+        );
+    }
+
+    // The stub and the pre-flight check are generic, so they work over BN254 too
+    #[test]
+    fn bn254_stub_and_witness_check() {
+        let mut rng = ark_std::test_rng();
+        let circuit = setup_bn254(&mut rng);
+        assert!(circuit.is_valid_witness());
+
+        let mut bad_serial = circuit.clone();
+        bad_serial.card_serial_num = bn254::Fr::rand(&mut rng);
+        assert!(!bad_serial.is_valid_witness());
+
+        let stub = PossessionCircuit::<Bn254Config>::stub(
+            circuit.leaf_crh_params.clone(),
+            circuit.two_to_one_crh_params.clone(),
+        );
+        let (stub_cs, cs) = (ConstraintSystem::new_ref(), ConstraintSystem::new_ref());
+        stub.generate_constraints(stub_cs.clone()).unwrap();
+        circuit.generate_constraints(cs.clone()).unwrap();
+        assert_eq!(stub_cs.num_constraints(), cs.num_constraints());
+    }
 }
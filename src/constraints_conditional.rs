@@ -0,0 +1,249 @@
+use crate::{
+    card::CardVar,
+    hash::{LeafHash, LeafHashParamsVar, TwoToOneHash, TwoToOneHashParamsVar},
+    merkle::{MerkleRoot, RootVar, SimplePath, SimplePathVar},
+    F, FV,
+};
+
+use ark_crypto_primitives::crh::{CRHScheme, TwoToOneCRHScheme};
+use ark_r1cs_std::{alloc::AllocVar, eq::EqGadget, fields::FieldVar, uint8::UInt8};
+use ark_relations::{
+    ns,
+    r1cs::{ConstraintSynthesizer, ConstraintSystemRef, SynthesisError},
+};
+
+/// A circuit that either shows possession of a card or burns it, depending on the public `mode`.
+/// Both operations share one verifying key, so a verifier only learns which one was done from the
+/// public inputs, not from which key they had to use.
+///
+/// A burn is a possession proof that also reveals the card's purchase price, as
+/// `burned_amount`, so the verifier can credit it. In possession mode, `burned_amount` must be 0.
+#[derive(Clone)]
+pub struct ConditionalCircuit {
+    // These are constants that will be embedded into the circuit. They describe how the hash
+    // function works. Don't worry about this.
+    pub leaf_crh_params: <LeafHash as CRHScheme>::Parameters,
+    pub two_to_one_crh_params: <TwoToOneHash as TwoToOneCRHScheme>::Parameters,
+
+    // Public inputs to the circuit
+    /// Which operation this is. 0 is possession and 1 is burn. Anything else is unsatisfiable.
+    pub mode: F,
+    /// The root of the merkle tree we're proving membership in
+    pub root: MerkleRoot,
+    /// The serial number of this card. See `PossessionCircuit` for why this is revealed.
+    pub card_serial_num: F,
+    /// The value destroyed by this proof. This is the card's purchase price in burn mode, and 0 in
+    /// possession mode.
+    pub burned_amount: F,
+
+    // Private inputs (aka "witnesses") for the circuit
+    /// The leaf in the tree. In our case, the leaf is also a commitment to the card
+    pub leaf: Vec<u8>,
+    /// The amount the card was purchased for
+    pub card_purchase_price: F,
+    /// The private randomness used to commit to the card
+    pub card_com_rand: F,
+    /// The merkle authentication path
+    pub auth_path: SimplePath,
+}
+
+impl ConditionalCircuit {
+    /// The mode for showing possession
+    pub const MODE_POSSESSION: u64 = 0;
+    /// The mode for burning
+    pub const MODE_BURN: u64 = 1;
+
+    /// The number of field elements in this circuit's public input: the mode (1), the Merkle root
+    /// (2), the card serial number (1), and the burned amount (1)
+    pub const NUM_PUBLIC_INPUTS: usize = 1 + 2 + 1 + 1;
+}
+
+impl ConstraintSynthesizer<F> for ConditionalCircuit {
+    fn generate_constraints(self, cs: ConstraintSystemRef<F>) -> Result<(), SynthesisError> {
+        // First, allocate the public parameters as constants
+        let leaf_crh_params = LeafHashParamsVar::new_constant(cs.clone(), &self.leaf_crh_params)?;
+        let two_to_one_crh_params =
+            TwoToOneHashParamsVar::new_constant(cs.clone(), &self.two_to_one_crh_params)?;
+
+        //
+        // Next, allocate the public inputs
+        //
+
+        // The mode
+        let mode = FV::new_input(ns!(cs, "mode"), || Ok(&self.mode))?;
+        // Merkle root
+        let claimed_root_var =
+            <RootVar as AllocVar<MerkleRoot, _>>::new_input(ns!(cs, "root"), || Ok(&self.root))?;
+        // Card's serial number
+        let card_serial_num = FV::new_input(ns!(cs, "card serial"), || Ok(&self.card_serial_num))?;
+        // The burned amount
+        let burned_amount = FV::new_input(ns!(cs, "burned amount"), || Ok(&self.burned_amount))?;
+
+        //
+        // Now we witness our private inputs
+        //
+
+        // Card commitment. This is also the leaf in our tree.
+        let claimed_card_com_var = UInt8::new_witness_vec(ns!(cs, "card com"), &self.leaf)?;
+        // The amount the card was purchase for
+        let card_purchase_price =
+            FV::new_witness(ns!(cs, "purchase price"), || Ok(&self.card_purchase_price))?;
+        // Commitment randomness
+        let com_rand_var = FV::new_witness(ns!(cs, "card com_rand"), || Ok(&self.card_com_rand))?;
+        // Merkle authentication path
+        let auth_path_var =
+            SimplePathVar::new_witness(ns!(cs, "merkle path"), || Ok(&self.auth_path))?;
+
+        //
+        // Ok everything has been inputted. Now we do the logic of the circuit.
+        //
+
+        // Put the pieces of our card together into a CardVar
+        let card_var: CardVar = CardVar {
+            amount: card_purchase_price,
+            serial_num: card_serial_num,
+        };
+
+        // CHECK #1: The mode is valid. The mode is possession iff it's 0. Otherwise it must be 1.
+        let is_possession = mode.is_zero()?;
+        let is_burn = is_possession.not();
+        mode.conditional_enforce_equal(&FV::one(), &is_burn)?;
+
+        // CHECK #2: Card opening
+        card_var
+            .commit(&leaf_crh_params, &com_rand_var)?
+            .enforce_equal(&claimed_card_com_var)?;
+
+        // CHECK #3: Membership test
+        auth_path_var
+            .calculate_root(
+                &leaf_crh_params,
+                &two_to_one_crh_params,
+                &claimed_card_com_var,
+            )?
+            .enforce_equal(&claimed_root_var)?;
+
+        // CHECK #4: Burned amount. A burn reveals the card's value. Possession reveals nothing.
+        burned_amount.conditional_enforce_equal(&card_var.amount, &is_burn)?;
+        burned_amount.conditional_enforce_equal(&FV::zero(), &is_possession)?;
+
+        // All done with the checks
+        Ok(())
+    }
+}
+
+//
+// TESTS
+//
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::{gen_test_tree, get_test_card, sample_crh_params};
+
+    use ark_ff::Zero;
+    use ark_relations::r1cs::ConstraintSystem;
+    use rand::RngCore;
+
+    // Sets up a legitimate circuit in the given mode, for the card at index 7 of the test tree
+    fn setup(mut rng: impl RngCore, mode: u64) -> ConditionalCircuit {
+        let (leaf_crh_params, two_to_one_crh_params) = sample_crh_params(&mut rng);
+
+        let tree = gen_test_tree(&leaf_crh_params, &two_to_one_crh_params);
+        let our_idx = 7;
+        let (card, card_com_rand) = get_test_card(our_idx);
+
+        let burned_amount = if mode == ConditionalCircuit::MODE_BURN {
+            card.purchase_price
+        } else {
+            F::zero()
+        };
+
+        ConditionalCircuit {
+            // Public inputs
+            mode: F::from(mode),
+            root: tree.root(),
+            card_serial_num: card.serial_num,
+            burned_amount,
+
+            // Private inputs
+            leaf: tree.leaves()[our_idx].to_vec(),
+            card_purchase_price: card.purchase_price,
+            card_com_rand,
+            auth_path: tree.generate_proof(our_idx).unwrap(),
+
+            // Constants for hashing
+            leaf_crh_params,
+            two_to_one_crh_params,
+        }
+    }
+
+    // Runs the circuit on a fresh constraint system and returns whether it's satisfied
+    fn is_satisfied(circuit: ConditionalCircuit) -> bool {
+        let cs = ConstraintSystem::new_ref();
+        circuit.generate_constraints(cs.clone()).unwrap();
+        assert_eq!(
+            cs.num_instance_variables() - 1,
+            ConditionalCircuit::NUM_PUBLIC_INPUTS
+        );
+        cs.is_satisfied().unwrap()
+    }
+
+    // Correctness test: Possession mode works, and reveals no amount
+    #[test]
+    fn possession_correctness() {
+        let mut rng = ark_std::test_rng();
+        let circuit = setup(&mut rng, ConditionalCircuit::MODE_POSSESSION);
+        assert!(
+            is_satisfied(circuit),
+            "circuit correctness check failed in possession mode"
+        );
+    }
+
+    // Possession soundness test: Possession mode can't claim to have burned anything
+    #[test]
+    fn possession_soundness() {
+        let mut rng = ark_std::test_rng();
+        let mut bad_circuit = setup(&mut rng, ConditionalCircuit::MODE_POSSESSION);
+        bad_circuit.burned_amount = bad_circuit.card_purchase_price;
+        assert!(
+            !is_satisfied(bad_circuit),
+            "circuit should not be satisfied when possession mode burns a nonzero amount"
+        );
+    }
+
+    // Correctness test: Burn mode works, and reveals the card's price
+    #[test]
+    fn burn_correctness() {
+        let mut rng = ark_std::test_rng();
+        let circuit = setup(&mut rng, ConditionalCircuit::MODE_BURN);
+        assert!(
+            is_satisfied(circuit),
+            "circuit correctness check failed in burn mode"
+        );
+    }
+
+    // Burn soundness test: Burn mode can't claim a different amount than the card's price
+    #[test]
+    fn burn_soundness() {
+        let mut rng = ark_std::test_rng();
+        let mut bad_circuit = setup(&mut rng, ConditionalCircuit::MODE_BURN);
+        bad_circuit.burned_amount += F::from(1u64);
+        assert!(
+            !is_satisfied(bad_circuit),
+            "circuit should not be satisfied when burning the wrong amount"
+        );
+    }
+
+    // Mode soundness test: Modes other than 0 and 1 are rejected
+    #[test]
+    fn mode_soundness() {
+        let mut rng = ark_std::test_rng();
+        let mut bad_circuit = setup(&mut rng, ConditionalCircuit::MODE_BURN);
+        bad_circuit.mode = F::from(2u64);
+        assert!(
+            !is_satisfied(bad_circuit),
+            "circuit should not be satisfied with an invalid mode"
+        );
+    }
+}
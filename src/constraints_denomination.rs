@@ -0,0 +1,193 @@
+use crate::{
+    card::CardVar,
+    hash::{LeafHash, LeafHashParamsVar},
+    F, FV,
+};
+
+use ark_crypto_primitives::crh::CRHScheme;
+use ark_r1cs_std::{
+    alloc::AllocVar, boolean::Boolean, eq::EqGadget, fields::FieldVar, uint8::UInt8,
+};
+use ark_relations::{
+    ns,
+    r1cs::{ConstraintSynthesizer, ConstraintSystemRef, SynthesisError},
+};
+
+/// Our ZK circuit for proving that a committed card's purchase price is one of a fixed set of
+/// denominations, e.g., `[1, 5, 10, 50]`, without revealing which one
+#[derive(Clone)]
+pub struct DenominationCircuit {
+    // These are constants that will be embedded into the circuit. They describe how the hash
+    // function works. Don't worry about this.
+    pub leaf_crh_params: <LeafHash as CRHScheme>::Parameters,
+
+    // Circuit configuration
+    /// The allowed purchase prices. The circuit gets a selector bit for each one, so the proving
+    /// key is tied to this exact list, in this order.
+    pub denominations: Vec<u64>,
+
+    // Public inputs to the circuit
+    /// The commitment to the card
+    pub card_com: Vec<u8>,
+    /// The serial number of the card
+    pub card_serial_num: F,
+
+    // Private inputs (aka "witnesses") for the circuit
+    /// The amount the card was purchased for
+    pub card_purchase_price: F,
+    /// The private randomness used to commit to the card
+    pub card_com_rand: F,
+}
+
+impl ConstraintSynthesizer<F> for DenominationCircuit {
+    fn generate_constraints(self, cs: ConstraintSystemRef<F>) -> Result<(), SynthesisError> {
+        // First, allocate the public parameters as constants
+        let leaf_crh_params = LeafHashParamsVar::new_constant(cs.clone(), &self.leaf_crh_params)?;
+
+        //
+        // Next, allocate the public inputs
+        //
+
+        // Card commitment
+        let claimed_card_com_var = UInt8::new_input_vec(ns!(cs, "card com"), &self.card_com)?;
+        // Card's serial number
+        let card_serial_num = FV::new_input(ns!(cs, "card serial"), || Ok(&self.card_serial_num))?;
+
+        //
+        // Now we witness our private inputs
+        //
+
+        // The amount the card was purchase for
+        let card_purchase_price =
+            FV::new_witness(ns!(cs, "purchase price"), || Ok(&self.card_purchase_price))?;
+        // Commitment randomness
+        let com_rand_var = FV::new_witness(ns!(cs, "card com_rand"), || Ok(&self.card_com_rand))?;
+        // One selector per denomination. The selector of the card's denomination is true, and the
+        // rest are false.
+        let selectors = self
+            .denominations
+            .iter()
+            .map(|&d| {
+                Boolean::new_witness(ns!(cs, "selector"), || {
+                    Ok(self.card_purchase_price == F::from(d))
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        //
+        // Ok everything has been inputted. Now we do the logic of the circuit.
+        //
+
+        // Put the pieces of our card together into a CardVar
+        let card_var: CardVar = CardVar {
+            amount: card_purchase_price,
+            serial_num: card_serial_num,
+        };
+
+        // CHECK #1: Card opening
+        let computed_card_com_var = card_var.commit(&leaf_crh_params, &com_rand_var)?;
+        computed_card_com_var.enforce_equal(&claimed_card_com_var)?;
+
+        // CHECK #2: Exactly one selector is true. The selectors are bits, so this is the same as
+        // their sum being 1.
+        let num_selected = selectors
+            .iter()
+            .fold(FV::zero(), |acc, s| acc + FV::from(s.clone()));
+        num_selected.enforce_equal(&FV::one())?;
+
+        // CHECK #3: The price is the selected denomination. Since only one selector is true, the
+        // sum of selector * denomination is just the selected denomination.
+        let selected_denomination = selectors
+            .iter()
+            .zip(self.denominations.iter())
+            .fold(FV::zero(), |acc, (s, &d)| {
+                acc + FV::from(s.clone()) * F::from(d)
+            });
+        card_var.amount.enforce_equal(&selected_denomination)?;
+
+        // All done with the checks
+        Ok(())
+    }
+}
+
+//
+// TESTS
+//
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{card::Card, util::is_satisfied};
+
+    use ark_ff::UniformRand;
+    use rand::RngCore;
+
+    const DENOMINATIONS: [u64; 4] = [1, 5, 10, 50];
+
+    // Sets up a circuit for a card with the given price. The circuit is valid iff the price is one
+    // of DENOMINATIONS.
+    fn setup(mut rng: impl RngCore, price: u64) -> DenominationCircuit {
+        let leaf_crh_params = <LeafHash as CRHScheme>::setup(&mut rng).unwrap();
+
+        let card = Card {
+            purchase_price: F::from(price),
+            serial_num: F::rand(&mut rng),
+        };
+        let card_com_rand = F::rand(&mut rng);
+        let card_com = card.commit(&leaf_crh_params, &card_com_rand);
+
+        DenominationCircuit {
+            // Public inputs
+            card_com: card_com.to_vec(),
+            card_serial_num: card.serial_num,
+
+            // Private inputs
+            card_purchase_price: card.purchase_price,
+            card_com_rand,
+
+            // Constants
+            leaf_crh_params,
+            denominations: DENOMINATIONS.to_vec(),
+        }
+    }
+
+    // Correctness test: Every denomination is accepted
+    #[test]
+    fn correctness() {
+        let mut rng = ark_std::test_rng();
+
+        for price in DENOMINATIONS {
+            assert!(
+                is_satisfied(setup(&mut rng, price)),
+                "circuit correctness check failed for the price {price}"
+            );
+        }
+    }
+
+    // Denomination soundness test: Prices that aren't denominations are rejected
+    #[test]
+    fn denomination_soundness() {
+        let mut rng = ark_std::test_rng();
+
+        for price in [0, 2, 6, 51] {
+            assert!(
+                !is_satisfied(setup(&mut rng, price)),
+                "circuit should not be satisfied for the price {price}"
+            );
+        }
+    }
+
+    // Card soundness test: Change the price to a different valid denomination, without changing the
+    // commitment
+    #[test]
+    fn card_soundness() {
+        let mut rng = ark_std::test_rng();
+        let mut bad_card_circuit = setup(&mut rng, 5);
+        bad_card_circuit.card_purchase_price = F::from(10u64);
+
+        assert!(
+            !is_satisfied(bad_card_circuit),
+            "circuit should not be satisfied when the price doesn't match the commitment"
+        );
+    }
+}
@@ -0,0 +1,464 @@
+use crate::{
+    card::CardVar,
+    constraints::ChainableCircuit,
+    hash::{LeafHash, LeafHashParamsVar, TwoToOneHash, TwoToOneHashParamsVar},
+    merkle::{MerkleRoot, RootVar, SimplePath, SimplePathVar},
+    F, FV,
+};
+
+use ark_crypto_primitives::crh::{CRHScheme, TwoToOneCRHScheme};
+use ark_r1cs_std::{alloc::AllocVar, eq::EqGadget, uint8::UInt8};
+use ark_relations::{
+    ns,
+    r1cs::{ConstraintSynthesizer, ConstraintSystemRef, SynthesisError},
+};
+
+/// Our ZK circuit for merging two cards into one, UTXO-style. The prover shows that two input
+/// cards are in the tree, and that the output card's purchase price is the sum of the input cards'
+/// prices. The input serials are revealed so the inputs can't be merged twice. It is up to the
+/// verifier to check that the two input serials are different.
+///
+/// All three prices are range-checked to 64 bits. Otherwise the sum could wrap around the field
+/// modulus, and a "negative" input card could be used to shrink or inflate the output.
+#[derive(Clone)]
+pub struct DepositCircuit {
+    // These are constants that will be embedded into the circuit. They describe how the hash
+    // function works. Don't worry about this.
+    pub leaf_crh_params: <LeafHash as CRHScheme>::Parameters,
+    pub two_to_one_crh_params: <TwoToOneHash as TwoToOneCRHScheme>::Parameters,
+
+    // Public inputs to the circuit
+    /// The root of the merkle tree both input cards are in
+    pub root: MerkleRoot,
+    /// The serial number of the first input card
+    pub input_a_serial_num: F,
+    /// The serial number of the second input card
+    pub input_b_serial_num: F,
+    /// The commitment to the output card
+    pub output_leaf: Vec<u8>,
+
+    // Private inputs (aka "witnesses") for the circuit
+    /// The commitment to the first input card, i.e., its leaf in the tree
+    pub input_a_leaf: Vec<u8>,
+    /// The amount the first input card was purchased for
+    pub input_a_purchase_price: F,
+    /// The randomness used to commit to the first input card
+    pub input_a_com_rand: F,
+    /// The merkle authentication path of the first input card
+    pub input_a_auth_path: SimplePath,
+    /// The commitment to the second input card, i.e., its leaf in the tree
+    pub input_b_leaf: Vec<u8>,
+    /// The amount the second input card was purchased for
+    pub input_b_purchase_price: F,
+    /// The randomness used to commit to the second input card
+    pub input_b_com_rand: F,
+    /// The merkle authentication path of the second input card
+    pub input_b_auth_path: SimplePath,
+    /// The purchase price of the output card. This must be the sum of the input prices.
+    pub output_purchase_price: F,
+    /// The serial number of the output card
+    pub output_serial_num: F,
+    /// The randomness used to commit to the output card
+    pub output_com_rand: F,
+}
+
+impl ConstraintSynthesizer<F> for DepositCircuit {
+    fn generate_constraints(self, cs: ConstraintSystemRef<F>) -> Result<(), SynthesisError> {
+        self.generate_chained_constraints(cs, None).map(|_| ())
+    }
+}
+
+// A deposit consumes nothing from a previous circuit, and creates the output card's commitment
+impl ChainableCircuit<F> for DepositCircuit {
+    type Output = Vec<u8>;
+    type OutputVar = Vec<UInt8<F>>;
+    type InputVar = ();
+
+    fn export(&self) -> Vec<u8> {
+        self.output_leaf.clone()
+    }
+
+    fn generate_chained_constraints(
+        self,
+        cs: ConstraintSystemRef<F>,
+        _input: Option<()>,
+    ) -> Result<Vec<UInt8<F>>, SynthesisError> {
+        // First, allocate the public parameters as constants
+        let leaf_crh_params = LeafHashParamsVar::new_constant(cs.clone(), &self.leaf_crh_params)?;
+        let two_to_one_crh_params =
+            TwoToOneHashParamsVar::new_constant(cs.clone(), &self.two_to_one_crh_params)?;
+
+        //
+        // Next, allocate the public inputs
+        //
+
+        // Merkle root
+        let claimed_root_var =
+            <RootVar as AllocVar<MerkleRoot, _>>::new_input(ns!(cs, "root"), || Ok(&self.root))?;
+        // Input serial numbers
+        let input_a_serial_num =
+            FV::new_input(ns!(cs, "input a serial"), || Ok(&self.input_a_serial_num))?;
+        let input_b_serial_num =
+            FV::new_input(ns!(cs, "input b serial"), || Ok(&self.input_b_serial_num))?;
+        // Output card commitment
+        let claimed_output_com_var =
+            UInt8::new_input_vec(ns!(cs, "output com"), &self.output_leaf)?;
+
+        //
+        // Now we witness our private inputs
+        //
+
+        // First input card
+        let claimed_input_a_com_var =
+            UInt8::new_witness_vec(ns!(cs, "input a com"), &self.input_a_leaf)?;
+        let input_a_purchase_price = FV::new_witness(ns!(cs, "input a purchase price"), || {
+            Ok(&self.input_a_purchase_price)
+        })?;
+        let input_a_com_rand =
+            FV::new_witness(ns!(cs, "input a com_rand"), || Ok(&self.input_a_com_rand))?;
+        let input_a_auth_path = SimplePathVar::new_witness(ns!(cs, "input a merkle path"), || {
+            Ok(&self.input_a_auth_path)
+        })?;
+
+        // Second input card
+        let claimed_input_b_com_var =
+            UInt8::new_witness_vec(ns!(cs, "input b com"), &self.input_b_leaf)?;
+        let input_b_purchase_price = FV::new_witness(ns!(cs, "input b purchase price"), || {
+            Ok(&self.input_b_purchase_price)
+        })?;
+        let input_b_com_rand =
+            FV::new_witness(ns!(cs, "input b com_rand"), || Ok(&self.input_b_com_rand))?;
+        let input_b_auth_path = SimplePathVar::new_witness(ns!(cs, "input b merkle path"), || {
+            Ok(&self.input_b_auth_path)
+        })?;
+
+        // Output card
+        let output_purchase_price = FV::new_witness(ns!(cs, "output purchase price"), || {
+            Ok(&self.output_purchase_price)
+        })?;
+        let output_serial_num =
+            FV::new_witness(ns!(cs, "output serial"), || Ok(&self.output_serial_num))?;
+        let output_com_rand =
+            FV::new_witness(ns!(cs, "output com_rand"), || Ok(&self.output_com_rand))?;
+
+        //
+        // Ok everything has been inputted. Now we do the logic of the circuit.
+        //
+
+        let input_a_var: CardVar = CardVar {
+            amount: input_a_purchase_price,
+            serial_num: input_a_serial_num,
+        };
+        let input_b_var: CardVar = CardVar {
+            amount: input_b_purchase_price,
+            serial_num: input_b_serial_num,
+        };
+        let output_var: CardVar = CardVar {
+            amount: output_purchase_price,
+            serial_num: output_serial_num,
+        };
+
+        // CHECK #1: Input card openings
+        input_a_var
+            .commit(&leaf_crh_params, &input_a_com_rand)?
+            .enforce_equal(&claimed_input_a_com_var)?;
+        input_b_var
+            .commit(&leaf_crh_params, &input_b_com_rand)?
+            .enforce_equal(&claimed_input_b_com_var)?;
+
+        // CHECK #2: Input card membership tests
+        input_a_auth_path
+            .calculate_root(
+                &leaf_crh_params,
+                &two_to_one_crh_params,
+                &claimed_input_a_com_var,
+            )?
+            .enforce_equal(&claimed_root_var)?;
+        input_b_auth_path
+            .calculate_root(
+                &leaf_crh_params,
+                &two_to_one_crh_params,
+                &claimed_input_b_com_var,
+            )?
+            .enforce_equal(&claimed_root_var)?;
+
+        // CHECK #3: Value conservation. The output price is the sum of the input prices. Each price
+        // fits in 64 bits, so the sum of the inputs is less than 2^65, and can't wrap around the
+        // modulus.
+        input_a_var.range_check(ns!(cs, "input a range").cs())?;
+        input_b_var.range_check(ns!(cs, "input b range").cs())?;
+        output_var.range_check(ns!(cs, "output range").cs())?;
+        let input_sum = &input_a_var.amount + &input_b_var.amount;
+        output_var.amount.enforce_equal(&input_sum)?;
+
+        // CHECK #4: Output card commitment
+        output_var
+            .commit(&leaf_crh_params, &output_com_rand)?
+            .enforce_equal(&claimed_output_com_var)?;
+
+        // All done with the checks
+        Ok(claimed_output_com_var)
+    }
+}
+
+//
+// TESTS
+//
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        card::Card,
+        constraints::PossessionCircuit,
+        util::{
+            gen_test_tree, gen_test_tree_with_card, get_test_card, is_satisfied, sample_crh_params,
+        },
+    };
+
+    use ark_bls12_381::Fr as F;
+    use ark_ff::UniformRand;
+    use ark_relations::r1cs::ConstraintSystem;
+    use rand::RngCore;
+
+    // Sets up a legitimate deposit circuit merging cards that cost 30 and 70 into one that costs 100
+    fn setup(mut rng: impl RngCore) -> DepositCircuit {
+        setup_with_prices(&mut rng, F::from(30u64), F::from(70u64), F::from(100u64))
+    }
+
+    // Sets up a deposit circuit with input cards of the given prices at indices 3 and 7 of the test
+    // tree, and an output card of the given price. The commitments are all computed honestly, so
+    // only the value checks can fail.
+    fn setup_with_prices(
+        mut rng: impl RngCore,
+        input_a_price: F,
+        input_b_price: F,
+        output_price: F,
+    ) -> DepositCircuit {
+        let (leaf_crh_params, two_to_one_crh_params) = sample_crh_params(&mut rng);
+
+        // Make our two input cards and put them in the test tree
+        let (idx_a, idx_b) = (3, 7);
+        let input_a = Card {
+            purchase_price: input_a_price,
+            serial_num: F::rand(&mut rng),
+        };
+        let input_a_com_rand = F::rand(&mut rng);
+        let input_b = Card {
+            purchase_price: input_b_price,
+            serial_num: F::rand(&mut rng),
+        };
+        let input_b_com_rand = F::rand(&mut rng);
+        let (mut tree, _) = gen_test_tree_with_card(
+            &leaf_crh_params,
+            &two_to_one_crh_params,
+            idx_a,
+            &input_a,
+            &input_a_com_rand,
+        );
+        tree.update_leaf(idx_b, &input_b.commit(&leaf_crh_params, &input_b_com_rand))
+            .unwrap();
+
+        // Make the output card
+        let output = Card {
+            purchase_price: output_price,
+            serial_num: F::rand(&mut rng),
+        };
+        let output_com_rand = F::rand(&mut rng);
+        let output_leaf = output.commit(&leaf_crh_params, &output_com_rand);
+
+        DepositCircuit {
+            // Public inputs
+            root: tree.root(),
+            input_a_serial_num: input_a.serial_num,
+            input_b_serial_num: input_b.serial_num,
+            output_leaf: output_leaf.to_vec(),
+
+            // Private inputs
+            input_a_leaf: tree.leaves()[idx_a].to_vec(),
+            input_a_purchase_price: input_a.purchase_price,
+            input_a_com_rand,
+            input_a_auth_path: tree.generate_proof(idx_a).unwrap(),
+            input_b_leaf: tree.leaves()[idx_b].to_vec(),
+            input_b_purchase_price: input_b.purchase_price,
+            input_b_com_rand,
+            input_b_auth_path: tree.generate_proof(idx_b).unwrap(),
+            output_purchase_price: output.purchase_price,
+            output_serial_num: output.serial_num,
+            output_com_rand,
+
+            // Constants for hashing
+            leaf_crh_params,
+            two_to_one_crh_params,
+        }
+    }
+
+    // Correctness test: Make a fresh constraint system and run the circuit.
+    #[test]
+    fn correctness() {
+        let mut rng = ark_std::test_rng();
+        let circuit = setup(&mut rng);
+
+        // Run the circuit on a fresh constraint system
+        let cs = ConstraintSystem::new_ref();
+        circuit.generate_constraints(cs.clone()).unwrap();
+
+        // The constraints should be satisfied. That is, the valid circuit should verify.
+        assert!(
+            cs.is_satisfied().unwrap(),
+            "circuit correctness check failed; a valid circuit did not succeed"
+        );
+    }
+
+    // Value soundness test: Make an output card that's worth more than the inputs, with a
+    // correctly computed commitment. Only the value conservation check should catch this.
+    #[test]
+    fn value_soundness() {
+        let mut rng = ark_std::test_rng();
+        let mut bad_value_circuit = setup(&mut rng);
+
+        let inflated_output = Card {
+            purchase_price: bad_value_circuit.output_purchase_price + F::from(1u64),
+            serial_num: bad_value_circuit.output_serial_num,
+        };
+        bad_value_circuit.output_purchase_price = inflated_output.purchase_price;
+        bad_value_circuit.output_leaf = inflated_output
+            .commit(
+                &bad_value_circuit.leaf_crh_params,
+                &bad_value_circuit.output_com_rand,
+            )
+            .to_vec();
+
+        assert!(
+            !is_satisfied(bad_value_circuit),
+            "circuit should not be satisfied when the output price isn't the sum of the inputs"
+        );
+    }
+
+    // Wraparound soundness test: Merge cards that cost 11 and p - 1 into one that costs 10. The
+    // prices add up in the field, so only the range checks catch this.
+    #[test]
+    fn wraparound_soundness() {
+        let mut rng = ark_std::test_rng();
+        let bad_circuit =
+            setup_with_prices(&mut rng, F::from(11u64), -F::from(1u64), F::from(10u64));
+
+        assert!(
+            !is_satisfied(bad_circuit),
+            "circuit should not be satisfied when the input prices wrap around the modulus"
+        );
+    }
+
+    // Card soundness test: Change the price of one of the inputs
+    #[test]
+    fn input_card_soundness() {
+        let mut rng = ark_std::test_rng();
+        let mut bad_card_circuit = setup(&mut rng);
+        bad_card_circuit.input_b_purchase_price = F::rand(&mut rng);
+
+        assert!(
+            !is_satisfied(bad_card_circuit),
+            "circuit should not be satisfied after changing an input card's purchase price"
+        );
+    }
+
+    // Tree soundness test: Modify the circuit to have a random Merkle tree root
+    #[test]
+    fn tree_soundness() {
+        let mut rng = ark_std::test_rng();
+        let mut bad_root_circuit = setup(&mut rng);
+        bad_root_circuit.root = MerkleRoot::rand(&mut rng);
+
+        assert!(
+            !is_satisfied(bad_root_circuit),
+            "circuit should not be satisfied after changing the Merkle root"
+        );
+    }
+
+    // Chaining test: Merge two cards, then show possession of the output card in a tree it was
+    // added to. Both statements go in one constraint system.
+    #[test]
+    fn chain_deposit_possession() {
+        let mut rng = ark_std::test_rng();
+        let deposit = setup(&mut rng);
+
+        // Put the output card in a new tree
+        let our_idx = 2;
+        let output = Card {
+            purchase_price: deposit.output_purchase_price,
+            serial_num: deposit.output_serial_num,
+        };
+        let (tree, output_leaf) = gen_test_tree_with_card(
+            &deposit.leaf_crh_params,
+            &deposit.two_to_one_crh_params,
+            our_idx,
+            &output,
+            &deposit.output_com_rand,
+        );
+        assert_eq!(deposit.export(), output_leaf.to_vec());
+
+        let possession: PossessionCircuit = PossessionCircuit {
+            leaf_crh_params: deposit.leaf_crh_params.clone(),
+            two_to_one_crh_params: deposit.two_to_one_crh_params.clone(),
+            enforce_nonzero_price: false,
+            root: tree.root(),
+            leaf: deposit.export(),
+            card_serial_num: output.serial_num,
+            auth_path: tree.generate_proof(our_idx).unwrap(),
+            card_purchase_price: output.purchase_price,
+            card_com_rand: deposit.output_com_rand,
+        };
+        let chained = deposit.chain(possession);
+        assert_eq!(chained.export(), output.serial_num);
+
+        assert!(
+            is_satisfied(chained),
+            "chained deposit and possession circuits should be satisfied"
+        );
+    }
+
+    // Chaining soundness test: The possession proof must be about the card the deposit created.
+    // Showing possession of some other card makes the chain unsatisfiable, even though both
+    // circuits are satisfied on their own.
+    #[test]
+    fn chain_mismatch_soundness() {
+        let mut rng = ark_std::test_rng();
+        let deposit = setup(&mut rng);
+
+        // Show possession of a card in the test tree, which isn't the deposit's output card
+        let tree = gen_test_tree(&deposit.leaf_crh_params, &deposit.two_to_one_crh_params);
+        let our_idx = 7;
+        let (card, card_com_rand) = get_test_card(our_idx);
+        let possession: PossessionCircuit = PossessionCircuit {
+            leaf_crh_params: deposit.leaf_crh_params.clone(),
+            two_to_one_crh_params: deposit.two_to_one_crh_params.clone(),
+            enforce_nonzero_price: false,
+            root: tree.root(),
+            leaf: tree.leaves()[our_idx].to_vec(),
+            card_serial_num: card.serial_num,
+            auth_path: tree.generate_proof(our_idx).unwrap(),
+            card_purchase_price: card.purchase_price,
+            card_com_rand,
+        };
+        assert_ne!(deposit.export(), possession.leaf);
+
+        // Each circuit is fine on its own
+        let cs = ConstraintSystem::new_ref();
+        deposit.clone().generate_constraints(cs.clone()).unwrap();
+        assert!(cs.is_satisfied().unwrap());
+        let cs = ConstraintSystem::new_ref();
+        possession.clone().generate_constraints(cs.clone()).unwrap();
+        assert!(cs.is_satisfied().unwrap());
+
+        // But not together
+        let cs = ConstraintSystem::new_ref();
+        deposit
+            .chain(possession)
+            .generate_constraints(cs.clone())
+            .unwrap();
+        assert!(
+            !cs.is_satisfied().unwrap(),
+            "chain should not be satisfied when possession is of a card the deposit didn't create"
+        );
+    }
+}
@@ -0,0 +1,179 @@
+use crate::{
+    card::CardVar,
+    hash::{LeafHash, LeafHashParamsVar, TwoToOneHash, TwoToOneHashParamsVar},
+    merkle::{MerkleRoot, RootVar, SimplePath, SimplePathVar},
+    F, FV,
+};
+
+use ark_crypto_primitives::crh::{CRHScheme, TwoToOneCRHScheme};
+use ark_r1cs_std::{alloc::AllocVar, boolean::Boolean, eq::EqGadget, uint8::UInt8};
+use ark_relations::{
+    ns,
+    r1cs::{ConstraintSynthesizer, ConstraintSystemRef, SynthesisError},
+};
+
+/// The same as `PossessionCircuit`, except there are `N` public roots, and the card only has to be
+/// in the tree of one of them. This is useful when the tree changes over time and the verifier
+/// accepts any of its recent states. The verifier doesn't learn which root the card is under.
+#[derive(Clone)]
+pub struct DisjunctiveMembershipCircuit<const N: usize> {
+    // These are constants that will be embedded into the circuit. They describe how the hash
+    // function works. Don't worry about this.
+    pub leaf_crh_params: <LeafHash as CRHScheme>::Parameters,
+    pub two_to_one_crh_params: <TwoToOneHash as TwoToOneCRHScheme>::Parameters,
+
+    // Public inputs to the circuit
+    /// The roots of the merkle trees we're proving membership in one of
+    pub roots: [MerkleRoot; N],
+    /// The leaf in that tree. In our case, the leaf is also a commitment to the card we're showing
+    pub leaf: Vec<u8>,
+    /// The serial number of this card. See `PossessionCircuit` for why this is revealed.
+    pub card_serial_num: F,
+
+    // Private inputs (aka "witnesses") for the circuit
+    /// The amount the card was purchased for
+    pub card_purchase_price: F,
+    /// The private randomness used to commit to the card
+    pub card_com_rand: F,
+    /// The merkle authentication path, with respect to one of the roots
+    pub auth_path: SimplePath,
+}
+
+impl<const N: usize> ConstraintSynthesizer<F> for DisjunctiveMembershipCircuit<N> {
+    fn generate_constraints(self, cs: ConstraintSystemRef<F>) -> Result<(), SynthesisError> {
+        // First, allocate the public parameters as constants
+        let leaf_crh_params = LeafHashParamsVar::new_constant(cs.clone(), &self.leaf_crh_params)?;
+        let two_to_one_crh_params =
+            TwoToOneHashParamsVar::new_constant(cs.clone(), &self.two_to_one_crh_params)?;
+
+        //
+        // Next, allocate the public inputs
+        //
+
+        // Merkle roots
+        let claimed_root_vars = self
+            .roots
+            .iter()
+            .map(|root| {
+                <RootVar as AllocVar<MerkleRoot, _>>::new_input(ns!(cs, "root"), || Ok(root))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        // Card's serial number. This is public so you can only show possession once
+        let card_serial_num = FV::new_input(ns!(cs, "card serial"), || Ok(&self.card_serial_num))?;
+        // Card commitment. This is also the leaf in our tree.
+        let claimed_card_com_var = UInt8::new_witness_vec(ns!(cs, "card com"), &self.leaf)?;
+
+        //
+        // Now we witness our private inputs
+        //
+
+        // The amount the card was purchase for
+        let card_purchase_price =
+            FV::new_witness(ns!(cs, "purchase price"), || Ok(&self.card_purchase_price))?;
+        // Commitment randomness
+        let com_rand_var = FV::new_witness(ns!(cs, "card com_rand"), || Ok(&self.card_com_rand))?;
+        // Merkle authentication path
+        let auth_path_var =
+            SimplePathVar::new_witness(ns!(cs, "merkle path"), || Ok(&self.auth_path))?;
+
+        //
+        // Ok everything has been inputted. Now we do the logic of the circuit.
+        //
+
+        // Put the pieces of our card together into a CardVar
+        let card_var: CardVar = CardVar {
+            amount: card_purchase_price,
+            serial_num: card_serial_num,
+        };
+
+        // CHECK #1: Card opening
+        let computed_card_com_var = card_var.commit(&leaf_crh_params, &com_rand_var)?;
+        computed_card_com_var.enforce_equal(&claimed_card_com_var)?;
+
+        // CHECK #2: Membership test, against any one of the roots. We compute the root once, then
+        // check that at least one of the equality tests is true.
+        let leaf_var = claimed_card_com_var;
+        let computed_root_var =
+            auth_path_var.calculate_root(&leaf_crh_params, &two_to_one_crh_params, &leaf_var)?;
+        let root_matches = claimed_root_vars
+            .iter()
+            .map(|root| computed_root_var.is_eq(root))
+            .collect::<Result<Vec<_>, _>>()?;
+        Boolean::kary_or(&root_matches)?.enforce_equal(&Boolean::TRUE)?;
+
+        // All done with the checks
+        Ok(())
+    }
+}
+
+//
+// TESTS
+//
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::{gen_test_tree, get_test_card, is_satisfied, sample_crh_params};
+
+    use ark_ff::UniformRand;
+    use rand::RngCore;
+
+    // Sets up a legitimate circuit with two roots. The real root is at `real_root_idx`, and the
+    // other one is random.
+    fn setup(mut rng: impl RngCore, real_root_idx: usize) -> DisjunctiveMembershipCircuit<2> {
+        let (leaf_crh_params, two_to_one_crh_params) = sample_crh_params(&mut rng);
+
+        // Generate a test tree, and put its root among some random ones
+        let tree = gen_test_tree(&leaf_crh_params, &two_to_one_crh_params);
+        let mut roots = [MerkleRoot::rand(&mut rng), MerkleRoot::rand(&mut rng)];
+        roots[real_root_idx] = tree.root();
+
+        // Also imagine we possess the card that appears at index 7 in the tree
+        let our_idx = 7;
+        let (card, card_com_rand) = get_test_card(our_idx);
+
+        DisjunctiveMembershipCircuit {
+            // Public inputs
+            roots,
+            leaf: tree.leaves()[our_idx].to_vec(),
+            card_serial_num: card.serial_num,
+
+            // Private inputs
+            auth_path: tree.generate_proof(our_idx).unwrap(),
+            card_purchase_price: card.purchase_price,
+            card_com_rand,
+
+            // Constants for hashing
+            leaf_crh_params,
+            two_to_one_crh_params,
+        }
+    }
+
+    // Correctness test: The circuit should be satisfied regardless of where the real root is
+    #[test]
+    fn correctness() {
+        let mut rng = ark_std::test_rng();
+
+        for real_root_idx in [0, 1] {
+            let circuit = setup(&mut rng, real_root_idx);
+
+            assert!(
+                is_satisfied(circuit),
+                "circuit correctness check failed with the real root at index {real_root_idx}"
+            );
+        }
+    }
+
+    // Tree soundness test: Replace the real root with a random one, so none of them are right
+    #[test]
+    fn tree_soundness() {
+        let mut rng = ark_std::test_rng();
+        let mut bad_root_circuit = setup(&mut rng, 1);
+        bad_root_circuit.roots[1] = MerkleRoot::rand(&mut rng);
+
+        assert!(
+            !is_satisfied(bad_root_circuit),
+            "circuit should not be satisfied when no root matches"
+        );
+    }
+}
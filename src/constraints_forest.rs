@@ -0,0 +1,200 @@
+use crate::{
+    card::CardVar,
+    hash::{LeafHash, LeafHashParamsVar, TwoToOneHash, TwoToOneHashParamsVar},
+    merkle::{MerkleRoot, RootVar, SimplePath, SimplePathVar},
+    F, FV,
+};
+
+use ark_crypto_primitives::crh::{CRHScheme, TwoToOneCRHScheme};
+use ark_r1cs_std::{
+    alloc::AllocVar, boolean::Boolean, eq::EqGadget, fields::FieldVar, uint8::UInt8,
+};
+use ark_relations::{
+    ns,
+    r1cs::{ConstraintSynthesizer, ConstraintSystemRef, SynthesisError},
+};
+
+/// The same as `PossessionCircuit`, except the card is in one of the `N` trees of a `MerkleForest`,
+/// and the index of that tree is public. Compare to `DisjunctiveMembershipCircuit`, which hides
+/// which tree the card is in.
+#[derive(Clone)]
+pub struct ForestMembershipCircuit<const N: usize> {
+    // These are constants that will be embedded into the circuit. They describe how the hash
+    // function works. Don't worry about this.
+    pub leaf_crh_params: <LeafHash as CRHScheme>::Parameters,
+    pub two_to_one_crh_params: <TwoToOneHash as TwoToOneCRHScheme>::Parameters,
+
+    // Public inputs to the circuit
+    /// The roots of all the trees in the forest
+    pub roots: [MerkleRoot; N],
+    /// The index of the tree the card is in
+    pub tree_idx: F,
+    /// The leaf in that tree. In our case, the leaf is also a commitment to the card we're showing
+    pub leaf: Vec<u8>,
+    /// The serial number of this card. See `PossessionCircuit` for why this is revealed.
+    pub card_serial_num: F,
+
+    // Private inputs (aka "witnesses") for the circuit
+    /// The amount the card was purchased for
+    pub card_purchase_price: F,
+    /// The private randomness used to commit to the card
+    pub card_com_rand: F,
+    /// The merkle authentication path, with respect to the root at `tree_idx`
+    pub auth_path: SimplePath,
+}
+
+impl<const N: usize> ConstraintSynthesizer<F> for ForestMembershipCircuit<N> {
+    fn generate_constraints(self, cs: ConstraintSystemRef<F>) -> Result<(), SynthesisError> {
+        // First, allocate the public parameters as constants
+        let leaf_crh_params = LeafHashParamsVar::new_constant(cs.clone(), &self.leaf_crh_params)?;
+        let two_to_one_crh_params =
+            TwoToOneHashParamsVar::new_constant(cs.clone(), &self.two_to_one_crh_params)?;
+
+        //
+        // Next, allocate the public inputs
+        //
+
+        // Merkle roots
+        let claimed_root_vars = self
+            .roots
+            .iter()
+            .map(|root| {
+                <RootVar as AllocVar<MerkleRoot, _>>::new_input(ns!(cs, "root"), || Ok(root))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        // Tree index
+        let tree_idx_var = FV::new_input(ns!(cs, "tree idx"), || Ok(&self.tree_idx))?;
+        // Card's serial number. This is public so you can only show possession once
+        let card_serial_num = FV::new_input(ns!(cs, "card serial"), || Ok(&self.card_serial_num))?;
+        // Card commitment. This is also the leaf in our tree.
+        let claimed_card_com_var = UInt8::new_witness_vec(ns!(cs, "card com"), &self.leaf)?;
+
+        //
+        // Now we witness our private inputs
+        //
+
+        // The amount the card was purchase for
+        let card_purchase_price =
+            FV::new_witness(ns!(cs, "purchase price"), || Ok(&self.card_purchase_price))?;
+        // Commitment randomness
+        let com_rand_var = FV::new_witness(ns!(cs, "card com_rand"), || Ok(&self.card_com_rand))?;
+        // Merkle authentication path
+        let auth_path_var =
+            SimplePathVar::new_witness(ns!(cs, "merkle path"), || Ok(&self.auth_path))?;
+
+        //
+        // Ok everything has been inputted. Now we do the logic of the circuit.
+        //
+
+        // Put the pieces of our card together into a CardVar
+        let card_var: CardVar = CardVar {
+            amount: card_purchase_price,
+            serial_num: card_serial_num,
+        };
+
+        // CHECK #1: Card opening
+        let computed_card_com_var = card_var.commit(&leaf_crh_params, &com_rand_var)?;
+        computed_card_com_var.enforce_equal(&claimed_card_com_var)?;
+
+        // CHECK #2: The tree index is in range. Exactly one of these selectors is true, namely the
+        // one for the given index.
+        let selectors = (0..N)
+            .map(|i| tree_idx_var.is_eq(&FV::constant(F::from(i as u64))))
+            .collect::<Result<Vec<_>, _>>()?;
+        Boolean::kary_or(&selectors)?.enforce_equal(&Boolean::TRUE)?;
+
+        // CHECK #3: Membership test, against the selected root
+        let leaf_var = claimed_card_com_var;
+        let computed_root_var =
+            auth_path_var.calculate_root(&leaf_crh_params, &two_to_one_crh_params, &leaf_var)?;
+        for (root, selected) in claimed_root_vars.iter().zip(selectors.iter()) {
+            computed_root_var.conditional_enforce_equal(root, selected)?;
+        }
+
+        // All done with the checks
+        Ok(())
+    }
+}
+
+//
+// TESTS
+//
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        merkle::{MerkleForest, SimpleMerkleTree},
+        util::{gen_test_tree, get_test_card, is_satisfied, sample_crh_params},
+    };
+
+    use rand::RngCore;
+
+    // Sets up a legitimate circuit over a forest of two trees. Our card is at index 7 of tree 1.
+    fn setup(mut rng: impl RngCore) -> ForestMembershipCircuit<2> {
+        let (leaf_crh_params, two_to_one_crh_params) = sample_crh_params(&mut rng);
+
+        // Tree 0 has the first half of the test cards, and tree 1 has the second half
+        let leaves = gen_test_tree(&leaf_crh_params, &two_to_one_crh_params)
+            .leaves()
+            .to_vec();
+        let mut forest = MerkleForest::new();
+        for half in leaves.chunks(8) {
+            let tree =
+                SimpleMerkleTree::new(&leaf_crh_params, &two_to_one_crh_params, half.to_vec())
+                    .unwrap();
+            forest.add_tree(tree);
+        }
+
+        // Test card 15 is leaf 7 of tree 1
+        let (card, card_com_rand) = get_test_card(15);
+        let (auth_path, tree_idx) = forest.generate_cross_tree_proof(1, 7).unwrap();
+
+        ForestMembershipCircuit {
+            // Public inputs
+            roots: [forest.root_of(0).unwrap(), forest.root_of(1).unwrap()],
+            tree_idx: F::from(tree_idx as u64),
+            leaf: leaves[15].to_vec(),
+            card_serial_num: card.serial_num,
+
+            // Private inputs
+            auth_path,
+            card_purchase_price: card.purchase_price,
+            card_com_rand,
+
+            // Constants for hashing
+            leaf_crh_params,
+            two_to_one_crh_params,
+        }
+    }
+
+    // Correctness test: Make a fresh constraint system and run the circuit.
+    #[test]
+    fn correctness() {
+        let mut rng = ark_std::test_rng();
+        let circuit = setup(&mut rng);
+
+        assert!(
+            is_satisfied(circuit),
+            "circuit correctness check failed; a valid circuit did not succeed"
+        );
+    }
+
+    // Tree index soundness test: Claim the card is in the other tree, or in a tree that doesn't
+    // exist
+    #[test]
+    fn tree_idx_soundness() {
+        let mut rng = ark_std::test_rng();
+        let circuit = setup(&mut rng);
+
+        for bad_idx in [0u64, 2] {
+            let mut bad_idx_circuit = circuit.clone();
+            bad_idx_circuit.tree_idx = F::from(bad_idx);
+
+            assert!(
+                !is_satisfied(bad_idx_circuit),
+                "circuit should not be satisfied with tree index {bad_idx}"
+            );
+        }
+    }
+}
@@ -0,0 +1,291 @@
+use crate::{
+    card::CardVar,
+    hash::{
+        LeafHash, LeafHashParams, LeafHashParamsVar, PedersenCommitmentGadget, TreeHasher,
+        TwoToOneHash, TwoToOneHashParamsVar,
+    },
+    merkle::{Leaf, MerkleRoot, RootVar, SimplePath, SimplePathVar},
+    F, FV,
+};
+
+use ark_crypto_primitives::crh::{CRHScheme, TwoToOneCRHScheme};
+use ark_r1cs_std::{alloc::AllocVar, eq::EqGadget, uint8::UInt8, ToBytesGadget};
+use ark_relations::{
+    ns,
+    r1cs::{ConstraintSynthesizer, ConstraintSystemRef, SynthesisError},
+};
+
+/// The domain-separation tag for price commitments. It sits where a card commitment has the card's
+/// price. Its last byte is too big for a serialized field element, so no card commitment is also a
+/// price commitment.
+pub const PRICE_COM_TAG: [u8; 32] = *b"fantastic-snarks price commit\xff\xff\xff";
+
+/// Commits to a purchase price. Concretely, this computes
+/// `Hash(price_com_rand || PRICE_COM_TAG || price)`.
+pub fn commit_price(leaf_crh_params: &LeafHashParams, price: &F, price_com_rand: &F) -> Leaf {
+    let mut hasher = TreeHasher::new(leaf_crh_params);
+    hasher.update_serialized(price_com_rand);
+    hasher.update(&PRICE_COM_TAG);
+    hasher.update_serialized(price);
+    hasher.finalize().expect("a price fits in the leaf hash")
+}
+
+/// The same as `PossessionShowPriceCircuit`, except the price stays hidden. Instead of the price
+/// itself, the prover reveals a separate commitment to it, and proves that it's a commitment to
+/// the same price that's in the card. The verifier can later be shown the price by opening that
+/// commitment, or compare it with other commitments made with the same randomness.
+#[derive(Clone)]
+pub struct PossessionHiddenPriceCircuit {
+    // These are constants that will be embedded into the circuit. They describe how the hash
+    // function works. Don't worry about this.
+    pub leaf_crh_params: <LeafHash as CRHScheme>::Parameters,
+    pub two_to_one_crh_params: <TwoToOneHash as TwoToOneCRHScheme>::Parameters,
+
+    // Public inputs to the circuit
+    /// The root of the merkle tree we're proving membership in
+    pub root: MerkleRoot,
+    /// The leaf in that tree. In our case, the leaf is also a commitment to the card we're showing
+    pub leaf: Vec<u8>,
+    /// The serial number of this card. See `PossessionCircuit` for why this is revealed.
+    pub card_serial_num: F,
+    /// The commitment to the card's purchase price, i.e., `commit_price(card_purchase_price,
+    /// price_com_rand)`
+    pub price_commitment: Leaf,
+
+    // Private inputs (aka "witnesses") for the circuit
+    /// The amount the card was purchased for
+    pub card_purchase_price: F,
+    /// The private randomness used to commit to the card
+    pub card_com_rand: F,
+    /// The private randomness used to commit to the price
+    pub price_com_rand: F,
+    /// The merkle authentication path
+    pub auth_path: SimplePath,
+}
+
+impl PossessionHiddenPriceCircuit {
+    /// The number of field elements in this circuit's public input: the Merkle root, which is a
+    /// curve point (2 field elements), the card serial number (1), and the price commitment, which
+    /// is 64 bytes packed 31 to a field element (3)
+    pub const NUM_PUBLIC_INPUTS: usize = 2 + 1 + 3;
+}
+
+impl ConstraintSynthesizer<F> for PossessionHiddenPriceCircuit {
+    fn generate_constraints(self, cs: ConstraintSystemRef<F>) -> Result<(), SynthesisError> {
+        // First, allocate the public parameters as constants
+        let leaf_crh_params = LeafHashParamsVar::new_constant(cs.clone(), &self.leaf_crh_params)?;
+        let two_to_one_crh_params =
+            TwoToOneHashParamsVar::new_constant(cs.clone(), &self.two_to_one_crh_params)?;
+
+        //
+        // Next, allocate the public inputs
+        //
+
+        // Merkle root
+        let claimed_root_var =
+            <RootVar as AllocVar<MerkleRoot, _>>::new_input(ns!(cs, "root"), || Ok(&self.root))?;
+        // Card's serial number. This is public so you can only show possession once
+        let card_serial_num = FV::new_input(ns!(cs, "card serial"), || Ok(&self.card_serial_num))?;
+        // Price commitment
+        let claimed_price_com_var =
+            UInt8::new_input_vec(ns!(cs, "price com"), &self.price_commitment)?;
+        // Card commitment. This is also the leaf in our tree.
+        let claimed_card_com_var = UInt8::new_witness_vec(ns!(cs, "card com"), &self.leaf)?;
+
+        //
+        // Now we witness our private inputs
+        //
+
+        // The amount the card was purchase for. Unlike in PossessionShowPriceCircuit, this stays
+        // a witness
+        let card_purchase_price =
+            FV::new_witness(ns!(cs, "purchase price"), || Ok(&self.card_purchase_price))?;
+        // Commitment randomness
+        let com_rand_var = FV::new_witness(ns!(cs, "card com_rand"), || Ok(&self.card_com_rand))?;
+        let price_com_rand_var =
+            FV::new_witness(ns!(cs, "price com_rand"), || Ok(&self.price_com_rand))?;
+        // Merkle authentication path
+        let auth_path_var =
+            SimplePathVar::new_witness(ns!(cs, "merkle path"), || Ok(&self.auth_path))?;
+
+        //
+        // Ok everything has been inputted. Now we do the logic of the circuit.
+        //
+
+        // Put the pieces of our card together into a CardVar
+        let card_var: CardVar = CardVar {
+            amount: card_purchase_price,
+            serial_num: card_serial_num,
+        };
+
+        // CHECK #1: Card opening
+        let computed_card_com_var = card_var.commit(&leaf_crh_params, &com_rand_var)?;
+        computed_card_com_var.enforce_equal(&claimed_card_com_var)?;
+
+        // CHECK #2: Membership test
+        let leaf_var = claimed_card_com_var;
+        let computed_root_var =
+            auth_path_var.calculate_root(&leaf_crh_params, &two_to_one_crh_params, &leaf_var)?;
+        computed_root_var.enforce_equal(&claimed_root_var)?;
+
+        // CHECK #3: Price commitment opening.
+        // The price in the card is the same one that's in the public price commitment
+        let price_bytes = [
+            UInt8::constant_vec(&PRICE_COM_TAG),
+            card_var.amount.to_bytes()?,
+        ]
+        .concat();
+        let computed_price_com_var =
+            PedersenCommitmentGadget::commit(&leaf_crh_params, &price_bytes, &price_com_rand_var)?;
+        computed_price_com_var.enforce_equal(&claimed_price_com_var)?;
+
+        // All done with the checks
+        Ok(())
+    }
+}
+
+//
+// TESTS
+//
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        card::Card,
+        util::{count_constraints, gen_test_tree, get_test_card, is_satisfied, sample_crh_params},
+    };
+
+    use ark_ff::{ToConstraintField, UniformRand, Zero};
+    use ark_serialize::CanonicalDeserialize;
+    use rand::RngCore;
+
+    // Sets up a legitimate hidden-price circuit
+    fn setup(mut rng: impl RngCore) -> PossessionHiddenPriceCircuit {
+        let (leaf_crh_params, two_to_one_crh_params) = sample_crh_params(&mut rng);
+
+        // Generate a test tree, and imagine we possess the card at index 7
+        let tree = gen_test_tree(&leaf_crh_params, &two_to_one_crh_params);
+        let our_idx = 7;
+        let (card, card_com_rand) = get_test_card(our_idx);
+
+        // Commit to the card's price
+        let price_com_rand = F::rand(&mut rng);
+        let price_commitment =
+            commit_price(&leaf_crh_params, &card.purchase_price, &price_com_rand);
+
+        PossessionHiddenPriceCircuit {
+            // Public inputs
+            root: tree.root(),
+            leaf: tree.leaves()[our_idx].to_vec(),
+            card_serial_num: card.serial_num,
+            price_commitment,
+
+            // Private inputs
+            auth_path: tree.generate_proof(our_idx).unwrap(),
+            card_purchase_price: card.purchase_price,
+            card_com_rand,
+            price_com_rand,
+
+            // Constants for hashing
+            leaf_crh_params,
+            two_to_one_crh_params,
+        }
+    }
+
+    // Correctness test: A legitimate circuit should be satisfied
+    #[test]
+    fn correctness() {
+        let mut rng = ark_std::test_rng();
+        let circuit = setup(&mut rng);
+
+        assert!(
+            is_satisfied(circuit),
+            "circuit correctness check failed; a valid circuit did not succeed"
+        );
+    }
+
+    // Price soundness test: Commit to a different price than the one in the card. The price
+    // commitment no longer opens to the card's price, so the proof should fail.
+    #[test]
+    fn price_soundness() {
+        let mut rng = ark_std::test_rng();
+        let mut bad_price_circuit = setup(&mut rng);
+        bad_price_circuit.price_commitment = commit_price(
+            &bad_price_circuit.leaf_crh_params,
+            &F::rand(&mut rng),
+            &bad_price_circuit.price_com_rand,
+        );
+
+        assert!(
+            !is_satisfied(bad_price_circuit),
+            "circuit should not be satisfied when the price commitment has a different price"
+        );
+    }
+
+    // Card soundness test: Change the price in both the card and the price commitment. The price
+    // commitment is consistent, but the card no longer opens.
+    #[test]
+    fn card_soundness() {
+        let mut rng = ark_std::test_rng();
+        let mut bad_card_circuit = setup(&mut rng);
+        bad_card_circuit.card_purchase_price = F::rand(&mut rng);
+        bad_card_circuit.price_commitment = commit_price(
+            &bad_card_circuit.leaf_crh_params,
+            &bad_card_circuit.card_purchase_price,
+            &bad_card_circuit.price_com_rand,
+        );
+
+        assert!(
+            !is_satisfied(bad_card_circuit),
+            "circuit should not be satisfied after changing the card purchase price"
+        );
+    }
+
+    // A price commitment is never a card commitment. Without the tag, committing to a price
+    // would be the same as committing to a card with that price and serial number 0.
+    #[test]
+    fn price_com_domain_separation() {
+        let mut rng = ark_std::test_rng();
+        let leaf_crh_params = <LeafHash as CRHScheme>::setup(&mut rng).unwrap();
+        let price = F::rand(&mut rng);
+        let com_rand = F::rand(&mut rng);
+
+        let card = Card {
+            purchase_price: price,
+            serial_num: F::zero(),
+        };
+        assert_ne!(
+            commit_price(&leaf_crh_params, &price, &com_rand),
+            card.commit(&leaf_crh_params, &com_rand)
+        );
+
+        // The tag isn't the serialization of any card price
+        assert!(F::deserialize_compressed(PRICE_COM_TAG.as_slice()).is_err());
+    }
+
+    // The price itself is not among the public inputs. Only the root, serial, and price commitment
+    // are.
+    #[test]
+    fn price_is_hidden() {
+        let mut rng = ark_std::test_rng();
+        let circuit = setup(&mut rng);
+        let public_inputs = [
+            circuit.root.to_field_elements().unwrap(),
+            circuit.card_serial_num.to_field_elements().unwrap(),
+            circuit.price_commitment[..].to_field_elements().unwrap(),
+        ]
+        .concat();
+        assert_eq!(
+            public_inputs.len(),
+            PossessionHiddenPriceCircuit::NUM_PUBLIC_INPUTS
+        );
+
+        // Subtract 1 for the constant 1 variable
+        let (num_instance_vars, _, _) = count_constraints(circuit);
+        assert_eq!(
+            num_instance_vars - 1,
+            PossessionHiddenPriceCircuit::NUM_PUBLIC_INPUTS
+        );
+    }
+}
@@ -0,0 +1,156 @@
+use crate::{
+    card::CardVar,
+    hash::{LeafHash, LeafHashParamsVar, TwoToOneHash, TwoToOneHashParamsVar},
+    merkle::{MerkleRoot, RootVar, SimplePath, SimplePathVar},
+    F, FV,
+};
+
+use ark_crypto_primitives::crh::{CRHScheme, TwoToOneCRHScheme};
+use ark_r1cs_std::{alloc::AllocVar, eq::EqGadget, uint8::UInt8};
+use ark_relations::{
+    ns,
+    r1cs::{ConstraintSynthesizer, ConstraintSystemRef, SynthesisError},
+};
+
+/// Our ZK circuit for proving possession of `K` cards at once, all of which are in the same Merkle
+/// tree. This is `K` copies of `PossessionCircuit` that share one root and one set of hash params.
+#[derive(Clone)]
+pub struct MultiMembershipCircuit<const K: usize> {
+    // These are constants that will be embedded into the circuit. They describe how the hash
+    // function works. Don't worry about this.
+    pub leaf_crh_params: <LeafHash as CRHScheme>::Parameters,
+    pub two_to_one_crh_params: <TwoToOneHash as TwoToOneCRHScheme>::Parameters,
+
+    // Public inputs to the circuit
+    /// The root of the merkle tree all the cards are in
+    pub root: MerkleRoot,
+    /// The leaves in that tree, i.e., the commitments to the cards we're showing
+    pub leaves: [Vec<u8>; K],
+    /// The serial numbers of the cards. See `PossessionCircuit` for why these are revealed.
+    pub card_serial_nums: [F; K],
+
+    // Private inputs (aka "witnesses") for the circuit
+    /// The amounts the cards were purchased for
+    pub card_purchase_prices: [F; K],
+    /// The private randomness used to commit to each card
+    pub card_com_rands: [F; K],
+    /// The merkle authentication path of each card commitment
+    pub auth_paths: [SimplePath; K],
+}
+
+impl<const K: usize> ConstraintSynthesizer<F> for MultiMembershipCircuit<K> {
+    fn generate_constraints(self, cs: ConstraintSystemRef<F>) -> Result<(), SynthesisError> {
+        // First, allocate the public parameters as constants. These are shared by all K checks.
+        let leaf_crh_params = LeafHashParamsVar::new_constant(cs.clone(), &self.leaf_crh_params)?;
+        let two_to_one_crh_params =
+            TwoToOneHashParamsVar::new_constant(cs.clone(), &self.two_to_one_crh_params)?;
+
+        // Merkle root. Also shared by all K checks.
+        let claimed_root_var =
+            <RootVar as AllocVar<MerkleRoot, _>>::new_input(ns!(cs, "root"), || Ok(&self.root))?;
+
+        // Now do the possession checks for every card
+        for i in 0..K {
+            // Public inputs
+            let card_serial_num =
+                FV::new_input(ns!(cs, "card serial"), || Ok(&self.card_serial_nums[i]))?;
+            let claimed_card_com_var =
+                UInt8::new_witness_vec(ns!(cs, "card com"), &self.leaves[i])?;
+
+            // Private inputs
+            let card_purchase_price = FV::new_witness(ns!(cs, "purchase price"), || {
+                Ok(&self.card_purchase_prices[i])
+            })?;
+            let com_rand_var =
+                FV::new_witness(ns!(cs, "card com_rand"), || Ok(&self.card_com_rands[i]))?;
+            let auth_path_var =
+                SimplePathVar::new_witness(ns!(cs, "merkle path"), || Ok(&self.auth_paths[i]))?;
+
+            let card_var: CardVar = CardVar {
+                amount: card_purchase_price,
+                serial_num: card_serial_num,
+            };
+
+            // CHECK #1: Card opening
+            let computed_card_com_var = card_var.commit(&leaf_crh_params, &com_rand_var)?;
+            computed_card_com_var.enforce_equal(&claimed_card_com_var)?;
+
+            // CHECK #2: Membership test, against the shared root
+            let leaf_var = claimed_card_com_var;
+            let computed_root_var = auth_path_var.calculate_root(
+                &leaf_crh_params,
+                &two_to_one_crh_params,
+                &leaf_var,
+            )?;
+            computed_root_var.enforce_equal(&claimed_root_var)?;
+        }
+
+        // All done with the checks
+        Ok(())
+    }
+}
+
+//
+// TESTS
+//
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::{gen_test_tree, get_test_card, is_satisfied, sample_crh_params};
+
+    use rand::RngCore;
+
+    // The indices of the cards we possess
+    const OUR_IDXS: [usize; 3] = [2, 7, 12];
+
+    // Sets up a legitimate circuit showing possession of 3 cards
+    fn setup(mut rng: impl RngCore) -> MultiMembershipCircuit<3> {
+        let (leaf_crh_params, two_to_one_crh_params) = sample_crh_params(&mut rng);
+
+        // Generate a test tree and the root
+        let tree = gen_test_tree(&leaf_crh_params, &two_to_one_crh_params);
+        let cards = OUR_IDXS.map(get_test_card);
+
+        MultiMembershipCircuit {
+            root: tree.root(),
+            leaves: OUR_IDXS.map(|i| tree.leaves()[i].to_vec()),
+            card_serial_nums: cards.clone().map(|(card, _)| card.serial_num),
+
+            card_purchase_prices: cards.clone().map(|(card, _)| card.purchase_price),
+            card_com_rands: cards.map(|(_, com_rand)| com_rand),
+            auth_paths: OUR_IDXS.map(|i| tree.generate_proof(i).unwrap()),
+
+            leaf_crh_params,
+            two_to_one_crh_params,
+        }
+    }
+
+    // Correctness test: Make a fresh constraint system and run the circuit.
+    #[test]
+    fn correctness() {
+        let mut rng = ark_std::test_rng();
+        let circuit = setup(&mut rng);
+
+        // The constraints should be satisfied. That is, the valid circuit should verify.
+        assert!(
+            is_satisfied(circuit),
+            "circuit correctness check failed; a valid circuit did not succeed"
+        );
+    }
+
+    // Tree soundness test: Replace the second card's auth path with the first card's. The second
+    // card's leaf no longer hashes up to the root, so the proof should fail.
+    #[test]
+    fn forged_path_soundness() {
+        let mut rng = ark_std::test_rng();
+        let mut bad_path_circuit = setup(&mut rng);
+        bad_path_circuit.auth_paths[1] = bad_path_circuit.auth_paths[0].clone();
+
+        // At least one constraint should not be satisfied
+        assert!(
+            !is_satisfied(bad_path_circuit),
+            "circuit should not be satisfied after forging one of the auth paths"
+        );
+    }
+}
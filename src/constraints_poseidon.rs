@@ -0,0 +1,226 @@
+use crate::{
+    card::CardVar,
+    hash::{PoseidonParams, PoseidonParamsVar},
+    merkle::{PoseidonMerkleRoot, PoseidonPath, PoseidonPathVar, PoseidonRootVar},
+    F, FV,
+};
+
+use ark_r1cs_std::{alloc::AllocVar, eq::EqGadget};
+use ark_relations::{
+    ns,
+    r1cs::{ConstraintSynthesizer, ConstraintSystemRef, SynthesisError},
+};
+
+/// The same as `PossessionCircuit`, except every hash, i.e., the card commitment and the Merkle
+/// tree, uses Poseidon instead of Pedersen. This makes for a much smaller circuit.
+#[derive(Clone)]
+pub struct PoseidonPossessionCircuit {
+    // These are constants that will be embedded into the circuit. They describe how the hash
+    // function works. Don't worry about this.
+    pub poseidon_params: PoseidonParams,
+
+    // Public inputs to the circuit
+    /// The root of the merkle tree we're proving membership in
+    pub root: PoseidonMerkleRoot,
+    /// The leaf in that tree. In our case, the leaf is also a commitment to the card we're showing
+    pub leaf: F,
+    /// The serial number of this card. This is revealed for the same reason as in
+    /// `PossessionCircuit`.
+    pub card_serial_num: F,
+
+    // Private inputs (aka "witnesses") for the circuit
+    /// The amount the card was purchased for
+    pub card_purchase_price: F,
+    /// The private randomness used to commit to the card
+    pub card_com_rand: F,
+    /// The merkle authentication path. Assuming the hash we use is secure, this path is proof that
+    /// the committed leaf is in the tree.
+    pub auth_path: PoseidonPath,
+}
+
+impl ConstraintSynthesizer<F> for PoseidonPossessionCircuit {
+    fn generate_constraints(self, cs: ConstraintSystemRef<F>) -> Result<(), SynthesisError> {
+        // First, allocate the public parameters as constants
+        let poseidon_params = PoseidonParamsVar::new_constant(cs.clone(), &self.poseidon_params)?;
+
+        //
+        // Next, allocate the public inputs
+        //
+
+        // Merkle root
+        let claimed_root_var = PoseidonRootVar::new_input(ns!(cs, "root"), || Ok(&self.root))?;
+        // Card's serial number. This is public so you can only show possession once
+        let card_serial_num = FV::new_input(ns!(cs, "card serial"), || Ok(&self.card_serial_num))?;
+        // Card commitment. This is also the leaf in our tree.
+        let claimed_card_com_var = FV::new_witness(ns!(cs, "card com"), || Ok(&self.leaf))?;
+
+        //
+        // Now we witness our private inputs
+        //
+
+        // The amount the card was purchase for
+        let card_purchase_price =
+            FV::new_witness(ns!(cs, "purchase price"), || Ok(&self.card_purchase_price))?;
+        // Commitment randomness
+        let com_rand_var = FV::new_witness(ns!(cs, "card com_rand"), || Ok(&self.card_com_rand))?;
+        // Merkle authentication path
+        let auth_path_var =
+            PoseidonPathVar::new_witness(ns!(cs, "merkle path"), || Ok(&self.auth_path))?;
+
+        //
+        // Ok everything has been inputted. Now we do the logic of the circuit.
+        //
+
+        // Put the pieces of our card together into a CardVar
+        let card_var: CardVar = CardVar {
+            amount: card_purchase_price,
+            serial_num: card_serial_num,
+        };
+
+        // CHECK #1: Card opening.
+        let computed_card_com_var = card_var.commit_poseidon(&poseidon_params, &com_rand_var)?;
+        computed_card_com_var.enforce_equal(&claimed_card_com_var)?;
+
+        // CHECK #2: Membership test. A Poseidon leaf is a slice of field elements, so our leaf is
+        // just the one-element slice containing the card commitment.
+        let leaf_var = core::slice::from_ref(&claimed_card_com_var);
+        let computed_root_var =
+            auth_path_var.calculate_root(&poseidon_params, &poseidon_params, leaf_var)?;
+        computed_root_var.enforce_equal(&claimed_root_var)?;
+
+        // All done with the checks
+        Ok(())
+    }
+}
+
+//
+// TESTS
+//
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        constraints::PossessionCircuit,
+        poseidon_params::poseidon_params,
+        util::{
+            gen_test_poseidon_tree, gen_test_tree, get_test_card, get_test_poseidon_leaf,
+            is_satisfied, sample_crh_params,
+        },
+    };
+
+    use ark_bls12_381::Fr as F;
+    use ark_ff::UniformRand;
+    use ark_relations::r1cs::ConstraintSystem;
+
+    // Sets up a legitimate Poseidon possession circuit
+    fn setup() -> PoseidonPossessionCircuit {
+        let poseidon_params = poseidon_params();
+
+        // Generate a test tree and the root
+        let tree = gen_test_poseidon_tree(&poseidon_params);
+        let correct_root = tree.root();
+        // Also imagine we possess the card that appears at index 7 in the tree
+        let our_idx = 7;
+        let (card, card_com_rand) = get_test_card(our_idx);
+        let claimed_leaf = get_test_poseidon_leaf(&poseidon_params, our_idx);
+
+        // Generate a Merkle authentication path that proves the membership of the 8th leaf
+        let auth_path = tree.generate_proof(our_idx).unwrap();
+
+        PoseidonPossessionCircuit {
+            // Constants for hashing
+            poseidon_params,
+
+            // Public inputs
+            root: correct_root,
+            leaf: claimed_leaf,
+            card_serial_num: card.serial_num,
+
+            // Private inputs
+            auth_path,
+            card_purchase_price: card.purchase_price,
+            card_com_rand,
+        }
+    }
+
+    // Correctness test: Make a fresh constraint system and run the circuit.
+    #[test]
+    fn correctness() {
+        let circuit = setup();
+
+        // Run the circuit on a fresh constraint system
+        let cs = ConstraintSystem::new_ref();
+        circuit.generate_constraints(cs.clone()).unwrap();
+
+        // The constraints should be satisfied. That is, the valid circuit should verify.
+        assert!(
+            cs.is_satisfied().unwrap(),
+            "circuit correctness check failed; a valid circuit did not succeed"
+        );
+    }
+
+    // Card soundness test: Modify the circuit to have a random amount
+    #[test]
+    fn card_soundness() {
+        let mut rng = ark_std::test_rng();
+        let mut bad_card_circuit = setup();
+        bad_card_circuit.card_purchase_price = F::rand(&mut rng);
+
+        assert!(
+            !is_satisfied(bad_card_circuit),
+            "circuit should not be satisfied after changing the card purchase price"
+        );
+    }
+
+    // Tree soundness test: Modify the circuit to have a random Merkle tree root
+    #[test]
+    fn tree_soundness() {
+        let mut rng = ark_std::test_rng();
+        let mut bad_root_circuit = setup();
+        bad_root_circuit.root = F::rand(&mut rng);
+
+        assert!(
+            !is_satisfied(bad_root_circuit),
+            "circuit should not be satisfied after changing the Merkle root"
+        );
+    }
+
+    // The whole point of Poseidon: it should take far fewer constraints than Pedersen to prove the
+    // same statement
+    #[test]
+    fn fewer_constraints_than_pedersen() {
+        let mut rng = ark_std::test_rng();
+
+        // Build the Pedersen possession circuit for the same card
+        let (leaf_crh_params, two_to_one_crh_params) = sample_crh_params(&mut rng);
+        let tree = gen_test_tree(&leaf_crh_params, &two_to_one_crh_params);
+        let our_idx = 7;
+        let (card, card_com_rand) = get_test_card(our_idx);
+        let pedersen_circuit: PossessionCircuit = PossessionCircuit {
+            leaf_crh_params,
+            two_to_one_crh_params,
+            enforce_nonzero_price: false,
+            root: tree.root(),
+            leaf: tree.leaves()[our_idx].to_vec(),
+            card_serial_num: card.serial_num,
+            auth_path: tree.generate_proof(our_idx).unwrap(),
+            card_purchase_price: card.purchase_price,
+            card_com_rand,
+        };
+
+        let pedersen_cs = ConstraintSystem::new_ref();
+        pedersen_circuit
+            .generate_constraints(pedersen_cs.clone())
+            .unwrap();
+        let poseidon_cs = ConstraintSystem::new_ref();
+        setup().generate_constraints(poseidon_cs.clone()).unwrap();
+
+        assert!(
+            poseidon_cs.num_constraints() < pedersen_cs.num_constraints(),
+            "Poseidon circuit has {} constraints, Pedersen circuit has {}",
+            poseidon_cs.num_constraints(),
+            pedersen_cs.num_constraints()
+        );
+    }
+}
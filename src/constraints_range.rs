@@ -0,0 +1,218 @@
+use crate::{
+    card::CardVar,
+    hash::{LeafHash, LeafHashParamsVar, TwoToOneHash, TwoToOneHashParamsVar},
+    merkle::{MerkleRoot, RootVar, SimplePath, SimplePathVar},
+    F, FV,
+};
+
+use core::cmp::Ordering;
+
+use ark_crypto_primitives::crh::{CRHScheme, TwoToOneCRHScheme};
+use ark_r1cs_std::{alloc::AllocVar, eq::EqGadget, uint8::UInt8};
+use ark_relations::{
+    ns,
+    r1cs::{ConstraintSynthesizer, ConstraintSystemRef, SynthesisError},
+};
+
+/// Our ZK circuit for proving possession of a card whose purchase price lies in the range
+/// `[lo, hi]`, without revealing the price itself
+#[derive(Clone)]
+pub struct RangeProofCircuit {
+    // These are constants that will be embedded into the circuit. They describe how the hash
+    // function works. Don't worry about this.
+    pub leaf_crh_params: <LeafHash as CRHScheme>::Parameters,
+    pub two_to_one_crh_params: <TwoToOneHash as TwoToOneCRHScheme>::Parameters,
+
+    // Public inputs to the circuit
+    /// The root of the merkle tree we're proving membership in
+    pub root: MerkleRoot,
+    /// The leaf in that tree. In our case, the leaf is also a commitment to the card we're showing
+    pub leaf: Vec<u8>,
+    /// The serial number of this card. This is revealed for the same reason as in
+    /// `PossessionCircuit`.
+    pub card_serial_num: F,
+    /// The lowest purchase price we're claiming the card could have
+    pub lo: F,
+    /// The highest purchase price we're claiming the card could have
+    pub hi: F,
+
+    // Private inputs (aka "witnesses") for the circuit
+    /// The amount the card was purchased for
+    pub card_purchase_price: F,
+    /// The private randomness used to commit to the card
+    pub card_com_rand: F,
+    /// The merkle authentication path. Assuming the hash we use is secure, this path is proof that
+    /// the committed leaf is in the tree.
+    pub auth_path: SimplePath,
+}
+
+impl ConstraintSynthesizer<F> for RangeProofCircuit {
+    fn generate_constraints(self, cs: ConstraintSystemRef<F>) -> Result<(), SynthesisError> {
+        // First, allocate the public parameters as constants
+        let leaf_crh_params = LeafHashParamsVar::new_constant(cs.clone(), &self.leaf_crh_params)?;
+        let two_to_one_crh_params =
+            TwoToOneHashParamsVar::new_constant(cs.clone(), &self.two_to_one_crh_params)?;
+
+        //
+        // Next, allocate the public inputs
+        //
+
+        // Merkle root
+        let claimed_root_var =
+            <RootVar as AllocVar<MerkleRoot, _>>::new_input(ns!(cs, "root"), || Ok(&self.root))?;
+        // Card's serial number
+        let card_serial_num = FV::new_input(ns!(cs, "card serial"), || Ok(&self.card_serial_num))?;
+        // Card commitment. This is also the leaf in our tree.
+        let claimed_card_com_var = UInt8::new_witness_vec(ns!(cs, "card com"), &self.leaf)?;
+        // The ends of the price range
+        let lo_var = FV::new_input(ns!(cs, "price lo"), || Ok(&self.lo))?;
+        let hi_var = FV::new_input(ns!(cs, "price hi"), || Ok(&self.hi))?;
+
+        //
+        // Now we witness our private inputs
+        //
+
+        // The amount the card was purchase for
+        let card_purchase_price =
+            FV::new_witness(ns!(cs, "purchase price"), || Ok(&self.card_purchase_price))?;
+        // Commitment randomness
+        let com_rand_var = FV::new_witness(ns!(cs, "card com_rand"), || Ok(&self.card_com_rand))?;
+        // Merkle authentication path
+        let auth_path_var =
+            SimplePathVar::new_witness(ns!(cs, "merkle path"), || Ok(&self.auth_path))?;
+
+        //
+        // Ok everything has been inputted. Now we do the logic of the circuit.
+        //
+
+        // Put the pieces of our card together into a CardVar
+        let card_var: CardVar = CardVar {
+            amount: card_purchase_price,
+            serial_num: card_serial_num,
+        };
+
+        // CHECK #1: Card opening.
+        let computed_card_com_var = card_var.commit(&leaf_crh_params, &com_rand_var)?;
+        computed_card_com_var.enforce_equal(&claimed_card_com_var)?;
+
+        // CHECK #2: Membership test.
+        let leaf_var = claimed_card_com_var;
+        let computed_root_var =
+            auth_path_var.calculate_root(&leaf_crh_params, &two_to_one_crh_params, &leaf_var)?;
+        computed_root_var.enforce_equal(&claimed_root_var)?;
+
+        // CHECK #3: Range check.
+        // The comparison gadgets bit-decompose their inputs. They only work on values that are at
+        // most (p-1)/2, which they also enforce, so a price that "wraps around" is rejected.
+        card_var
+            .amount
+            .enforce_cmp(&lo_var, Ordering::Greater, true)?;
+        card_var.amount.enforce_cmp(&hi_var, Ordering::Less, true)?;
+
+        // All done with the checks
+        Ok(())
+    }
+}
+
+//
+// TESTS
+//
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        card::Card,
+        util::{gen_test_tree_with_card, is_satisfied, sample_crh_params},
+    };
+
+    use ark_bls12_381::Fr as F;
+    use ark_ff::UniformRand;
+    use rand::RngCore;
+
+    // Sets up a legitimate range proof circuit for a card that cost 100, with the range [50, 200]
+    fn setup(mut rng: impl RngCore) -> RangeProofCircuit {
+        let (leaf_crh_params, two_to_one_crh_params) = sample_crh_params(&mut rng);
+
+        // Put a card with a small purchase price at index 7 of the test tree
+        let our_idx = 7;
+        let card = Card {
+            purchase_price: F::from(100u64),
+            serial_num: F::rand(&mut rng),
+        };
+        let card_com_rand = F::rand(&mut rng);
+        let (tree, claimed_leaf) = gen_test_tree_with_card(
+            &leaf_crh_params,
+            &two_to_one_crh_params,
+            our_idx,
+            &card,
+            &card_com_rand,
+        );
+
+        // Generate a Merkle authentication path that proves the membership of our leaf
+        let auth_path = tree.generate_proof(our_idx).unwrap();
+
+        RangeProofCircuit {
+            // Constants for hashing
+            leaf_crh_params,
+            two_to_one_crh_params,
+
+            // Public inputs
+            root: tree.root(),
+            leaf: claimed_leaf.to_vec(),
+            card_serial_num: card.serial_num,
+            lo: F::from(50u64),
+            hi: F::from(200u64),
+
+            // Private inputs
+            auth_path,
+            card_purchase_price: card.purchase_price,
+            card_com_rand,
+        }
+    }
+
+    // Correctness test: The price is in range, so the circuit should be satisfied. The endpoints of
+    // the range are also allowed.
+    #[test]
+    fn correctness() {
+        let mut rng = ark_std::test_rng();
+        let circuit = setup(&mut rng);
+
+        for (lo, hi) in [(50u64, 200u64), (100, 200), (50, 100), (100, 100)] {
+            let mut circuit = circuit.clone();
+            circuit.lo = F::from(lo);
+            circuit.hi = F::from(hi);
+
+            assert!(
+                is_satisfied(circuit),
+                "circuit correctness check failed for the range [{lo}, {hi}]"
+            );
+        }
+    }
+
+    // Lower bound soundness test: The price is 100, so a range starting at 101 should fail
+    #[test]
+    fn below_lo_soundness() {
+        let mut rng = ark_std::test_rng();
+        let mut bad_range_circuit = setup(&mut rng);
+        bad_range_circuit.lo = F::from(101u64);
+
+        assert!(
+            !is_satisfied(bad_range_circuit),
+            "circuit should not be satisfied when the price is below lo"
+        );
+    }
+
+    // Upper bound soundness test: The price is 100, so a range ending at 99 should fail
+    #[test]
+    fn above_hi_soundness() {
+        let mut rng = ark_std::test_rng();
+        let mut bad_range_circuit = setup(&mut rng);
+        bad_range_circuit.hi = F::from(99u64);
+
+        assert!(
+            !is_satisfied(bad_range_circuit),
+            "circuit should not be satisfied when the price is above hi"
+        );
+    }
+}
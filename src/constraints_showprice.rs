@@ -21,6 +21,12 @@ pub struct PossessionShowPriceCircuit {
     pub leaf_crh_params: <LeafHash as CRHScheme>::Parameters,
     pub two_to_one_crh_params: <TwoToOneHash as TwoToOneCRHScheme>::Parameters,
 
+    // Circuit configuration
+    /// Whether to enforce that the card's purchase price fits in a `u64`. The price is public
+    /// anyway, so this only stops a prover from showing a nonsense price like `-1`. It costs about
+    /// 65 constraints, and the CRS must be generated with the same setting.
+    pub check_price_range: bool,
+
     // Public inputs to the circuit
     /// The root of the merkle tree we're proving membership in
     pub root: MerkleRoot,
@@ -41,6 +47,12 @@ pub struct PossessionShowPriceCircuit {
     pub auth_path: SimplePath,
 }
 
+impl PossessionShowPriceCircuit {
+    /// The number of field elements in this circuit's public input: the Merkle root, which is a
+    /// curve point (2 field elements), the card serial number (1), and the purchase price (1)
+    pub const NUM_PUBLIC_INPUTS: usize = 2 + 1 + 1;
+}
+
 /// generate_constraints is where the circuit functionality is defined. It doesn't return any
 /// value. Rather, it takes in a constraint system, and adds a bunch of constraints to that system
 /// (implicitly or explicitly). A proof is valid if and only if the final constraint system is
@@ -84,7 +96,7 @@ impl ConstraintSynthesizer<F> for PossessionShowPriceCircuit {
         //
 
         // Put the pieces of our card together into a CardVar
-        let card_var = CardVar {
+        let card_var: CardVar = CardVar {
             amount: card_purchase_price,
             serial_num: card_serial_num,
         };
@@ -105,6 +117,12 @@ impl ConstraintSynthesizer<F> for PossessionShowPriceCircuit {
             auth_path_var.calculate_root(&leaf_crh_params, &two_to_one_crh_params, &leaf_var)?;
         computed_root_var.enforce_equal(&claimed_root_var)?;
 
+        // CHECK #3 (optional): Price range.
+        // The price is public, but a verifier would still like to know it's a sensible number
+        if self.check_price_range {
+            card_var.range_check(cs.clone())?;
+        }
+
         // All done with the checks
         Ok(())
     }
@@ -117,7 +135,12 @@ impl ConstraintSynthesizer<F> for PossessionShowPriceCircuit {
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::util::{gen_test_tree, get_test_card, get_test_leaf};
+    use crate::{
+        card::Card,
+        util::{
+            gen_test_tree, gen_test_tree_with_card, get_test_card, is_satisfied, sample_crh_params,
+        },
+    };
 
     use ark_bls12_381::Fr as F;
     use ark_ff::UniformRand;
@@ -129,9 +152,7 @@ mod test {
         // Let's set up an RNG for use within tests. Note that this is NOT safe for any production
         // use
 
-        // First, let's sample the public parameters for the hash functions
-        let leaf_crh_params = <LeafHash as CRHScheme>::setup(&mut rng).unwrap();
-        let two_to_one_crh_params = <TwoToOneHash as TwoToOneCRHScheme>::setup(&mut rng).unwrap();
+        let (leaf_crh_params, two_to_one_crh_params) = sample_crh_params(&mut rng);
 
         // Generate a test tree and the root
         let tree = gen_test_tree(&leaf_crh_params, &two_to_one_crh_params);
@@ -147,7 +168,7 @@ mod test {
         // We'll reveal and prove membership of the 8th leaf in the tree, i.e., the card com we
         // just created.
         let idx_to_prove = our_idx;
-        let claimed_leaf = get_test_leaf(&leaf_crh_params, idx_to_prove);
+        let claimed_leaf = tree.leaves()[idx_to_prove];
 
         // Generate a Merkle authentication path that proves the membership of the 8th leaf
         let auth_path = tree.generate_proof(idx_to_prove).unwrap();
@@ -158,6 +179,9 @@ mod test {
             leaf_crh_params,
             two_to_one_crh_params,
 
+            // Circuit configuration
+            check_price_range: false,
+
             // Public inputs
             root: correct_root,
             leaf: claimed_leaf.to_vec(),
@@ -228,4 +252,53 @@ mod test {
             "circuit should not be satisfied after changing the Merkle root"
         );
     }
+
+    // Price range test: With the range check on, a price that fits in a u64 should be accepted
+    #[test]
+    fn price_range_correctness() {
+        let mut rng = ark_std::test_rng();
+        let mut circuit = setup(&mut rng);
+
+        // Replace our card with one whose price is the largest u64
+        let our_idx = 7;
+        let card = Card {
+            purchase_price: F::from(u64::MAX),
+            serial_num: F::rand(&mut rng),
+        };
+        let card_com_rand = F::rand(&mut rng);
+        let (tree, leaf) = gen_test_tree_with_card(
+            &circuit.leaf_crh_params,
+            &circuit.two_to_one_crh_params,
+            our_idx,
+            &card,
+            &card_com_rand,
+        );
+        circuit.root = tree.root();
+        circuit.leaf = leaf.to_vec();
+        circuit.card_serial_num = card.serial_num;
+        circuit.card_purchase_price = card.purchase_price;
+        circuit.card_com_rand = card_com_rand;
+        circuit.auth_path = tree.generate_proof(our_idx).unwrap();
+        circuit.check_price_range = true;
+
+        assert!(
+            is_satisfied(circuit),
+            "circuit with price range check failed on a price that fits in a u64"
+        );
+    }
+
+    // Price range soundness test: The test cards have uniformly random prices, which are almost
+    // certainly too big for a u64. These are accepted without the range check, and rejected with
+    // it.
+    #[test]
+    fn price_range_soundness() {
+        let mut rng = ark_std::test_rng();
+        let mut big_price_circuit = setup(&mut rng);
+        big_price_circuit.check_price_range = true;
+
+        assert!(
+            !is_satisfied(big_price_circuit),
+            "circuit should not be satisfied by a price that doesn't fit in a u64"
+        );
+    }
 }
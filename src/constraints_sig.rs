@@ -0,0 +1,166 @@
+use crate::{
+    hash::{LeafHash, LeafHashParams},
+    F,
+};
+
+use ark_crypto_primitives::crh::CRHScheme;
+use ark_ec::{AffineRepr, CurveGroup, Group};
+use ark_ed_on_bls12_381::{
+    constraints::EdwardsVar as JubjubVar, EdwardsAffine as JubjubAffine,
+    EdwardsProjective as Jubjub, Fr as JubjubScalar,
+};
+use ark_ff::{BigInteger, PrimeField};
+use ark_r1cs_std::{alloc::AllocVar, boolean::Boolean, eq::EqGadget, groups::CurveVar};
+use ark_relations::{
+    ns,
+    r1cs::{ConstraintSynthesizer, ConstraintSystemRef, SynthesisError},
+};
+
+/// Hashes a message to a Jubjub point. This is just the Pedersen leaf hash, so messages can be up
+/// to 96 bytes long. Nobody knows the discrete log of the output.
+pub fn hash_msg(leaf_crh_params: &LeafHashParams, msg: &[u8]) -> JubjubAffine {
+    LeafHash::evaluate(leaf_crh_params, msg).unwrap()
+}
+
+/// Returns the public key `sk * G` of the secret key `sk`
+pub fn public_key(sk: &JubjubScalar) -> JubjubAffine {
+    (Jubjub::generator() * sk).into_affine()
+}
+
+/// Returns the PLUME-style signature `sk * msg_hash`. Unlike `sk`, this is safe to reveal, and
+/// it's the same every time the same key signs the same message.
+pub fn sign(sk: &JubjubScalar, msg_hash: &JubjubAffine) -> JubjubAffine {
+    (msg_hash.into_group() * sk).into_affine()
+}
+
+/// Our ZK circuit for proving knowledge of a Jubjub secret key. The prover shows that the key
+/// belongs to a public key, and that it was used to make a signature on a public message hash,
+/// without revealing the key.
+#[derive(Clone)]
+pub struct JubjubSignatureCircuit {
+    // Public inputs to the circuit
+    /// The public key, i.e., `sk * G`
+    pub pk: JubjubAffine,
+    /// The hash of the message being signed. See `hash_msg`.
+    pub msg_hash: JubjubAffine,
+    /// The signature, i.e., `sk * msg_hash`
+    pub sig: JubjubAffine,
+
+    // Private inputs (aka "witnesses") for the circuit
+    /// The secret key
+    pub sk: JubjubScalar,
+}
+
+impl ConstraintSynthesizer<F> for JubjubSignatureCircuit {
+    fn generate_constraints(self, cs: ConstraintSystemRef<F>) -> Result<(), SynthesisError> {
+        // First, allocate the generator as a constant
+        let generator = JubjubVar::new_constant(cs.clone(), Jubjub::generator())?;
+
+        //
+        // Next, allocate the public inputs
+        //
+
+        let pk_var = JubjubVar::new_input(ns!(cs, "pk"), || Ok(self.pk))?;
+        let msg_hash_var = JubjubVar::new_input(ns!(cs, "msg hash"), || Ok(self.msg_hash))?;
+        let sig_var = JubjubVar::new_input(ns!(cs, "sig"), || Ok(self.sig))?;
+
+        //
+        // Now we witness our private inputs
+        //
+
+        // The secret key, as little-endian bits. We witness them one at a time so that there are
+        // always the same number of them, even when the key has no value during CRS generation.
+        let sk_bits = (0..JubjubScalar::MODULUS_BIT_SIZE as usize)
+            .map(|i| {
+                Boolean::new_witness(ns!(cs, "sk bit"), || Ok(self.sk.into_bigint().get_bit(i)))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        //
+        // Ok everything has been inputted. Now we do the logic of the circuit.
+        //
+
+        // CHECK #1: Public key.
+        // The secret key is the discrete log of the public key.
+        generator
+            .scalar_mul_le(sk_bits.iter())?
+            .enforce_equal(&pk_var)?;
+
+        // CHECK #2: Signature.
+        // The same secret key was used to make the signature.
+        msg_hash_var
+            .scalar_mul_le(sk_bits.iter())?
+            .enforce_equal(&sig_var)?;
+
+        // All done with the checks
+        Ok(())
+    }
+}
+
+//
+// TESTS
+//
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::is_satisfied;
+
+    use ark_ff::UniformRand;
+    use rand::RngCore;
+
+    // Sets up a legitimate signature circuit
+    fn setup(mut rng: impl RngCore) -> JubjubSignatureCircuit {
+        let leaf_crh_params = <LeafHash as CRHScheme>::setup(&mut rng).unwrap();
+        let msg_hash = hash_msg(&leaf_crh_params, b"I own card #7");
+
+        let sk = JubjubScalar::rand(&mut rng);
+        JubjubSignatureCircuit {
+            pk: public_key(&sk),
+            msg_hash,
+            sig: sign(&sk, &msg_hash),
+            sk,
+        }
+    }
+
+    // Correctness test: Make a fresh constraint system and run the circuit.
+    #[test]
+    fn correctness() {
+        let mut rng = ark_std::test_rng();
+        let circuit = setup(&mut rng);
+
+        assert!(
+            is_satisfied(circuit),
+            "circuit correctness check failed; a valid circuit did not succeed"
+        );
+    }
+
+    // Key soundness test: Use a secret key that doesn't match the public key, but that did make the
+    // signature
+    #[test]
+    fn key_soundness() {
+        let mut rng = ark_std::test_rng();
+        let mut bad_key_circuit = setup(&mut rng);
+        bad_key_circuit.sk = JubjubScalar::rand(&mut rng);
+        bad_key_circuit.sig = sign(&bad_key_circuit.sk, &bad_key_circuit.msg_hash);
+
+        assert!(
+            !is_satisfied(bad_key_circuit),
+            "circuit should not be satisfied when the secret key doesn't match the public key"
+        );
+    }
+
+    // Signature soundness test: Use a signature on a different message
+    #[test]
+    fn sig_soundness() {
+        let mut rng = ark_std::test_rng();
+        let mut bad_sig_circuit = setup(&mut rng);
+        let other_msg_hash = (bad_sig_circuit.msg_hash + JubjubAffine::generator()).into_affine();
+        bad_sig_circuit.sig = sign(&bad_sig_circuit.sk, &other_msg_hash);
+
+        assert!(
+            !is_satisfied(bad_sig_circuit),
+            "circuit should not be satisfied when the signature is on a different message"
+        );
+    }
+}
@@ -0,0 +1,341 @@
+use crate::{
+    card::CardVar,
+    hash::{LeafHash, LeafHashParamsVar, TwoToOneHash, TwoToOneHashParamsVar},
+    merkle::{MerkleRoot, RootVar, SimplePath, SimplePathVar},
+    F, FV,
+};
+
+use ark_crypto_primitives::crh::{CRHScheme, TwoToOneCRHScheme};
+use ark_r1cs_std::{alloc::AllocVar, eq::EqGadget, uint8::UInt8};
+use ark_relations::{
+    ns,
+    r1cs::{ConstraintSynthesizer, ConstraintSystemRef, SynthesisError},
+};
+
+/// Our ZK circuit for splitting one card into two. This is the reverse of `DepositCircuit`. The
+/// prover shows that the input card is in the tree, and that the output cards' purchase prices sum
+/// to the input card's price. The input serial is revealed so the input can't be split twice.
+///
+/// All three prices are range-checked to 64 bits. Otherwise the sum could wrap around the field
+/// modulus, and a prover could split a cheap card into an expensive one and a "negative" one.
+#[derive(Clone)]
+pub struct SplitCircuit {
+    // These are constants that will be embedded into the circuit. They describe how the hash
+    // function works. Don't worry about this.
+    pub leaf_crh_params: <LeafHash as CRHScheme>::Parameters,
+    pub two_to_one_crh_params: <TwoToOneHash as TwoToOneCRHScheme>::Parameters,
+
+    // Public inputs to the circuit
+    /// The root of the merkle tree the input card is in
+    pub root: MerkleRoot,
+    /// The serial number of the input card
+    pub input_serial_num: F,
+    /// The commitment to the first output card
+    pub output_a_leaf: Vec<u8>,
+    /// The commitment to the second output card
+    pub output_b_leaf: Vec<u8>,
+
+    // Private inputs (aka "witnesses") for the circuit
+    /// The commitment to the input card, i.e., its leaf in the tree
+    pub input_leaf: Vec<u8>,
+    /// The amount the input card was purchased for
+    pub input_purchase_price: F,
+    /// The randomness used to commit to the input card
+    pub input_com_rand: F,
+    /// The merkle authentication path of the input card
+    pub input_auth_path: SimplePath,
+    /// The purchase price of the first output card
+    pub output_a_purchase_price: F,
+    /// The serial number of the first output card
+    pub output_a_serial_num: F,
+    /// The randomness used to commit to the first output card
+    pub output_a_com_rand: F,
+    /// The purchase price of the second output card
+    pub output_b_purchase_price: F,
+    /// The serial number of the second output card
+    pub output_b_serial_num: F,
+    /// The randomness used to commit to the second output card
+    pub output_b_com_rand: F,
+}
+
+impl ConstraintSynthesizer<F> for SplitCircuit {
+    fn generate_constraints(self, cs: ConstraintSystemRef<F>) -> Result<(), SynthesisError> {
+        // First, allocate the public parameters as constants
+        let leaf_crh_params = LeafHashParamsVar::new_constant(cs.clone(), &self.leaf_crh_params)?;
+        let two_to_one_crh_params =
+            TwoToOneHashParamsVar::new_constant(cs.clone(), &self.two_to_one_crh_params)?;
+
+        //
+        // Next, allocate the public inputs
+        //
+
+        // Merkle root
+        let claimed_root_var =
+            <RootVar as AllocVar<MerkleRoot, _>>::new_input(ns!(cs, "root"), || Ok(&self.root))?;
+        // Input serial number
+        let input_serial_num =
+            FV::new_input(ns!(cs, "input serial"), || Ok(&self.input_serial_num))?;
+        // Output card commitments
+        let claimed_output_a_com_var =
+            UInt8::new_input_vec(ns!(cs, "output a com"), &self.output_a_leaf)?;
+        let claimed_output_b_com_var =
+            UInt8::new_input_vec(ns!(cs, "output b com"), &self.output_b_leaf)?;
+
+        //
+        // Now we witness our private inputs
+        //
+
+        // Input card
+        let claimed_input_com_var = UInt8::new_witness_vec(ns!(cs, "input com"), &self.input_leaf)?;
+        let input_purchase_price = FV::new_witness(ns!(cs, "input purchase price"), || {
+            Ok(&self.input_purchase_price)
+        })?;
+        let input_com_rand =
+            FV::new_witness(ns!(cs, "input com_rand"), || Ok(&self.input_com_rand))?;
+        let input_auth_path =
+            SimplePathVar::new_witness(ns!(cs, "input merkle path"), || Ok(&self.input_auth_path))?;
+
+        // First output card
+        let output_a_purchase_price = FV::new_witness(ns!(cs, "output a purchase price"), || {
+            Ok(&self.output_a_purchase_price)
+        })?;
+        let output_a_serial_num =
+            FV::new_witness(ns!(cs, "output a serial"), || Ok(&self.output_a_serial_num))?;
+        let output_a_com_rand =
+            FV::new_witness(ns!(cs, "output a com_rand"), || Ok(&self.output_a_com_rand))?;
+
+        // Second output card
+        let output_b_purchase_price = FV::new_witness(ns!(cs, "output b purchase price"), || {
+            Ok(&self.output_b_purchase_price)
+        })?;
+        let output_b_serial_num =
+            FV::new_witness(ns!(cs, "output b serial"), || Ok(&self.output_b_serial_num))?;
+        let output_b_com_rand =
+            FV::new_witness(ns!(cs, "output b com_rand"), || Ok(&self.output_b_com_rand))?;
+
+        //
+        // Ok everything has been inputted. Now we do the logic of the circuit.
+        //
+
+        let input_var: CardVar = CardVar {
+            amount: input_purchase_price,
+            serial_num: input_serial_num,
+        };
+        let output_a_var: CardVar = CardVar {
+            amount: output_a_purchase_price,
+            serial_num: output_a_serial_num,
+        };
+        let output_b_var: CardVar = CardVar {
+            amount: output_b_purchase_price,
+            serial_num: output_b_serial_num,
+        };
+
+        // CHECK #1: Input card opening
+        input_var
+            .commit(&leaf_crh_params, &input_com_rand)?
+            .enforce_equal(&claimed_input_com_var)?;
+
+        // CHECK #2: Input card membership test
+        input_auth_path
+            .calculate_root(
+                &leaf_crh_params,
+                &two_to_one_crh_params,
+                &claimed_input_com_var,
+            )?
+            .enforce_equal(&claimed_root_var)?;
+
+        // CHECK #3: Value conservation. The output prices sum to the input price. Each price fits in
+        // 64 bits, so the sum of the outputs is less than 2^65, and can't wrap around the modulus.
+        input_var.range_check(ns!(cs, "input range").cs())?;
+        output_a_var.range_check(ns!(cs, "output a range").cs())?;
+        output_b_var.range_check(ns!(cs, "output b range").cs())?;
+        let output_sum = &output_a_var.amount + &output_b_var.amount;
+        input_var.amount.enforce_equal(&output_sum)?;
+
+        // CHECK #4: Output card commitments
+        output_a_var
+            .commit(&leaf_crh_params, &output_a_com_rand)?
+            .enforce_equal(&claimed_output_a_com_var)?;
+        output_b_var
+            .commit(&leaf_crh_params, &output_b_com_rand)?
+            .enforce_equal(&claimed_output_b_com_var)?;
+
+        // All done with the checks
+        Ok(())
+    }
+}
+
+//
+// TESTS
+//
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        card::Card,
+        util::{gen_test_tree_with_card, is_satisfied, sample_crh_params},
+    };
+
+    use ark_bls12_381::Fr as F;
+    use ark_ff::UniformRand;
+    use ark_relations::r1cs::ConstraintSystem;
+    use rand::RngCore;
+
+    // Sets up a legitimate split circuit, splitting a card that cost 100 into 30 and 70
+    fn setup(mut rng: impl RngCore) -> SplitCircuit {
+        setup_with_amounts(&mut rng, 100, F::from(30u64), F::from(70u64))
+    }
+
+    // Sets up a split circuit for a card of the given price, with the given output prices. The
+    // commitments are all computed honestly, so only the value checks can fail.
+    fn setup_with_amounts(
+        mut rng: impl RngCore,
+        input_price: u64,
+        amount_a: F,
+        amount_b: F,
+    ) -> SplitCircuit {
+        let (leaf_crh_params, two_to_one_crh_params) = sample_crh_params(&mut rng);
+
+        // Put our input card at index 7 of the test tree
+        let our_idx = 7;
+        let input = Card {
+            purchase_price: F::from(input_price),
+            serial_num: F::rand(&mut rng),
+        };
+        let input_com_rand = F::rand(&mut rng);
+        let (tree, input_leaf) = gen_test_tree_with_card(
+            &leaf_crh_params,
+            &two_to_one_crh_params,
+            our_idx,
+            &input,
+            &input_com_rand,
+        );
+
+        // Split the input price into two pieces. This doesn't use Card::split, since some tests
+        // want output prices that it rejects.
+        let output_a = Card {
+            purchase_price: amount_a,
+            serial_num: F::rand(&mut rng),
+        };
+        let output_b = Card {
+            purchase_price: amount_b,
+            serial_num: F::rand(&mut rng),
+        };
+        let output_a_com_rand = F::rand(&mut rng);
+        let output_b_com_rand = F::rand(&mut rng);
+
+        SplitCircuit {
+            // Public inputs
+            root: tree.root(),
+            input_serial_num: input.serial_num,
+            output_a_leaf: output_a
+                .commit(&leaf_crh_params, &output_a_com_rand)
+                .to_vec(),
+            output_b_leaf: output_b
+                .commit(&leaf_crh_params, &output_b_com_rand)
+                .to_vec(),
+
+            // Private inputs
+            input_leaf: input_leaf.to_vec(),
+            input_purchase_price: input.purchase_price,
+            input_com_rand,
+            input_auth_path: tree.generate_proof(our_idx).unwrap(),
+            output_a_purchase_price: output_a.purchase_price,
+            output_a_serial_num: output_a.serial_num,
+            output_a_com_rand,
+            output_b_purchase_price: output_b.purchase_price,
+            output_b_serial_num: output_b.serial_num,
+            output_b_com_rand,
+
+            // Constants for hashing
+            leaf_crh_params,
+            two_to_one_crh_params,
+        }
+    }
+
+    // Correctness test: Make a fresh constraint system and run the circuit.
+    #[test]
+    fn correctness() {
+        let mut rng = ark_std::test_rng();
+        let circuit = setup(&mut rng);
+
+        // Run the circuit on a fresh constraint system
+        let cs = ConstraintSystem::new_ref();
+        circuit.generate_constraints(cs.clone()).unwrap();
+
+        // The constraints should be satisfied. That is, the valid circuit should verify.
+        assert!(
+            cs.is_satisfied().unwrap(),
+            "circuit correctness check failed; a valid circuit did not succeed"
+        );
+    }
+
+    // Value soundness test: Inflate the second output card, with a correctly computed commitment.
+    // Only the value conservation check should catch this.
+    #[test]
+    fn value_soundness() {
+        let mut rng = ark_std::test_rng();
+        let mut bad_value_circuit = setup(&mut rng);
+
+        let inflated_output = Card {
+            purchase_price: bad_value_circuit.output_b_purchase_price + F::from(1u64),
+            serial_num: bad_value_circuit.output_b_serial_num,
+        };
+        bad_value_circuit.output_b_purchase_price = inflated_output.purchase_price;
+        bad_value_circuit.output_b_leaf = inflated_output
+            .commit(
+                &bad_value_circuit.leaf_crh_params,
+                &bad_value_circuit.output_b_com_rand,
+            )
+            .to_vec();
+
+        assert!(
+            !is_satisfied(bad_value_circuit),
+            "circuit should not be satisfied when the output prices don't sum to the input price"
+        );
+    }
+
+    // Wraparound soundness test: Split a card that cost 10 into 11 and p - 1. The prices sum to 10
+    // in the field, so only the range checks catch this.
+    #[test]
+    fn wraparound_soundness() {
+        let mut rng = ark_std::test_rng();
+        let bad_circuit = setup_with_amounts(&mut rng, 10, F::from(11u64), -F::from(1u64));
+
+        assert!(
+            !is_satisfied(bad_circuit),
+            "circuit should not be satisfied when the output prices wrap around the modulus"
+        );
+    }
+
+    // Output soundness test: Change the first output's price without updating its commitment
+    #[test]
+    fn output_com_soundness() {
+        let mut rng = ark_std::test_rng();
+        let mut bad_com_circuit = setup(&mut rng);
+
+        // Keep the sum the same, and the prices in range, so that only the commitment checks fail
+        let delta = F::from(1u64);
+        bad_com_circuit.output_a_purchase_price += delta;
+        bad_com_circuit.output_b_purchase_price -= delta;
+
+        assert!(
+            !is_satisfied(bad_com_circuit),
+            "circuit should not be satisfied when the output commitments don't match"
+        );
+    }
+
+    // Tree soundness test: Modify the circuit to have a random Merkle tree root
+    #[test]
+    fn tree_soundness() {
+        let mut rng = ark_std::test_rng();
+        let mut bad_root_circuit = setup(&mut rng);
+        bad_root_circuit.root = MerkleRoot::rand(&mut rng);
+
+        assert!(
+            !is_satisfied(bad_root_circuit),
+            "circuit should not be satisfied after changing the Merkle root"
+        );
+    }
+}
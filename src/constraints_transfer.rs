@@ -0,0 +1,472 @@
+use crate::{
+    card::CardVar,
+    hash::{
+        LeafHash, LeafHashParamsVar, OwnedLeafHash, OwnedLeafHashParamsVar, TwoToOneHash,
+        TwoToOneHashParamsVar,
+    },
+    merkle::{MerkleRoot, RootVar, SimplePath, SimplePathVar},
+    F, FV,
+};
+
+use ark_crypto_primitives::crh::{CRHScheme, TwoToOneCRHScheme};
+use ark_r1cs_std::{alloc::AllocVar, eq::EqGadget, uint8::UInt8};
+use ark_relations::{
+    ns,
+    r1cs::{ConstraintSynthesizer, ConstraintSystemRef, SynthesisError},
+};
+
+/// Our ZK circuit for transferring a card to a new owner. The prover shows possession of a card,
+/// like in `PossessionCircuit`, and that the new leaf commits to the same card, with the same
+/// commitment randomness, along with the new owner's public key. The serial is revealed so the
+/// card can't be transferred twice.
+#[derive(Clone)]
+pub struct TransferCircuit {
+    // These are constants that will be embedded into the circuit. They describe how the hash
+    // function works. Don't worry about this.
+    pub leaf_crh_params: <LeafHash as CRHScheme>::Parameters,
+    pub two_to_one_crh_params: <TwoToOneHash as TwoToOneCRHScheme>::Parameters,
+    /// The hash for the transferred card's commitment, which is too long for the leaf hash
+    pub owned_leaf_crh_params: <OwnedLeafHash as CRHScheme>::Parameters,
+
+    // Public inputs to the circuit
+    /// The root of the merkle tree the card is in
+    pub root: MerkleRoot,
+    /// The serial number of the card
+    pub card_serial_num: F,
+    /// The public key of the card's new owner
+    pub owner_pubkey: F,
+    /// The commitment to the transferred card, i.e., `OwnedLeafHash(com_rand || amount || serial
+    /// || owner_pubkey)`
+    pub new_leaf: Vec<u8>,
+
+    // Private inputs (aka "witnesses") for the circuit
+    /// The commitment to the card, i.e., its leaf in the tree
+    pub leaf: Vec<u8>,
+    /// The amount the card was purchased for
+    pub card_purchase_price: F,
+    /// The private randomness used to commit to the card
+    pub card_com_rand: F,
+    /// The merkle authentication path of the card
+    pub auth_path: SimplePath,
+}
+
+impl ConstraintSynthesizer<F> for TransferCircuit {
+    fn generate_constraints(self, cs: ConstraintSystemRef<F>) -> Result<(), SynthesisError> {
+        // First, allocate the public parameters as constants
+        let leaf_crh_params = LeafHashParamsVar::new_constant(cs.clone(), &self.leaf_crh_params)?;
+        let two_to_one_crh_params =
+            TwoToOneHashParamsVar::new_constant(cs.clone(), &self.two_to_one_crh_params)?;
+        let owned_leaf_crh_params =
+            OwnedLeafHashParamsVar::new_constant(cs.clone(), &self.owned_leaf_crh_params)?;
+
+        //
+        // Next, allocate the public inputs
+        //
+
+        // Merkle root
+        let claimed_root_var =
+            <RootVar as AllocVar<MerkleRoot, _>>::new_input(ns!(cs, "root"), || Ok(&self.root))?;
+        // Card's serial number. This is public so you can only transfer once
+        let card_serial_num = FV::new_input(ns!(cs, "card serial"), || Ok(&self.card_serial_num))?;
+        // New owner
+        let owner_pubkey = FV::new_input(ns!(cs, "owner pubkey"), || Ok(&self.owner_pubkey))?;
+        // Transferred card commitment
+        let claimed_new_com_var = UInt8::new_input_vec(ns!(cs, "new card com"), &self.new_leaf)?;
+
+        //
+        // Now we witness our private inputs
+        //
+
+        // Card commitment. This is the leaf in our tree.
+        let claimed_card_com_var = UInt8::new_witness_vec(ns!(cs, "card com"), &self.leaf)?;
+        // The amount the card was purchase for
+        let card_purchase_price =
+            FV::new_witness(ns!(cs, "purchase price"), || Ok(&self.card_purchase_price))?;
+        // Commitment randomness
+        let com_rand_var = FV::new_witness(ns!(cs, "card com_rand"), || Ok(&self.card_com_rand))?;
+        // Merkle authentication path
+        let auth_path_var =
+            SimplePathVar::new_witness(ns!(cs, "merkle path"), || Ok(&self.auth_path))?;
+
+        //
+        // Ok everything has been inputted. Now we do the logic of the circuit.
+        //
+
+        // Put the pieces of our card together into a CardVar
+        let card_var: CardVar = CardVar {
+            amount: card_purchase_price,
+            serial_num: card_serial_num,
+        };
+
+        // CHECK #1: Card opening
+        card_var
+            .commit(&leaf_crh_params, &com_rand_var)?
+            .enforce_equal(&claimed_card_com_var)?;
+
+        // CHECK #2: Membership test
+        auth_path_var
+            .calculate_root(
+                &leaf_crh_params,
+                &two_to_one_crh_params,
+                &claimed_card_com_var,
+            )?
+            .enforce_equal(&claimed_root_var)?;
+
+        // CHECK #3: The new commitment is to the same card, now owned by owner_pubkey. Since this
+        // uses the same CardVar, the price and serial can't change in the transfer.
+        card_var
+            .commit_with_owner(&owned_leaf_crh_params, &com_rand_var, &owner_pubkey)?
+            .enforce_equal(&claimed_new_com_var)?;
+
+        // All done with the checks
+        Ok(())
+    }
+}
+
+/// Our ZK circuit for reissuing a card under a new serial number. The prover shows possession of
+/// a card, like in `PossessionCircuit`, and that the new leaf commits to a fresh card of the same
+/// value. This spends the old card (its serial is revealed) and makes the new one in a single
+/// proof. Unlike `TransferCircuit`, the new card's serial and commitment randomness are fresh, so
+/// nobody can link the new leaf to the old card.
+#[derive(Clone)]
+pub struct ReissueCircuit {
+    // These are constants that will be embedded into the circuit. They describe how the hash
+    // function works. Don't worry about this.
+    pub leaf_crh_params: <LeafHash as CRHScheme>::Parameters,
+    pub two_to_one_crh_params: <TwoToOneHash as TwoToOneCRHScheme>::Parameters,
+
+    // Public inputs to the circuit
+    /// The root of the merkle tree the old card is in
+    pub root: MerkleRoot,
+    /// The serial number of the old card. This is revealed so it can't be spent twice.
+    pub card_serial_num: F,
+    /// The commitment to the new card
+    pub new_leaf: Vec<u8>,
+
+    // Private inputs (aka "witnesses") for the circuit
+    /// The commitment to the old card, i.e., its leaf in the tree
+    pub leaf: Vec<u8>,
+    /// The amount the old card was purchased for
+    pub card_purchase_price: F,
+    /// The private randomness used to commit to the old card
+    pub card_com_rand: F,
+    /// The merkle authentication path of the old card
+    pub auth_path: SimplePath,
+    /// The amount of the new card. This must equal the old card's.
+    pub new_card_purchase_price: F,
+    /// The serial number of the new card
+    pub new_card_serial_num: F,
+    /// The private randomness used to commit to the new card
+    pub new_card_com_rand: F,
+}
+
+impl ReissueCircuit {
+    /// The number of field elements in this circuit's public input: the Merkle root (2), the old
+    /// card's serial number (1), and the new leaf (64 bytes, packed into 3 field elements)
+    pub const NUM_PUBLIC_INPUTS: usize = 2 + 1 + 3;
+}
+
+impl ConstraintSynthesizer<F> for ReissueCircuit {
+    fn generate_constraints(self, cs: ConstraintSystemRef<F>) -> Result<(), SynthesisError> {
+        // First, allocate the public parameters as constants
+        let leaf_crh_params = LeafHashParamsVar::new_constant(cs.clone(), &self.leaf_crh_params)?;
+        let two_to_one_crh_params =
+            TwoToOneHashParamsVar::new_constant(cs.clone(), &self.two_to_one_crh_params)?;
+
+        //
+        // Next, allocate the public inputs
+        //
+
+        // Merkle root
+        let claimed_root_var =
+            <RootVar as AllocVar<MerkleRoot, _>>::new_input(ns!(cs, "root"), || Ok(&self.root))?;
+        // Old card's serial number
+        let card_serial_num = FV::new_input(ns!(cs, "card serial"), || Ok(&self.card_serial_num))?;
+        // New card commitment
+        let claimed_new_com_var = UInt8::new_input_vec(ns!(cs, "new card com"), &self.new_leaf)?;
+
+        //
+        // Now we witness our private inputs
+        //
+
+        // Old card commitment. This is the leaf in our tree.
+        let claimed_card_com_var = UInt8::new_witness_vec(ns!(cs, "card com"), &self.leaf)?;
+        // The amount the old card was purchase for
+        let card_purchase_price =
+            FV::new_witness(ns!(cs, "purchase price"), || Ok(&self.card_purchase_price))?;
+        // Old commitment randomness
+        let com_rand_var = FV::new_witness(ns!(cs, "card com_rand"), || Ok(&self.card_com_rand))?;
+        // Merkle authentication path
+        let auth_path_var =
+            SimplePathVar::new_witness(ns!(cs, "merkle path"), || Ok(&self.auth_path))?;
+        // The new card and its commitment randomness
+        let new_card_purchase_price = FV::new_witness(ns!(cs, "new purchase price"), || {
+            Ok(&self.new_card_purchase_price)
+        })?;
+        let new_card_serial_num =
+            FV::new_witness(ns!(cs, "new card serial"), || Ok(&self.new_card_serial_num))?;
+        let new_com_rand_var =
+            FV::new_witness(ns!(cs, "new card com_rand"), || Ok(&self.new_card_com_rand))?;
+
+        //
+        // Ok everything has been inputted. Now we do the logic of the circuit.
+        //
+
+        // Put the pieces of our cards together into CardVars
+        let card_var: CardVar = CardVar {
+            amount: card_purchase_price,
+            serial_num: card_serial_num,
+        };
+        let new_card_var: CardVar = CardVar {
+            amount: new_card_purchase_price,
+            serial_num: new_card_serial_num,
+        };
+
+        // CHECK #1: Old card opening
+        card_var
+            .commit(&leaf_crh_params, &com_rand_var)?
+            .enforce_equal(&claimed_card_com_var)?;
+
+        // CHECK #2: Membership test
+        auth_path_var
+            .calculate_root(
+                &leaf_crh_params,
+                &two_to_one_crh_params,
+                &claimed_card_com_var,
+            )?
+            .enforce_equal(&claimed_root_var)?;
+
+        // CHECK #3: Value is conserved
+        new_card_var.amount.enforce_equal(&card_var.amount)?;
+
+        // CHECK #4: New card opening
+        new_card_var
+            .commit(&leaf_crh_params, &new_com_rand_var)?
+            .enforce_equal(&claimed_new_com_var)?;
+
+        // All done with the checks
+        Ok(())
+    }
+}
+
+//
+// TESTS
+//
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        card::Card,
+        util::{gen_test_tree, get_test_card, is_satisfied, sample_crh_params},
+    };
+
+    use ark_bls12_381::Fr as F;
+    use ark_ff::UniformRand;
+    use ark_relations::r1cs::ConstraintSystem;
+    use rand::RngCore;
+
+    // Sets up a legitimate transfer of card 7 to a random owner
+    fn setup(mut rng: impl RngCore) -> TransferCircuit {
+        let (leaf_crh_params, two_to_one_crh_params) = sample_crh_params(&mut rng);
+        let owned_leaf_crh_params = <OwnedLeafHash as CRHScheme>::setup(&mut rng).unwrap();
+
+        // Generate a test tree and get our card from it
+        let tree = gen_test_tree(&leaf_crh_params, &two_to_one_crh_params);
+        let our_idx = 7;
+        let (card, card_com_rand) = get_test_card(our_idx);
+
+        // Commit to the transferred card
+        let owner_pubkey = F::rand(&mut rng);
+        let new_leaf =
+            card.commit_with_owner(&owned_leaf_crh_params, &card_com_rand, &owner_pubkey);
+
+        TransferCircuit {
+            // Public inputs
+            root: tree.root(),
+            card_serial_num: card.serial_num,
+            owner_pubkey,
+            new_leaf: new_leaf.to_vec(),
+
+            // Private inputs
+            leaf: tree.leaves()[our_idx].to_vec(),
+            card_purchase_price: card.purchase_price,
+            card_com_rand,
+            auth_path: tree.generate_proof(our_idx).unwrap(),
+
+            // Constants for hashing
+            leaf_crh_params,
+            two_to_one_crh_params,
+            owned_leaf_crh_params,
+        }
+    }
+
+    // Replaces the new leaf with a commitment to the given card, owned by the same owner
+    fn recommit(circuit: &mut TransferCircuit, card: &Card) {
+        circuit.new_leaf = card
+            .commit_with_owner(
+                &circuit.owned_leaf_crh_params,
+                &circuit.card_com_rand,
+                &circuit.owner_pubkey,
+            )
+            .to_vec();
+    }
+
+    // Correctness test: Make a fresh constraint system and run the circuit.
+    #[test]
+    fn correctness() {
+        let mut rng = ark_std::test_rng();
+        let circuit = setup(&mut rng);
+
+        // Run the circuit on a fresh constraint system
+        let cs = ConstraintSystem::new_ref();
+        circuit.generate_constraints(cs.clone()).unwrap();
+
+        // The constraints should be satisfied. That is, the valid circuit should verify.
+        assert!(
+            cs.is_satisfied().unwrap(),
+            "circuit correctness check failed; a valid circuit did not succeed"
+        );
+    }
+
+    // Price soundness test: The new leaf commits to a different price than the old card
+    #[test]
+    fn price_soundness() {
+        let mut rng = ark_std::test_rng();
+        let mut bad_price_circuit = setup(&mut rng);
+        let changed_card = Card {
+            purchase_price: F::rand(&mut rng),
+            serial_num: bad_price_circuit.card_serial_num,
+        };
+        recommit(&mut bad_price_circuit, &changed_card);
+
+        assert!(
+            !is_satisfied(bad_price_circuit),
+            "circuit should not be satisfied if the price changes during transfer"
+        );
+    }
+
+    // Serial soundness test: The new leaf commits to a different serial than the old card
+    #[test]
+    fn serial_soundness() {
+        let mut rng = ark_std::test_rng();
+        let mut bad_serial_circuit = setup(&mut rng);
+        let changed_card = Card {
+            purchase_price: bad_serial_circuit.card_purchase_price,
+            serial_num: F::rand(&mut rng),
+        };
+        recommit(&mut bad_serial_circuit, &changed_card);
+
+        assert!(
+            !is_satisfied(bad_serial_circuit),
+            "circuit should not be satisfied if the serial changes during transfer"
+        );
+    }
+
+    // Owner soundness test: The new leaf commits to a different owner than the public one
+    #[test]
+    fn owner_soundness() {
+        let mut rng = ark_std::test_rng();
+        let mut bad_owner_circuit = setup(&mut rng);
+        bad_owner_circuit.owner_pubkey = F::rand(&mut rng);
+
+        assert!(
+            !is_satisfied(bad_owner_circuit),
+            "circuit should not be satisfied if the new leaf is for a different owner"
+        );
+    }
+
+    // Tree soundness test: Modify the circuit to have a random Merkle tree root
+    #[test]
+    fn tree_soundness() {
+        let mut rng = ark_std::test_rng();
+        let mut bad_root_circuit = setup(&mut rng);
+        bad_root_circuit.root = MerkleRoot::rand(&mut rng);
+
+        assert!(
+            !is_satisfied(bad_root_circuit),
+            "circuit should not be satisfied after changing the Merkle root"
+        );
+    }
+
+    // Sets up a legitimate reissue of card 7 as a fresh card of the same value
+    fn setup_reissue(mut rng: impl RngCore) -> ReissueCircuit {
+        let (leaf_crh_params, two_to_one_crh_params) = sample_crh_params(&mut rng);
+
+        let tree = gen_test_tree(&leaf_crh_params, &two_to_one_crh_params);
+        let our_idx = 7;
+        let (card, card_com_rand) = get_test_card(our_idx);
+
+        // Make a fresh card with the same price
+        let new_card = Card {
+            purchase_price: card.purchase_price,
+            serial_num: F::rand(&mut rng),
+        };
+        let new_card_com_rand = F::rand(&mut rng);
+        let new_leaf = new_card.commit(&leaf_crh_params, &new_card_com_rand);
+
+        ReissueCircuit {
+            // Public inputs
+            root: tree.root(),
+            card_serial_num: card.serial_num,
+            new_leaf: new_leaf.to_vec(),
+
+            // Private inputs
+            leaf: tree.leaves()[our_idx].to_vec(),
+            card_purchase_price: card.purchase_price,
+            card_com_rand,
+            auth_path: tree.generate_proof(our_idx).unwrap(),
+            new_card_purchase_price: new_card.purchase_price,
+            new_card_serial_num: new_card.serial_num,
+            new_card_com_rand,
+
+            // Constants for hashing
+            leaf_crh_params,
+            two_to_one_crh_params,
+        }
+    }
+
+    // Reissue correctness test: A value-preserving reissue should succeed
+    #[test]
+    fn reissue_correctness() {
+        let mut rng = ark_std::test_rng();
+        let circuit = setup_reissue(&mut rng);
+
+        // Run the circuit on a fresh constraint system
+        let cs = ConstraintSystem::new_ref();
+        circuit.generate_constraints(cs.clone()).unwrap();
+
+        assert!(
+            cs.is_satisfied().unwrap(),
+            "circuit correctness check failed; a valid reissue did not succeed"
+        );
+        assert_eq!(
+            cs.num_instance_variables() - 1,
+            ReissueCircuit::NUM_PUBLIC_INPUTS
+        );
+    }
+
+    // Reissue value soundness test: The new card is worth something else, and the new leaf
+    // honestly commits to it
+    #[test]
+    fn reissue_value_soundness() {
+        let mut rng = ark_std::test_rng();
+        let mut bad_value_circuit = setup_reissue(&mut rng);
+        let changed_card = Card {
+            purchase_price: bad_value_circuit.card_purchase_price + F::from(1u64),
+            serial_num: bad_value_circuit.new_card_serial_num,
+        };
+        bad_value_circuit.new_card_purchase_price = changed_card.purchase_price;
+        bad_value_circuit.new_leaf = changed_card
+            .commit(
+                &bad_value_circuit.leaf_crh_params,
+                &bad_value_circuit.new_card_com_rand,
+            )
+            .to_vec();
+
+        assert!(
+            !is_satisfied(bad_value_circuit),
+            "circuit should not be satisfied if the value changes during a reissue"
+        );
+    }
+}
@@ -0,0 +1,351 @@
+use crate::{
+    constraints_sig::public_key,
+    hash::{
+        LeafHash, LeafHashGadget, LeafHashParams, LeafHashParamsVar, OwnedLeafHash,
+        OwnedLeafHashParams, OwnedLeafHashParamsVar, PedersenCommitmentGadget, TreeHasher,
+        TwoToOneHash, TwoToOneHashParamsVar,
+    },
+    merkle::{Leaf, MerkleRoot, RootVar, SimplePath, SimplePathVar},
+    F, FV,
+};
+
+use ark_crypto_primitives::crh::{CRHScheme, CRHSchemeGadget, TwoToOneCRHScheme};
+use ark_ec::Group;
+use ark_ed_on_bls12_381::{
+    constraints::EdwardsVar as JubjubVar, EdwardsAffine as JubjubAffine,
+    EdwardsProjective as Jubjub, Fr as JubjubScalar,
+};
+use ark_ff::{BigInteger, One, PrimeField};
+use ark_r1cs_std::{
+    alloc::AllocVar, boolean::Boolean, eq::EqGadget, groups::CurveVar, uint8::UInt8, ToBytesGadget,
+};
+use ark_relations::{
+    ns,
+    r1cs::{ConstraintSynthesizer, ConstraintSystemRef, Namespace, SynthesisError},
+};
+use ark_serialize::CanonicalSerialize;
+
+/// A baseball card that belongs to a Jubjub public key. Unlike `Card`, this has no serial number.
+/// Instead, the serial number is derived from the owner's secret key and the card commitment (see
+/// `CardV2::serial_num`), so nobody can pick it themselves.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CardV2 {
+    pub purchase_price: F,
+    /// The public key of the card's owner, i.e., `sk * G`
+    pub owner_pk: JubjubAffine,
+}
+
+impl CardV2 {
+    /// Makes a card with the given price that belongs to the holder of `sk`
+    pub fn new(purchase_price: F, sk: &JubjubScalar) -> Self {
+        CardV2 {
+            purchase_price,
+            owner_pk: public_key(sk),
+        }
+    }
+
+    /// Commits to this card using `com_rand` as the commitment randomness. Concretely, this
+    /// computes `OwnedLeafHash(com_rand || purchase_price || owner_pk.x || owner_pk.y)`
+    pub fn commit(&self, owned_leaf_crh_params: &OwnedLeafHashParams, com_rand: &F) -> Leaf {
+        let mut hasher = TreeHasher::new_owned(owned_leaf_crh_params);
+        hasher.update_serialized(com_rand);
+        hasher.update_serialized(&self.purchase_price);
+        hasher.update_serialized(&self.owner_pk.x);
+        hasher.update_serialized(&self.owner_pk.y);
+        hasher.finalize().expect("a card fits in the leaf hash")
+    }
+
+    /// Returns the serial number of the card with commitment `card_com`, owned by `sk`. Concretely,
+    /// this is the x-coordinate of `Hash(sk || card_com)`. Only the owner can compute it, and
+    /// there is exactly one per card.
+    pub fn serial_num(leaf_crh_params: &LeafHashParams, sk: &JubjubScalar, card_com: &Leaf) -> F {
+        let mut buf = Vec::new();
+        sk.serialize_uncompressed(&mut buf).unwrap();
+        buf.extend_from_slice(card_com);
+        LeafHash::evaluate(leaf_crh_params, buf.as_slice())
+            .unwrap()
+            .x
+    }
+}
+
+/// Witnesses the little-endian bits of a Jubjub scalar, and enforces that they're less than the
+/// scalar field modulus. See `JubjubSignatureCircuit` for why we witness them one at a time.
+///
+/// The range check matters. Without it, the bits of `sk + r` for the Jubjub group order `r` are a
+/// different witness for the same public key whenever they fit in `MODULUS_BIT_SIZE` bits, and
+/// anything else hashed from the bits, like a serial number, would change.
+fn witness_scalar_bits(
+    cs: impl Into<Namespace<F>>,
+    scalar: <JubjubScalar as PrimeField>::BigInt,
+) -> Result<Vec<Boolean<F>>, SynthesisError> {
+    let cs = cs.into().cs();
+    let bits = (0..JubjubScalar::MODULUS_BIT_SIZE as usize)
+        .map(|i| Boolean::new_witness(ns!(cs, "bit"), || Ok(scalar.get_bit(i))))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // The largest valid scalar is -1
+    Boolean::enforce_smaller_or_equal_than_le(&bits, (-JubjubScalar::one()).into_bigint())?;
+
+    Ok(bits)
+}
+
+/// The same as `PossessionCircuit`, except the card is a `CardV2`. The prover shows that they own
+/// a card in the tree, and that the public serial number was derived from their secret key and the
+/// card's commitment. So a card has exactly one valid serial number, and nobody but its owner can
+/// show it.
+#[derive(Clone)]
+pub struct PossessionCircuitV2 {
+    // These are constants that will be embedded into the circuit. They describe how the hash
+    // function works. Don't worry about this.
+    pub leaf_crh_params: <LeafHash as CRHScheme>::Parameters,
+    pub two_to_one_crh_params: <TwoToOneHash as TwoToOneCRHScheme>::Parameters,
+    /// The hash for card commitments. A `CardV2` commits to its owner's public key, which makes
+    /// it too long for the leaf hash.
+    pub owned_leaf_crh_params: <OwnedLeafHash as CRHScheme>::Parameters,
+
+    // Public inputs to the circuit
+    /// The root of the merkle tree we're proving membership in
+    pub root: MerkleRoot,
+    /// The serial number of this card. This is `CardV2::serial_num` of the card commitment.
+    pub card_serial_num: F,
+
+    // Private inputs (aka "witnesses") for the circuit
+    /// The leaf in the tree. In our case, the leaf is also a commitment to the card we're showing
+    pub leaf: Leaf,
+    /// The amount the card was purchased for
+    pub card_purchase_price: F,
+    /// The private randomness used to commit to the card
+    pub card_com_rand: F,
+    /// The secret key of the card's owner
+    pub owner_sk: JubjubScalar,
+    /// The merkle authentication path
+    pub auth_path: SimplePath,
+}
+
+impl PossessionCircuitV2 {
+    /// The number of public inputs: the Merkle root (2 field elements) and the serial number
+    pub const NUM_PUBLIC_INPUTS: usize = 3;
+}
+
+impl ConstraintSynthesizer<F> for PossessionCircuitV2 {
+    fn generate_constraints(self, cs: ConstraintSystemRef<F>) -> Result<(), SynthesisError> {
+        // First, allocate the public parameters as constants
+        let leaf_crh_params = LeafHashParamsVar::new_constant(cs.clone(), &self.leaf_crh_params)?;
+        let two_to_one_crh_params =
+            TwoToOneHashParamsVar::new_constant(cs.clone(), &self.two_to_one_crh_params)?;
+        let owned_leaf_crh_params =
+            OwnedLeafHashParamsVar::new_constant(cs.clone(), &self.owned_leaf_crh_params)?;
+        let generator = JubjubVar::new_constant(cs.clone(), Jubjub::generator())?;
+
+        //
+        // Next, allocate the public inputs
+        //
+
+        // Merkle root
+        let claimed_root_var =
+            <RootVar as AllocVar<MerkleRoot, _>>::new_input(ns!(cs, "root"), || Ok(&self.root))?;
+        // Card's serial number. This is public so you can only show possession once
+        let card_serial_num = FV::new_input(ns!(cs, "card serial"), || Ok(&self.card_serial_num))?;
+
+        //
+        // Now we witness our private inputs
+        //
+
+        // Card commitment. This is also the leaf in our tree.
+        let card_com_var = UInt8::new_witness_vec(ns!(cs, "card com"), &self.leaf)?;
+        // The amount the card was purchase for
+        let card_purchase_price =
+            FV::new_witness(ns!(cs, "purchase price"), || Ok(&self.card_purchase_price))?;
+        // Commitment randomness
+        let com_rand_var = FV::new_witness(ns!(cs, "card com_rand"), || Ok(&self.card_com_rand))?;
+        // The owner's secret key, as little-endian bits
+        let sk_bits = witness_scalar_bits(ns!(cs, "sk"), self.owner_sk.into_bigint())?;
+        // Merkle authentication path
+        let auth_path_var =
+            SimplePathVar::new_witness(ns!(cs, "merkle path"), || Ok(&self.auth_path))?;
+
+        //
+        // Ok everything has been inputted. Now we do the logic of the circuit.
+        //
+
+        // CHECK #1: Public key.
+        // The owner's public key is derived from the secret key
+        let owner_pk = generator.scalar_mul_le(sk_bits.iter())?;
+
+        // CHECK #2: Card opening.
+        // The card commitment is to the price and the owner's public key
+        let card_bytes = [
+            card_purchase_price.to_bytes()?,
+            owner_pk.x.to_bytes()?,
+            owner_pk.y.to_bytes()?,
+        ]
+        .concat();
+        let computed_card_com_var = PedersenCommitmentGadget::commit_owned(
+            &owned_leaf_crh_params,
+            &card_bytes,
+            &com_rand_var,
+        )?;
+        computed_card_com_var.enforce_equal(&card_com_var)?;
+
+        // CHECK #3: Membership test
+        let computed_root_var = auth_path_var.calculate_root(
+            &leaf_crh_params,
+            &two_to_one_crh_params,
+            &card_com_var,
+        )?;
+        computed_root_var.enforce_equal(&claimed_root_var)?;
+
+        // CHECK #4: Serial number.
+        // The serial number is Hash(sk || card_com). The secret key serializes to 32 bytes, so we
+        // pad its bits out to a multiple of 8 before packing them into bytes.
+        let mut padded_sk_bits = sk_bits;
+        padded_sk_bits.resize(256, Boolean::FALSE);
+        let sk_bytes = padded_sk_bits
+            .chunks(8)
+            .map(UInt8::from_bits_le)
+            .collect::<Vec<_>>();
+        let serial_hash =
+            LeafHashGadget::evaluate(&leaf_crh_params, &[sk_bytes, card_com_var].concat())?;
+        serial_hash.x.enforce_equal(&card_serial_num)?;
+
+        // All done with the checks
+        Ok(())
+    }
+}
+
+//
+// TESTS
+//
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        merkle::SimpleMerkleTree,
+        util::{gen_test_tree, is_satisfied, sample_crh_params},
+    };
+
+    use ark_ff::UniformRand;
+    use ark_relations::r1cs::ConstraintSystem;
+    use rand::RngCore;
+
+    // Sets up a legitimate circuit. Our card replaces the one at index 7 of the test tree.
+    fn setup(mut rng: impl RngCore) -> PossessionCircuitV2 {
+        let (leaf_crh_params, two_to_one_crh_params) = sample_crh_params(&mut rng);
+        let owned_leaf_crh_params = <OwnedLeafHash as CRHScheme>::setup(&mut rng).unwrap();
+
+        // Make a card that we own, and put its commitment in the tree
+        let owner_sk = JubjubScalar::rand(&mut rng);
+        let card = CardV2::new(F::rand(&mut rng), &owner_sk);
+        let card_com_rand = F::rand(&mut rng);
+        let leaf = card.commit(&owned_leaf_crh_params, &card_com_rand);
+
+        let our_idx = 7;
+        let mut leaves = gen_test_tree(&leaf_crh_params, &two_to_one_crh_params)
+            .leaves()
+            .to_vec();
+        leaves[our_idx] = leaf;
+        let tree = SimpleMerkleTree::new(&leaf_crh_params, &two_to_one_crh_params, leaves).unwrap();
+
+        PossessionCircuitV2 {
+            // Public inputs
+            root: tree.root(),
+            card_serial_num: CardV2::serial_num(&leaf_crh_params, &owner_sk, &leaf),
+
+            // Private inputs
+            leaf,
+            card_purchase_price: card.purchase_price,
+            card_com_rand,
+            owner_sk,
+            auth_path: tree.generate_proof(our_idx).unwrap(),
+
+            // Constants for hashing
+            leaf_crh_params,
+            two_to_one_crh_params,
+            owned_leaf_crh_params,
+        }
+    }
+
+    // Correctness test: Make a fresh constraint system and run the circuit.
+    #[test]
+    fn correctness() {
+        let mut rng = ark_std::test_rng();
+        let circuit = setup(&mut rng);
+
+        // Run the circuit on a fresh constraint system
+        let cs = ConstraintSystem::new_ref();
+        circuit.generate_constraints(cs.clone()).unwrap();
+
+        assert!(
+            cs.is_satisfied().unwrap(),
+            "circuit correctness check failed; a valid circuit did not succeed"
+        );
+        assert_eq!(
+            cs.num_instance_variables() - 1,
+            PossessionCircuitV2::NUM_PUBLIC_INPUTS
+        );
+    }
+
+    // Serial soundness test: The prover can't pick their own serial number
+    #[test]
+    fn serial_soundness() {
+        let mut rng = ark_std::test_rng();
+        let mut bad_serial_circuit = setup(&mut rng);
+        bad_serial_circuit.card_serial_num = F::rand(&mut rng);
+
+        assert!(
+            !is_satisfied(bad_serial_circuit),
+            "circuit should not be satisfied when the serial number isn't derived from the key"
+        );
+    }
+
+    // Key soundness test: Someone who doesn't own the card can't show it, even with a serial
+    // number derived from their own key
+    #[test]
+    fn key_soundness() {
+        let mut rng = ark_std::test_rng();
+        let mut bad_key_circuit = setup(&mut rng);
+        bad_key_circuit.owner_sk = JubjubScalar::rand(&mut rng);
+        bad_key_circuit.card_serial_num = CardV2::serial_num(
+            &bad_key_circuit.leaf_crh_params,
+            &bad_key_circuit.owner_sk,
+            &bad_key_circuit.leaf,
+        );
+
+        assert!(
+            !is_satisfied(bad_key_circuit),
+            "circuit should not be satisfied when the secret key doesn't own the card"
+        );
+    }
+
+    // Key range soundness test: The bits of `sk + r`, where `r` is the Jubjub group order, give
+    // the same public key but hash to a different serial. They must be rejected.
+    #[test]
+    fn key_range_soundness() {
+        use ark_ec::CurveGroup;
+
+        // Pick a small key, so that sk + r still fits in MODULUS_BIT_SIZE bits
+        let sk = JubjubScalar::from(5u64);
+        let mut sk_plus_r = sk.into_bigint();
+        assert!(!sk_plus_r.add_with_carry(&JubjubScalar::MODULUS));
+        let num_bits = JubjubScalar::MODULUS_BIT_SIZE as usize;
+        assert!((num_bits..256).all(|i| !sk_plus_r.get_bit(i)));
+
+        // Both give the same public key
+        let pk = Jubjub::generator().mul_bigint(sk_plus_r);
+        assert_eq!(pk.into_affine(), public_key(&sk));
+
+        // The honest bits are fine
+        let cs = ConstraintSystem::new_ref();
+        witness_scalar_bits(ns!(cs, "sk"), sk.into_bigint()).unwrap();
+        assert!(cs.is_satisfied().unwrap());
+
+        // The bits of sk + r aren't
+        let cs = ConstraintSystem::new_ref();
+        witness_scalar_bits(ns!(cs, "sk"), sk_plus_r).unwrap();
+        assert!(
+            !cs.is_satisfied().unwrap(),
+            "secret key bits should not be allowed to exceed the scalar field modulus"
+        );
+    }
+}
@@ -0,0 +1,265 @@
+use crate::{
+    card::{Card, CardVar},
+    hash::{LeafHash, LeafHashParams, LeafHashParamsVar, TwoToOneHash, TwoToOneHashParamsVar},
+    merkle::{Leaf, MerkleRoot, RootVar, SimplePath, SimplePathVar},
+    F, FV,
+};
+
+use ark_crypto_primitives::crh::{CRHScheme, TwoToOneCRHScheme};
+use ark_r1cs_std::{alloc::AllocVar, boolean::Boolean, eq::EqGadget, uint8::UInt8};
+use ark_relations::{
+    ns,
+    r1cs::{ConstraintSynthesizer, ConstraintSystemRef, SynthesisError},
+};
+
+/// Commits to a ballot. A ballot is committed exactly like a card, with the choice in place of the
+/// purchase price and the voter's credential nullifier in place of the serial number.
+pub fn commit_ballot(
+    leaf_crh_params: &LeafHashParams,
+    credential_nullifier: &F,
+    choice: bool,
+    ballot_com_rand: &F,
+) -> Leaf {
+    let ballot = Card {
+        purchase_price: F::from(choice),
+        serial_num: *credential_nullifier,
+    };
+    ballot.commit(leaf_crh_params, ballot_com_rand)
+}
+
+/// Our ZK circuit for casting a yes/no vote. The registry of voters is a Merkle tree of credential
+/// commitments, where a credential has the same shape as a card. The voter proves that they hold a
+/// credential in the registry and that their ballot commitment contains a 0 or 1 vote, without
+/// revealing which credential they hold or how they voted. The credential's nullifier is revealed,
+/// so nobody can vote twice.
+#[derive(Clone)]
+pub struct VotingCircuit {
+    // These are constants that will be embedded into the circuit. They describe how the hash
+    // function works. Don't worry about this.
+    pub leaf_crh_params: <LeafHash as CRHScheme>::Parameters,
+    pub two_to_one_crh_params: <TwoToOneHash as TwoToOneCRHScheme>::Parameters,
+
+    // Public inputs to the circuit
+    /// The root of the voter registry
+    pub root: MerkleRoot,
+    /// The nullifier of the voter's credential. A repeated nullifier means someone voted twice.
+    pub credential_nullifier: F,
+    /// The commitment to the ballot, i.e., to `(credential_nullifier, choice)`
+    pub ballot_com: Vec<u8>,
+
+    // Private inputs (aka "witnesses") for the circuit
+    /// The voter's credential commitment, i.e., their leaf in the registry
+    pub leaf: Vec<u8>,
+    /// The secret value of the credential
+    pub credential_secret: F,
+    /// The randomness used to commit to the credential
+    pub credential_com_rand: F,
+    /// The merkle authentication path of the credential
+    pub auth_path: SimplePath,
+    /// The vote
+    pub choice: bool,
+    /// The randomness used to commit to the ballot
+    pub ballot_com_rand: F,
+}
+
+impl ConstraintSynthesizer<F> for VotingCircuit {
+    fn generate_constraints(self, cs: ConstraintSystemRef<F>) -> Result<(), SynthesisError> {
+        // First, allocate the public parameters as constants
+        let leaf_crh_params = LeafHashParamsVar::new_constant(cs.clone(), &self.leaf_crh_params)?;
+        let two_to_one_crh_params =
+            TwoToOneHashParamsVar::new_constant(cs.clone(), &self.two_to_one_crh_params)?;
+
+        //
+        // Next, allocate the public inputs
+        //
+
+        // Merkle root
+        let claimed_root_var =
+            <RootVar as AllocVar<MerkleRoot, _>>::new_input(ns!(cs, "root"), || Ok(&self.root))?;
+        // Credential nullifier. This is public so you can only vote once
+        let credential_nullifier = FV::new_input(ns!(cs, "credential nullifier"), || {
+            Ok(&self.credential_nullifier)
+        })?;
+        // Ballot commitment
+        let claimed_ballot_com_var = UInt8::new_input_vec(ns!(cs, "ballot com"), &self.ballot_com)?;
+
+        //
+        // Now we witness our private inputs
+        //
+
+        // Credential commitment. This is the leaf in our tree.
+        let claimed_credential_com_var =
+            UInt8::new_witness_vec(ns!(cs, "credential com"), &self.leaf)?;
+        // Credential opening
+        let credential_secret =
+            FV::new_witness(ns!(cs, "credential secret"), || Ok(&self.credential_secret))?;
+        let credential_com_rand = FV::new_witness(ns!(cs, "credential com_rand"), || {
+            Ok(&self.credential_com_rand)
+        })?;
+        // Merkle authentication path
+        let auth_path_var =
+            SimplePathVar::new_witness(ns!(cs, "merkle path"), || Ok(&self.auth_path))?;
+        // The vote. Allocating it as a Boolean enforces that it's 0 or 1.
+        let choice = Boolean::new_witness(ns!(cs, "choice"), || Ok(self.choice))?;
+        // Ballot commitment randomness
+        let ballot_com_rand =
+            FV::new_witness(ns!(cs, "ballot com_rand"), || Ok(&self.ballot_com_rand))?;
+
+        //
+        // Ok everything has been inputted. Now we do the logic of the circuit.
+        //
+
+        let credential_var: CardVar = CardVar {
+            amount: credential_secret,
+            serial_num: credential_nullifier.clone(),
+        };
+        let ballot_var: CardVar = CardVar {
+            amount: FV::from(choice),
+            serial_num: credential_nullifier,
+        };
+
+        // CHECK #1: Credential opening
+        credential_var
+            .commit(&leaf_crh_params, &credential_com_rand)?
+            .enforce_equal(&claimed_credential_com_var)?;
+
+        // CHECK #2: Credential membership test
+        auth_path_var
+            .calculate_root(
+                &leaf_crh_params,
+                &two_to_one_crh_params,
+                &claimed_credential_com_var,
+            )?
+            .enforce_equal(&claimed_root_var)?;
+
+        // CHECK #3: Ballot opening. Since the ballot uses the credential's nullifier, it's bound to
+        // the credential. The choice was already constrained to be 0 or 1 when it was allocated.
+        ballot_var
+            .commit(&leaf_crh_params, &ballot_com_rand)?
+            .enforce_equal(&claimed_ballot_com_var)?;
+
+        // All done with the checks
+        Ok(())
+    }
+}
+
+//
+// TESTS
+//
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::{gen_test_tree, get_test_card, is_satisfied, sample_crh_params};
+
+    use ark_ff::UniformRand;
+    use ark_relations::r1cs::ConstraintSystem;
+    use rand::RngCore;
+
+    // Sets up a legitimate voting circuit. The voter holds credential 7 in the test tree.
+    fn setup(mut rng: impl RngCore, choice: bool) -> VotingCircuit {
+        let (leaf_crh_params, two_to_one_crh_params) = sample_crh_params(&mut rng);
+
+        // Our test tree doubles as the voter registry
+        let tree = gen_test_tree(&leaf_crh_params, &two_to_one_crh_params);
+        let our_idx = 7;
+        let (credential, credential_com_rand) = get_test_card(our_idx);
+
+        // Fill out the ballot
+        let ballot_com_rand = F::rand(&mut rng);
+        let ballot_com = commit_ballot(
+            &leaf_crh_params,
+            &credential.serial_num,
+            choice,
+            &ballot_com_rand,
+        );
+
+        VotingCircuit {
+            // Public inputs
+            root: tree.root(),
+            credential_nullifier: credential.serial_num,
+            ballot_com: ballot_com.to_vec(),
+
+            // Private inputs
+            leaf: tree.leaves()[our_idx].to_vec(),
+            credential_secret: credential.purchase_price,
+            credential_com_rand,
+            auth_path: tree.generate_proof(our_idx).unwrap(),
+            choice,
+            ballot_com_rand,
+
+            // Constants for hashing
+            leaf_crh_params,
+            two_to_one_crh_params,
+        }
+    }
+
+    // Correctness test: Both votes should be accepted
+    #[test]
+    fn correctness() {
+        let mut rng = ark_std::test_rng();
+
+        for choice in [false, true] {
+            let circuit = setup(&mut rng, choice);
+
+            assert!(
+                is_satisfied(circuit),
+                "circuit correctness check failed for choice {choice}"
+            );
+        }
+    }
+
+    // Ballot soundness test: Flip the vote without changing the ballot commitment
+    #[test]
+    fn ballot_soundness() {
+        let mut rng = ark_std::test_rng();
+        let mut bad_ballot_circuit = setup(&mut rng, false);
+        bad_ballot_circuit.choice = true;
+
+        assert!(
+            !is_satisfied(bad_ballot_circuit),
+            "circuit should not be satisfied after changing the vote"
+        );
+    }
+
+    // Nullifier soundness test: Reveal someone else's nullifier, with a ballot that matches it
+    #[test]
+    fn nullifier_soundness() {
+        let mut rng = ark_std::test_rng();
+        let mut bad_nullifier_circuit = setup(&mut rng, true);
+
+        let (other_credential, _) = get_test_card(3);
+        bad_nullifier_circuit.credential_nullifier = other_credential.serial_num;
+        bad_nullifier_circuit.ballot_com = commit_ballot(
+            &bad_nullifier_circuit.leaf_crh_params,
+            &other_credential.serial_num,
+            true,
+            &bad_nullifier_circuit.ballot_com_rand,
+        )
+        .to_vec();
+
+        // Run the circuit on a fresh constraint system
+        let cs = ConstraintSystem::new_ref();
+        bad_nullifier_circuit
+            .generate_constraints(cs.clone())
+            .unwrap();
+
+        assert!(
+            !cs.is_satisfied().unwrap(),
+            "circuit should not be satisfied with a nullifier from a different credential"
+        );
+    }
+
+    // Tree soundness test: Modify the circuit to have a random Merkle tree root
+    #[test]
+    fn tree_soundness() {
+        let mut rng = ark_std::test_rng();
+        let mut bad_root_circuit = setup(&mut rng, true);
+        bad_root_circuit.root = MerkleRoot::rand(&mut rng);
+
+        assert!(
+            !is_satisfied(bad_root_circuit),
+            "circuit should not be satisfied after changing the Merkle root"
+        );
+    }
+}
@@ -0,0 +1,178 @@
+use crate::{card::Card, F};
+
+use ark_ec::{CurveGroup, Group};
+use ark_ed_on_bls12_381::{
+    EdwardsAffine as JubjubAffine, EdwardsProjective as Jubjub, Fr as JubjubScalar,
+};
+use ark_ff::{PrimeField, UniformRand};
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+use core::fmt;
+use rand::RngCore;
+
+// Domain separator for deriving keys from the shared secret
+const KDF_CONTEXT: &str = "fantastic-snarks 2023 card encryption";
+
+/// A card encrypted to someone's Jubjub public key. This is hashed ElGamal: the sender picks an
+/// ephemeral key `r`, and both sides can compute the shared secret `r * pk = sk * R`, which is
+/// hashed into one-time pads for the card fields and a MAC key.
+#[derive(Clone, CanonicalSerialize, CanonicalDeserialize)]
+pub struct EncryptedCard {
+    /// The ephemeral public key `R = r * G`
+    pub ephemeral_pk: JubjubAffine,
+    /// The purchase price, masked with a pad derived from the shared secret
+    pub price_ct: F,
+    /// The serial number, masked with a pad derived from the shared secret
+    pub serial_ct: F,
+    /// A MAC over all of the above, so decrypting with the wrong key is caught
+    pub tag: [u8; 32],
+}
+
+/// An error that occurs while decrypting
+#[derive(Debug, PartialEq, Eq)]
+pub enum DecryptionError {
+    /// The MAC didn't check out. Either this card wasn't encrypted to our key, or the ciphertext
+    /// was tampered with.
+    BadTag,
+}
+
+impl fmt::Display for DecryptionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecryptionError::BadTag => write!(f, "wrong key or corrupted ciphertext"),
+        }
+    }
+}
+
+impl std::error::Error for DecryptionError {}
+
+// The keys derived from a shared secret
+struct DerivedKeys {
+    price_pad: F,
+    serial_pad: F,
+    mac_key: [u8; 32],
+}
+
+// Hashes the shared secret into the pads and the MAC key. The pads are reduced from 64 bytes
+// each, so they're statistically close to uniform.
+fn derive_keys(shared_secret: &JubjubAffine) -> DerivedKeys {
+    let mut buf = Vec::new();
+    shared_secret.serialize_compressed(&mut buf).unwrap();
+
+    let mut hasher = blake3::Hasher::new_derive_key(KDF_CONTEXT);
+    hasher.update(&buf);
+    let mut okm = [0u8; 64 + 64 + 32];
+    hasher.finalize_xof().fill(&mut okm);
+
+    DerivedKeys {
+        price_pad: F::from_le_bytes_mod_order(&okm[..64]),
+        serial_pad: F::from_le_bytes_mod_order(&okm[64..128]),
+        mac_key: okm[128..].try_into().unwrap(),
+    }
+}
+
+// Computes the MAC of everything in the ciphertext but the tag
+fn compute_tag(mac_key: &[u8; 32], ephemeral_pk: &JubjubAffine, cts: &[F; 2]) -> blake3::Hash {
+    let mut buf = Vec::new();
+    ephemeral_pk.serialize_compressed(&mut buf).unwrap();
+    for ct in cts {
+        ct.serialize_compressed(&mut buf).unwrap();
+    }
+    blake3::keyed_hash(mac_key, &buf)
+}
+
+/// Encrypts the given card to the holder of the secret key of `pk`
+pub fn encrypt_card(pk: &Jubjub, card: &Card, rng: &mut impl RngCore) -> EncryptedCard {
+    let r = JubjubScalar::rand(rng);
+    let ephemeral_pk = (Jubjub::generator() * r).into_affine();
+    let keys = derive_keys(&(*pk * r).into_affine());
+
+    let price_ct = card.purchase_price + keys.price_pad;
+    let serial_ct = card.serial_num + keys.serial_pad;
+    let tag = compute_tag(&keys.mac_key, &ephemeral_pk, &[price_ct, serial_ct]);
+
+    EncryptedCard {
+        ephemeral_pk,
+        price_ct,
+        serial_ct,
+        tag: tag.into(),
+    }
+}
+
+/// Decrypts a card that was encrypted to the public key `sk * G`
+pub fn decrypt_card(sk: &JubjubScalar, enc: &EncryptedCard) -> Result<Card, DecryptionError> {
+    let keys = derive_keys(&(enc.ephemeral_pk * sk).into_affine());
+
+    // blake3::Hash comparisons are constant-time
+    let expected_tag = compute_tag(
+        &keys.mac_key,
+        &enc.ephemeral_pk,
+        &[enc.price_ct, enc.serial_ct],
+    );
+    if expected_tag != blake3::Hash::from(enc.tag) {
+        return Err(DecryptionError::BadTag);
+    }
+
+    Ok(Card {
+        purchase_price: enc.price_ct - keys.price_pad,
+        serial_num: enc.serial_ct - keys.serial_pad,
+    })
+}
+
+//
+// TESTS
+//
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // Makes a random keypair
+    fn keygen(rng: &mut impl RngCore) -> (JubjubScalar, Jubjub) {
+        let sk = JubjubScalar::rand(rng);
+        (sk, Jubjub::generator() * sk)
+    }
+
+    // Encrypting then decrypting with the right key gives back the card, even after a round trip
+    // through bytes
+    #[test]
+    fn encryption_round_trip() {
+        let mut rng = ark_std::test_rng();
+        let (sk, pk) = keygen(&mut rng);
+        let card = Card::rand(&mut rng);
+
+        let enc = encrypt_card(&pk, &card, &mut rng);
+        assert!(decrypt_card(&sk, &enc).unwrap() == card);
+
+        let mut buf = Vec::new();
+        enc.serialize_compressed(&mut buf).unwrap();
+        let decoded = EncryptedCard::deserialize_compressed(buf.as_slice()).unwrap();
+        assert!(decrypt_card(&sk, &decoded).unwrap() == card);
+
+        // Encryption is randomized
+        let enc2 = encrypt_card(&pk, &card, &mut rng);
+        assert_ne!(enc.price_ct, enc2.price_ct);
+    }
+
+    // Decrypting with the wrong key, or decrypting a modified ciphertext, fails
+    #[test]
+    fn decryption_failures() {
+        let mut rng = ark_std::test_rng();
+        let (_, pk) = keygen(&mut rng);
+        let (wrong_sk, _) = keygen(&mut rng);
+        let card = Card::rand(&mut rng);
+        let enc = encrypt_card(&pk, &card, &mut rng);
+
+        assert_eq!(
+            decrypt_card(&wrong_sk, &enc).err(),
+            Some(DecryptionError::BadTag)
+        );
+
+        let (sk, pk) = keygen(&mut rng);
+        let mut tampered = encrypt_card(&pk, &card, &mut rng);
+        tampered.price_ct += F::from(1u64);
+        assert_eq!(
+            decrypt_card(&sk, &tampered).err(),
+            Some(DecryptionError::BadTag)
+        );
+    }
+}
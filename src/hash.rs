@@ -1,10 +1,23 @@
-use crate::F;
+use crate::{
+    merkle::{Leaf, MerkleConfig, MerkleRoot},
+    Bls12Config, CryptoConfig, LeafHashGadgetOf, LeafHashParamsOf, LeafHashParamsVarOf, F, FV,
+};
 
-use ark_crypto_primitives::crh::{
-    constraints::{CRHSchemeGadget, TwoToOneCRHSchemeGadget},
-    pedersen, CRHScheme, TwoToOneCRHScheme,
+use ark_crypto_primitives::{
+    crh::{
+        constraints::{CRHSchemeGadget, TwoToOneCRHSchemeGadget},
+        pedersen, poseidon, CRHScheme, TwoToOneCRHScheme,
+    },
+    merkle_tree::{Config, DigestConverter},
+    sponge::poseidon::PoseidonConfig,
 };
 use ark_ed_on_bls12_381::{constraints::EdwardsVar as JubjubVar, EdwardsProjective as Jubjub};
+use ark_r1cs_std::{fields::fp::FpVar, uint8::UInt8, ToBytesGadget};
+use ark_relations::r1cs::SynthesisError;
+use ark_serialize::CanonicalSerialize;
+use subtle::ConstantTimeEq;
+
+use core::{fmt, marker::PhantomData};
 
 pub type LeafHash = pedersen::CRH<Jubjub, LeafWindow>;
 pub type TwoToOneHash = pedersen::TwoToOneCRH<Jubjub, TwoToOneWindow>;
@@ -20,6 +33,19 @@ impl pedersen::Window for LeafWindow {
     const NUM_WINDOWS: usize = 128;
 }
 
+// A card that belongs to someone commits to their public key as well, so its commitment needs to
+// handle inputs of 256*4-bits, or 128 bytes. Only those commitments use this. Every other hash
+// stays on the smaller leaf hash, since each extra window costs constraints.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct OwnedLeafWindow;
+impl pedersen::Window for OwnedLeafWindow {
+    const WINDOW_SIZE: usize = 6;
+    const NUM_WINDOWS: usize = 171;
+}
+
+pub type OwnedLeafHash = pedersen::CRH<Jubjub, OwnedLeafWindow>;
+pub type OwnedLeafHashParams = <OwnedLeafHash as CRHScheme>::Parameters;
+
 // `WINDOW_SIZE * NUM_WINDOWS` > 2 * 512 bits = enough for hashing two outputs. Affine curve points
 // are 512 bits because there currently isn't a DigestConverterGadget that knows how to do
 // compressed curve points.
@@ -36,5 +62,365 @@ pub type TwoToOneHashGadget =
 pub type LeafHashGadget = pedersen::constraints::CRHGadget<Jubjub, JubjubVar, LeafWindow>;
 
 pub type LeafHashParamsVar = <LeafHashGadget as CRHSchemeGadget<LeafHash, F>>::ParametersVar;
+pub type OwnedLeafHashGadget = pedersen::constraints::CRHGadget<Jubjub, JubjubVar, OwnedLeafWindow>;
+pub type OwnedLeafHashParamsVar =
+    <OwnedLeafHashGadget as CRHSchemeGadget<OwnedLeafHash, F>>::ParametersVar;
 pub type TwoToOneHashParamsVar =
     <TwoToOneHashGadget as TwoToOneCRHSchemeGadget<TwoToOneHash, F>>::ParametersVar;
+
+/// Computes a leaf hash of a sequence of values. The values are hashed in the order they're given
+/// to `update`, so the serialization order of a commitment reads top to bottom at the call site.
+/// The window `W` picks the hash: `LeafWindow` for `LeafHash`, or `OwnedLeafWindow` for
+/// `OwnedLeafHash`.
+pub struct TreeHasher<'a, W: pedersen::Window = LeafWindow> {
+    buf: Vec<u8>,
+    params: &'a pedersen::Parameters<Jubjub>,
+    _window: PhantomData<W>,
+}
+
+impl<'a> TreeHasher<'a> {
+    /// Makes a `LeafHash` hasher with nothing in it yet
+    pub fn new(params: &'a LeafHashParams) -> Self {
+        TreeHasher {
+            buf: Vec::new(),
+            params,
+            _window: PhantomData,
+        }
+    }
+}
+
+impl<'a> TreeHasher<'a, OwnedLeafWindow> {
+    /// Makes an `OwnedLeafHash` hasher with nothing in it yet
+    pub fn new_owned(params: &'a OwnedLeafHashParams) -> Self {
+        TreeHasher {
+            buf: Vec::new(),
+            params,
+            _window: PhantomData,
+        }
+    }
+}
+
+impl<'a, W: pedersen::Window> TreeHasher<'a, W> {
+    /// Appends the given bytes to the hash input
+    pub fn update(&mut self, data: &[u8]) {
+        self.buf.extend_from_slice(data);
+    }
+
+    /// Appends the uncompressed serialization of the given value to the hash input
+    pub fn update_serialized<S: CanonicalSerialize>(&mut self, val: &S) {
+        val.serialize_uncompressed(&mut self.buf).unwrap();
+    }
+
+    /// Hashes everything that's been given to `update` so far. Errors if that's more than the
+    /// hash can take, i.e., more than `W` has room for.
+    pub fn finalize(mut self) -> Result<Leaf, InputTooLongError> {
+        self.finalize_reset()
+    }
+
+    /// The same as `finalize`, except the hasher is emptied rather than consumed, so it can hash
+    /// something else. The input buffer's allocation is kept for the next hash.
+    pub fn finalize_reset(&mut self) -> Result<Leaf, InputTooLongError> {
+        // The Pedersen hash panics on inputs that don't fit in its windows, so check first
+        let max = W::WINDOW_SIZE * W::NUM_WINDOWS / 8;
+        let len = self.buf.len();
+        if len > max {
+            self.buf.clear();
+            return Err(InputTooLongError { max, got: len });
+        }
+
+        let hash = pedersen::CRH::<Jubjub, W>::evaluate(self.params, self.buf.as_slice()).unwrap();
+        self.buf.clear();
+        Ok(
+            <MerkleConfig as Config>::LeafInnerDigestConverter::convert(hash)
+                .unwrap()
+                .try_into()
+                .unwrap(),
+        )
+    }
+}
+
+/// The error returned when a `TreeHasher` is given more bytes than its hash can take
+#[derive(Debug)]
+pub struct InputTooLongError {
+    /// The most bytes the hash can take, i.e., 96 for `LeafHash` and 128 for `OwnedLeafHash`
+    pub max: usize,
+    /// The number of bytes we were given
+    pub got: usize,
+}
+
+impl fmt::Display for InputTooLongError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "the hash takes at most {} bytes, got {} bytes",
+            self.max, self.got
+        )
+    }
+}
+
+impl std::error::Error for InputTooLongError {}
+
+/// Checks that `expected` is the leaf hash of `input`. The comparison is constant-time.
+pub fn verify_leaf_hash(
+    params: &LeafHashParams,
+    input: &[u8],
+    expected: &<LeafHash as CRHScheme>::Output,
+) -> bool {
+    match LeafHash::evaluate(params, input) {
+        Ok(computed) => points_ct_eq(&computed, expected),
+        Err(_) => false,
+    }
+}
+
+/// Checks that `expected` is the two-to-one hash of `left` and `right`. This is how the bottom
+/// level of the tree is computed, where `left` and `right` are serialized leaf hashes. The
+/// comparison is constant-time.
+pub fn verify_two_to_one_hash(
+    params: &TwoToOneHashParams,
+    left: &[u8],
+    right: &[u8],
+    expected: &MerkleRoot,
+) -> bool {
+    match TwoToOneHash::evaluate(params, left, right) {
+        Ok(computed) => points_ct_eq(&computed, expected),
+        Err(_) => false,
+    }
+}
+
+// Compares the uncompressed serializations of two values in constant time
+fn points_ct_eq<S: CanonicalSerialize>(a: &S, b: &S) -> bool {
+    let mut a_bytes = Vec::new();
+    let mut b_bytes = Vec::new();
+    a.serialize_uncompressed(&mut a_bytes).unwrap();
+    b.serialize_uncompressed(&mut b_bytes).unwrap();
+
+    a_bytes.ct_eq(&b_bytes).into()
+}
+
+/// A commitment scheme built from the leaf hash. The commitment to `value` with randomness `nonce`
+/// is `Hash(nonce || value)`, serialized to bytes.
+pub struct PedersenCommitmentGadget;
+
+impl PedersenCommitmentGadget {
+    /// Commits to the given bytes using `nonce` as the commitment randomness. Concretely, this
+    /// computes `Hash(nonce || value)`.
+    pub fn commit(
+        params: &LeafHashParamsVar,
+        value: &[UInt8<F>],
+        nonce: &FV,
+    ) -> Result<Vec<UInt8<F>>, SynthesisError> {
+        commit_var::<Bls12Config>(params, value, nonce)
+    }
+
+    /// The same as `commit`, except with `OwnedLeafHash`, so `value` can be up to 96 bytes long
+    pub fn commit_owned(
+        params: &OwnedLeafHashParamsVar,
+        value: &[UInt8<F>],
+        nonce: &FV,
+    ) -> Result<Vec<UInt8<F>>, SynthesisError> {
+        let nonce_bytes = nonce.to_bytes()?;
+        let hash =
+            OwnedLeafHashGadget::evaluate(params, &[nonce_bytes.as_slice(), value].concat())?;
+        hash.to_bytes()
+    }
+}
+
+/// Commits to the given bytes with `C`'s leaf hash, using `nonce` as the commitment randomness.
+/// Concretely, this computes `Hash(nonce || value)`, serialized to bytes. For `Bls12Config`, this
+/// is how `Card::commit` commits to a card's serialization.
+pub fn commit<C: CryptoConfig>(
+    params: &LeafHashParamsOf<C>,
+    value: &[u8],
+    nonce: &C::F,
+) -> Vec<u8> {
+    let mut input = Vec::new();
+    nonce.serialize_uncompressed(&mut input).unwrap();
+    input.extend_from_slice(value);
+
+    let hash = C::LeafHash::evaluate(params, input.as_slice()).unwrap();
+    let mut bytes = Vec::new();
+    hash.serialize_uncompressed(&mut bytes).unwrap();
+    bytes
+}
+
+/// The gadget version of `commit`. For `Bls12Config`, this is `PedersenCommitmentGadget::commit`.
+pub fn commit_var<C: CryptoConfig>(
+    params: &LeafHashParamsVarOf<C>,
+    value: &[UInt8<C::F>],
+    nonce: &FpVar<C::F>,
+) -> Result<Vec<UInt8<C::F>>, SynthesisError> {
+    let nonce_bytes = nonce.to_bytes()?;
+    let hash = LeafHashGadgetOf::<C>::evaluate(params, &[nonce_bytes.as_slice(), value].concat())?;
+    hash.to_bytes()
+}
+
+//
+// POSEIDON
+//
+
+// Poseidon is an arithmetization-friendly hash. It operates directly on field elements rather than
+// bytes and curve points, so it takes far fewer constraints than Pedersen. We use the same
+// parameters (see `poseidon_params.rs`) for the leaf hash and the two-to-one hash.
+
+pub type PoseidonParams = PoseidonConfig<F>;
+pub type PoseidonLeafHash = poseidon::CRH<F>;
+pub type PoseidonTwoToOneHash = poseidon::TwoToOneCRH<F>;
+
+pub type PoseidonLeafHashGadget = poseidon::constraints::CRHGadget<F>;
+pub type PoseidonTwoToOneHashGadget = poseidon::constraints::TwoToOneCRHGadget<F>;
+
+pub type PoseidonParamsVar = poseidon::constraints::CRHParametersVar<F>;
+
+//
+// TESTS
+//
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        card::{Card, CardVar},
+        merkle::SimpleMerkleTree,
+        util::gen_test_tree,
+    };
+
+    use ark_ff::UniformRand;
+    use ark_r1cs_std::{alloc::AllocVar, R1CSVar};
+    use ark_relations::r1cs::ConstraintSystem;
+
+    // Hashing with TreeHasher should be the same as hashing the concatenated input all at once
+    #[test]
+    fn tree_hasher_matches_evaluate() {
+        let mut rng = ark_std::test_rng();
+        let leaf_crh_params = <LeafHash as CRHScheme>::setup(&mut rng).unwrap();
+
+        let card = Card::rand(&mut rng);
+        let com_rand = F::rand(&mut rng);
+
+        // Serialize everything into one buffer and hash it
+        let mut buf = Vec::new();
+        com_rand.serialize_uncompressed(&mut buf).unwrap();
+        card.serialize_uncompressed(&mut buf).unwrap();
+        let hash = LeafHash::evaluate(&leaf_crh_params, buf.as_slice()).unwrap();
+        let expected: Leaf = <MerkleConfig as Config>::LeafInnerDigestConverter::convert(hash)
+            .unwrap()
+            .try_into()
+            .unwrap();
+
+        // Feed the same bytes in pieces, both raw and serialized by the hasher
+        let mut hasher = TreeHasher::new(&leaf_crh_params);
+        hasher.update(&buf[..10]);
+        hasher.update(&buf[10..32]);
+        hasher.update_serialized(&card);
+        assert_eq!(hasher.finalize().unwrap(), expected);
+
+        // Card::commit is the same computation
+        assert_eq!(card.commit(&leaf_crh_params, &com_rand), expected);
+
+        // And so is the generic commitment, given the card's serialization
+        let card_bytes = &buf[32..];
+        assert_eq!(
+            commit::<Bls12Config>(&leaf_crh_params, card_bytes, &com_rand),
+            expected.to_vec()
+        );
+    }
+
+    // Feeding TreeHasher more than the leaf hash takes is an error, not a panic
+    #[test]
+    fn tree_hasher_too_long() {
+        let mut rng = ark_std::test_rng();
+        let leaf_crh_params = <LeafHash as CRHScheme>::setup(&mut rng).unwrap();
+
+        // 96 bytes is the most that fits
+        let mut hasher = TreeHasher::new(&leaf_crh_params);
+        hasher.update(&[1u8; 96]);
+        assert!(hasher.finalize().is_ok());
+
+        let mut hasher = TreeHasher::new(&leaf_crh_params);
+        hasher.update(&[1u8; 97]);
+        assert!(hasher.finalize().is_err());
+
+        // The owned leaf hash takes 128
+        let owned_leaf_crh_params = <OwnedLeafHash as CRHScheme>::setup(&mut rng).unwrap();
+        let mut hasher = TreeHasher::new_owned(&owned_leaf_crh_params);
+        hasher.update(&[1u8; 128]);
+        assert!(hasher.finalize().is_ok());
+
+        let mut hasher = TreeHasher::new_owned(&owned_leaf_crh_params);
+        hasher.update(&[1u8; 129]);
+        assert!(hasher.finalize().is_err());
+    }
+
+    // The commitment gadget should compute exactly the same thing as the native Card::commit
+    #[test]
+    fn commitment_gadget_matches_native() {
+        let mut rng = ark_std::test_rng();
+        let leaf_crh_params = <LeafHash as CRHScheme>::setup(&mut rng).unwrap();
+
+        // Commit natively
+        let card = Card::rand(&mut rng);
+        let com_rand = F::rand(&mut rng);
+        let native_com = card.commit(&leaf_crh_params, &com_rand);
+
+        // Commit in the circuit
+        let cs = ConstraintSystem::new_ref();
+        let params_var = LeafHashParamsVar::new_constant(cs.clone(), &leaf_crh_params).unwrap();
+        let card_var: CardVar = CardVar {
+            amount: FV::new_witness(cs.clone(), || Ok(card.purchase_price)).unwrap(),
+            serial_num: FV::new_witness(cs.clone(), || Ok(card.serial_num)).unwrap(),
+        };
+        let com_rand_var = FV::new_witness(cs.clone(), || Ok(com_rand)).unwrap();
+        let card_bytes = card_var.to_bytes().unwrap();
+        let gadget_com =
+            PedersenCommitmentGadget::commit(&params_var, &card_bytes, &com_rand_var).unwrap();
+
+        assert_eq!(gadget_com.value().unwrap(), native_com.to_vec());
+        assert!(cs.is_satisfied().unwrap());
+    }
+
+    // The standalone hash checks should agree with the hashes the tree computes
+    #[test]
+    fn verify_hashes_against_tree() {
+        let mut rng = ark_std::test_rng();
+        let leaf_crh_params = <LeafHash as CRHScheme>::setup(&mut rng).unwrap();
+        let two_to_one_crh_params = <TwoToOneHash as TwoToOneCRHScheme>::setup(&mut rng).unwrap();
+
+        // The leaf sibling hash in an auth path is the leaf hash of the neighboring leaf
+        let tree = gen_test_tree(&leaf_crh_params, &two_to_one_crh_params);
+        let path = tree.generate_proof(6).unwrap();
+        let sibling = tree.leaves()[7];
+        assert!(verify_leaf_hash(
+            &leaf_crh_params,
+            &sibling,
+            &path.leaf_sibling_hash
+        ));
+        assert!(!verify_leaf_hash(
+            &leaf_crh_params,
+            &tree.leaves()[6],
+            &path.leaf_sibling_hash
+        ));
+
+        // In a two-leaf tree, the root is the two-to-one hash of the serialized leaf hashes
+        let leaves = vec![tree.leaves()[0], tree.leaves()[1]];
+        let small_tree =
+            SimpleMerkleTree::new(&leaf_crh_params, &two_to_one_crh_params, leaves.clone())
+                .unwrap();
+        let [left, right] = [&leaves[0], &leaves[1]].map(|leaf| {
+            let hash = LeafHash::evaluate(&leaf_crh_params, leaf.as_slice()).unwrap();
+            <MerkleConfig as Config>::LeafInnerDigestConverter::convert(hash).unwrap()
+        });
+        assert!(verify_two_to_one_hash(
+            &two_to_one_crh_params,
+            &left,
+            &right,
+            &small_tree.root()
+        ));
+        // Swapping the children changes the hash
+        assert!(!verify_two_to_one_hash(
+            &two_to_one_crh_params,
+            &right,
+            &left,
+            &small_tree.root()
+        ));
+    }
+}
@@ -1,12 +1,42 @@
 pub mod util;
 
+pub mod batch;
+pub mod bn254;
 pub mod card;
 pub mod constraints;
+pub mod constraints_conditional;
+pub mod constraints_denomination;
+pub mod constraints_deposit;
+pub mod constraints_disjunctive;
+pub mod constraints_forest;
+pub mod constraints_hidden_price;
+pub mod constraints_multi;
+pub mod constraints_poseidon;
+pub mod constraints_range;
+pub mod constraints_range_possession;
 pub mod constraints_showprice;
+pub mod constraints_sig;
+pub mod constraints_split;
+pub mod constraints_transfer;
+pub mod constraints_v2;
+pub mod constraints_voting;
+pub mod encryption;
 pub mod hash;
 pub mod merkle;
+pub mod nullifier;
+pub mod poseidon_params;
+pub mod proof_package;
+pub mod prover;
+pub mod registry;
 
-use ark_r1cs_std::fields::fp::FpVar;
+use ark_crypto_primitives::{
+    crh::{constraints::CRHSchemeGadget, CRHScheme, TwoToOneCRHScheme, TwoToOneCRHSchemeGadget},
+    merkle_tree::{constraints::ConfigGadget, Config},
+};
+use ark_ec::pairing::Pairing;
+use ark_ff::PrimeField;
+use ark_r1cs_std::{fields::fp::FpVar, uint8::UInt8};
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
 
 /// The bilinear pairing we will be using for our Groth16 proofs
 pub type E = ark_bls12_381::Bls12_381;
@@ -16,6 +46,93 @@ pub type F = ark_bls12_381::Fr;
 /// R1CS representation of a field element
 pub type FV = FpVar<F>;
 
+/// The curve and hashes a circuit is built from. The circuit is defined over the scalar field of
+/// the pairing engine, and the hashes are used for card commitments and the Merkle tree. Leaves
+/// are bytestrings, like our `Leaf`, so the same circuit logic works for any choice of hash.
+pub trait CryptoConfig {
+    /// The bilinear pairing used for Groth16 proofs
+    type E: Pairing<ScalarField = Self::F>;
+    /// The scalar field of `E`. This is the field the circuit is defined over.
+    type F: PrimeField;
+    /// The hash used for card commitments and Merkle leaves
+    type LeafHash: CRHScheme<Input = [u8], Parameters: CanonicalSerialize + CanonicalDeserialize>;
+    /// The hash used for the inner nodes of the Merkle tree
+    type TwoToOneHash: TwoToOneCRHScheme<
+        Input = [u8],
+        Parameters: CanonicalSerialize + CanonicalDeserialize,
+    >;
+    /// The Merkle tree built from the two hashes above
+    type MerkleConfig: Config<
+        Leaf = [u8],
+        LeafHash = Self::LeafHash,
+        TwoToOneHash = Self::TwoToOneHash,
+    >;
+    /// R1CS version of `MerkleConfig`. This also determines the hash gadgets.
+    type MerkleConfigGadget: ConfigGadget<Self::MerkleConfig, Self::F, Leaf = [UInt8<Self::F>]>;
+}
+
+/// The parameters of a config's leaf hash
+pub type LeafHashParamsOf<C> = <<C as CryptoConfig>::LeafHash as CRHScheme>::Parameters;
+/// The parameters of a config's two-to-one hash
+pub type TwoToOneHashParamsOf<C> =
+    <<C as CryptoConfig>::TwoToOneHash as TwoToOneCRHScheme>::Parameters;
+/// The root of a config's Merkle tree
+pub type MerkleRootOf<C> = <<C as CryptoConfig>::MerkleConfig as Config>::InnerDigest;
+
+/// The R1CS gadget for a config's leaf hash
+pub type LeafHashGadgetOf<C> = <<C as CryptoConfig>::MerkleConfigGadget as ConfigGadget<
+    <C as CryptoConfig>::MerkleConfig,
+    <C as CryptoConfig>::F,
+>>::LeafHash;
+/// The R1CS gadget for a config's two-to-one hash
+pub type TwoToOneHashGadgetOf<C> = <<C as CryptoConfig>::MerkleConfigGadget as ConfigGadget<
+    <C as CryptoConfig>::MerkleConfig,
+    <C as CryptoConfig>::F,
+>>::TwoToOneHash;
+/// R1CS representation of `LeafHashParamsOf<C>`
+pub type LeafHashParamsVarOf<C> = <LeafHashGadgetOf<C> as CRHSchemeGadget<
+    <C as CryptoConfig>::LeafHash,
+    <C as CryptoConfig>::F,
+>>::ParametersVar;
+/// R1CS representation of `TwoToOneHashParamsOf<C>`
+pub type TwoToOneHashParamsVarOf<C> = <TwoToOneHashGadgetOf<C> as TwoToOneCRHSchemeGadget<
+    <C as CryptoConfig>::TwoToOneHash,
+    <C as CryptoConfig>::F,
+>>::ParametersVar;
+/// R1CS representation of `MerkleRootOf<C>`
+pub type MerkleRootVarOf<C> = <<C as CryptoConfig>::MerkleConfigGadget as ConfigGadget<
+    <C as CryptoConfig>::MerkleConfig,
+    <C as CryptoConfig>::F,
+>>::InnerDigest;
+
+/// The config everything in this crate uses by default: BLS12-381, with Pedersen hashes over
+/// Jubjub
+#[derive(Clone)]
+pub struct Bls12Config;
+
+impl CryptoConfig for Bls12Config {
+    type E = E;
+    type F = F;
+    type LeafHash = hash::LeafHash;
+    type TwoToOneHash = hash::TwoToOneHash;
+    type MerkleConfig = merkle::MerkleConfig;
+    type MerkleConfigGadget = merkle::MerkleConfigGadget;
+}
+
+/// BN254, with Pedersen hashes over Baby Jubjub. BN254 is cheaper to prove and verify over than
+/// BLS12-381, at a lower security level. See `bn254` for the hashes.
+#[derive(Clone)]
+pub struct Bn254Config;
+
+impl CryptoConfig for Bn254Config {
+    type E = ark_bn254::Bn254;
+    type F = ark_bn254::Fr;
+    type LeafHash = bn254::LeafHash;
+    type TwoToOneHash = bn254::TwoToOneHash;
+    type MerkleConfig = bn254::MerkleConfig;
+    type MerkleConfigGadget = bn254::MerkleConfigGadget;
+}
+
 // This is a basic functionality test of the native Merkle tree. This does no ZK operations at all.
 // It just checks that you can prove membership in a tree by giving a verifier the Merkle
 // authentication path.
@@ -1,18 +1,37 @@
 use crate::{
-    hash::{LeafHash, LeafHashGadget, TwoToOneHash, TwoToOneHashGadget},
-    F,
+    hash::{
+        LeafHash, LeafHashGadget, LeafHashParams, LeafHashParamsVar, PoseidonLeafHash,
+        PoseidonLeafHashGadget, PoseidonTwoToOneHash, PoseidonTwoToOneHashGadget, TwoToOneHash,
+        TwoToOneHashGadget, TwoToOneHashParams, TwoToOneHashParamsVar,
+    },
+    F, FV,
 };
 
 use ark_crypto_primitives::{
     crh::{CRHScheme, TwoToOneCRHScheme},
-    merkle_tree::{ByteDigestConverter, Config, MerkleTree, Path},
+    merkle_tree::{
+        ByteDigestConverter, Config, DigestConverter, IdentityDigestConverter, MerkleTree, Path,
+    },
 };
 
 use ark_crypto_primitives::crh::{constraints::CRHSchemeGadget, TwoToOneCRHSchemeGadget};
 use ark_crypto_primitives::merkle_tree::constraints::{
-    BytesVarDigestConverter, ConfigGadget, PathVar,
+    BytesVarDigestConverter, ConfigGadget, DigestVarConverter, PathVar,
 };
-use ark_r1cs_std::uint8::UInt8;
+use ark_r1cs_std::{
+    alloc::{AllocVar, AllocationMode},
+    boolean::Boolean,
+    uint8::UInt8,
+};
+use ark_relations::{
+    ns,
+    r1cs::{Namespace, SynthesisError},
+};
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize, SerializationError};
+
+use core::{borrow::Borrow, fmt};
+
+pub mod audit;
 
 //
 // NATIVE IMPLEMENTATIONS
@@ -40,15 +59,792 @@ impl Config for MerkleConfig {
     type TwoToOneHash = TwoToOneHash;
 }
 
-/// A Merkle tree containing account information.
-pub type SimpleMerkleTree = MerkleTree<MerkleConfig>;
-
 /// The root of the account Merkle tree.
 pub type MerkleRoot = <TwoToOneHash as TwoToOneCRHScheme>::Output;
 
 /// A membership proof for a given account.
 pub type SimplePath = Path<MerkleConfig>;
 
+/// Serializes a Merkle root to bytes. This is the canonical encoding of a root, i.e., the
+/// compressed serialization.
+pub fn root_to_bytes(root: &MerkleRoot) -> Vec<u8> {
+    let mut buf = Vec::new();
+    root.serialize_compressed(&mut buf)
+        .expect("failed to serialize Merkle root");
+    buf
+}
+
+/// Deserializes a Merkle root from the bytes output by `root_to_bytes`
+pub fn root_from_bytes(bytes: &[u8]) -> Result<MerkleRoot, SerializationError> {
+    MerkleRoot::deserialize_compressed(bytes)
+}
+
+/// An error from converting bytes to a `Leaf`
+#[derive(Debug, PartialEq, Eq)]
+pub struct LeafConversionError {
+    /// The number of bytes in a leaf, i.e., 64
+    pub expected: usize,
+    /// The number of bytes we were given
+    pub got: usize,
+}
+
+impl fmt::Display for LeafConversionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "a leaf is {} bytes long, got {} bytes",
+            self.expected, self.got
+        )
+    }
+}
+
+impl std::error::Error for LeafConversionError {}
+
+/// Converts the given bytes to a leaf. This is `<Leaf as TryFrom<&[u8]>>` with a more helpful
+/// error. We can't implement `TryFrom` ourselves, since `Leaf` is just an alias of `[u8; 64]`.
+/// Circuits take their leaves as `Vec<u8>`, which this accepts too, by deref.
+pub fn leaf_from_bytes(bytes: &[u8]) -> Result<Leaf, LeafConversionError> {
+    bytes.try_into().map_err(|_| LeafConversionError {
+        expected: core::mem::size_of::<Leaf>(),
+        got: bytes.len(),
+    })
+}
+
+/// An error from building or using one of our Merkle trees
+#[derive(Debug)]
+pub enum MerkleError {
+    /// The leaf index `idx` was requested from a tree with only `len` leaves
+    IndexOutOfBounds { idx: usize, len: usize },
+    /// The tree was given `len` leaves, which isn't a power of two (or is less than 2)
+    BadNumLeaves { len: usize },
+    /// The tree index `idx` was requested from a forest with only `len` trees
+    TreeIndexOutOfBounds { idx: usize, len: usize },
+    /// A `TreeBuilder` was built without setting the named hash parameters
+    MissingParams { which: &'static str },
+    /// A batch of `batch_size` leaves was requested starting at `start_idx`, which isn't a
+    /// multiple of `batch_size`
+    UnalignedBatch { start_idx: usize, batch_size: usize },
+    /// The underlying arkworks Merkle tree failed
+    Arkworks(ark_crypto_primitives::Error),
+}
+
+impl fmt::Display for MerkleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MerkleError::IndexOutOfBounds { idx, len } => {
+                write!(
+                    f,
+                    "leaf index {idx} is out of bounds for a tree of {len} leaves"
+                )
+            }
+            MerkleError::BadNumLeaves { len } => {
+                write!(
+                    f,
+                    "a tree needs a power of two (at least 2) leaves, got {len}"
+                )
+            }
+            MerkleError::TreeIndexOutOfBounds { idx, len } => {
+                write!(
+                    f,
+                    "tree index {idx} is out of bounds for a forest of {len} trees"
+                )
+            }
+            MerkleError::MissingParams { which } => {
+                write!(f, "cannot build a tree without the {which} hash parameters")
+            }
+            MerkleError::UnalignedBatch {
+                start_idx,
+                batch_size,
+            } => {
+                write!(
+                    f,
+                    "a batch of {batch_size} leaves must start at a multiple of {batch_size}, got \
+                     {start_idx}"
+                )
+            }
+            MerkleError::Arkworks(e) => write!(f, "Merkle tree error: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for MerkleError {}
+
+impl From<ark_crypto_primitives::Error> for MerkleError {
+    fn from(e: ark_crypto_primitives::Error) -> Self {
+        MerkleError::Arkworks(e)
+    }
+}
+
+/// A Merkle tree containing account information. This wraps the arkworks `MerkleTree` so that
+/// out-of-bounds accesses are errors rather than panics. It also keeps a copy of the leaves, since
+/// the arkworks tree only stores their hashes.
+pub struct SimpleMerkleTree {
+    tree: MerkleTree<MerkleConfig>,
+    leaves: Vec<Leaf>,
+    // Kept so we can build subtrees
+    leaf_crh_params: LeafHashParams,
+    two_to_one_crh_params: TwoToOneHashParams,
+}
+
+impl SimpleMerkleTree {
+    /// Makes a Merkle tree out of the given leaves. The number of leaves must be a power of two,
+    /// and at least 2.
+    pub fn new(
+        leaf_crh_params: &LeafHashParams,
+        two_to_one_crh_params: &TwoToOneHashParams,
+        leaves: Vec<Leaf>,
+    ) -> Result<Self, MerkleError> {
+        let num_leaves = leaves.len();
+        if num_leaves < 2 || !num_leaves.is_power_of_two() {
+            return Err(MerkleError::BadNumLeaves { len: num_leaves });
+        }
+
+        let tree = MerkleTree::new(
+            leaf_crh_params,
+            two_to_one_crh_params,
+            leaves.iter().map(|l| l.as_slice()),
+        )?;
+        Ok(SimpleMerkleTree {
+            tree,
+            leaves,
+            leaf_crh_params: leaf_crh_params.clone(),
+            two_to_one_crh_params: two_to_one_crh_params.clone(),
+        })
+    }
+
+    /// Returns the root of the tree
+    pub fn root(&self) -> MerkleRoot {
+        self.tree.root()
+    }
+
+    /// Returns the number of leaves in the tree
+    pub fn num_leaves(&self) -> usize {
+        self.leaves.len()
+    }
+
+    /// Returns the leaves of the tree, in order. The `idx`-th leaf here is the one that
+    /// `generate_proof(idx)` proves membership of.
+    pub fn leaves(&self) -> &[Leaf] {
+        &self.leaves
+    }
+
+    /// Generates a membership proof for the `idx`-th leaf
+    pub fn generate_proof(&self, idx: usize) -> Result<SimplePath, MerkleError> {
+        self.check_idx(idx)?;
+        Ok(self.tree.generate_proof(idx)?)
+    }
+
+    /// Replaces the `idx`-th leaf with `new_leaf`, and recomputes the path from it to the root
+    pub fn update_leaf(&mut self, idx: usize, new_leaf: &Leaf) -> Result<(), MerkleError> {
+        self.check_idx(idx)?;
+        self.tree.update(idx, new_leaf.as_slice())?;
+        self.leaves[idx] = *new_leaf;
+        Ok(())
+    }
+
+    /// Returns what the root would be if the `idx`-th leaf were replaced with `new_leaf`. This
+    /// does not modify the tree. Only the nodes on the path from the leaf to the root change, so
+    /// this rehashes just those, using the leaf's current siblings. That's O(log n) hashes.
+    pub fn root_after_update(
+        &self,
+        idx: usize,
+        new_leaf: &Leaf,
+    ) -> Result<MerkleRoot, MerkleError> {
+        let path = self.generate_proof(idx)?;
+        let mut siblings = SiblingsIterator::new(&path);
+        let mut node_idx = idx;
+
+        // The bottom level hashes the serialized digests of the leaf and its sibling
+        let leaf_digest = LeafHash::evaluate(&self.leaf_crh_params, new_leaf.as_slice())?;
+        let leaf_sibling = siblings.next().expect("a tree has at least 2 leaves");
+        let (left, right) = order_children(node_idx, &leaf_digest, leaf_sibling);
+        let mut node = TwoToOneHash::evaluate(
+            &self.two_to_one_crh_params,
+            <MerkleConfig as Config>::LeafInnerDigestConverter::convert(*left)?.as_slice(),
+            <MerkleConfig as Config>::LeafInnerDigestConverter::convert(*right)?.as_slice(),
+        )?;
+
+        // Every level above that hashes the digests themselves
+        for sibling in siblings {
+            node_idx >>= 1;
+            let (left, right) = order_children(node_idx, &node, sibling);
+            node = TwoToOneHash::compress(&self.two_to_one_crh_params, left, right)?;
+        }
+
+        Ok(node)
+    }
+
+    /// Returns the root of the tree made of the `len` leaves starting at `start`. If `len` isn't a
+    /// power of two, the subtree is padded with `EMPTY_LEAF` like a `SparseSimpleMerkleTree`.
+    pub fn subtree_root(&self, start: usize, len: usize) -> Result<MerkleRoot, MerkleError> {
+        if len == 0 {
+            return Err(MerkleError::BadNumLeaves { len });
+        }
+        // The last leaf is at start + len - 1. If that overflows, it's certainly out of bounds.
+        let end = start
+            .checked_add(len)
+            .ok_or(MerkleError::IndexOutOfBounds {
+                idx: usize::MAX,
+                len: self.leaves.len(),
+            })?;
+        self.check_idx(end - 1)?;
+
+        let subtree = SparseSimpleMerkleTree::new_sparse(
+            &self.leaf_crh_params,
+            &self.two_to_one_crh_params,
+            self.leaves[start..end].to_vec(),
+        )?;
+        Ok(subtree.root())
+    }
+
+    /// Returns the leaves that differ between `old` and `new`, as `(idx, old_leaf, new_leaf)`, in
+    /// order of index. If the trees have different sizes, only the indices they have in common
+    /// are compared.
+    pub fn diff(old: &SimpleMerkleTree, new: &SimpleMerkleTree) -> Vec<(usize, Leaf, Leaf)> {
+        old.leaves
+            .iter()
+            .zip(new.leaves.iter())
+            .enumerate()
+            .filter(|(_, (old_leaf, new_leaf))| old_leaf != new_leaf)
+            .map(|(idx, (old_leaf, new_leaf))| (idx, *old_leaf, *new_leaf))
+            .collect()
+    }
+
+    /// Returns an error if `idx` isn't the index of a leaf in this tree
+    fn check_idx(&self, idx: usize) -> Result<(), MerkleError> {
+        if idx >= self.leaves.len() {
+            Err(MerkleError::IndexOutOfBounds {
+                idx,
+                len: self.leaves.len(),
+            })
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Checks membership proofs against a tree. After `update_leaf`, proofs generated before the
+/// update no longer verify against the new root. This finds them.
+pub struct TreeConsistencyChecker<'a>(pub &'a SimpleMerkleTree);
+
+impl<'a> TreeConsistencyChecker<'a> {
+    /// Returns whether `path` proves that `leaf` is in the tree with root `root`, at an index that
+    /// exists in our tree
+    pub fn is_consistent(&self, path: &SimplePath, leaf: &Leaf, root: &MerkleRoot) -> bool {
+        let tree = self.0;
+        path.leaf_index < tree.num_leaves()
+            && path
+                .verify(
+                    &tree.leaf_crh_params,
+                    &tree.two_to_one_crh_params,
+                    root,
+                    leaf.as_slice(),
+                )
+                .unwrap_or(false)
+    }
+
+    /// Returns the indices, into `paths`, of the paths that aren't consistent with `root`
+    pub fn stale_paths_in(&self, paths: &[(SimplePath, Leaf)], root: &MerkleRoot) -> Vec<usize> {
+        paths
+            .iter()
+            .enumerate()
+            .filter(|(_, (path, leaf))| !self.is_consistent(path, leaf, root))
+            .map(|(i, _)| i)
+            .collect()
+    }
+}
+
+/// An error from validating an auth path outside of a circuit
+#[derive(Debug)]
+pub enum PathValidationError {
+    /// The path doesn't lead from the given leaf to the expected root
+    RootMismatch,
+    /// The underlying arkworks path verification failed
+    Arkworks(ark_crypto_primitives::Error),
+}
+
+impl fmt::Display for PathValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PathValidationError::RootMismatch => {
+                write!(
+                    f,
+                    "auth path does not lead from the leaf to the expected root"
+                )
+            }
+            PathValidationError::Arkworks(e) => write!(f, "path verification error: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for PathValidationError {}
+
+/// Checks auth paths natively against a known root. Proving is expensive, so it's worth running
+/// this on a path before handing it to the prover, rather than finding out from an unsatisfied
+/// circuit.
+pub struct MerklePathValidator<'a>(
+    pub &'a LeafHashParams,
+    pub &'a TwoToOneHashParams,
+    pub &'a MerkleRoot,
+);
+
+impl<'a> MerklePathValidator<'a> {
+    /// Checks that `path` proves that `leaf` is in the tree with our root
+    pub fn validate(&self, path: &SimplePath, leaf: &Leaf) -> Result<(), PathValidationError> {
+        let MerklePathValidator(leaf_crh_params, two_to_one_crh_params, root) = *self;
+        let is_valid = path
+            .verify(
+                leaf_crh_params,
+                two_to_one_crh_params,
+                root,
+                leaf.as_slice(),
+            )
+            .map_err(PathValidationError::Arkworks)?;
+
+        if is_valid {
+            Ok(())
+        } else {
+            Err(PathValidationError::RootMismatch)
+        }
+    }
+}
+
+/// A builder for `SimpleMerkleTree`. Both sets of hash parameters must be set before building.
+#[derive(Default)]
+pub struct TreeBuilder {
+    leaf_crh_params: Option<LeafHashParams>,
+    two_to_one_crh_params: Option<TwoToOneHashParams>,
+    leaves: Vec<Leaf>,
+}
+
+impl TreeBuilder {
+    /// Makes a builder with no parameters and no leaves
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the parameters of the leaf hash
+    pub fn leaf_params(mut self, p: LeafHashParams) -> Self {
+        self.leaf_crh_params = Some(p);
+        self
+    }
+
+    /// Sets the parameters of the two-to-one hash
+    pub fn two_to_one_params(mut self, p: TwoToOneHashParams) -> Self {
+        self.two_to_one_crh_params = Some(p);
+        self
+    }
+
+    /// Sets the leaves of the tree. The number of leaves must be a power of two, and at least 2.
+    pub fn leaves(mut self, ls: Vec<Leaf>) -> Self {
+        self.leaves = ls;
+        self
+    }
+
+    /// Appends a leaf. This is for collecting leaves one at a time, e.g., from a stream. The hash
+    /// parameters can be set before or after.
+    pub fn push(&mut self, leaf: Leaf) {
+        self.leaves.push(leaf);
+    }
+
+    /// Returns the number of leaves so far
+    pub fn len(&self) -> usize {
+        self.leaves.len()
+    }
+
+    /// Returns whether there are no leaves yet
+    pub fn is_empty(&self) -> bool {
+        self.leaves.is_empty()
+    }
+
+    /// Builds the tree
+    pub fn build(self) -> Result<SimpleMerkleTree, MerkleError> {
+        let leaf_crh_params = self
+            .leaf_crh_params
+            .ok_or(MerkleError::MissingParams { which: "leaf" })?;
+        let two_to_one_crh_params =
+            self.two_to_one_crh_params
+                .ok_or(MerkleError::MissingParams {
+                    which: "two-to-one",
+                })?;
+
+        SimpleMerkleTree::new(&leaf_crh_params, &two_to_one_crh_params, self.leaves)
+    }
+}
+
+/// The leaf we use to fill the empty slots of a tree whose size isn't a power of two. Nobody knows
+/// an opening of this value, so it can't be used to show possession of anything.
+pub const EMPTY_LEAF: Leaf = [0u8; 64];
+
+/// A Merkle tree that holds any number of leaves. `SimpleMerkleTree` needs a power-of-two number
+/// of leaves, so this pads the given leaves with `EMPTY_LEAF` until it gets one.
+pub struct SparseSimpleMerkleTree {
+    tree: SimpleMerkleTree,
+    /// The number of real (i.e., non-padding) leaves in the tree
+    num_leaves: usize,
+}
+
+impl SparseSimpleMerkleTree {
+    /// Makes a Merkle tree out of the given leaves, padding the end with `EMPTY_LEAF` as needed
+    pub fn new_sparse(
+        leaf_crh_params: &LeafHashParams,
+        two_to_one_crh_params: &TwoToOneHashParams,
+        mut leaves: Vec<Leaf>,
+    ) -> Result<Self, MerkleError> {
+        let num_leaves = leaves.len();
+
+        // The underlying tree needs at least 2 leaves, and a power of two of them
+        let padded_len = num_leaves.next_power_of_two().max(2);
+        leaves.resize(padded_len, EMPTY_LEAF);
+
+        let tree = SimpleMerkleTree::new(leaf_crh_params, two_to_one_crh_params, leaves)?;
+        Ok(SparseSimpleMerkleTree { tree, num_leaves })
+    }
+
+    /// Returns the root of the tree
+    pub fn root(&self) -> MerkleRoot {
+        self.tree.root()
+    }
+
+    /// Returns the number of real (i.e., non-padding) leaves in the tree
+    pub fn num_leaves(&self) -> usize {
+        self.num_leaves
+    }
+
+    /// Returns whether the slot at index `idx` is padding rather than a real leaf
+    pub fn is_empty_slot(&self, idx: usize) -> bool {
+        idx >= self.num_leaves
+    }
+
+    /// Generates a membership proof for the `idx`-th leaf. Padding leaves don't count, so `idx`
+    /// must be less than `self.num_leaves()`.
+    pub fn generate_proof(&self, idx: usize) -> Result<SimplePath, MerkleError> {
+        if self.is_empty_slot(idx) {
+            return Err(MerkleError::IndexOutOfBounds {
+                idx,
+                len: self.num_leaves,
+            });
+        }
+        self.tree.generate_proof(idx)
+    }
+}
+
+/// A collection of Merkle trees, e.g., one per epoch or shard. Trees are referred to by the index
+/// they were added at.
+#[derive(Default)]
+pub struct MerkleForest {
+    trees: Vec<SimpleMerkleTree>,
+}
+
+impl MerkleForest {
+    /// Makes an empty forest
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a tree to the forest and returns its index
+    pub fn add_tree(&mut self, tree: SimpleMerkleTree) -> usize {
+        self.trees.push(tree);
+        self.trees.len() - 1
+    }
+
+    /// Returns the number of trees in the forest
+    pub fn num_trees(&self) -> usize {
+        self.trees.len()
+    }
+
+    /// Returns the root of the `tree_idx`-th tree
+    pub fn root_of(&self, tree_idx: usize) -> Result<MerkleRoot, MerkleError> {
+        Ok(self.tree(tree_idx)?.root())
+    }
+
+    /// Returns the roots of all the trees, in order
+    pub fn roots(&self) -> Vec<MerkleRoot> {
+        self.trees.iter().map(SimpleMerkleTree::root).collect()
+    }
+
+    /// Generates a membership proof for the `leaf_idx`-th leaf of the `tree_idx`-th tree. Returns
+    /// the proof along with the tree index it's valid for.
+    pub fn generate_cross_tree_proof(
+        &self,
+        tree_idx: usize,
+        leaf_idx: usize,
+    ) -> Result<(SimplePath, usize), MerkleError> {
+        let path = self.tree(tree_idx)?.generate_proof(leaf_idx)?;
+        Ok((path, tree_idx))
+    }
+
+    /// Returns the `tree_idx`-th tree
+    fn tree(&self, tree_idx: usize) -> Result<&SimpleMerkleTree, MerkleError> {
+        self.trees
+            .get(tree_idx)
+            .ok_or(MerkleError::TreeIndexOutOfBounds {
+                idx: tree_idx,
+                len: self.trees.len(),
+            })
+    }
+}
+
+/// The last `N` roots of a tree that changes over time, each with the block number it was the root
+/// at. Proofs made against a slightly old state of the tree can be checked against this, rather
+/// than just the current root. Once more than `N` roots are pushed, the oldest ones are forgotten.
+/// `N` must be nonzero.
+#[derive(Clone)]
+pub struct MerkleRootHistory<const N: usize> {
+    /// A ring buffer of `(root, block_number)` pairs. Empty slots are `None`.
+    entries: [Option<(MerkleRoot, u64)>; N],
+    /// The slot the next root goes in
+    next: usize,
+}
+
+impl<const N: usize> MerkleRootHistory<N> {
+    // A history with no slots has nowhere to put a root. Evaluating this fails the build.
+    const NONEMPTY: () = assert!(N > 0, "a root history needs room for at least one root");
+
+    /// Makes an empty history. `N` must be at least 1, which is checked at compile time.
+    pub fn new() -> Self {
+        let () = Self::NONEMPTY;
+
+        MerkleRootHistory {
+            entries: [None; N],
+            next: 0,
+        }
+    }
+
+    /// Records `root` as the root of the tree at block number `block`. If the history is full,
+    /// this evicts the oldest root.
+    pub fn push(&mut self, root: MerkleRoot, block: u64) {
+        self.entries[self.next] = Some((root, block));
+        self.next = (self.next + 1) % N;
+    }
+
+    /// Returns whether `root` is one of the last `N` roots
+    pub fn contains_root(&self, root: &MerkleRoot) -> bool {
+        self.entries
+            .iter()
+            .flatten()
+            .any(|(known_root, _)| known_root == root)
+    }
+
+    /// Returns the most recently pushed root and its block number, if any
+    pub fn latest(&self) -> Option<(MerkleRoot, u64)> {
+        self.entries[(self.next + N - 1) % N]
+    }
+}
+
+impl<const N: usize> Default for MerkleRootHistory<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A membership proof along with some accessors for inspecting it. Levels are counted from the
+/// top: level 0 is the pair of nodes just below the root, and level `depth() - 1` is the pair of
+/// leaves.
+#[derive(Clone)]
+pub struct MembershipPath {
+    path: SimplePath,
+    // The serialized sibling digests, top to bottom, so sibling_at can hand out slices
+    sibling_bytes: Vec<Vec<u8>>,
+}
+
+impl MembershipPath {
+    /// Wraps the given path
+    pub fn new(path: SimplePath) -> Self {
+        // SiblingsIterator goes bottom to top, so flip it
+        let mut sibling_bytes: Vec<Vec<u8>> =
+            SiblingsIterator::new(&path).map(root_to_bytes).collect();
+        sibling_bytes.reverse();
+
+        MembershipPath {
+            path,
+            sibling_bytes,
+        }
+    }
+
+    /// Returns the underlying path
+    pub fn path(&self) -> &SimplePath {
+        &self.path
+    }
+
+    /// Returns the number of hashes it takes to get from the leaf to the root
+    pub fn depth(&self) -> usize {
+        self.sibling_bytes.len()
+    }
+
+    /// Returns the compressed serialization of the sibling digest at the given level. Panics if
+    /// `level >= self.depth()`.
+    pub fn sibling_at(&self, level: usize) -> &[u8] {
+        &self.sibling_bytes[level]
+    }
+
+    /// Returns the direction taken at each level on the way from the root to the leaf. `true`
+    /// means right. This is the binary expansion of the leaf index, most significant bit first.
+    pub fn leaf_index_bits(&self) -> Vec<bool> {
+        let depth = self.depth();
+        (0..depth)
+            .map(|level| (self.path.leaf_index >> (depth - 1 - level)) & 1 == 1)
+            .collect()
+    }
+}
+
+impl From<SimplePath> for MembershipPath {
+    fn from(path: SimplePath) -> Self {
+        MembershipPath::new(path)
+    }
+}
+
+// Returns a node and its sibling in the order they're hashed, i.e., `(left, right)`. The node at
+// index `idx` in its level is a left child iff `idx` is even.
+fn order_children<T>(idx: usize, node: T, sibling: T) -> (T, T) {
+    if idx & 1 == 0 {
+        (node, sibling)
+    } else {
+        (sibling, node)
+    }
+}
+
+/// Iterates over the sibling digests of a membership proof, starting with the leaf's sibling and
+/// ending with the child of the root. The `i`-th sibling is a left child iff bit `i` of the leaf
+/// index is 1.
+///
+/// Two things to watch for:
+/// * The items are digests, not bytes. A `SimplePath` stores its siblings as Jubjub points, so
+///   there are no serialized bytes that live as long as the path for us to hand out. To get bytes,
+///   serialize each item with `root_to_bytes`, or use `MembershipPath::sibling_at`, which keeps
+///   them around.
+/// * This goes from the bottom up, whereas `MembershipPath` counts levels from the top down. The
+///   `i`-th item here serializes to `MembershipPath::sibling_at(depth - 1 - i)`. Bottom-up is the
+///   order the root is recomputed in, e.g., by `SimpleMerkleTree::root_after_update`.
+pub struct SiblingsIterator<'a> {
+    path: &'a SimplePath,
+    // How many siblings we've yielded so far
+    level: usize,
+}
+
+impl<'a> SiblingsIterator<'a> {
+    /// Makes an iterator over the siblings of the given path
+    pub fn new(path: &'a SimplePath) -> Self {
+        SiblingsIterator { path, level: 0 }
+    }
+}
+
+impl<'a> Iterator for SiblingsIterator<'a> {
+    // Leaf digests and inner digests are both Jubjub points
+    type Item = &'a <MerkleConfig as Config>::InnerDigest;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // The leaf's sibling is stored separately. The rest of the path is stored root-first, so
+        // we walk it backwards.
+        let num_inner = self.path.auth_path.len();
+        let sibling = if self.level == 0 {
+            &self.path.leaf_sibling_hash
+        } else if self.level <= num_inner {
+            &self.path.auth_path[num_inner - self.level]
+        } else {
+            return None;
+        };
+
+        self.level += 1;
+        Some(sibling)
+    }
+}
+
+/// A membership proof for the `N` consecutive leaves starting at `start_idx`. `N` must be a power
+/// of two and `start_idx` a multiple of `N`, so the leaves make up a whole subtree. The bottom of
+/// that subtree is just the leaves themselves, so the only siblings we need are the ones shared by
+/// all of them, on the path from the subtree's root to the tree's root.
+#[derive(Clone)]
+pub struct BatchMerkleProof<const N: usize> {
+    /// The index of the first leaf in the batch
+    pub start_idx: usize,
+    /// The siblings of the path from the subtree root to the tree root. Like `Path::auth_path`,
+    /// these are stored root-first.
+    pub shared_siblings: Vec<MerkleRoot>,
+}
+
+/// Generates a membership proof for the `N` leaves of `tree` starting at `start_idx`. See
+/// `BatchMerkleProof` for the restrictions on `N` and `start_idx`.
+pub fn generate_batch_proof<const N: usize>(
+    tree: &SimpleMerkleTree,
+    start_idx: usize,
+) -> Result<BatchMerkleProof<N>, MerkleError> {
+    if N < 2 || !N.is_power_of_two() {
+        return Err(MerkleError::BadNumLeaves { len: N });
+    }
+    if !start_idx.is_multiple_of(N) {
+        return Err(MerkleError::UnalignedBatch {
+            start_idx,
+            batch_size: N,
+        });
+    }
+    tree.check_idx(start_idx + N - 1)?;
+
+    // The auth path has a sibling for every level but the bottom one. The bottom log2(N) levels
+    // are inside the subtree, so we drop those.
+    let path = tree.generate_proof(start_idx)?;
+    let num_shared = path.auth_path.len() + 1 - N.trailing_zeros() as usize;
+    Ok(BatchMerkleProof {
+        start_idx,
+        shared_siblings: path.auth_path[..num_shared].to_vec(),
+    })
+}
+
+impl<const N: usize> BatchMerkleProof<N> {
+    /// Checks that `leaves` are the `N` leaves starting at `self.start_idx` in the tree with the
+    /// given root
+    pub fn verify_batch(
+        &self,
+        leaf_crh_params: &LeafHashParams,
+        two_to_one_crh_params: &TwoToOneHashParams,
+        root: &MerkleRoot,
+        leaves: &[Leaf; N],
+    ) -> Result<bool, MerkleError> {
+        // The leaves form a whole subtree, so hash them into one
+        let mut cur_node =
+            SimpleMerkleTree::new(leaf_crh_params, two_to_one_crh_params, leaves.to_vec())?.root();
+
+        // Now walk up to the root. The subtree's position at each level tells us which side the
+        // sibling goes on.
+        let mut pos = self.start_idx / N;
+        for sibling in self.shared_siblings.iter().rev() {
+            cur_node = if pos & 1 == 0 {
+                TwoToOneHash::compress(two_to_one_crh_params, &cur_node, sibling)
+            } else {
+                TwoToOneHash::compress(two_to_one_crh_params, sibling, &cur_node)
+            }?;
+            pos >>= 1;
+        }
+
+        Ok(&cur_node == root)
+    }
+}
+
+/// Defines a Merkle tree whose leaves are single field elements, hashed with Poseidon rather than
+/// Pedersen. Since Poseidon outputs field elements, no digest conversion is necessary.
+#[derive(Clone)]
+pub struct PoseidonMerkleConfig;
+
+impl Config for PoseidonMerkleConfig {
+    type Leaf = [F];
+
+    type LeafDigest = F;
+    type LeafInnerDigestConverter = IdentityDigestConverter<F>;
+    type InnerDigest = F;
+
+    type LeafHash = PoseidonLeafHash;
+    type TwoToOneHash = PoseidonTwoToOneHash;
+}
+
+/// A Merkle tree that uses Poseidon for all its hashing
+pub type PoseidonMerkleTree = MerkleTree<PoseidonMerkleConfig>;
+
+/// The root of a Poseidon Merkle tree
+pub type PoseidonMerkleRoot = F;
+
+/// A membership proof in a Poseidon Merkle tree
+pub type PoseidonPath = Path<PoseidonMerkleConfig>;
+
 //
 // R1CS IMPLEMENTATIONS
 //
@@ -72,3 +868,864 @@ pub type RootVar = <TwoToOneHashGadget as TwoToOneCRHSchemeGadget<TwoToOneHash,
 
 /// R1CS representation of SimplePath, i.e., the Merkle tree path
 pub type SimplePathVar = PathVar<MerkleConfig, F, MerkleConfigGadget>;
+
+/// Allocation of Merkle paths that might not be there. This lets circuits with an optional auth path
+/// (e.g., one left empty for parameter generation) fail with an error rather than a panic.
+pub trait PathVarExt: Sized {
+    /// Witnesses the given path. Returns `SynthesisError::AssignmentMissing` if `path` is `None`.
+    fn new_witness_optional(
+        cs: impl Into<Namespace<F>>,
+        path: Option<&SimplePath>,
+    ) -> Result<Self, SynthesisError>;
+}
+
+impl PathVarExt for SimplePathVar {
+    fn new_witness_optional(
+        cs: impl Into<Namespace<F>>,
+        path: Option<&SimplePath>,
+    ) -> Result<Self, SynthesisError> {
+        SimplePathVar::new_witness(cs, || path.ok_or(SynthesisError::AssignmentMissing))
+    }
+}
+
+/// R1CS representation of `BatchMerkleProof`. The position of the batch is a witness, so the
+/// verifier doesn't learn where in the tree the leaves are.
+pub struct BatchMerkleProofVar<const N: usize> {
+    /// The shared siblings, root-first
+    shared_siblings: Vec<RootVar>,
+    /// The bits of `start_idx / N`, least significant first. Bit `i` says whether the path is a
+    /// right child `i` levels above the subtree root.
+    pos_bits: Vec<Boolean<F>>,
+}
+
+impl<const N: usize> AllocVar<BatchMerkleProof<N>, F> for BatchMerkleProofVar<N> {
+    fn new_variable<T: Borrow<BatchMerkleProof<N>>>(
+        cs: impl Into<Namespace<F>>,
+        f: impl FnOnce() -> Result<T, SynthesisError>,
+        mode: AllocationMode,
+    ) -> Result<Self, SynthesisError> {
+        let ns = cs.into();
+        let cs = ns.cs();
+
+        let proof = f()?;
+        let proof = proof.borrow();
+        let pos = proof.start_idx / N;
+
+        let shared_siblings = proof
+            .shared_siblings
+            .iter()
+            .map(|sibling| {
+                <RootVar as AllocVar<MerkleRoot, _>>::new_variable(
+                    ns!(cs, "sibling"),
+                    || Ok(sibling),
+                    mode,
+                )
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let pos_bits = (0..shared_siblings.len())
+            .map(|i| Boolean::new_variable(ns!(cs, "pos bit"), || Ok((pos >> i) & 1 == 1), mode))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(BatchMerkleProofVar {
+            shared_siblings,
+            pos_bits,
+        })
+    }
+}
+
+/// Computes the root of the tree whose leaves are `leaves`, the same way `SimpleMerkleTree` does.
+/// The number of leaves must be a power of two, and at least 2.
+pub fn subtree_root_var(
+    leaf_crh_params: &LeafHashParamsVar,
+    two_to_one_crh_params: &TwoToOneHashParamsVar,
+    leaves: &[Vec<UInt8<F>>],
+) -> Result<RootVar, SynthesisError> {
+    assert!(
+        leaves.len() >= 2 && leaves.len().is_power_of_two(),
+        "a tree needs a power of two (at least 2) leaves"
+    );
+
+    // Hash the leaves, and convert the digests to bytes like the native tree does
+    let leaf_digests = leaves
+        .iter()
+        .map(|leaf| {
+            let digest = LeafHashGadget::evaluate(leaf_crh_params, leaf)?;
+            <MerkleConfigGadget as ConfigGadget<MerkleConfig, F>>::LeafInnerConverter::convert(
+                digest,
+            )
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // The bottom level hashes pairs of leaf digests as bytes
+    let mut level = leaf_digests
+        .chunks(2)
+        .map(|pair| TwoToOneHashGadget::evaluate(two_to_one_crh_params, &pair[0], &pair[1]))
+        .collect::<Result<Vec<_>, _>>()?;
+    // Every level above that compresses pairs of digests, until we get to the subtree root
+    while level.len() > 1 {
+        level = level
+            .chunks(2)
+            .map(|pair| TwoToOneHashGadget::compress(two_to_one_crh_params, &pair[0], &pair[1]))
+            .collect::<Result<Vec<_>, _>>()?;
+    }
+    Ok(level.pop().unwrap())
+}
+
+impl<const N: usize> BatchMerkleProofVar<N> {
+    /// Computes the root of the tree that `leaves` are in, according to this proof. The circuit
+    /// analog of `BatchMerkleProof::verify_batch` is to enforce that this equals the claimed root.
+    pub fn calculate_root(
+        &self,
+        leaf_crh_params: &LeafHashParamsVar,
+        two_to_one_crh_params: &TwoToOneHashParamsVar,
+        leaves: &[Vec<UInt8<F>>; N],
+    ) -> Result<RootVar, SynthesisError> {
+        let mut cur_node = subtree_root_var(leaf_crh_params, two_to_one_crh_params, leaves)?;
+
+        // Now walk up to the root, putting the sibling on the side that the position bit says
+        for (sibling, is_right) in self.shared_siblings.iter().rev().zip(&self.pos_bits) {
+            let left = is_right.select(sibling, &cur_node)?;
+            let right = is_right.select(&cur_node, sibling)?;
+            cur_node = TwoToOneHashGadget::compress(two_to_one_crh_params, &left, &right)?;
+        }
+
+        Ok(cur_node)
+    }
+}
+
+/// Poseidon Merkle tree params for R1CS. This is analogous to our PoseidonMerkleConfig
+pub struct PoseidonMerkleConfigGadget;
+impl ConfigGadget<PoseidonMerkleConfig, F> for PoseidonMerkleConfigGadget {
+    type Leaf = [FV];
+    type LeafDigest = FV;
+    type LeafInnerConverter = IdentityDigestConverter<FV>;
+    type InnerDigest = FV;
+    type LeafHash = PoseidonLeafHashGadget;
+    type TwoToOneHash = PoseidonTwoToOneHashGadget;
+}
+
+/// R1CS representation of PoseidonMerkleRoot
+pub type PoseidonRootVar = FV;
+
+/// R1CS representation of PoseidonPath
+pub type PoseidonPathVar = PathVar<PoseidonMerkleConfig, F, PoseidonMerkleConfigGadget>;
+
+//
+// TESTS
+//
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::get_test_leaf;
+
+    use ark_crypto_primitives::merkle_tree::DigestConverter;
+
+    // Checks that a sparse tree makes valid proofs for every real leaf, for a bunch of leaf counts
+    // that aren't powers of two
+    #[test]
+    fn sparse_tree_proofs() {
+        let mut rng = ark_std::test_rng();
+        let leaf_crh_params = <LeafHash as CRHScheme>::setup(&mut rng).unwrap();
+        let two_to_one_crh_params = <TwoToOneHash as TwoToOneCRHScheme>::setup(&mut rng).unwrap();
+
+        for num_leaves in [1, 3, 5, 7, 9, 11, 15] {
+            let leaves: Vec<Leaf> = (0..num_leaves)
+                .map(|i| get_test_leaf(&leaf_crh_params, i))
+                .collect();
+            let tree = SparseSimpleMerkleTree::new_sparse(
+                &leaf_crh_params,
+                &two_to_one_crh_params,
+                leaves.clone(),
+            )
+            .unwrap();
+            assert_eq!(tree.num_leaves(), num_leaves);
+
+            // Every real leaf should have a valid proof
+            let root = tree.root();
+            for (idx, leaf) in leaves.iter().enumerate() {
+                let proof = tree.generate_proof(idx).unwrap();
+                assert!(proof
+                    .verify(
+                        &leaf_crh_params,
+                        &two_to_one_crh_params,
+                        &root,
+                        leaf.as_slice()
+                    )
+                    .unwrap());
+            }
+
+            // The slot right after the real leaves is padding
+            assert!(tree.is_empty_slot(num_leaves));
+        }
+    }
+
+    // Asking for a proof past the end of the tree should be an error, not a panic
+    #[test]
+    fn out_of_bounds_proof() {
+        let mut rng = ark_std::test_rng();
+        let leaf_crh_params = <LeafHash as CRHScheme>::setup(&mut rng).unwrap();
+        let two_to_one_crh_params = <TwoToOneHash as TwoToOneCRHScheme>::setup(&mut rng).unwrap();
+
+        let leaves: Vec<Leaf> = (0..8).map(|i| get_test_leaf(&leaf_crh_params, i)).collect();
+        let tree = SimpleMerkleTree::new(&leaf_crh_params, &two_to_one_crh_params, leaves.clone())
+            .unwrap();
+        assert!(tree.generate_proof(7).is_ok());
+        assert!(matches!(
+            tree.generate_proof(8),
+            Err(MerkleError::IndexOutOfBounds { idx: 8, len: 8 })
+        ));
+
+        // Same thing for sparse trees, where the padding doesn't count
+        let sparse_tree = SparseSimpleMerkleTree::new_sparse(
+            &leaf_crh_params,
+            &two_to_one_crh_params,
+            leaves[..5].to_vec(),
+        )
+        .unwrap();
+        assert!(matches!(
+            sparse_tree.generate_proof(5),
+            Err(MerkleError::IndexOutOfBounds { idx: 5, len: 5 })
+        ));
+
+        // And a tree with a bad number of leaves can't be built at all
+        assert!(matches!(
+            SimpleMerkleTree::new(
+                &leaf_crh_params,
+                &two_to_one_crh_params,
+                leaves[..5].to_vec()
+            ),
+            Err(MerkleError::BadNumLeaves { len: 5 })
+        ));
+    }
+
+    // Update a leaf, and check that the new leaf is provably in the tree under the new root
+    #[test]
+    fn update_leaf() {
+        let mut rng = ark_std::test_rng();
+        let leaf_crh_params = <LeafHash as CRHScheme>::setup(&mut rng).unwrap();
+        let two_to_one_crh_params = <TwoToOneHash as TwoToOneCRHScheme>::setup(&mut rng).unwrap();
+
+        let leaves: Vec<Leaf> = (0..16)
+            .map(|i| get_test_leaf(&leaf_crh_params, i))
+            .collect();
+        let mut tree =
+            SimpleMerkleTree::new(&leaf_crh_params, &two_to_one_crh_params, leaves).unwrap();
+        let old_root = tree.root();
+
+        // Replace leaf 3 with leaf 9
+        let idx = 3;
+        let new_leaf = get_test_leaf(&leaf_crh_params, 9);
+
+        // The dry run shouldn't change anything
+        let predicted_root = tree.root_after_update(idx, &new_leaf).unwrap();
+        assert!(tree.root() == old_root);
+        assert!(predicted_root != old_root);
+
+        // Now do the real update. The root should be what we predicted.
+        tree.update_leaf(idx, &new_leaf).unwrap();
+        let new_root = tree.root();
+        assert!(new_root == predicted_root);
+
+        // The new leaf should be provably in the tree under the new root
+        let proof = tree.generate_proof(idx).unwrap();
+        assert!(proof
+            .verify(
+                &leaf_crh_params,
+                &two_to_one_crh_params,
+                &new_root,
+                new_leaf.as_slice()
+            )
+            .unwrap());
+
+        // Out-of-bounds updates fail
+        assert!(matches!(
+            tree.update_leaf(16, &new_leaf),
+            Err(MerkleError::IndexOutOfBounds { idx: 16, len: 16 })
+        ));
+    }
+
+    // Predicting the root only rehashes one path, so check it against a tree built from scratch
+    // at every index, left and right children alike
+    #[test]
+    fn root_after_update_every_index() {
+        let mut rng = ark_std::test_rng();
+        let leaf_crh_params = <LeafHash as CRHScheme>::setup(&mut rng).unwrap();
+        let two_to_one_crh_params = <TwoToOneHash as TwoToOneCRHScheme>::setup(&mut rng).unwrap();
+
+        let leaves: Vec<Leaf> = (0..8).map(|i| get_test_leaf(&leaf_crh_params, i)).collect();
+        let tree = SimpleMerkleTree::new(&leaf_crh_params, &two_to_one_crh_params, leaves.clone())
+            .unwrap();
+        let new_leaf = get_test_leaf(&leaf_crh_params, 12);
+
+        for idx in 0..leaves.len() {
+            let mut new_leaves = leaves.clone();
+            new_leaves[idx] = new_leaf;
+            let rebuilt =
+                SimpleMerkleTree::new(&leaf_crh_params, &two_to_one_crh_params, new_leaves)
+                    .unwrap();
+            assert!(tree.root_after_update(idx, &new_leaf).unwrap() == rebuilt.root());
+        }
+
+        // Out of bounds is still an error
+        assert!(matches!(
+            tree.root_after_update(8, &new_leaf),
+            Err(MerkleError::IndexOutOfBounds { idx: 8, len: 8 })
+        ));
+    }
+
+    // The builder should make the same tree as SimpleMerkleTree::new, and should complain about
+    // missing params and bad leaf counts
+    #[test]
+    fn tree_builder() {
+        let mut rng = ark_std::test_rng();
+        let leaf_crh_params = <LeafHash as CRHScheme>::setup(&mut rng).unwrap();
+        let two_to_one_crh_params = <TwoToOneHash as TwoToOneCRHScheme>::setup(&mut rng).unwrap();
+        let leaves: Vec<Leaf> = (0..8).map(|i| get_test_leaf(&leaf_crh_params, i)).collect();
+
+        let tree = TreeBuilder::new()
+            .leaf_params(leaf_crh_params.clone())
+            .two_to_one_params(two_to_one_crh_params.clone())
+            .leaves(leaves.clone())
+            .build()
+            .unwrap();
+        let expected_tree =
+            SimpleMerkleTree::new(&leaf_crh_params, &two_to_one_crh_params, leaves.clone())
+                .unwrap();
+        assert!(tree.root() == expected_tree.root());
+
+        // Missing either set of params is an error
+        assert!(matches!(
+            TreeBuilder::new()
+                .two_to_one_params(two_to_one_crh_params.clone())
+                .leaves(leaves.clone())
+                .build(),
+            Err(MerkleError::MissingParams { which: "leaf" })
+        ));
+        assert!(matches!(
+            TreeBuilder::new()
+                .leaf_params(leaf_crh_params.clone())
+                .leaves(leaves)
+                .build(),
+            Err(MerkleError::MissingParams {
+                which: "two-to-one"
+            })
+        ));
+
+        // So is having no leaves
+        assert!(matches!(
+            TreeBuilder::new()
+                .leaf_params(leaf_crh_params)
+                .two_to_one_params(two_to_one_crh_params)
+                .build(),
+            Err(MerkleError::BadNumLeaves { len: 0 })
+        ));
+    }
+
+    // Roots should survive a round trip through bytes, and garbage bytes should be rejected
+    #[test]
+    fn root_bytes() {
+        use ark_ff::UniformRand;
+
+        let mut rng = ark_std::test_rng();
+        let root = MerkleRoot::rand(&mut rng);
+
+        let bytes = root_to_bytes(&root);
+        assert_eq!(root_from_bytes(&bytes).unwrap(), root);
+
+        // Too short
+        assert!(root_from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        // Not a point on the curve. The all-ones string encodes an x-coordinate bigger than the
+        // modulus.
+        assert!(root_from_bytes(&vec![0xff; bytes.len()]).is_err());
+    }
+
+    // The tree should hand back the leaves it was built with, and keep them up to date
+    #[test]
+    fn leaves_match_proofs() {
+        let mut rng = ark_std::test_rng();
+        let leaf_crh_params = <LeafHash as CRHScheme>::setup(&mut rng).unwrap();
+        let two_to_one_crh_params = <TwoToOneHash as TwoToOneCRHScheme>::setup(&mut rng).unwrap();
+
+        let leaves: Vec<Leaf> = (0..8).map(|i| get_test_leaf(&leaf_crh_params, i)).collect();
+        let mut tree =
+            SimpleMerkleTree::new(&leaf_crh_params, &two_to_one_crh_params, leaves.clone())
+                .unwrap();
+        assert_eq!(tree.leaves(), leaves.as_slice());
+
+        // Every leaf should verify against the proof for its index
+        let root = tree.root();
+        for (idx, leaf) in tree.leaves().iter().enumerate() {
+            let proof = tree.generate_proof(idx).unwrap();
+            assert!(proof
+                .verify(
+                    &leaf_crh_params,
+                    &two_to_one_crh_params,
+                    &root,
+                    leaf.as_slice()
+                )
+                .unwrap());
+        }
+
+        // Updates show up in the leaves
+        let new_leaf = get_test_leaf(&leaf_crh_params, 9);
+        tree.update_leaf(2, &new_leaf).unwrap();
+        assert_eq!(tree.leaves()[2], new_leaf);
+    }
+
+    // The subtree of all the leaves is the whole tree, and a subtree of half of them is the same as
+    // a tree made from just that half
+    #[test]
+    fn subtree_roots() {
+        let mut rng = ark_std::test_rng();
+        let leaf_crh_params = <LeafHash as CRHScheme>::setup(&mut rng).unwrap();
+        let two_to_one_crh_params = <TwoToOneHash as TwoToOneCRHScheme>::setup(&mut rng).unwrap();
+
+        let leaves: Vec<Leaf> = (0..16)
+            .map(|i| get_test_leaf(&leaf_crh_params, i))
+            .collect();
+        let tree = SimpleMerkleTree::new(&leaf_crh_params, &two_to_one_crh_params, leaves.clone())
+            .unwrap();
+        assert!(tree.subtree_root(0, 16).unwrap() == tree.root());
+
+        let half_tree = SimpleMerkleTree::new(
+            &leaf_crh_params,
+            &two_to_one_crh_params,
+            leaves[8..].to_vec(),
+        )
+        .unwrap();
+        assert!(tree.subtree_root(8, 8).unwrap() == half_tree.root());
+
+        // Subtrees can't run off the end, or be empty
+        assert!(matches!(
+            tree.subtree_root(8, 9),
+            Err(MerkleError::IndexOutOfBounds { idx: 16, len: 16 })
+        ));
+        assert!(matches!(
+            tree.subtree_root(0, 0),
+            Err(MerkleError::BadNumLeaves { len: 0 })
+        ));
+        assert!(matches!(
+            tree.subtree_root(8, usize::MAX),
+            Err(MerkleError::IndexOutOfBounds { len: 16, .. })
+        ));
+    }
+
+    // Proofs from a forest should verify against the root of the tree they came from, and only
+    // that tree
+    #[test]
+    fn forest_proofs() {
+        let mut rng = ark_std::test_rng();
+        let leaf_crh_params = <LeafHash as CRHScheme>::setup(&mut rng).unwrap();
+        let two_to_one_crh_params = <TwoToOneHash as TwoToOneCRHScheme>::setup(&mut rng).unwrap();
+
+        let mut forest = MerkleForest::new();
+        for offset in [0, 8] {
+            let leaves: Vec<Leaf> = (offset..offset + 8)
+                .map(|i| get_test_leaf(&leaf_crh_params, i))
+                .collect();
+            let tree =
+                SimpleMerkleTree::new(&leaf_crh_params, &two_to_one_crh_params, leaves).unwrap();
+            assert_eq!(forest.add_tree(tree), offset / 8);
+        }
+        assert_eq!(forest.num_trees(), 2);
+
+        // Leaf 3 of tree 1 is test leaf 11
+        let leaf = get_test_leaf(&leaf_crh_params, 11);
+        let (path, tree_idx) = forest.generate_cross_tree_proof(1, 3).unwrap();
+        assert_eq!(tree_idx, 1);
+        let verify_against = |root: MerkleRoot| {
+            path.verify(
+                &leaf_crh_params,
+                &two_to_one_crh_params,
+                &root,
+                leaf.as_slice(),
+            )
+            .unwrap()
+        };
+        assert!(verify_against(forest.root_of(1).unwrap()));
+        assert!(!verify_against(forest.root_of(0).unwrap()));
+
+        // Out-of-bounds trees are errors
+        assert!(matches!(
+            forest.generate_cross_tree_proof(2, 0),
+            Err(MerkleError::TreeIndexOutOfBounds { idx: 2, len: 2 })
+        ));
+    }
+
+    // Only exactly 64 bytes make a leaf
+    #[test]
+    fn leaf_conversion() {
+        let leaf: Leaf = core::array::from_fn(|i| i as u8);
+        let leaf_vec = leaf.to_vec();
+        assert_eq!(leaf_from_bytes(&leaf), Ok(leaf));
+        assert_eq!(leaf_from_bytes(&leaf_vec), Ok(leaf));
+
+        for len in [0, 32, 63, 65, 128] {
+            assert_eq!(
+                leaf_from_bytes(&vec![0u8; len]),
+                Err(LeafConversionError {
+                    expected: 64,
+                    got: len
+                })
+            );
+        }
+    }
+
+    // Updating a leaf makes every old proof stale, since they all end at the old root. Fresh
+    // proofs are fine.
+    #[test]
+    fn stale_paths_after_update() {
+        let mut rng = ark_std::test_rng();
+        let leaf_crh_params = <LeafHash as CRHScheme>::setup(&mut rng).unwrap();
+        let two_to_one_crh_params = <TwoToOneHash as TwoToOneCRHScheme>::setup(&mut rng).unwrap();
+
+        let leaves: Vec<Leaf> = (0..8).map(|i| get_test_leaf(&leaf_crh_params, i)).collect();
+        let mut tree =
+            SimpleMerkleTree::new(&leaf_crh_params, &two_to_one_crh_params, leaves).unwrap();
+        let proofs_of = |tree: &SimpleMerkleTree, idxs: &[usize]| -> Vec<(SimplePath, Leaf)> {
+            idxs.iter()
+                .map(|&i| (tree.generate_proof(i).unwrap(), tree.leaves()[i]))
+                .collect()
+        };
+
+        let old_paths = proofs_of(&tree, &[1, 2, 5]);
+        let checker = TreeConsistencyChecker(&tree);
+        assert!(checker.stale_paths_in(&old_paths, &tree.root()).is_empty());
+
+        // A proof of the wrong leaf is inconsistent
+        let (path, _) = &old_paths[0];
+        assert!(!checker.is_consistent(path, &EMPTY_LEAF, &tree.root()));
+
+        tree.update_leaf(2, &[100u8; 64]).unwrap();
+        let new_root = tree.root();
+        let checker = TreeConsistencyChecker(&tree);
+        assert_eq!(checker.stale_paths_in(&old_paths, &new_root), vec![0, 1, 2]);
+        let new_paths = proofs_of(&tree, &[1, 2, 5]);
+        assert!(checker.stale_paths_in(&new_paths, &new_root).is_empty());
+    }
+
+    // Pushing leaves one by one gives the same tree as passing them all at once
+    #[test]
+    fn tree_builder_push() {
+        let mut rng = ark_std::test_rng();
+        let leaf_crh_params = <LeafHash as CRHScheme>::setup(&mut rng).unwrap();
+        let two_to_one_crh_params = <TwoToOneHash as TwoToOneCRHScheme>::setup(&mut rng).unwrap();
+
+        let leaves: Vec<Leaf> = (0..16)
+            .map(|i| get_test_leaf(&leaf_crh_params, i))
+            .collect();
+        let tree = SimpleMerkleTree::new(&leaf_crh_params, &two_to_one_crh_params, leaves.clone())
+            .unwrap();
+
+        let mut builder = TreeBuilder::new();
+        assert!(builder.is_empty());
+        for leaf in leaves {
+            builder.push(leaf);
+        }
+        assert_eq!(builder.len(), 16);
+        let built_tree = builder
+            .leaf_params(leaf_crh_params.clone())
+            .two_to_one_params(two_to_one_crh_params.clone())
+            .build()
+            .unwrap();
+        assert!(built_tree.root() == tree.root());
+
+        // The usual restrictions on the number of leaves apply
+        let mut builder = TreeBuilder::new()
+            .leaf_params(leaf_crh_params)
+            .two_to_one_params(two_to_one_crh_params);
+        builder.push(EMPTY_LEAF);
+        builder.push(EMPTY_LEAF);
+        builder.push(EMPTY_LEAF);
+        assert!(matches!(
+            builder.build(),
+            Err(MerkleError::BadNumLeaves { len: 3 })
+        ));
+    }
+
+    // Checks the accessors of MembershipPath against the path they wrap
+    #[test]
+    fn membership_path_accessors() {
+        let mut rng = ark_std::test_rng();
+        let leaf_crh_params = <LeafHash as CRHScheme>::setup(&mut rng).unwrap();
+        let two_to_one_crh_params = <TwoToOneHash as TwoToOneCRHScheme>::setup(&mut rng).unwrap();
+
+        let leaves: Vec<Leaf> = (0..16)
+            .map(|i| get_test_leaf(&leaf_crh_params, i))
+            .collect();
+        let tree = SimpleMerkleTree::new(&leaf_crh_params, &two_to_one_crh_params, leaves).unwrap();
+
+        // Index 6 is 0110 in binary, so the way down is left, right, right, left
+        let path = MembershipPath::new(tree.generate_proof(6).unwrap());
+        assert_eq!(path.depth(), 4);
+        assert_eq!(path.leaf_index_bits(), vec![false, true, true, false]);
+
+        // The top sibling is the first element of the auth path, and the bottom one is the leaf's
+        // sibling
+        let inner = path.path();
+        assert_eq!(path.sibling_at(0), root_to_bytes(&inner.auth_path[0]));
+        assert_eq!(path.sibling_at(3), root_to_bytes(&inner.leaf_sibling_hash));
+
+        // All the levels agree with SiblingsIterator, which goes the other way
+        let siblings: Vec<_> = SiblingsIterator::new(inner).collect();
+        for level in 0..path.depth() {
+            assert_eq!(
+                path.sibling_at(level),
+                root_to_bytes(siblings[path.depth() - 1 - level])
+            );
+        }
+
+        // The first and last leaves go all left and all right
+        let first = MembershipPath::from(tree.generate_proof(0).unwrap());
+        assert_eq!(first.leaf_index_bits(), vec![false; 4]);
+        let last = MembershipPath::from(tree.generate_proof(15).unwrap());
+        assert_eq!(last.leaf_index_bits(), vec![true; 4]);
+    }
+
+    // Recomputing the root by hand from the siblings should give the tree's root
+    #[test]
+    fn siblings_recompute_root() {
+        let mut rng = ark_std::test_rng();
+        let leaf_crh_params = <LeafHash as CRHScheme>::setup(&mut rng).unwrap();
+        let two_to_one_crh_params = <TwoToOneHash as TwoToOneCRHScheme>::setup(&mut rng).unwrap();
+
+        let leaves: Vec<Leaf> = (0..16)
+            .map(|i| get_test_leaf(&leaf_crh_params, i))
+            .collect();
+        let tree = SimpleMerkleTree::new(&leaf_crh_params, &two_to_one_crh_params, leaves).unwrap();
+
+        for (idx, leaf) in tree.leaves().iter().enumerate() {
+            let path = tree.generate_proof(idx).unwrap();
+            let mut siblings = SiblingsIterator::new(&path);
+            // A 16-leaf tree has 4 levels below the root
+            assert_eq!(SiblingsIterator::new(&path).count(), 4);
+
+            // The bottom level hashes the leaf digests as bytes
+            let leaf_digest = LeafHash::evaluate(&leaf_crh_params, leaf.as_slice()).unwrap();
+            let leaf_sibling = siblings.next().unwrap();
+            let (left, right) = if idx & 1 == 0 {
+                (&leaf_digest, leaf_sibling)
+            } else {
+                (leaf_sibling, &leaf_digest)
+            };
+            let to_bytes =
+                |d: &<LeafHash as CRHScheme>::Output| ByteDigestConverter::convert(*d).unwrap();
+            let mut cur_node =
+                TwoToOneHash::evaluate(&two_to_one_crh_params, to_bytes(left), to_bytes(right))
+                    .unwrap();
+
+            // Every level above that compresses the digests directly
+            let mut pos = idx >> 1;
+            for sibling in siblings {
+                cur_node = if pos & 1 == 0 {
+                    TwoToOneHash::compress(&two_to_one_crh_params, &cur_node, sibling)
+                } else {
+                    TwoToOneHash::compress(&two_to_one_crh_params, sibling, &cur_node)
+                }
+                .unwrap();
+                pos >>= 1;
+            }
+
+            assert!(cur_node == tree.root());
+        }
+    }
+
+    // Pushing more than N roots should evict the oldest ones, and keep the rest
+    #[test]
+    fn root_history_eviction() {
+        use ark_ff::UniformRand;
+
+        let mut rng = ark_std::test_rng();
+        let roots: Vec<MerkleRoot> = (0..5).map(|_| MerkleRoot::rand(&mut rng)).collect();
+
+        let mut history = MerkleRootHistory::<3>::new();
+        assert_eq!(history.latest(), None);
+        for (block, root) in roots.iter().enumerate() {
+            history.push(*root, block as u64);
+            assert_eq!(history.latest(), Some((*root, block as u64)));
+        }
+
+        // Only the last 3 roots remain
+        assert!(!history.contains_root(&roots[0]));
+        assert!(!history.contains_root(&roots[1]));
+        for root in &roots[2..] {
+            assert!(history.contains_root(root));
+        }
+    }
+
+    // A batch proof should verify exactly the leaves it was made for, both natively and in the
+    // circuit
+    #[test]
+    fn batch_proofs() {
+        use ark_r1cs_std::{eq::EqGadget, R1CSVar};
+        use ark_relations::r1cs::ConstraintSystem;
+
+        let mut rng = ark_std::test_rng();
+        let leaf_crh_params = <LeafHash as CRHScheme>::setup(&mut rng).unwrap();
+        let two_to_one_crh_params = <TwoToOneHash as TwoToOneCRHScheme>::setup(&mut rng).unwrap();
+
+        let leaves: Vec<Leaf> = (0..16)
+            .map(|i| get_test_leaf(&leaf_crh_params, i))
+            .collect();
+        let tree = SimpleMerkleTree::new(&leaf_crh_params, &two_to_one_crh_params, leaves).unwrap();
+        let root = tree.root();
+
+        // Every aligned batch of 4 should verify
+        for start_idx in (0..16).step_by(4) {
+            let proof = generate_batch_proof::<4>(&tree, start_idx).unwrap();
+            let batch: [Leaf; 4] = tree.leaves()[start_idx..start_idx + 4].try_into().unwrap();
+            assert!(proof
+                .verify_batch(&leaf_crh_params, &two_to_one_crh_params, &root, &batch)
+                .unwrap());
+
+            // Swapping two of the leaves breaks it
+            let mut swapped = batch;
+            swapped.swap(0, 1);
+            assert!(!proof
+                .verify_batch(&leaf_crh_params, &two_to_one_crh_params, &root, &swapped)
+                .unwrap());
+        }
+
+        // A batch as big as the tree has no shared siblings
+        let whole = generate_batch_proof::<16>(&tree, 0).unwrap();
+        assert!(whole.shared_siblings.is_empty());
+
+        // Batches have to be aligned, and fit in the tree
+        assert!(matches!(
+            generate_batch_proof::<4>(&tree, 2),
+            Err(MerkleError::UnalignedBatch {
+                start_idx: 2,
+                batch_size: 4
+            })
+        ));
+        assert!(generate_batch_proof::<32>(&tree, 0).is_err());
+
+        // Now do the same thing in a circuit
+        let start_idx = 8;
+        let proof = generate_batch_proof::<4>(&tree, start_idx).unwrap();
+        let cs = ConstraintSystem::new_ref();
+        let leaf_crh_params_var =
+            LeafHashParamsVar::new_constant(cs.clone(), &leaf_crh_params).unwrap();
+        let two_to_one_crh_params_var =
+            TwoToOneHashParamsVar::new_constant(cs.clone(), &two_to_one_crh_params).unwrap();
+        let proof_var = BatchMerkleProofVar::<4>::new_witness(cs.clone(), || Ok(&proof)).unwrap();
+        let leaf_vars: [Vec<UInt8<F>>; 4] = core::array::from_fn(|i| {
+            UInt8::new_witness_vec(cs.clone(), &tree.leaves()[start_idx + i]).unwrap()
+        });
+        let root_var =
+            <RootVar as AllocVar<MerkleRoot, _>>::new_input(cs.clone(), || Ok(&root)).unwrap();
+
+        let computed_root_var = proof_var
+            .calculate_root(&leaf_crh_params_var, &two_to_one_crh_params_var, &leaf_vars)
+            .unwrap();
+        assert!(computed_root_var.value().unwrap() == root);
+        computed_root_var.enforce_equal(&root_var).unwrap();
+        assert!(cs.is_satisfied().unwrap());
+    }
+
+    // Diffing two versions of a tree should find exactly the leaves that were updated
+    #[test]
+    fn tree_diff() {
+        let mut rng = ark_std::test_rng();
+        let leaf_crh_params = <LeafHash as CRHScheme>::setup(&mut rng).unwrap();
+        let two_to_one_crh_params = <TwoToOneHash as TwoToOneCRHScheme>::setup(&mut rng).unwrap();
+
+        let leaves: Vec<Leaf> = (0..16)
+            .map(|i| get_test_leaf(&leaf_crh_params, i))
+            .collect();
+        let old = SimpleMerkleTree::new(&leaf_crh_params, &two_to_one_crh_params, leaves).unwrap();
+        assert!(SimpleMerkleTree::diff(&old, &old).is_empty());
+
+        // Update three leaves
+        let mut new = SimpleMerkleTree::new(
+            &leaf_crh_params,
+            &two_to_one_crh_params,
+            old.leaves().to_vec(),
+        )
+        .unwrap();
+        let changed_idxs = [2, 9, 15];
+        for &idx in &changed_idxs {
+            new.update_leaf(idx, &[idx as u8; 64]).unwrap();
+        }
+
+        let diff = SimpleMerkleTree::diff(&old, &new);
+        assert_eq!(diff.len(), 3);
+        for ((idx, old_leaf, new_leaf), expected_idx) in diff.into_iter().zip(changed_idxs) {
+            assert_eq!(idx, expected_idx);
+            assert_eq!(old_leaf, old.leaves()[idx]);
+            assert_eq!(new_leaf, [idx as u8; 64]);
+        }
+    }
+
+    // Witnessing a missing path is an error, not a panic
+    #[test]
+    fn optional_path_witness() {
+        use ark_relations::r1cs::ConstraintSystem;
+
+        let mut rng = ark_std::test_rng();
+        let leaf_crh_params = <LeafHash as CRHScheme>::setup(&mut rng).unwrap();
+        let two_to_one_crh_params = <TwoToOneHash as TwoToOneCRHScheme>::setup(&mut rng).unwrap();
+
+        let leaves: Vec<Leaf> = (0..4).map(|i| get_test_leaf(&leaf_crh_params, i)).collect();
+        let tree = SimpleMerkleTree::new(&leaf_crh_params, &two_to_one_crh_params, leaves).unwrap();
+        let path = tree.generate_proof(1).unwrap();
+
+        // A present path allocates fine
+        let cs = ConstraintSystem::<F>::new_ref();
+        assert!(SimplePathVar::new_witness_optional(ns!(cs, "path"), Some(&path)).is_ok());
+
+        // A missing one gives AssignmentMissing
+        let cs = ConstraintSystem::<F>::new_ref();
+        assert!(matches!(
+            SimplePathVar::new_witness_optional(ns!(cs, "path"), None),
+            Err(SynthesisError::AssignmentMissing)
+        ));
+    }
+
+    // The validator accepts real paths and rejects the wrong root, leaf, or sibling
+    #[test]
+    fn path_validator() {
+        let mut rng = ark_std::test_rng();
+        let leaf_crh_params = <LeafHash as CRHScheme>::setup(&mut rng).unwrap();
+        let two_to_one_crh_params = <TwoToOneHash as TwoToOneCRHScheme>::setup(&mut rng).unwrap();
+
+        let leaves: Vec<Leaf> = (0..8).map(|i| get_test_leaf(&leaf_crh_params, i)).collect();
+        let tree = SimpleMerkleTree::new(&leaf_crh_params, &two_to_one_crh_params, leaves).unwrap();
+        let root = tree.root();
+        let validator = MerklePathValidator(&leaf_crh_params, &two_to_one_crh_params, &root);
+
+        let path = tree.generate_proof(2).unwrap();
+        let leaf = tree.leaves()[2];
+        assert!(validator.validate(&path, &leaf).is_ok());
+
+        // Wrong tree root
+        let mut other_leaves = tree.leaves().to_vec();
+        other_leaves[5] = [5u8; 64];
+        let other_root =
+            SimpleMerkleTree::new(&leaf_crh_params, &two_to_one_crh_params, other_leaves)
+                .unwrap()
+                .root();
+        let other_validator =
+            MerklePathValidator(&leaf_crh_params, &two_to_one_crh_params, &other_root);
+        assert!(matches!(
+            other_validator.validate(&path, &leaf),
+            Err(PathValidationError::RootMismatch)
+        ));
+
+        // Wrong leaf
+        assert!(matches!(
+            validator.validate(&path, &tree.leaves()[3]),
+            Err(PathValidationError::RootMismatch)
+        ));
+
+        // Wrong sibling
+        let mut bad_sibling_path = path.clone();
+        bad_sibling_path.leaf_sibling_hash =
+            LeafHash::evaluate(&leaf_crh_params, tree.leaves()[4].as_slice()).unwrap();
+        assert!(matches!(
+            validator.validate(&bad_sibling_path, &leaf),
+            Err(PathValidationError::RootMismatch)
+        ));
+    }
+}
@@ -0,0 +1,66 @@
+use crate::{
+    hash::{LeafHashParams, TwoToOneHashParams},
+    merkle::SimpleMerkleTree,
+};
+
+/// Checks every leaf of the tree against its root, and returns the indices of the leaves that
+/// have valid membership proofs. In a healthy tree, this is every index. This is mainly useful
+/// after detecting that the tree's state has been corrupted, to find out which leaves can still
+/// be shown.
+pub fn audit_leaves(
+    tree: &SimpleMerkleTree,
+    params: &LeafHashParams,
+    two_to_one_params: &TwoToOneHashParams,
+) -> Vec<usize> {
+    let root = tree.root();
+    tree.leaves()
+        .iter()
+        .enumerate()
+        .filter(|(idx, leaf)| {
+            // A proof that fails to generate or verify is just an invalid index
+            tree.generate_proof(*idx)
+                .and_then(|proof| {
+                    Ok(proof.verify(params, two_to_one_params, &root, leaf.as_slice())?)
+                })
+                .unwrap_or(false)
+        })
+        .map(|(idx, _)| idx)
+        .collect()
+}
+
+//
+// TESTS
+//
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        hash::{LeafHash, TwoToOneHash},
+        util::gen_test_tree,
+    };
+
+    use ark_crypto_primitives::crh::{CRHScheme, TwoToOneCRHScheme};
+
+    // Every leaf of a fresh tree passes the audit. If we corrupt the stored copy of one leaf, that
+    // leaf no longer matches the root, so it drops out.
+    #[test]
+    fn audit_corrupted_leaf() {
+        let mut rng = ark_std::test_rng();
+        let leaf_crh_params = <LeafHash as CRHScheme>::setup(&mut rng).unwrap();
+        let two_to_one_crh_params = <TwoToOneHash as TwoToOneCRHScheme>::setup(&mut rng).unwrap();
+
+        let mut tree = gen_test_tree(&leaf_crh_params, &two_to_one_crh_params);
+        let num_leaves = tree.num_leaves();
+        let valid = audit_leaves(&tree, &leaf_crh_params, &two_to_one_crh_params);
+        assert_eq!(valid, (0..num_leaves).collect::<Vec<_>>());
+
+        // Flip a bit in leaf 3 without touching the hashes above it
+        let bad_idx = 3;
+        tree.leaves[bad_idx][0] ^= 1;
+
+        let valid = audit_leaves(&tree, &leaf_crh_params, &two_to_one_crh_params);
+        assert_eq!(valid.len(), num_leaves - 1);
+        assert!(!valid.contains(&bad_idx));
+    }
+}
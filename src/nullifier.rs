@@ -0,0 +1,281 @@
+use crate::{card::Card, merkle::Leaf, F};
+
+use core::fmt;
+use std::collections::{HashMap, HashSet};
+
+use ark_serialize::{
+    CanonicalDeserialize, CanonicalSerialize, Compress, Read, SerializationError, Valid, Validate,
+    Write,
+};
+
+/// The number of bytes in a serialized nullifier
+const NULLIFIER_BYTES: usize = 32;
+
+/// A set of the nullifiers that have been revealed so far. In our case, a nullifier is a card's
+/// serial number, which is revealed every time someone shows possession of the card. If a
+/// nullifier shows up twice, the same card was shown twice.
+#[derive(Clone, Default)]
+pub struct NullifierSet(HashSet<[u8; NULLIFIER_BYTES]>);
+
+impl NullifierSet {
+    /// Makes an empty nullifier set
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the given nullifier to the set. Returns `false` if it was already present, i.e., if
+    /// this is a double-show.
+    pub fn insert(&mut self, nullifier: F) -> bool {
+        self.0.insert(nullifier_key(&nullifier))
+    }
+
+    /// Returns whether the given nullifier has been seen before
+    pub fn contains(&self, nullifier: &F) -> bool {
+        self.0.contains(&nullifier_key(nullifier))
+    }
+
+    /// Returns the number of nullifiers in the set
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Returns whether the set is empty
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// Serializes a nullifier into the form we store in the set
+fn nullifier_key(nullifier: &F) -> [u8; NULLIFIER_BYTES] {
+    let mut key = [0u8; NULLIFIER_BYTES];
+    nullifier
+        .serialize_compressed(&mut key[..])
+        .expect("field element is larger than 32 bytes");
+    key
+}
+
+/// An error from spending a card in a `NotePool`
+#[derive(Debug)]
+pub enum NullifierError {
+    /// No card in the pool has this nullifier
+    NotFound,
+    /// The card with this nullifier was already spent
+    AlreadySpent,
+}
+
+impl fmt::Display for NullifierError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NullifierError::NotFound => write!(f, "no card has the given nullifier"),
+            NullifierError::AlreadySpent => write!(f, "the card was already spent"),
+        }
+    }
+}
+
+impl std::error::Error for NullifierError {}
+
+/// A wallet of cards, along with their commitments and commitment randomness. A card's nullifier
+/// is its serial number. Spending a card records its nullifier, so it can't be spent again.
+#[derive(Clone, Default)]
+pub struct NotePool {
+    /// Every card ever added, as `(card, leaf, com_rand)`
+    notes: Vec<(Card, Leaf, F)>,
+    /// Maps a nullifier to the index of its card in `notes`
+    by_nullifier: HashMap<[u8; NULLIFIER_BYTES], usize>,
+    /// The nullifiers of the spent cards
+    spent: NullifierSet,
+}
+
+impl NotePool {
+    /// Makes an empty pool
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a card to the pool. `leaf` is its commitment under the randomness `com_rand`. If a card
+    /// with the same nullifier is already in the pool, it is replaced.
+    pub fn add(&mut self, card: Card, com_rand: F, leaf: Leaf) {
+        let key = nullifier_key(&card.serial_num);
+        let entry = (card, leaf, com_rand);
+        match self.by_nullifier.get(&key) {
+            Some(&idx) => self.notes[idx] = entry,
+            None => {
+                self.by_nullifier.insert(key, self.notes.len());
+                self.notes.push(entry);
+            }
+        }
+    }
+
+    /// Returns the card with the given nullifier, spent or not
+    pub fn find_by_nullifier(&self, nullifier: &F) -> Option<&(Card, Leaf, F)> {
+        self.by_nullifier
+            .get(&nullifier_key(nullifier))
+            .map(|&idx| &self.notes[idx])
+    }
+
+    /// Marks the card with the given nullifier as spent
+    pub fn mark_spent(&mut self, nullifier: &F) -> Result<(), NullifierError> {
+        if self.find_by_nullifier(nullifier).is_none() {
+            return Err(NullifierError::NotFound);
+        }
+        if !self.spent.insert(*nullifier) {
+            return Err(NullifierError::AlreadySpent);
+        }
+        Ok(())
+    }
+
+    /// Returns all the cards that haven't been spent, in the order they were added
+    pub fn unspent(&self) -> impl Iterator<Item = &(Card, Leaf, F)> {
+        self.notes
+            .iter()
+            .filter(|(card, _, _)| !self.spent.contains(&card.serial_num))
+    }
+}
+
+// We serialize the set as its length followed by all the nullifiers in sorted order. Sorting makes
+// the serialization independent of the HashSet's iteration order.
+impl CanonicalSerialize for NullifierSet {
+    fn serialize_with_mode<W: Write>(
+        &self,
+        mut writer: W,
+        compress: Compress,
+    ) -> Result<(), SerializationError> {
+        let mut keys: Vec<_> = self.0.iter().collect();
+        keys.sort();
+
+        (keys.len() as u64).serialize_with_mode(&mut writer, compress)?;
+        for key in keys {
+            writer.write_all(key)?;
+        }
+        Ok(())
+    }
+
+    fn serialized_size(&self, compress: Compress) -> usize {
+        (self.0.len() as u64).serialized_size(compress) + self.0.len() * NULLIFIER_BYTES
+    }
+}
+
+impl Valid for NullifierSet {
+    fn check(&self) -> Result<(), SerializationError> {
+        Ok(())
+    }
+}
+
+impl CanonicalDeserialize for NullifierSet {
+    fn deserialize_with_mode<R: Read>(
+        mut reader: R,
+        compress: Compress,
+        validate: Validate,
+    ) -> Result<Self, SerializationError> {
+        let len = u64::deserialize_with_mode(&mut reader, compress, validate)?;
+
+        let mut set = HashSet::new();
+        for _ in 0..len {
+            let mut key = [0u8; NULLIFIER_BYTES];
+            reader.read_exact(&mut key)?;
+            set.insert(key);
+        }
+        Ok(NullifierSet(set))
+    }
+}
+
+//
+// TESTS
+//
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::hash::LeafHash;
+
+    use ark_crypto_primitives::crh::CRHScheme;
+    use ark_ff::UniformRand;
+
+    // Inserting the same nullifier twice should be caught
+    #[test]
+    fn double_show_detection() {
+        let mut rng = ark_std::test_rng();
+        let mut set = NullifierSet::new();
+
+        let nf1 = F::rand(&mut rng);
+        let nf2 = F::rand(&mut rng);
+
+        assert!(!set.contains(&nf1));
+        assert!(set.insert(nf1));
+        assert!(set.contains(&nf1));
+        assert!(!set.contains(&nf2));
+
+        // Second insertion of the same nullifier fails
+        assert!(!set.insert(nf1));
+        assert_eq!(set.len(), 1);
+
+        // A different nullifier is fine
+        assert!(set.insert(nf2));
+        assert_eq!(set.len(), 2);
+    }
+
+    // The set should survive a round trip through bytes
+    #[test]
+    fn serialization_round_trip() {
+        let mut rng = ark_std::test_rng();
+        let mut set = NullifierSet::new();
+        let nullifiers: Vec<F> = (0..10).map(|_| F::rand(&mut rng)).collect();
+        for nf in &nullifiers {
+            set.insert(*nf);
+        }
+
+        let mut buf = Vec::new();
+        set.serialize_compressed(&mut buf).unwrap();
+        assert_eq!(buf.len(), set.compressed_size());
+        let mut deserialized_set = NullifierSet::deserialize_compressed(buf.as_slice()).unwrap();
+
+        // Everything that was in the set is still there, and the restored set still catches
+        // double-shows
+        assert_eq!(deserialized_set.len(), nullifiers.len());
+        for nf in &nullifiers {
+            assert!(deserialized_set.contains(nf));
+            assert!(!deserialized_set.insert(*nf));
+        }
+    }
+
+    // Cards can be found by nullifier and spent exactly once
+    #[test]
+    fn note_pool_spend() {
+        let mut rng = ark_std::test_rng();
+        let leaf_crh_params = <LeafHash as CRHScheme>::setup(&mut rng).unwrap();
+        let mut pool = NotePool::new();
+
+        let cards: Vec<Card> = (0..3).map(|_| Card::rand(&mut rng)).collect();
+        for card in &cards {
+            let com_rand = F::rand(&mut rng);
+            let leaf = card.commit(&leaf_crh_params, &com_rand);
+            pool.add(card.clone(), com_rand, leaf);
+        }
+
+        // Find
+        let (found_card, found_leaf, found_com_rand) =
+            pool.find_by_nullifier(&cards[1].serial_num).unwrap();
+        assert_eq!(found_card, &cards[1]);
+        assert!(found_card.verify_commitment(&leaf_crh_params, found_com_rand, found_leaf));
+        assert!(pool.find_by_nullifier(&F::rand(&mut rng)).is_none());
+
+        // Spend
+        pool.mark_spent(&cards[1].serial_num).unwrap();
+        let unspent: Vec<&Card> = pool.unspent().map(|(card, _, _)| card).collect();
+        assert_eq!(unspent, vec![&cards[0], &cards[2]]);
+        // Spent cards can still be found
+        assert!(pool.find_by_nullifier(&cards[1].serial_num).is_some());
+
+        // Double-spend
+        assert!(matches!(
+            pool.mark_spent(&cards[1].serial_num),
+            Err(NullifierError::AlreadySpent)
+        ));
+        // Spending something that isn't there
+        assert!(matches!(
+            pool.mark_spent(&F::rand(&mut rng)),
+            Err(NullifierError::NotFound)
+        ));
+    }
+}
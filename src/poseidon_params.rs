@@ -0,0 +1,41 @@
+use crate::F;
+
+use ark_crypto_primitives::sponge::poseidon::{find_poseidon_ark_and_mds, PoseidonConfig};
+use ark_ff::PrimeField;
+
+// These are the usual Poseidon parameters for a 255-bit field with a width-3 permutation, i.e., a
+// rate of 2 and a capacity of 1. See Table 2 of the Poseidon paper
+// (https://eprint.iacr.org/2019/458.pdf) for where the round numbers come from.
+
+/// The number of field elements absorbed per permutation
+pub const POSEIDON_RATE: usize = 2;
+/// The number of field elements of hidden state
+pub const POSEIDON_CAPACITY: usize = 1;
+/// The number of rounds where every state element goes through the S-box
+pub const POSEIDON_FULL_ROUNDS: usize = 8;
+/// The number of rounds where only one state element goes through the S-box
+pub const POSEIDON_PARTIAL_ROUNDS: usize = 57;
+/// The exponent of the S-box. This must be coprime to `p - 1`
+pub const POSEIDON_ALPHA: u64 = 5;
+
+/// Returns the Poseidon parameters we use for every Poseidon hash over `F`. The round constants and
+/// MDS matrix are derived deterministically, so this always returns the same thing.
+pub fn poseidon_params() -> PoseidonConfig<F> {
+    let (ark, mds) = find_poseidon_ark_and_mds::<F>(
+        F::MODULUS_BIT_SIZE as u64,
+        POSEIDON_RATE,
+        POSEIDON_FULL_ROUNDS as u64,
+        POSEIDON_PARTIAL_ROUNDS as u64,
+        0,
+    );
+
+    PoseidonConfig::new(
+        POSEIDON_FULL_ROUNDS,
+        POSEIDON_PARTIAL_ROUNDS,
+        POSEIDON_ALPHA,
+        mds,
+        ark,
+        POSEIDON_RATE,
+        POSEIDON_CAPACITY,
+    )
+}
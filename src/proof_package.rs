@@ -0,0 +1,85 @@
+use ark_ec::pairing::Pairing;
+use ark_groth16::{verify_proof, PreparedVerifyingKey, Proof};
+use ark_relations::r1cs::SynthesisError;
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+
+/// Everything a verifier needs to check a proof, bundled together: the proof, the verifying key of
+/// the circuit it's for, and the public inputs it was proven with.
+#[derive(Clone, CanonicalSerialize, CanonicalDeserialize)]
+pub struct ProofPackage<E: Pairing> {
+    /// The Groth16 proof
+    pub proof: Proof<E>,
+    /// The verifying key of the circuit that was proven
+    pub vk: PreparedVerifyingKey<E>,
+    /// The public inputs to the circuit, serialized to field elements
+    pub public_inputs: Vec<E::ScalarField>,
+}
+
+impl<E: Pairing> ProofPackage<E> {
+    /// Returns whether the proof is valid with respect to the packaged verifying key and public
+    /// inputs
+    pub fn verify(&self) -> Result<bool, SynthesisError> {
+        verify_proof(&self.vk, &self.proof, &self.public_inputs)
+    }
+}
+
+//
+// TESTS
+//
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{util::SquareCircuit, E, F};
+
+    use ark_ff::{UniformRand, Zero};
+    use ark_groth16::{
+        create_random_proof, generate_random_parameters, prepare_verifying_key, ProvingKey,
+    };
+
+    // Makes a package containing a valid proof
+    fn setup() -> ProofPackage<E> {
+        let mut rng = ark_std::test_rng();
+
+        let placeholder = SquareCircuit {
+            root: Some(F::zero()),
+            square: F::zero(),
+        };
+        let pk: ProvingKey<E> = generate_random_parameters(placeholder, &mut rng).unwrap();
+
+        let root = F::rand(&mut rng);
+        let square = root * root;
+        let proof = create_random_proof(
+            SquareCircuit {
+                root: Some(root),
+                square,
+            },
+            &pk,
+            &mut rng,
+        )
+        .unwrap();
+
+        ProofPackage {
+            proof,
+            vk: prepare_verifying_key(&pk.vk),
+            public_inputs: vec![square],
+        }
+    }
+
+    // A package should still verify after a round trip through bytes, and should fail to verify if
+    // its public inputs are changed
+    #[test]
+    fn package_round_trip() {
+        let package = setup();
+        assert!(package.verify().unwrap());
+
+        let mut buf = Vec::new();
+        package.serialize_compressed(&mut buf).unwrap();
+        let mut deserialized_package =
+            ProofPackage::<E>::deserialize_compressed(buf.as_slice()).unwrap();
+        assert!(deserialized_package.verify().unwrap());
+
+        deserialized_package.public_inputs[0] += F::from(1u64);
+        assert!(!deserialized_package.verify().unwrap());
+    }
+}
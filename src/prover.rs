@@ -0,0 +1,273 @@
+use crate::util::VkExt;
+
+use ark_ec::pairing::Pairing;
+use ark_groth16::{
+    create_random_proof, generate_random_parameters, prepare_verifying_key, verify_proof,
+    PreparedVerifyingKey, Proof, ProvingKey, VerifyingKey,
+};
+use ark_relations::r1cs::{ConstraintSynthesizer, SynthesisError};
+use core::{fmt, marker::PhantomData};
+use rand::{RngCore, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+use sha2::{Digest, Sha256};
+
+/// An error that occurs while proving
+#[derive(Debug)]
+pub enum ProvingError {
+    /// The circuit failed to synthesize, e.g., because a witness was missing
+    Synthesis(SynthesisError),
+}
+
+impl fmt::Display for ProvingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProvingError::Synthesis(e) => write!(f, "could not synthesize circuit: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for ProvingError {}
+
+/// An error that occurs while verifying. Note an invalid proof is not an error. It just fails to
+/// verify.
+#[derive(Debug)]
+pub enum VerifyingError {
+    /// The verifying key expects `expected` public inputs, but `got` were given
+    WrongNumInputs { expected: usize, got: usize },
+    /// The verifier failed internally
+    Synthesis(SynthesisError),
+}
+
+impl fmt::Display for VerifyingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VerifyingError::WrongNumInputs { expected, got } => {
+                write!(f, "expected {expected} public inputs, got {got}")
+            }
+            VerifyingError::Synthesis(e) => write!(f, "could not verify proof: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for VerifyingError {}
+
+/// An error that occurs while generating Groth16 parameters
+#[derive(Debug)]
+pub enum SetupError {
+    /// Nobody contributed any entropy
+    NoParticipants,
+    /// The circuit failed to synthesize
+    Synthesis(SynthesisError),
+}
+
+impl fmt::Display for SetupError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SetupError::NoParticipants => write!(f, "a trusted setup needs at least 1 participant"),
+            SetupError::Synthesis(e) => write!(f, "could not synthesize circuit: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for SetupError {}
+
+/// Simulates a multi-party trusted setup. Every participant contributes 32 bytes of entropy, and
+/// the parameters are generated from the XOR of all of them. As long as one participant's entropy
+/// is uniform and secret, so is the seed. This is a simulation: whoever runs it sees the seed.
+#[derive(Clone, Default)]
+pub struct TrustedSetupBuilder {
+    contributions: Vec<[u8; 32]>,
+}
+
+impl TrustedSetupBuilder {
+    /// Makes a setup with no participants yet
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Makes a setup with the given participants' entropy
+    pub fn with_participants(contributions: Vec<[u8; 32]>) -> Self {
+        TrustedSetupBuilder { contributions }
+    }
+
+    /// Adds a participant's entropy
+    pub fn contribute(mut self, entropy: [u8; 32]) -> Self {
+        self.contributions.push(entropy);
+        self
+    }
+
+    /// Generates Groth16 parameters for `circuit` from the combined entropy of all the
+    /// participants. Logs the SHA-256 hash of every contribution, so participants can check theirs
+    /// was included.
+    pub fn generate<E: Pairing, C: ConstraintSynthesizer<E::ScalarField>>(
+        &self,
+        circuit: C,
+    ) -> Result<ProvingKey<E>, SetupError> {
+        if self.contributions.is_empty() {
+            return Err(SetupError::NoParticipants);
+        }
+
+        let mut seed = [0u8; 32];
+        for (i, contribution) in self.contributions.iter().enumerate() {
+            let hash = Sha256::digest(contribution);
+            let hash_hex: String = hash.iter().map(|b| format!("{b:02x}")).collect();
+            tracing::info!("participant {i} contributed entropy with hash {hash_hex}");
+
+            seed.iter_mut().zip(contribution).for_each(|(s, c)| *s ^= c);
+        }
+
+        let mut rng = ChaCha20Rng::from_seed(seed);
+        generate_random_parameters(circuit, &mut rng).map_err(SetupError::Synthesis)
+    }
+}
+
+/// Makes Groth16 proofs for the circuit `C`
+pub struct Groth16Prover<E: Pairing, C: ConstraintSynthesizer<E::ScalarField>> {
+    pk: ProvingKey<E>,
+    _circuit: PhantomData<C>,
+}
+
+impl<E: Pairing, C: ConstraintSynthesizer<E::ScalarField>> Groth16Prover<E, C> {
+    /// Makes a prover that uses the given proving key. The key must have been generated for `C`.
+    pub fn new(pk: ProvingKey<E>) -> Self {
+        Groth16Prover {
+            pk,
+            _circuit: PhantomData,
+        }
+    }
+
+    /// Returns the verifying key that goes with this prover's proving key
+    pub fn vk(&self) -> &VerifyingKey<E> {
+        &self.pk.vk
+    }
+
+    /// Proves the given circuit
+    pub fn prove(&self, circuit: C, rng: &mut impl RngCore) -> Result<Proof<E>, ProvingError> {
+        create_random_proof(circuit, &self.pk, rng).map_err(ProvingError::Synthesis)
+    }
+}
+
+/// Verifies Groth16 proofs
+pub struct Groth16Verifier<E: Pairing> {
+    pvk: PreparedVerifyingKey<E>,
+}
+
+impl<E: Pairing> Groth16Verifier<E> {
+    /// Makes a verifier that uses the given prepared verifying key
+    pub fn new(pvk: PreparedVerifyingKey<E>) -> Self {
+        Groth16Verifier { pvk }
+    }
+
+    /// Makes a verifier that uses the given verifying key
+    pub fn from_vk(vk: &VerifyingKey<E>) -> Self {
+        Self::new(prepare_verifying_key(vk))
+    }
+
+    /// Returns whether `proof` is valid with respect to the given public inputs
+    pub fn verify(
+        &self,
+        proof: &Proof<E>,
+        public_inputs: &[E::ScalarField],
+    ) -> Result<bool, VerifyingError> {
+        let expected = self.pvk.num_public_inputs();
+        if public_inputs.len() != expected {
+            return Err(VerifyingError::WrongNumInputs {
+                expected,
+                got: public_inputs.len(),
+            });
+        }
+
+        verify_proof(&self.pvk, proof, public_inputs).map_err(VerifyingError::Synthesis)
+    }
+}
+
+//
+// TESTS
+//
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{util::SquareCircuit, E, F};
+
+    use ark_ff::{UniformRand, Zero};
+
+    // Proves and verifies, and checks that the errors show up where they should
+    #[test]
+    fn prove_then_verify() {
+        let mut rng = ark_std::test_rng();
+        let placeholder = SquareCircuit {
+            root: Some(F::zero()),
+            square: F::zero(),
+        };
+        let pk: ProvingKey<E> = generate_random_parameters(placeholder, &mut rng).unwrap();
+        let prover = Groth16Prover::new(pk);
+        let verifier = Groth16Verifier::from_vk(prover.vk());
+
+        // An honest proof verifies
+        let root = F::rand(&mut rng);
+        let square = root * root;
+        let circuit = SquareCircuit {
+            root: Some(root),
+            square,
+        };
+        let proof = prover.prove(circuit, &mut rng).unwrap();
+        assert!(verifier.verify(&proof, &[square]).unwrap());
+
+        // The wrong public input doesn't verify, but isn't an error
+        assert!(!verifier.verify(&proof, &[square + F::from(1u64)]).unwrap());
+
+        // The wrong number of public inputs is an error
+        assert!(matches!(
+            verifier.verify(&proof, &[square, square]),
+            Err(VerifyingError::WrongNumInputs {
+                expected: 1,
+                got: 2
+            })
+        ));
+
+        // A missing witness is a proving error
+        let missing_witness = SquareCircuit { root: None, square };
+        assert!(matches!(
+            prover.prove(missing_witness, &mut rng),
+            Err(ProvingError::Synthesis(SynthesisError::AssignmentMissing))
+        ));
+    }
+
+    // Parameters from a simulated three-party setup should make valid proofs. The same
+    // contributions should give the same parameters.
+    #[test]
+    fn three_party_setup() {
+        let mut rng = ark_std::test_rng();
+        let placeholder = SquareCircuit {
+            root: Some(F::zero()),
+            square: F::zero(),
+        };
+
+        let mut contributions = [[0u8; 32]; 3];
+        contributions.iter_mut().for_each(|c| rng.fill_bytes(c));
+        let setup = TrustedSetupBuilder::with_participants(contributions.to_vec());
+        let pk: ProvingKey<E> = setup.generate(placeholder.clone()).unwrap();
+
+        let prover = Groth16Prover::new(pk);
+        let verifier = Groth16Verifier::from_vk(prover.vk());
+        let root = F::rand(&mut rng);
+        let square = root * root;
+        let circuit = SquareCircuit {
+            root: Some(root),
+            square,
+        };
+        let proof = prover.prove(circuit, &mut rng).unwrap();
+        assert!(verifier.verify(&proof, &[square]).unwrap());
+
+        // Deterministic
+        let pk2: ProvingKey<E> = setup.generate(placeholder.clone()).unwrap();
+        assert!(pk2.vk == *prover.vk());
+
+        // Nobody showed up
+        assert!(matches!(
+            TrustedSetupBuilder::new().generate::<E, _>(placeholder),
+            Err(SetupError::NoParticipants)
+        ));
+    }
+}
@@ -0,0 +1,189 @@
+use crate::{
+    card::Card,
+    constraints::PossessionCircuit,
+    hash::{LeafHashParams, TwoToOneHashParams},
+    merkle::{Leaf, MerkleError, MerkleRoot, SimpleMerkleTree, SimplePath, EMPTY_LEAF},
+    nullifier::NullifierSet,
+    F,
+};
+
+use core::fmt;
+
+use ark_ff::UniformRand;
+use rand::RngCore;
+
+/// An error from using a `CardRegistry`
+#[derive(Debug)]
+pub enum RegistryError {
+    /// Every slot in the registry has been issued
+    Full { capacity: usize },
+    /// No card has been issued at index `idx`
+    NotIssued { idx: usize },
+    /// The card at index `idx` has been revoked
+    Revoked { idx: usize },
+    /// The underlying Merkle tree failed
+    Merkle(MerkleError),
+}
+
+impl fmt::Display for RegistryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RegistryError::Full { capacity } => {
+                write!(f, "registry is full; all {capacity} cards have been issued")
+            }
+            RegistryError::NotIssued { idx } => write!(f, "no card has been issued at index {idx}"),
+            RegistryError::Revoked { idx } => write!(f, "the card at index {idx} was revoked"),
+            RegistryError::Merkle(e) => write!(f, "registry tree error: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for RegistryError {}
+
+impl From<MerkleError> for RegistryError {
+    fn from(e: MerkleError) -> Self {
+        RegistryError::Merkle(e)
+    }
+}
+
+/// Everything a card owner needs to show possession of their card, i.e., the non-constant fields
+/// of a `PossessionCircuit`
+#[derive(Clone)]
+pub struct PossessionInputs {
+    /// The root of the registry's tree when these inputs were made
+    pub root: MerkleRoot,
+    /// The commitment to the card, i.e., its leaf in the tree
+    pub leaf: Leaf,
+    /// The card itself
+    pub card: Card,
+    /// The randomness used to commit to the card
+    pub card_com_rand: F,
+    /// The membership proof of the leaf
+    pub auth_path: SimplePath,
+}
+
+impl PossessionInputs {
+    /// Makes a possession circuit out of these inputs and the given hash parameters
+    pub fn into_circuit(
+        self,
+        leaf_crh_params: LeafHashParams,
+        two_to_one_crh_params: TwoToOneHashParams,
+    ) -> PossessionCircuit {
+        PossessionCircuit {
+            leaf_crh_params,
+            two_to_one_crh_params,
+            enforce_nonzero_price: false,
+            root: self.root,
+            leaf: self.leaf.to_vec(),
+            card_serial_num: self.card.serial_num,
+            auth_path: self.auth_path,
+            card_purchase_price: self.card.purchase_price,
+            card_com_rand: self.card_com_rand,
+        }
+    }
+}
+
+/// Issues cards into a fixed-size Merkle tree, and revokes them. Slots are handed out in order and
+/// never reused. Unissued and revoked slots hold `EMPTY_LEAF`, so nobody can show possession of
+/// them.
+pub struct CardRegistry {
+    leaf_crh_params: LeafHashParams,
+    tree: SimpleMerkleTree,
+    /// The card and commitment randomness in every issued slot
+    cards: Vec<(Card, F)>,
+    /// The serial numbers of all the revoked cards
+    revoked: NullifierSet,
+}
+
+impl CardRegistry {
+    /// Makes an empty registry that can hold `capacity` cards. The capacity must be a power of
+    /// two, and at least 2.
+    pub fn new(
+        leaf_crh_params: &LeafHashParams,
+        two_to_one_crh_params: &TwoToOneHashParams,
+        capacity: usize,
+    ) -> Result<Self, RegistryError> {
+        let tree = SimpleMerkleTree::new(
+            leaf_crh_params,
+            two_to_one_crh_params,
+            vec![EMPTY_LEAF; capacity],
+        )?;
+
+        Ok(CardRegistry {
+            leaf_crh_params: leaf_crh_params.clone(),
+            tree,
+            cards: Vec::new(),
+            revoked: NullifierSet::new(),
+        })
+    }
+
+    /// Commits to `card` with fresh randomness and puts it in the next free slot. Returns the
+    /// slot index and the commitment randomness.
+    pub fn issue_card(
+        &mut self,
+        card: Card,
+        rng: &mut impl RngCore,
+    ) -> Result<(usize, F), RegistryError> {
+        let idx = self.cards.len();
+        if idx == self.tree.num_leaves() {
+            return Err(RegistryError::Full { capacity: idx });
+        }
+
+        let com_rand = F::rand(rng);
+        let leaf = card.commit(&self.leaf_crh_params, &com_rand);
+        self.tree.update_leaf(idx, &leaf)?;
+        self.cards.push((card, com_rand));
+
+        Ok((idx, com_rand))
+    }
+
+    /// Revokes the card at slot `idx`. Its leaf is cleared, so it can no longer be shown under
+    /// the new root.
+    pub fn revoke_card(&mut self, idx: usize) -> Result<(), RegistryError> {
+        let (card, _) = self.issued_card(idx)?;
+        let serial_num = card.serial_num;
+
+        self.tree.update_leaf(idx, &EMPTY_LEAF)?;
+        self.revoked.insert(serial_num);
+        Ok(())
+    }
+
+    /// Returns whether a card with the given serial number has been revoked
+    pub fn is_revoked(&self, serial_num: &F) -> bool {
+        self.revoked.contains(serial_num)
+    }
+
+    /// Returns the current root of the registry's tree
+    pub fn root(&self) -> MerkleRoot {
+        self.tree.root()
+    }
+
+    /// Returns everything the owner of the card at slot `idx` needs to show possession of it
+    /// under the current root
+    pub fn generate_possession_proof_inputs(
+        &self,
+        idx: usize,
+    ) -> Result<PossessionInputs, RegistryError> {
+        let (card, card_com_rand) = self.issued_card(idx)?;
+
+        Ok(PossessionInputs {
+            root: self.root(),
+            leaf: self.tree.leaves()[idx],
+            card: card.clone(),
+            card_com_rand: *card_com_rand,
+            auth_path: self.tree.generate_proof(idx)?,
+        })
+    }
+
+    /// Returns the card at slot `idx`, if it was issued and hasn't been revoked
+    fn issued_card(&self, idx: usize) -> Result<&(Card, F), RegistryError> {
+        let issued = self
+            .cards
+            .get(idx)
+            .ok_or(RegistryError::NotIssued { idx })?;
+        if self.is_revoked(&issued.0.serial_num) {
+            return Err(RegistryError::Revoked { idx });
+        }
+        Ok(issued)
+    }
+}
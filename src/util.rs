@@ -1,23 +1,47 @@
 use crate::{
     card::Card,
-    hash::{LeafHashParams, TwoToOneHashParams},
-    merkle::{Leaf, SimpleMerkleTree},
-    F,
+    hash::{LeafHash, LeafHashParams, PoseidonParams, TwoToOneHash, TwoToOneHashParams},
+    merkle::{
+        leaf_from_bytes, root_from_bytes, root_to_bytes, Leaf, LeafConversionError, MerkleRoot,
+        PoseidonMerkleTree, SimpleMerkleTree,
+    },
+    E, F, FV,
 };
 
+use core::{marker::PhantomData, str::FromStr};
 use std::{
-    fs::OpenOptions,
-    io::{Read, Write},
+    collections::HashMap,
+    fmt,
+    fs::{self, OpenOptions},
+    io::{self, Read, Write},
     path::Path,
 };
 
-use ark_ff::UniformRand;
-use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+use ark_crypto_primitives::crh::{CRHScheme, TwoToOneCRHScheme};
+use ark_ec::pairing::Pairing;
+use ark_ff::{PrimeField, UniformRand};
+use ark_groth16::{create_random_proof, PreparedVerifyingKey, Proof, ProvingKey};
+use ark_r1cs_std::{alloc::AllocVar, eq::EqGadget, fields::FieldVar};
+use ark_relations::{
+    ns,
+    r1cs::{
+        ConstraintLayer, ConstraintSynthesizer, ConstraintSystem, ConstraintSystemRef,
+        SynthesisError, TracingMode,
+    },
+};
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize, SerializationError, Valid};
+use rand::{RngCore, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing_subscriber::layer::SubscriberExt;
 
 pub const POSSESSION_PK_FILENAME: &str = "possession_proving_key.bin";
 pub const POSSESSION_VK_FILENAME: &str = "possession_verifying_key.bin";
 pub const POSSESSION_PROOF_FILENAME: &str = "possession_proof.bin";
 pub const POSSESSION_REVEALED_SERIAL_FILENAME: &str = "possession_revealed_serial.bin";
+pub const POSSESSION_PROOF_CACHE_FILENAME: &str = "possession_proof_cache.bin";
+pub const POSSESSION_PROOF_PACKAGE_FILENAME: &str = "possession_proof_package.bin";
 
 // INSERTED IN EXTRA CREDIT SOLUTION
 pub const POSSESSION_SHOWPRICE_PK_FILENAME: &str = "possession_showprice_proving_key.bin";
@@ -25,10 +49,25 @@ pub const POSSESSION_SHOWPRICE_VK_FILENAME: &str = "possession_showprice_verifyi
 pub const POSSESSION_SHOWPRICE_PROOF_FILENAME: &str = "possession_showprice_proof.bin";
 pub const POSSESSION_REVEALED_PRICE_FILENAME: &str = "possession_revealed_price.bin";
 
+pub const VOTING_PK_FILENAME: &str = "voting_proving_key.bin";
+pub const VOTING_VK_FILENAME: &str = "voting_verifying_key.bin";
+pub const VOTING_PROOF_FILENAME: &str = "voting_proof.bin";
+pub const VOTING_REVEALED_NULLIFIER_FILENAME: &str = "voting_revealed_nullifier.bin";
+pub const VOTING_BALLOT_FILENAME: &str = "voting_ballot.bin";
+
+pub const POSSESSION_BN254_PK_FILENAME: &str = "possession_bn254_proving_key.bin";
+pub const POSSESSION_BN254_VK_FILENAME: &str = "possession_bn254_verifying_key.bin";
+pub const POSSESSION_BN254_PROOF_FILENAME: &str = "possession_bn254_proof.bin";
+pub const POSSESSION_BN254_REVEALED_SERIAL_FILENAME: &str = "possession_bn254_revealed_serial.bin";
+
 pub const PEDERSEN_PARAMS_FILENAME: &str = "pedersen_params.bin";
+pub const PEDERSEN_PARAMS_BN254_FILENAME: &str = "pedersen_params_bn254.bin";
 
-/// A helper function that deterministically creates 16 baseball cards and their commitment
-/// randomness
+/// The number of cards in the test tree
+pub const NUM_TEST_CARDS: usize = 16;
+
+/// A helper function that deterministically creates `NUM_TEST_CARDS` baseball cards and their
+/// commitment randomness
 fn all_cards() -> Vec<(Card, F)> {
     // Use a deterministic RNG
     let mut rng = ark_std::test_rng();
@@ -38,7 +77,7 @@ fn all_cards() -> Vec<(Card, F)> {
         let card_com_rand = F::rand(&mut rng);
         (card, card_com_rand)
     })
-    .take(16)
+    .take(NUM_TEST_CARDS)
     .collect()
 }
 
@@ -49,17 +88,95 @@ pub fn gen_test_tree(
 ) -> SimpleMerkleTree {
     let leaves: Vec<Leaf> = all_cards()
         .into_iter()
-        .map(|(card, com_rand)| card.commit(&leaf_crh_params, &com_rand))
+        .map(|(card, com_rand)| card.commit(leaf_crh_params, &com_rand))
+        .collect();
+
+    SimpleMerkleTree::new(leaf_crh_params, two_to_one_crh_params, leaves).unwrap()
+}
+
+/// Commits to every `(card, com_rand)` pair in parallel. The output is in the same order as the
+/// input, and is the same as committing to each card with `Card::commit`.
+#[cfg(feature = "parallel")]
+pub fn commit_cards_parallel(leaf_crh_params: &LeafHashParams, cards: &[(Card, F)]) -> Vec<Leaf> {
+    use rayon::prelude::*;
+
+    cards
+        .par_iter()
+        .map(|(card, com_rand)| card.commit(leaf_crh_params, com_rand))
+        .collect()
+}
+
+/// Like the cards used in `gen_test_tree`, except the RNG is seeded with `seed`. Different seeds
+/// give different cards, and the same seed always gives the same cards.
+pub fn gen_test_cards_with_seed(seed: u64) -> Vec<(Card, F)> {
+    let mut rng = ChaCha20Rng::seed_from_u64(seed);
+
+    core::iter::repeat_with(|| {
+        let card = Card::rand(&mut rng);
+        let card_com_rand = F::rand(&mut rng);
+        (card, card_com_rand)
+    })
+    .take(NUM_TEST_CARDS)
+    .collect()
+}
+
+/// Returns a Merkle tree of the cards given by `gen_test_cards_with_seed(seed)`
+pub fn gen_test_tree_with_seed(
+    leaf_crh_params: &LeafHashParams,
+    two_to_one_crh_params: &TwoToOneHashParams,
+    seed: u64,
+) -> SimpleMerkleTree {
+    let leaves: Vec<Leaf> = gen_test_cards_with_seed(seed)
+        .into_iter()
+        .map(|(card, com_rand)| card.commit(leaf_crh_params, &com_rand))
         .collect();
 
-    SimpleMerkleTree::new(&leaf_crh_params, &two_to_one_crh_params, leaves).unwrap()
+    SimpleMerkleTree::new(leaf_crh_params, two_to_one_crh_params, leaves).unwrap()
 }
 
-/// Unfortuantely you can't get leaves out of trees, so we need a separate function for returning
-/// the i-th leaf.
+/// Returns a Merkle tree of all the cards generated above, except the `i`-th card is replaced with
+/// the given card and commitment randomness. Also returns the leaf that replaced the `i`-th one.
+/// This is useful for testing circuits on cards with specific values.
+pub fn gen_test_tree_with_card(
+    leaf_crh_params: &LeafHashParams,
+    two_to_one_crh_params: &TwoToOneHashParams,
+    i: usize,
+    card: &Card,
+    com_rand: &F,
+) -> (SimpleMerkleTree, Leaf) {
+    let our_leaf = card.commit(leaf_crh_params, com_rand);
+    let mut leaves: Vec<Leaf> = all_cards()
+        .into_iter()
+        .map(|(card, com_rand)| card.commit(leaf_crh_params, &com_rand))
+        .collect();
+    leaves[i] = our_leaf;
+
+    let tree = SimpleMerkleTree::new(leaf_crh_params, two_to_one_crh_params, leaves).unwrap();
+    (tree, our_leaf)
+}
+
+/// Returns the i-th leaf of the test tree. If you have the tree, `tree.leaves()[i]` is the same
+/// thing.
 pub fn get_test_leaf(leaf_crh_params: &LeafHashParams, i: usize) -> Leaf {
     let (card, com_rand) = all_cards().get(i).unwrap().clone();
-    card.commit(&leaf_crh_params, &com_rand)
+    card.commit(leaf_crh_params, &com_rand)
+}
+
+/// Returns a Poseidon Merkle tree of all the cards generated above. Each leaf is the Poseidon
+/// commitment to a card.
+pub fn gen_test_poseidon_tree(poseidon_params: &PoseidonParams) -> PoseidonMerkleTree {
+    let leaves: Vec<[F; 1]> = all_cards()
+        .into_iter()
+        .map(|(card, com_rand)| [card.commit_poseidon(poseidon_params, &com_rand)])
+        .collect();
+
+    PoseidonMerkleTree::new(poseidon_params, poseidon_params, leaves).unwrap()
+}
+
+/// Returns the i-th leaf of the Poseidon test tree
+pub fn get_test_poseidon_leaf(poseidon_params: &PoseidonParams, i: usize) -> F {
+    let (card, com_rand) = all_cards().get(i).unwrap().clone();
+    card.commit_poseidon(poseidon_params, &com_rand)
 }
 
 /// Returns the i-th card and commitment randomness in the test tree.
@@ -67,6 +184,312 @@ pub fn get_test_card(i: usize) -> (Card, F) {
     all_cards().get(i).unwrap().clone()
 }
 
+/// Runs the given circuit on a fresh constraint system and returns
+/// `(num_instance_vars, num_witness_vars, num_constraints)`. Note the instance variables include
+/// the constant 1 that every constraint system has.
+pub fn count_constraints<C: ConstraintSynthesizer<F>>(circuit: C) -> (usize, usize, usize) {
+    let cs = ConstraintSystem::new_ref();
+    circuit
+        .generate_constraints(cs.clone())
+        .expect("circuit failed to synthesize");
+
+    (
+        cs.num_instance_variables(),
+        cs.num_witness_variables(),
+        cs.num_constraints(),
+    )
+}
+
+/// Runs the given circuit on a fresh constraint system and returns whether it's satisfied. This is
+/// the check behind every circuit's correctness and soundness tests.
+pub fn is_satisfied<ConstraintF, C>(circuit: C) -> bool
+where
+    ConstraintF: PrimeField,
+    C: ConstraintSynthesizer<ConstraintF>,
+{
+    let cs = ConstraintSystem::<ConstraintF>::new_ref();
+    circuit
+        .generate_constraints(cs.clone())
+        .expect("circuit failed to synthesize");
+    cs.is_satisfied().unwrap()
+}
+
+/// Samples fresh parameters for the leaf hash and the two-to-one hash, for setting up a test
+/// circuit
+pub fn sample_crh_params(rng: &mut impl RngCore) -> (LeafHashParams, TwoToOneHashParams) {
+    let leaf_crh_params = <LeafHash as CRHScheme>::setup(rng).unwrap();
+    let two_to_one_crh_params = <TwoToOneHash as TwoToOneCRHScheme>::setup(rng).unwrap();
+    (leaf_crh_params, two_to_one_crh_params)
+}
+
+/// A circuit with no cryptographic logic, for testing the infrastructure around circuits. It
+/// witnesses a single variable and enforces `num_constraints` constraints on it. Either every
+/// constraint is satisfied or every one is violated, depending on `should_satisfy`. It has no
+/// public inputs.
+#[derive(Clone, Copy, Debug)]
+pub struct MockCircuit {
+    pub num_constraints: usize,
+    pub should_satisfy: bool,
+}
+
+impl ConstraintSynthesizer<F> for MockCircuit {
+    fn generate_constraints(self, cs: ConstraintSystemRef<F>) -> Result<(), SynthesisError> {
+        // Every constraint is x * x = x. This holds for x = 1 and fails for x = 2.
+        let x = if self.should_satisfy {
+            F::from(1u64)
+        } else {
+            F::from(2u64)
+        };
+        let x_var = FV::new_witness(ns!(cs, "x"), || Ok(x))?;
+        for _ in 0..self.num_constraints {
+            x_var.mul_equals(&x_var, &x_var)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A tiny circuit proving knowledge of a square root of a public value. It's much faster to set up
+/// and prove than any of our real circuits, for testing the infrastructure around circuits that
+/// needs real proofs. Its one public input is `square`. A `root` of `None` is a missing witness.
+#[derive(Clone, Debug)]
+pub struct SquareCircuit {
+    pub root: Option<F>,
+    pub square: F,
+}
+
+impl ConstraintSynthesizer<F> for SquareCircuit {
+    fn generate_constraints(self, cs: ConstraintSystemRef<F>) -> Result<(), SynthesisError> {
+        let square = FV::new_input(ns!(cs, "square"), || Ok(self.square))?;
+        let root = FV::new_witness(ns!(cs, "root"), || {
+            self.root.ok_or(SynthesisError::AssignmentMissing)
+        })?;
+        (&root * &root).enforce_equal(&square)
+    }
+}
+
+/// Some statistics about the size of a circuit
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CircuitStats {
+    /// The number of public inputs, not counting the constant 1
+    pub pub_inputs: usize,
+    /// The number of witness variables
+    pub witnesses: usize,
+    /// The number of constraints
+    pub constraints: usize,
+    /// The number of constraints per variable, i.e., `constraints / (pub_inputs + witnesses)`
+    pub density: f64,
+}
+
+/// Runs the given circuit on a fresh constraint system and returns its statistics
+pub fn analyze_circuit<C: ConstraintSynthesizer<F> + Clone>(c: C) -> CircuitStats {
+    let (num_instance_vars, witnesses, constraints) = count_constraints(c);
+    let pub_inputs = num_instance_vars - 1;
+
+    CircuitStats {
+        pub_inputs,
+        witnesses,
+        constraints,
+        density: constraints as f64 / (pub_inputs + witnesses) as f64,
+    }
+}
+
+/// The compressed sizes, in bytes, of a Groth16 proof and the keys for its circuit
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ProofSize {
+    pub proof_bytes: usize,
+    pub pk_bytes: usize,
+    pub vk_bytes: usize,
+}
+
+/// Measures the compressed sizes of the given proof, proving key, and the verifying key inside the
+/// proving key. The proof size only depends on the curve, but the key sizes grow with the
+/// circuit.
+pub fn measure_proof_size<E: Pairing>(pk: &ProvingKey<E>, proof: &Proof<E>) -> ProofSize {
+    ProofSize {
+        proof_bytes: proof.compressed_size(),
+        pk_bytes: pk.compressed_size(),
+        vk_bytes: pk.vk.compressed_size(),
+    }
+}
+
+/// A short identifier for a verifying key. Two keys have the same fingerprint iff they're the
+/// same key, so this tells you which key a proof is meant for without comparing whole files.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct VerifyingKeyFingerprint(pub [u8; 32]);
+
+impl fmt::Display for VerifyingKeyFingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", hex::encode(self.0))
+    }
+}
+
+/// Computes the fingerprint of the given verifying key. Concretely, this is the BLAKE3 hash of
+/// its compressed serialization.
+pub fn fingerprint(vk: &PreparedVerifyingKey<E>) -> VerifyingKeyFingerprint {
+    let mut buf = Vec::new();
+    vk.serialize_compressed(&mut buf)
+        .expect("failed to serialize verifying key");
+    VerifyingKeyFingerprint(*blake3::hash(&buf).as_bytes())
+}
+
+/// Returns whether every element of the proof is on its curve and in the prime-order subgroup.
+/// Proofs read from untrusted bytes without validation can fail this, and pairing-based checks
+/// on such points are meaningless.
+pub fn is_canonical_proof<E: Pairing>(proof: &Proof<E>) -> bool {
+    // For curve points, `check` is exactly
+    // `is_on_curve() && is_in_correct_subgroup_assuming_on_curve()`
+    proof.a.check().is_ok() && proof.b.check().is_ok() && proof.c.check().is_ok()
+}
+
+/// The size and sparsity of a circuit's R1CS matrices
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ConstraintSystemReport {
+    /// The number of constraints, i.e., the number of rows in each matrix
+    pub num_constraints: usize,
+    /// The number of nonzero entries in the A matrix
+    pub non_zeros_a: usize,
+    /// The number of nonzero entries in the B matrix
+    pub non_zeros_b: usize,
+    /// The number of nonzero entries in the C matrix
+    pub non_zeros_c: usize,
+}
+
+/// Runs the given circuit on a fresh constraint system and reports the sizes of its matrices.
+/// Groth16 proving time depends on the number of nonzero entries as well as the number of
+/// constraints.
+pub fn report_circuit<C: ConstraintSynthesizer<F>>(c: C) -> ConstraintSystemReport {
+    let cs = ConstraintSystem::new_ref();
+    c.generate_constraints(cs.clone())
+        .expect("circuit failed to synthesize");
+
+    // Inline all the linear combinations so we can read off the constraint matrices
+    cs.finalize();
+    let matrices = cs
+        .to_matrices()
+        .expect("constraint system doesn't have matrices");
+
+    ConstraintSystemReport {
+        num_constraints: matrices.num_constraints,
+        non_zeros_a: matrices.a_num_non_zero,
+        non_zeros_b: matrices.b_num_non_zero,
+        non_zeros_c: matrices.c_num_non_zero,
+    }
+}
+
+/// Runs a circuit with constraint tracing turned on, so that we can tell which constraints failed.
+/// This is meant for debugging tests. Tracing makes synthesis much slower.
+pub struct ConstraintSystemInspector {
+    cs: ConstraintSystemRef<F>,
+}
+
+impl ConstraintSystemInspector {
+    /// Runs the given circuit on a fresh constraint system, recording where every constraint came
+    /// from
+    pub fn new<C: ConstraintSynthesizer<F>>(circuit: C) -> Self {
+        let cs = ConstraintSystem::new_ref();
+
+        // Constraints only get labels if a ConstraintLayer is listening when they're made
+        let subscriber =
+            tracing_subscriber::Registry::default().with(ConstraintLayer::new(TracingMode::All));
+        tracing::subscriber::with_default(subscriber, || {
+            circuit
+                .generate_constraints(cs.clone())
+                .expect("circuit failed to synthesize");
+        });
+
+        // Inline all the linear combinations so we can read off the constraint matrices
+        cs.finalize();
+        ConstraintSystemInspector { cs }
+    }
+
+    /// Returns the underlying constraint system
+    pub fn cs(&self) -> ConstraintSystemRef<F> {
+        self.cs.clone()
+    }
+
+    /// Returns whether every constraint is satisfied
+    pub fn is_satisfied(&self) -> bool {
+        self.cs.is_satisfied().unwrap()
+    }
+
+    /// Returns the labels of all the unsatisfied constraints, in the order they were made. A
+    /// label is the path of namespaces and gadgets the constraint was made in. If no label was
+    /// recorded, the label is just the index of the constraint.
+    pub fn unsatisfied_constraint_labels(&self) -> Vec<String> {
+        let matrices = self
+            .cs
+            .to_matrices()
+            .expect("constraint system doesn't have matrices");
+        let names = self.cs.constraint_names();
+
+        // The full assignment. The column indices of the matrices index into this.
+        let cs = self.cs.borrow().unwrap();
+        let assignment = [
+            cs.instance_assignment.as_slice(),
+            cs.witness_assignment.as_slice(),
+        ]
+        .concat();
+        let eval_row = |row: &[(F, usize)]| -> F {
+            row.iter().map(|(coeff, i)| *coeff * assignment[*i]).sum()
+        };
+
+        // Check a * b = c for every constraint
+        (0..matrices.num_constraints)
+            .filter(|&i| {
+                eval_row(&matrices.a[i]) * eval_row(&matrices.b[i]) != eval_row(&matrices.c[i])
+            })
+            .map(|i| match &names {
+                Some(names) => names[i].clone(),
+                None => format!("constraint #{i}"),
+            })
+            .collect()
+    }
+}
+
+/// The difference between two versions of a circuit, as computed by `diff_circuits`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ConstraintDiff {
+    /// The number of constraints in the new circuit that aren't in the old one
+    pub added: usize,
+    /// The number of constraints in the old circuit that aren't in the new one
+    pub removed: usize,
+    /// The density of the new circuit minus the density of the old one. See `CircuitStats`.
+    pub delta_density: f64,
+}
+
+/// Compares two versions of a circuit. Constraints are matched up by their labels, i.e., the
+/// namespaces and gadgets they were made in, so this can tell you where constraints were added or
+/// removed, not just how many. Two constraints with the same label are interchangeable.
+pub fn diff_circuits<C: ConstraintSynthesizer<F> + Clone>(before: C, after: C) -> ConstraintDiff {
+    let before = ConstraintSystemInspector::new(before).cs();
+    let after = ConstraintSystemInspector::new(after).cs();
+
+    // Count how many times every label appears. Labels from the old circuit count negatively, so
+    // whatever is left over is the difference.
+    let mut label_counts: HashMap<String, isize> = HashMap::new();
+    for label in before.constraint_names().unwrap_or_default() {
+        *label_counts.entry(label).or_default() -= 1;
+    }
+    for label in after.constraint_names().unwrap_or_default() {
+        *label_counts.entry(label).or_default() += 1;
+    }
+    let added = label_counts.values().filter(|&&c| c > 0).sum::<isize>() as usize;
+    let removed = -label_counts.values().filter(|&&c| c < 0).sum::<isize>() as usize;
+
+    // Same as in analyze_circuit, not counting the constant 1
+    let density = |cs: &ConstraintSystemRef<F>| {
+        let num_vars = cs.num_instance_variables() - 1 + cs.num_witness_variables();
+        cs.num_constraints() as f64 / num_vars as f64
+    };
+
+    ConstraintDiff {
+        added,
+        removed,
+        delta_density: density(&after) - density(&before),
+    }
+}
+
 pub fn write_to_file<S: CanonicalSerialize>(path_str: &str, data: &S) {
     // Convert string to FS path
     let path = Path::new(path_str);
@@ -75,16 +498,64 @@ pub fn write_to_file<S: CanonicalSerialize>(path_str: &str, data: &S) {
     let mut f = OpenOptions::new()
         .write(true)
         .create(true)
+        .truncate(true)
         .open(path)
-        .expect(&format!("could not open {path_str} for writing"));
+        .unwrap_or_else(|_| panic!("could not open {path_str} for writing"));
 
     // Serialize the data
     let mut buf = Vec::new();
     data.serialize_compressed(&mut buf)
-        .expect(&format!("failed to serialize to {path_str}"));
+        .unwrap_or_else(|_| panic!("failed to serialize to {path_str}"));
 
     // Write to file
-    f.write(&buf).expect("failed to write to {path_str}");
+    f.write_all(&buf)
+        .unwrap_or_else(|_| panic!("failed to write to {path_str}"));
+}
+
+/// Like `write_to_file`, except the file is never left half-written. The data is written to
+/// `<path>.tmp` and synced to disk, and only then renamed to `<path>`. Renaming is atomic, so if we
+/// crash partway through, `<path>` is either the old file or the new one. The data is compressed,
+/// same as in `write_to_file`.
+pub fn write_to_file_atomic<S: CanonicalSerialize>(path_str: &str, data: &S) -> io::Result<()> {
+    write_to_file_atomic_compressed(path_str, data)
+}
+
+/// Does `write_to_file_atomic` with compressed serialization
+pub fn write_to_file_atomic_compressed<S: CanonicalSerialize>(
+    path_str: &str,
+    data: &S,
+) -> io::Result<()> {
+    let mut buf = Vec::new();
+    data.serialize_compressed(&mut buf)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    write_bytes_atomic(path_str, &buf)
+}
+
+/// Does `write_to_file_atomic` with uncompressed serialization
+pub fn write_to_file_atomic_uncompressed<S: CanonicalSerialize>(
+    path_str: &str,
+    data: &S,
+) -> io::Result<()> {
+    let mut buf = Vec::new();
+    data.serialize_uncompressed(&mut buf)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    write_bytes_atomic(path_str, &buf)
+}
+
+/// Writes `bytes` to `<path>.tmp`, syncs it, and renames it to `<path>`
+fn write_bytes_atomic(path_str: &str, bytes: &[u8]) -> io::Result<()> {
+    let tmp_path_str = format!("{path_str}.tmp");
+
+    let mut f = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&tmp_path_str)?;
+    f.write_all(bytes)?;
+    f.flush()?;
+    f.sync_all()?;
+
+    fs::rename(&tmp_path_str, path_str)
 }
 
 pub fn read_from_file<S: CanonicalDeserialize>(path_str: &str) -> S {
@@ -95,14 +566,1162 @@ pub fn read_from_file<S: CanonicalDeserialize>(path_str: &str) -> S {
     let mut f = OpenOptions::new()
         .read(true)
         .open(path)
-        .expect(&format!("could not open {path_str} for reading"));
+        .unwrap_or_else(|_| panic!("could not open {path_str} for reading"));
 
     // Read from file
     let mut buf = Vec::new();
     f.read_to_end(&mut buf)
-        .expect(&format!("failed to read from {path_str}"));
+        .unwrap_or_else(|_| panic!("failed to read from {path_str}"));
 
     // Deserialize the data
     S::deserialize_compressed_unchecked(buf.as_slice())
-        .expect(&format!("failed to deserialize from {path_str}"))
+        .unwrap_or_else(|_| panic!("failed to deserialize from {path_str}"))
+}
+
+/// An error that occurs when decoding a Merkle root from a string
+#[derive(Debug)]
+pub enum DecodeError {
+    /// The string is not valid zbase32
+    InvalidEncoding,
+    /// The string decoded to bytes, but the bytes are not a valid Merkle root
+    DeserializationFailed(SerializationError),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::InvalidEncoding => write!(f, "could not decode Merkle root string"),
+            DecodeError::DeserializationFailed(e) => {
+                write!(f, "Merkle root string is an invalid hash: {e}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// A card and its commitment randomness, as JSON. Every field is a field element written in
+/// decimal, e.g.,
+/// ```text
+/// { "card_purchase_price": "100", "card_serial_num": "5678", "card_com_rand": "1234" }
+/// ```
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct CardInputJson {
+    pub card_purchase_price: String,
+    pub card_serial_num: String,
+    pub card_com_rand: String,
+}
+
+/// An error that occurs when parsing a `CardInputJson`
+#[derive(Debug)]
+pub enum CardInputError {
+    /// The input is not valid JSON, or is missing a field
+    InvalidJson(serde_json::Error),
+    /// The named field is not a decimal field element
+    InvalidFieldElement { field: &'static str },
+}
+
+impl fmt::Display for CardInputError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CardInputError::InvalidJson(e) => write!(f, "could not parse card JSON: {e}"),
+            CardInputError::InvalidFieldElement { field } => {
+                write!(f, "{field} is not a decimal field element")
+            }
+        }
+    }
+}
+
+impl std::error::Error for CardInputError {}
+
+impl CardInputJson {
+    /// Parses the given JSON string
+    pub fn from_json(s: &str) -> Result<Self, CardInputError> {
+        serde_json::from_str(s).map_err(CardInputError::InvalidJson)
+    }
+
+    /// Returns the card and commitment randomness described by this JSON
+    pub fn to_card(&self) -> Result<(Card, F), CardInputError> {
+        let parse = |val: &str, field| {
+            F::from_str(val).map_err(|_| CardInputError::InvalidFieldElement { field })
+        };
+
+        let card = Card {
+            purchase_price: parse(&self.card_purchase_price, "card_purchase_price")?,
+            serial_num: parse(&self.card_serial_num, "card_serial_num")?,
+        };
+        let com_rand = parse(&self.card_com_rand, "card_com_rand")?;
+        Ok((card, com_rand))
+    }
+}
+
+/// Encodes the given Merkle root as a zbase32 string. This is the format the binaries take roots
+/// in on the command line.
+pub fn root_to_zbase32(root: &MerkleRoot) -> String {
+    zbase32::encode_full_bytes(&root_to_bytes(root))
+}
+
+/// Decodes a Merkle root from a zbase32 string, as output by `root_to_zbase32`
+pub fn root_from_zbase32(s: &str) -> Result<MerkleRoot, DecodeError> {
+    let bytes =
+        zbase32::decode_full_bytes(s.as_bytes()).map_err(|_| DecodeError::InvalidEncoding)?;
+    root_from_bytes(&bytes).map_err(DecodeError::DeserializationFailed)
+}
+
+/// An error that occurs when decoding a leaf from a string
+#[derive(Debug)]
+pub enum LeafDecodeError {
+    /// The string is not valid zbase32
+    InvalidEncoding,
+    /// The string decoded to bytes, but there are the wrong number of them
+    WrongLength(LeafConversionError),
+}
+
+impl fmt::Display for LeafDecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LeafDecodeError::InvalidEncoding => write!(f, "could not decode leaf string"),
+            LeafDecodeError::WrongLength(e) => write!(f, "leaf string is invalid: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for LeafDecodeError {}
+
+/// Encodes the given leaf as a zbase32 string, like `root_to_zbase32` does for roots
+pub fn leaf_to_zbase32(leaf: &Leaf) -> String {
+    zbase32::encode_full_bytes(leaf)
+}
+
+/// Decodes a leaf from a zbase32 string, as output by `leaf_to_zbase32`
+pub fn leaf_from_zbase32(s: &str) -> Result<Leaf, LeafDecodeError> {
+    let bytes =
+        zbase32::decode_full_bytes(s.as_bytes()).map_err(|_| LeafDecodeError::InvalidEncoding)?;
+    leaf_from_bytes(&bytes).map_err(LeafDecodeError::WrongLength)
+}
+
+/// An error when decoding a proof or public inputs from a hex string
+#[derive(Debug)]
+pub enum HexProofError {
+    /// The string is not valid hex
+    InvalidHex(hex::FromHexError),
+    /// The string decoded to bytes, but the bytes don't deserialize to the expected type
+    DeserializationFailed(SerializationError),
+}
+
+impl fmt::Display for HexProofError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HexProofError::InvalidHex(e) => write!(f, "could not decode hex string: {e}"),
+            HexProofError::DeserializationFailed(e) => {
+                write!(f, "hex string is not a valid encoding: {e}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for HexProofError {}
+
+// Serializes the given value and hex-encodes the bytes
+fn to_hex<S: CanonicalSerialize + ?Sized>(val: &S) -> String {
+    let mut buf = Vec::new();
+    val.serialize_compressed(&mut buf).unwrap();
+    hex::encode(buf)
+}
+
+// Hex-decodes the given string and deserializes the bytes
+fn from_hex<S: CanonicalDeserialize>(s: &str) -> Result<S, HexProofError> {
+    let bytes = hex::decode(s).map_err(HexProofError::InvalidHex)?;
+    S::deserialize_compressed(bytes.as_slice()).map_err(HexProofError::DeserializationFailed)
+}
+
+/// Hex encoding for Groth16 proofs. This is handy for passing proofs around as text rather than as
+/// files.
+pub trait ProofHexExt<E: Pairing>: Sized {
+    /// Encodes the proof as a hex string
+    fn to_hex(&self) -> String;
+
+    /// Decodes a proof from a hex string, as output by `to_hex`
+    fn from_hex(s: &str) -> Result<Proof<E>, HexProofError>;
+}
+
+impl<E: Pairing> ProofHexExt<E> for Proof<E> {
+    fn to_hex(&self) -> String {
+        to_hex(self)
+    }
+
+    fn from_hex(s: &str) -> Result<Proof<E>, HexProofError> {
+        from_hex(s)
+    }
+}
+
+/// Hex encoding for a list of public inputs
+pub trait PublicInputsHexExt: Sized {
+    /// Encodes the public inputs as a hex string
+    fn to_hex(&self) -> String;
+
+    /// Decodes public inputs from a hex string, as output by `to_hex`
+    fn from_hex(s: &str) -> Result<Vec<F>, HexProofError>;
+}
+
+impl PublicInputsHexExt for Vec<F> {
+    fn to_hex(&self) -> String {
+        to_hex(self)
+    }
+
+    fn from_hex(s: &str) -> Result<Vec<F>, HexProofError> {
+        from_hex(s)
+    }
+}
+
+/// Extra methods for Groth16 verifying keys
+pub trait VkExt<E: Pairing> {
+    /// Returns the number of public inputs this key verifies proofs against, not counting the
+    /// constant 1
+    fn num_public_inputs(&self) -> usize;
+}
+
+impl<E: Pairing> VkExt<E> for PreparedVerifyingKey<E> {
+    fn num_public_inputs(&self) -> usize {
+        // The key has one element per public input, plus one for the constant 1
+        self.vk.gamma_abc_g1.len() - 1
+    }
+}
+
+/// A Fiat-Shamir transcript. Absorb the proofs and public inputs of a statement, then squeeze out
+/// challenges that depend on all of them.
+#[derive(Clone, Default)]
+pub struct FiatShamirTranscript {
+    hasher: blake3::Hasher,
+}
+
+impl FiatShamirTranscript {
+    /// Makes an empty transcript
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Absorbs the given proof
+    pub fn absorb_proof(&mut self, proof: &Proof<E>) {
+        self.absorb(proof);
+    }
+
+    /// Absorbs the given public inputs. The number of inputs is absorbed too, so that inputs can't
+    /// be moved from one call to the next without changing the transcript.
+    pub fn absorb_public_inputs(&mut self, inputs: &[F]) {
+        self.absorb(inputs);
+    }
+
+    /// Returns a challenge derived from everything absorbed so far. The challenge is absorbed
+    /// afterwards, so calling this twice in a row gives two different challenges.
+    pub fn challenge_field_element(&mut self) -> F {
+        // Take 64 bytes so the reduction mod p is close to uniform
+        let mut buf = [0u8; 64];
+        self.hasher.finalize_xof().fill(&mut buf);
+        let challenge = F::from_le_bytes_mod_order(&buf);
+
+        self.absorb(&challenge);
+        challenge
+    }
+
+    /// Absorbs the compressed serialization of `item`
+    fn absorb<S: CanonicalSerialize + ?Sized>(&mut self, item: &S) {
+        let mut buf = Vec::new();
+        item.serialize_compressed(&mut buf).unwrap();
+        self.hasher.update(&buf);
+    }
+}
+
+/// An error from `SimulatedProver`
+#[derive(Debug)]
+pub enum CircuitError {
+    /// The circuit synthesized, but its constraints aren't satisfied. Any proof of it would fail to
+    /// verify.
+    Unsatisfied,
+    /// The circuit failed to synthesize, or the prover failed
+    Synthesis(SynthesisError),
+}
+
+impl fmt::Display for CircuitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CircuitError::Unsatisfied => write!(f, "circuit is not satisfied"),
+            CircuitError::Synthesis(e) => write!(f, "could not synthesize circuit: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for CircuitError {}
+
+/// A Groth16 prover that first runs the circuit on a plain constraint system to check that it's
+/// satisfied. This is much cheaper than proving, and proving an unsatisfied circuit just makes a
+/// proof that won't verify.
+pub struct SimulatedProver<E: Pairing, C: ConstraintSynthesizer<E::ScalarField> + Clone> {
+    _marker: PhantomData<(E, C)>,
+}
+
+impl<E: Pairing, C: ConstraintSynthesizer<E::ScalarField> + Clone> SimulatedProver<E, C> {
+    /// Proves `circuit` with `pk`, unless the circuit isn't satisfied
+    pub fn prove_if_satisfiable(
+        circuit: C,
+        pk: &ProvingKey<E>,
+        rng: &mut impl RngCore,
+    ) -> Result<Proof<E>, CircuitError> {
+        // Dry run
+        let cs = ConstraintSystem::new_ref();
+        circuit
+            .clone()
+            .generate_constraints(cs.clone())
+            .map_err(CircuitError::Synthesis)?;
+        if !cs.is_satisfied().map_err(CircuitError::Synthesis)? {
+            return Err(CircuitError::Unsatisfied);
+        }
+
+        create_random_proof(circuit, pk, rng).map_err(CircuitError::Synthesis)
+    }
+}
+
+/// A set of cards and their commitment randomness, keyed by serial number. This is for finding a
+/// card without scanning every card, e.g., when a serial number is revealed.
+#[derive(Default)]
+pub struct CardWallet {
+    cards: HashMap<[u8; 32], (Card, F)>,
+}
+
+impl CardWallet {
+    /// Makes an empty wallet
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the given card and its commitment randomness. A card with the same serial number is
+    /// replaced.
+    pub fn insert(&mut self, card: Card, com_rand: F) {
+        self.cards
+            .insert(Self::key(&card.serial_num), (card, com_rand));
+    }
+
+    /// Returns the card with the given serial number and its commitment randomness, if there is one
+    pub fn lookup_by_serial(&self, serial: &F) -> Option<&(Card, F)> {
+        self.cards.get(&Self::key(serial))
+    }
+
+    /// Removes and returns the card with the given serial number and its commitment randomness,
+    /// if there is one
+    pub fn remove_by_serial(&mut self, serial: &F) -> Option<(Card, F)> {
+        self.cards.remove(&Self::key(serial))
+    }
+
+    /// Returns the number of cards in the wallet
+    pub fn len(&self) -> usize {
+        self.cards.len()
+    }
+
+    /// Returns whether the wallet is empty
+    pub fn is_empty(&self) -> bool {
+        self.cards.is_empty()
+    }
+
+    // The key for a serial number is its compressed serialization
+    fn key(serial: &F) -> [u8; 32] {
+        let mut buf = [0u8; 32];
+        serial.serialize_compressed(buf.as_mut_slice()).unwrap();
+        buf
+    }
+}
+
+/// A cache of Groth16 proofs, keyed by the SHA-256 hash of the proving key's verifying key and
+/// the public inputs. This lets a prover skip proving a statement it has already proven. Proofs
+/// made under one proving key are never returned for another, so regenerating the parameters or
+/// changing the circuit doesn't give back stale proofs.
+#[derive(Default)]
+pub struct ProofCache {
+    entries: HashMap<[u8; 32], CachedProof>,
+}
+
+// A cached proof, along with what its key is computed from
+type CachedProof = (Proof<E>, Vec<F>, [u8; 32]);
+
+impl ProofCache {
+    /// Makes an empty cache
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Loads the cache from the given file. If the file doesn't exist, returns an empty cache.
+    pub fn load(path_str: &str) -> Self {
+        if !Path::new(path_str).exists() {
+            return Self::new();
+        }
+
+        // We only store the values. The keys are recomputed from the proving key fingerprints and
+        // the public inputs.
+        let values: Vec<CachedProof> = read_from_file(path_str);
+        let entries = values
+            .into_iter()
+            .map(|entry| (Self::key(&entry.2, &entry.1), entry))
+            .collect();
+        ProofCache { entries }
+    }
+
+    /// Writes the cache to the given file
+    pub fn save(&self, path_str: &str) {
+        let values: Vec<CachedProof> = self.entries.values().cloned().collect();
+        write_to_file_atomic(path_str, &values).expect("could not write proof cache");
+    }
+
+    /// Returns the number of cached proofs
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns whether the cache is empty
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns the cached proof for the given public inputs under `pk`, if there is one
+    pub fn get(&self, pk: &ProvingKey<E>, public_inputs: &[F]) -> Option<&Proof<E>> {
+        self.entries
+            .get(&Self::key(&Self::fingerprint(pk), public_inputs))
+            .map(|(proof, _, _)| proof)
+    }
+
+    /// Returns the cached proof for the given public inputs under `pk`. If there isn't one,
+    /// proves `circuit` with `pk`, caches the result, and returns it. `public_inputs` must be the
+    /// public inputs of `circuit`. Errors from the prover are passed along, and nothing is cached.
+    pub fn get_or_prove<C: ConstraintSynthesizer<F>>(
+        &mut self,
+        circuit: C,
+        public_inputs: &[F],
+        pk: &ProvingKey<E>,
+        rng: &mut impl RngCore,
+    ) -> Result<Proof<E>, SynthesisError> {
+        let fingerprint = Self::fingerprint(pk);
+        let key = Self::key(&fingerprint, public_inputs);
+        if let Some((proof, _, _)) = self.entries.get(&key) {
+            return Ok(proof.clone());
+        }
+
+        let proof = create_random_proof(circuit, pk, rng)?;
+        self.entries
+            .insert(key, (proof.clone(), public_inputs.to_vec(), fingerprint));
+        Ok(proof)
+    }
+
+    /// Computes the fingerprint of a proving key. This is the SHA-256 hash of its verifying key,
+    /// which changes whenever the parameters are regenerated or the circuit changes shape.
+    fn fingerprint(pk: &ProvingKey<E>) -> [u8; 32] {
+        let mut buf = Vec::new();
+        pk.vk.serialize_compressed(&mut buf).unwrap();
+        Sha256::digest(&buf).into()
+    }
+
+    /// Computes the cache key of the given public inputs under the proving key with the given
+    /// fingerprint
+    fn key(fingerprint: &[u8; 32], public_inputs: &[F]) -> [u8; 32] {
+        let mut buf = fingerprint.to_vec();
+        public_inputs.serialize_compressed(&mut buf).unwrap();
+        Sha256::digest(&buf).into()
+    }
+}
+
+/// A single commitment recorded in a `CommitmentAuditLog`
+#[derive(Clone, CanonicalSerialize, CanonicalDeserialize)]
+pub struct AuditEntry {
+    /// The card that was committed to
+    pub card: Card,
+    /// The commitment randomness
+    pub nonce: F,
+    /// The resulting commitment
+    pub leaf: Leaf,
+    /// When the commitment was made. The log doesn't care about the units.
+    pub timestamp: u64,
+}
+
+/// A record of every commitment made during a session, for testing and debugging. This holds
+/// openings of every commitment in it, so don't keep it around anywhere secrets matter.
+#[derive(Clone, Default, CanonicalSerialize, CanonicalDeserialize)]
+pub struct CommitmentAuditLog {
+    entries: Vec<AuditEntry>,
+}
+
+impl CommitmentAuditLog {
+    /// Makes an empty log
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records that `card` was committed to with randomness `nonce`, giving `leaf`
+    pub fn record(&mut self, card: &Card, nonce: &F, leaf: &Leaf, timestamp: u64) {
+        self.entries.push(AuditEntry {
+            card: card.clone(),
+            nonce: *nonce,
+            leaf: *leaf,
+            timestamp,
+        });
+    }
+
+    /// Returns the earliest entry whose commitment is `leaf`, if there is one
+    pub fn find_by_leaf(&self, leaf: &Leaf) -> Option<&AuditEntry> {
+        self.entries.iter().find(|entry| &entry.leaf == leaf)
+    }
+
+    /// Returns all the entries, in the order they were recorded
+    pub fn entries(&self) -> &[AuditEntry] {
+        &self.entries
+    }
+
+    /// Returns the number of entries
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns whether the log is empty
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+//
+// TESTS
+//
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use ark_ff::Zero;
+    use ark_groth16::{generate_random_parameters, prepare_verifying_key, verify_proof};
+
+    // A circuit that blows up if anyone tries to prove it
+    struct PanicCircuit;
+
+    impl ConstraintSynthesizer<F> for PanicCircuit {
+        fn generate_constraints(self, _: ConstraintSystemRef<F>) -> Result<(), SynthesisError> {
+            panic!("the prover was invoked");
+        }
+    }
+
+    // The second time we ask for the same statement, we should get the cached proof back without
+    // running the prover
+    #[test]
+    fn proof_cache_hit() {
+        let mut rng = ark_std::test_rng();
+        let placeholder = SquareCircuit {
+            root: Some(F::zero()),
+            square: F::zero(),
+        };
+        let pk: ProvingKey<E> = generate_random_parameters(placeholder, &mut rng).unwrap();
+        let vk = prepare_verifying_key(&pk.vk);
+
+        let root = F::rand(&mut rng);
+        let square = root * root;
+        let public_inputs = [square];
+
+        // First call actually proves
+        let mut cache = ProofCache::new();
+        let proof = cache
+            .get_or_prove(
+                SquareCircuit {
+                    root: Some(root),
+                    square,
+                },
+                &public_inputs,
+                &pk,
+                &mut rng,
+            )
+            .unwrap();
+        assert!(verify_proof(&vk, &proof, &public_inputs).unwrap());
+        assert_eq!(cache.len(), 1);
+
+        // Second call hits the cache. If it ran the prover, PanicCircuit would panic.
+        let cached_proof = cache
+            .get_or_prove(PanicCircuit, &public_inputs, &pk, &mut rng)
+            .unwrap();
+        assert!(cached_proof == proof);
+        assert_eq!(cache.len(), 1);
+
+        // A different statement misses the cache
+        assert!(cache.get(&pk, &[square + F::from(1u64)]).is_none());
+    }
+
+    // A proof made under one proving key is never returned for another, e.g., after the params
+    // are regenerated
+    #[test]
+    fn proof_cache_new_params_miss() {
+        let mut rng = ark_std::test_rng();
+        let placeholder = SquareCircuit {
+            root: Some(F::zero()),
+            square: F::zero(),
+        };
+        let old_pk: ProvingKey<E> =
+            generate_random_parameters(placeholder.clone(), &mut rng).unwrap();
+        let new_pk: ProvingKey<E> = generate_random_parameters(placeholder, &mut rng).unwrap();
+
+        let root = F::rand(&mut rng);
+        let square = root * root;
+        let public_inputs = [square];
+        let circuit = SquareCircuit {
+            root: Some(root),
+            square,
+        };
+
+        let mut cache = ProofCache::new();
+        cache
+            .get_or_prove(circuit.clone(), &public_inputs, &old_pk, &mut rng)
+            .unwrap();
+        assert!(cache.get(&new_pk, &public_inputs).is_none());
+
+        // Proving under the new key gives a proof that verifies under the new key
+        let proof = cache
+            .get_or_prove(circuit, &public_inputs, &new_pk, &mut rng)
+            .unwrap();
+        let new_vk = prepare_verifying_key(&new_pk.vk);
+        assert!(verify_proof(&new_vk, &proof, &public_inputs).unwrap());
+        assert_eq!(cache.len(), 2);
+
+        // A prover error is passed along, and nothing gets cached
+        let missing_witness = SquareCircuit { root: None, square };
+        let other_inputs = [square + F::from(1u64)];
+        assert!(cache
+            .get_or_prove(missing_witness, &other_inputs, &new_pk, &mut rng)
+            .is_err());
+        assert_eq!(cache.len(), 2);
+    }
+
+    // Every recorded commitment can be found by its leaf, and the log survives a round trip
+    // through bytes
+    #[test]
+    fn commitment_audit_log() {
+        use crate::hash::LeafHash;
+        use ark_crypto_primitives::crh::CRHScheme;
+
+        let mut rng = ark_std::test_rng();
+        let leaf_crh_params = <LeafHash as CRHScheme>::setup(&mut rng).unwrap();
+
+        let mut log = CommitmentAuditLog::new();
+        for timestamp in 0..100 {
+            let card = Card::rand(&mut rng);
+            let nonce = F::rand(&mut rng);
+            let leaf = card.commit(&leaf_crh_params, &nonce);
+            log.record(&card, &nonce, &leaf, timestamp);
+        }
+        assert_eq!(log.len(), 100);
+
+        // Every entry opens its own leaf, and searching for that leaf finds it
+        for entry in log.entries() {
+            assert!(entry
+                .card
+                .verify_commitment(&leaf_crh_params, &entry.nonce, &entry.leaf));
+            let found = log.find_by_leaf(&entry.leaf).unwrap();
+            assert_eq!(found.timestamp, entry.timestamp);
+        }
+        assert!(log.find_by_leaf(&[0u8; 64]).is_none());
+
+        // Serialize and deserialize
+        let mut buf = Vec::new();
+        log.serialize_compressed(&mut buf).unwrap();
+        let decoded = CommitmentAuditLog::deserialize_compressed(buf.as_slice()).unwrap();
+        assert_eq!(decoded.len(), log.len());
+        for (a, b) in decoded.entries().iter().zip(log.entries()) {
+            assert!(a.card == b.card);
+            assert_eq!(
+                (a.nonce, a.leaf, a.timestamp),
+                (b.nonce, b.leaf, b.timestamp)
+            );
+        }
+    }
+
+    // Encoding a root and decoding it should give back the same root
+    #[test]
+    fn zbase32_root_round_trip() {
+        let mut rng = ark_std::test_rng();
+
+        for _ in 0..10 {
+            let root = MerkleRoot::rand(&mut rng);
+            let encoded = root_to_zbase32(&root);
+            assert_eq!(root_from_zbase32(&encoded).unwrap(), root);
+        }
+    }
+
+    // Bad strings should be rejected with the appropriate error
+    #[test]
+    fn zbase32_root_bad_input() {
+        // '0' isn't in the zbase32 alphabet
+        assert!(matches!(
+            root_from_zbase32("0000"),
+            Err(DecodeError::InvalidEncoding)
+        ));
+
+        // Valid zbase32, but far too short to be a root
+        let short = zbase32::encode_full_bytes(&[1, 2, 3]);
+        assert!(matches!(
+            root_from_zbase32(&short),
+            Err(DecodeError::DeserializationFailed(_))
+        ));
+    }
+
+    // Encoding a leaf and decoding it should give back the same leaf, and leaves of the wrong
+    // length should be rejected
+    #[test]
+    fn zbase32_leaf_round_trip() {
+        use crate::hash::LeafHash;
+        use ark_crypto_primitives::crh::CRHScheme;
+
+        let mut rng = ark_std::test_rng();
+        let leaf_crh_params = <LeafHash as CRHScheme>::setup(&mut rng).unwrap();
+
+        for i in 0..10 {
+            let leaf = get_test_leaf(&leaf_crh_params, i);
+            let encoded = leaf_to_zbase32(&leaf);
+            assert_eq!(leaf_from_zbase32(&encoded).unwrap(), leaf);
+        }
+
+        // One byte short
+        let short = zbase32::encode_full_bytes(&[7u8; 63]);
+        assert!(matches!(
+            leaf_from_zbase32(&short),
+            Err(LeafDecodeError::WrongLength(LeafConversionError {
+                expected: 64,
+                got: 63
+            }))
+        ));
+
+        // '0' isn't in the zbase32 alphabet
+        assert!(matches!(
+            leaf_from_zbase32("0000"),
+            Err(LeafDecodeError::InvalidEncoding)
+        ));
+    }
+
+    // Encoding a proof and its public inputs as hex and decoding them should give back the same
+    // values
+    #[test]
+    fn hex_proof_round_trip() {
+        let mut rng = ark_std::test_rng();
+        let placeholder = SquareCircuit {
+            root: Some(F::zero()),
+            square: F::zero(),
+        };
+        let pk: ProvingKey<E> = generate_random_parameters(placeholder, &mut rng).unwrap();
+
+        let root = F::rand(&mut rng);
+        let square = root * root;
+        let proof = create_random_proof(
+            SquareCircuit {
+                root: Some(root),
+                square,
+            },
+            &pk,
+            &mut rng,
+        )
+        .unwrap();
+        let public_inputs = vec![square, root];
+
+        let decoded_proof = <Proof<E> as ProofHexExt<E>>::from_hex(&proof.to_hex()).unwrap();
+        assert!(decoded_proof == proof);
+        let decoded_inputs = <Vec<F> as PublicInputsHexExt>::from_hex(&public_inputs.to_hex());
+        assert_eq!(decoded_inputs.unwrap(), public_inputs);
+    }
+
+    // Corrupted hex strings should be rejected with the appropriate error
+    #[test]
+    fn hex_proof_bad_input() {
+        use ark_bls12_381::{Fq, G1Affine};
+
+        let mut rng = ark_std::test_rng();
+        let placeholder = SquareCircuit {
+            root: Some(F::zero()),
+            square: F::zero(),
+        };
+        let pk: ProvingKey<E> = generate_random_parameters(placeholder, &mut rng).unwrap();
+        let proof = create_random_proof(
+            SquareCircuit {
+                root: Some(F::from(3u64)),
+                square: F::from(9u64),
+            },
+            &pk,
+            &mut rng,
+        )
+        .unwrap();
+        let encoded = proof.to_hex();
+
+        // 'z' isn't a hex digit
+        let mut not_hex = encoded.clone();
+        not_hex.replace_range(0..1, "z");
+        assert!(matches!(
+            <Proof<E> as ProofHexExt<E>>::from_hex(&not_hex),
+            Err(HexProofError::InvalidHex(_))
+        ));
+
+        // Valid hex, but cut short
+        let truncated = &encoded[..encoded.len() - 2];
+        assert!(matches!(
+            <Proof<E> as ProofHexExt<E>>::from_hex(truncated),
+            Err(HexProofError::DeserializationFailed(_))
+        ));
+
+        // Valid hex of the right length, but the first point's x-coordinate isn't on the curve.
+        // A compressed G1 point is 48 big-endian bytes with the top bit set as a flag.
+        let x = (0u8..)
+            .find(|&i| G1Affine::get_point_from_x_unchecked(Fq::from(i), false).is_none())
+            .unwrap();
+        let mut off_curve_a = [0u8; 48];
+        off_curve_a[0] = 0x80;
+        off_curve_a[47] = x;
+        let off_curve = hex::encode(off_curve_a) + &encoded[96..];
+        assert!(matches!(
+            <Proof<E> as ProofHexExt<E>>::from_hex(&off_curve),
+            Err(HexProofError::DeserializationFailed(_))
+        ));
+    }
+
+    // Breaking the square circuit should make exactly its one constraint fail
+    #[test]
+    fn inspector_finds_unsatisfied() {
+        let good_circuit = SquareCircuit {
+            root: Some(F::from(3u64)),
+            square: F::from(9u64),
+        };
+        let inspector = ConstraintSystemInspector::new(good_circuit);
+        assert!(inspector.is_satisfied());
+        assert!(inspector.unsatisfied_constraint_labels().is_empty());
+
+        let bad_circuit = SquareCircuit {
+            root: Some(F::from(3u64)),
+            square: F::from(10u64),
+        };
+        let inspector = ConstraintSystemInspector::new(bad_circuit);
+        assert!(!inspector.is_satisfied());
+        assert_eq!(inspector.unsatisfied_constraint_labels().len(), 1);
+    }
+
+    // The square circuit computes root * root into a new variable, then enforces that it equals
+    // square. So it has two constraints:
+    //     root * root = prod
+    //     (prod - square) * 1 = 0
+    // which have 3 nonzero entries in A, 2 in B, and 1 in C.
+    #[test]
+    fn square_circuit_report() {
+        let report = report_circuit(SquareCircuit {
+            root: Some(F::from(3u64)),
+            square: F::from(9u64),
+        });
+        assert_eq!(
+            report,
+            ConstraintSystemReport {
+                num_constraints: 2,
+                non_zeros_a: 3,
+                non_zeros_b: 2,
+                non_zeros_c: 1,
+            }
+        );
+    }
+
+    // The simulated prover should prove good circuits, and refuse bad ones without proving them
+    #[test]
+    fn simulated_prover_guard() {
+        let mut rng = ark_std::test_rng();
+        let good_circuit = MockCircuit {
+            num_constraints: 4,
+            should_satisfy: true,
+        };
+        let pk: ProvingKey<E> = generate_random_parameters(good_circuit, &mut rng).unwrap();
+        let vk = prepare_verifying_key(&pk.vk);
+
+        // A good circuit gets proven
+        let proof = SimulatedProver::prove_if_satisfiable(good_circuit, &pk, &mut rng).unwrap();
+        assert!(verify_proof(&vk, &proof, &[]).unwrap());
+
+        // A bad circuit of the same shape is caught by the dry run, before the prover sees it
+        let bad_circuit = MockCircuit {
+            num_constraints: 4,
+            should_satisfy: false,
+        };
+        assert!(matches!(
+            SimulatedProver::prove_if_satisfiable(bad_circuit, &pk, &mut rng),
+            Err(CircuitError::Unsatisfied)
+        ));
+    }
+
+    // The verifying key knows how many public inputs its circuit has
+    #[test]
+    fn vk_num_public_inputs() {
+        let mut rng = ark_std::test_rng();
+
+        let mock = MockCircuit {
+            num_constraints: 2,
+            should_satisfy: true,
+        };
+        let pk: ProvingKey<E> = generate_random_parameters(mock, &mut rng).unwrap();
+        assert_eq!(prepare_verifying_key(&pk.vk).num_public_inputs(), 0);
+
+        let square = SquareCircuit {
+            root: Some(F::zero()),
+            square: F::zero(),
+        };
+        let pk: ProvingKey<E> = generate_random_parameters(square, &mut rng).unwrap();
+        assert_eq!(prepare_verifying_key(&pk.vk).num_public_inputs(), 1);
+    }
+
+    // A key's fingerprint should be the same every time, and different from another key's
+    #[test]
+    fn vk_fingerprint() {
+        let mut rng = ark_std::test_rng();
+        let square = SquareCircuit {
+            root: Some(F::zero()),
+            square: F::zero(),
+        };
+
+        let pk1: ProvingKey<E> = generate_random_parameters(square.clone(), &mut rng).unwrap();
+        let pk2: ProvingKey<E> = generate_random_parameters(square, &mut rng).unwrap();
+        let (vk1, vk2) = (
+            prepare_verifying_key(&pk1.vk),
+            prepare_verifying_key(&pk2.vk),
+        );
+
+        assert_eq!(fingerprint(&vk1), fingerprint(&vk1.clone()));
+        assert_ne!(fingerprint(&vk1), fingerprint(&vk2));
+
+        // Displayed as 32 bytes of hex
+        let displayed = fingerprint(&vk1).to_string();
+        assert_eq!(displayed.len(), 64);
+        assert!(displayed.chars().all(|c| c.is_ascii_hexdigit()));
+    }
+
+    // Real proofs are canonical. Proofs with points off the curve or outside the subgroup aren't.
+    #[test]
+    fn canonical_proofs() {
+        use ark_bls12_381::{Fq, G1Affine};
+        use ark_ec::AffineRepr;
+        use ark_ff::One;
+
+        let mut rng = ark_std::test_rng();
+        let root = F::rand(&mut rng);
+        let circuit = SquareCircuit {
+            root: Some(root),
+            square: root * root,
+        };
+        let pk: ProvingKey<E> = generate_random_parameters(circuit.clone(), &mut rng).unwrap();
+        let proof = create_random_proof(circuit, &pk, &mut rng).unwrap();
+        assert!(is_canonical_proof(&proof));
+
+        // A point that's on the curve but not in the subgroup. G1's cofactor is huge, so the first
+        // x-coordinate that's on the curve will do.
+        let off_subgroup = (0u64..)
+            .find_map(|i| G1Affine::get_point_from_x_unchecked(Fq::from(i), false))
+            .unwrap();
+        assert!(off_subgroup.is_on_curve());
+        assert!(!off_subgroup.is_in_correct_subgroup_assuming_on_curve());
+        let mut bad_proof = proof.clone();
+        bad_proof.c = off_subgroup;
+        assert!(!is_canonical_proof(&bad_proof));
+
+        // A point that isn't on the curve at all: 1 != 1 + 4
+        let mut bad_proof = proof.clone();
+        bad_proof.a = G1Affine::new_unchecked(Fq::one(), Fq::one());
+        assert!(!is_canonical_proof(&bad_proof));
+
+        // The identity is fine
+        let mut zero_proof = proof;
+        zero_proof.a = G1Affine::zero();
+        assert!(is_canonical_proof(&zero_proof));
+    }
+
+    // A BLS12-381 Groth16 proof is two G1 points (48 bytes each, compressed) and a G2 point (96
+    // bytes), no matter the circuit. The keys grow with the circuit.
+    #[test]
+    fn bls12_381_proof_size() {
+        let mut rng = ark_std::test_rng();
+
+        let mut measure = |num_constraints| {
+            let circuit = MockCircuit {
+                num_constraints,
+                should_satisfy: true,
+            };
+            let pk: ProvingKey<E> = generate_random_parameters(circuit, &mut rng).unwrap();
+            let proof = create_random_proof(circuit, &pk, &mut rng).unwrap();
+            measure_proof_size(&pk, &proof)
+        };
+
+        let small = measure(1);
+        let big = measure(64);
+        assert_eq!(small.proof_bytes, 192);
+        assert_eq!(big.proof_bytes, 192);
+        assert!(big.pk_bytes > small.pk_bytes);
+        assert!(small.vk_bytes < small.pk_bytes);
+    }
+
+    // The mock circuit has exactly the constraints it says it has, and one witness. Whether it's
+    // satisfied doesn't change its shape.
+    #[test]
+    fn mock_circuit_counts() {
+        for num_constraints in [0, 1, 10] {
+            for should_satisfy in [true, false] {
+                let circuit = MockCircuit {
+                    num_constraints,
+                    should_satisfy,
+                };
+                assert_eq!(count_constraints(circuit), (1, 1, num_constraints));
+
+                let inspector = ConstraintSystemInspector::new(circuit);
+                let num_unsatisfied = if should_satisfy { 0 } else { num_constraints };
+                assert_eq!(
+                    inspector.unsatisfied_constraint_labels().len(),
+                    num_unsatisfied
+                );
+            }
+        }
+    }
+
+    // Transcripts of the same things should agree, and transcripts of different things shouldn't
+    #[test]
+    fn transcript_challenges() {
+        let mut rng = ark_std::test_rng();
+        let placeholder = SquareCircuit {
+            root: Some(F::zero()),
+            square: F::zero(),
+        };
+        let pk: ProvingKey<E> = generate_random_parameters(placeholder, &mut rng).unwrap();
+        let root = F::rand(&mut rng);
+        let square = root * root;
+        let proof = create_random_proof(
+            SquareCircuit {
+                root: Some(root),
+                square,
+            },
+            &pk,
+            &mut rng,
+        )
+        .unwrap();
+
+        let transcript_of = |inputs: &[F]| {
+            let mut transcript = FiatShamirTranscript::new();
+            transcript.absorb_proof(&proof);
+            transcript.absorb_public_inputs(inputs);
+            transcript
+        };
+
+        // Same inputs, same challenge
+        let mut t1 = transcript_of(&[square]);
+        let mut t2 = transcript_of(&[square]);
+        let c1 = t1.challenge_field_element();
+        assert_eq!(c1, t2.challenge_field_element());
+
+        // The next challenge is different from the first
+        assert_ne!(c1, t1.challenge_field_element());
+
+        // Different inputs, different challenge
+        let mut t3 = transcript_of(&[square + F::from(1u64)]);
+        assert_ne!(c1, t3.challenge_field_element());
+    }
+
+    // Atomic writes should read back the same as ordinary ones, and not leave the temp file behind
+    #[test]
+    fn atomic_write_round_trip() {
+        let dir = std::env::temp_dir().join(format!("atomic-write-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("val.bin");
+        let path_str = path.to_str().unwrap();
+
+        // Write twice, to make sure an existing file gets replaced
+        let val = F::from(1337u64);
+        write_to_file_atomic(path_str, &F::from(1u64)).unwrap();
+        write_to_file_atomic(path_str, &val).unwrap();
+        assert_eq!(read_from_file::<F>(path_str), val);
+        assert!(!Path::new(&format!("{path_str}.tmp")).exists());
+
+        // The uncompressed variant writes something that deserializes the same way
+        let root = MerkleRoot::rand(&mut ark_std::test_rng());
+        write_to_file_atomic_uncompressed(path_str, &root).unwrap();
+        let bytes = fs::read(&path).unwrap();
+        assert_eq!(
+            MerkleRoot::deserialize_uncompressed(bytes.as_slice()).unwrap(),
+            root
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    // The parallel commitments should be exactly the serial ones, in the same order
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn parallel_commit_matches_serial() {
+        use crate::hash::LeafHash;
+        use ark_crypto_primitives::crh::CRHScheme;
+
+        let mut rng = ark_std::test_rng();
+        let leaf_crh_params = <LeafHash as CRHScheme>::setup(&mut rng).unwrap();
+
+        let cards = gen_test_cards_with_seed(1);
+        let serial: Vec<Leaf> = cards
+            .iter()
+            .map(|(card, com_rand)| card.commit(&leaf_crh_params, com_rand))
+            .collect();
+        assert_eq!(commit_cards_parallel(&leaf_crh_params, &cards), serial);
+    }
+
+    // Card JSON should parse into the card it describes, and bad values should name the field
+    #[test]
+    fn card_input_json() {
+        let input = CardInputJson::from_json(
+            r#"{ "card_purchase_price": "100", "card_serial_num": "5678", "card_com_rand": "1234" }"#,
+        )
+        .unwrap();
+        let (card, com_rand) = input.to_card().unwrap();
+        assert_eq!(card.purchase_price, F::from(100u64));
+        assert_eq!(card.serial_num, F::from(5678u64));
+        assert_eq!(com_rand, F::from(1234u64));
+
+        let mut bad_input = input;
+        bad_input.card_serial_num = "not a number".to_string();
+        assert!(matches!(
+            bad_input.to_card(),
+            Err(CardInputError::InvalidFieldElement {
+                field: "card_serial_num"
+            })
+        ));
+
+        // Missing fields are a JSON error
+        assert!(matches!(
+            CardInputJson::from_json(r#"{ "card_purchase_price": "100" }"#),
+            Err(CardInputError::InvalidJson(_))
+        ));
+    }
+
+    // Cards can be found and removed by serial number
+    #[test]
+    fn card_wallet() {
+        let mut rng = ark_std::test_rng();
+        let mut wallet = CardWallet::new();
+        assert!(wallet.is_empty());
+
+        // Insert all the test cards
+        let cards = all_cards();
+        for (card, com_rand) in cards.iter().cloned() {
+            wallet.insert(card, com_rand);
+        }
+        assert_eq!(wallet.len(), cards.len());
+
+        // Every card can be looked up
+        for (card, com_rand) in &cards {
+            let (found_card, found_com_rand) = wallet.lookup_by_serial(&card.serial_num).unwrap();
+            assert_eq!(found_card, card);
+            assert_eq!(found_com_rand, com_rand);
+        }
+
+        // A serial that isn't there isn't found
+        assert!(wallet.lookup_by_serial(&F::rand(&mut rng)).is_none());
+
+        // Removing a card returns it, and it's gone after
+        let (card, com_rand) = cards[3].clone();
+        assert_eq!(
+            wallet.remove_by_serial(&card.serial_num),
+            Some((card.clone(), com_rand))
+        );
+        assert_eq!(wallet.len(), cards.len() - 1);
+        assert!(wallet.lookup_by_serial(&card.serial_num).is_none());
+        assert!(wallet.remove_by_serial(&card.serial_num).is_none());
+    }
 }
@@ -0,0 +1,219 @@
+// Runs the show-price binaries end to end
+
+use arkworks_merkle_tree_example::{
+    card::Card,
+    hash::{LeafHashParams, TwoToOneHashParams},
+    merkle::MerkleRoot,
+    util::{
+        gen_test_tree, gen_test_tree_with_card, read_from_file, root_to_zbase32,
+        PEDERSEN_PARAMS_FILENAME, POSSESSION_REVEALED_PRICE_FILENAME,
+        POSSESSION_REVEALED_SERIAL_FILENAME, POSSESSION_SHOWPRICE_PK_FILENAME,
+        POSSESSION_SHOWPRICE_PROOF_FILENAME, POSSESSION_SHOWPRICE_VK_FILENAME,
+    },
+    F,
+};
+
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Command, Output, Stdio},
+};
+
+use ark_ff::UniformRand;
+
+// Runs the given binary in the given directory
+fn run(dir: &Path, bin_path: &str, args: &[&str]) -> Output {
+    Command::new(bin_path)
+        .current_dir(dir)
+        .args(args)
+        .output()
+        .expect("could not run binary")
+}
+
+// Runs the given binary in the given directory, with `input` as its STDIN
+fn run_with_stdin(dir: &Path, bin_path: &str, args: &[&str], input: &str) -> Output {
+    let mut child = Command::new(bin_path)
+        .current_dir(dir)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("could not run binary");
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    child.wait_with_output().expect("could not run binary")
+}
+
+// Makes a fresh directory for the binaries to write their files to
+fn fresh_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+// The verifier should complain about bad arguments rather than do anything
+#[test]
+fn verify_showprice_bad_args() {
+    let dir = fresh_dir("verify-showprice-bad-args");
+    let output = run(&dir, env!("CARGO_BIN_EXE_verify_showprice"), &[]);
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Usage:"));
+}
+
+// The prover should reject malformed card JSON before doing any work. It checks the JSON before
+// reading any files, so it doesn't matter that there aren't any.
+#[test]
+fn prove_showprice_stdin_bad_json() {
+    let dir = fresh_dir("prove-showprice-bad-json");
+    let root_str = root_to_zbase32(&MerkleRoot::rand(&mut ark_std::test_rng()));
+    let args = [
+        "--stdin",
+        PEDERSEN_PARAMS_FILENAME,
+        POSSESSION_SHOWPRICE_PK_FILENAME,
+        &root_str,
+    ];
+
+    let output = run_with_stdin(
+        &dir,
+        env!("CARGO_BIN_EXE_prove_showprice"),
+        &args,
+        r#"{ "card_purchase_price": "100" }"#,
+    );
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("could not parse card JSON"));
+
+    let output = run_with_stdin(
+        &dir,
+        env!("CARGO_BIN_EXE_prove_showprice"),
+        &args,
+        r#"{ "card_purchase_price": "100", "card_serial_num": "x", "card_com_rand": "1" }"#,
+    );
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("card_serial_num"));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+// Proves possession of a card given on STDIN, and verifies it. Like `showprice_end_to_end`, this
+// is slow.
+#[test]
+#[ignore]
+fn showprice_stdin_end_to_end() {
+    let dir = fresh_dir("showprice-stdin-end-to-end");
+
+    let output = run(&dir, env!("CARGO_BIN_EXE_gen_params_showprice"), &[]);
+    assert!(output.status.success(), "gen_params_showprice failed");
+
+    // Our card goes in slot 7 of the test tree
+    let card = Card {
+        purchase_price: 100u64.into(),
+        serial_num: 5678u64.into(),
+    };
+    let params_path = dir.join(PEDERSEN_PARAMS_FILENAME);
+    let (leaf_crh_params, two_to_one_crh_params): (LeafHashParams, TwoToOneHashParams) =
+        read_from_file(params_path.to_str().unwrap());
+    let (tree, _) = gen_test_tree_with_card(
+        &leaf_crh_params,
+        &two_to_one_crh_params,
+        7,
+        &card,
+        &1234u64.into(),
+    );
+    let root_str = root_to_zbase32(&tree.root());
+
+    let output = run_with_stdin(
+        &dir,
+        env!("CARGO_BIN_EXE_prove_showprice"),
+        &[
+            "--stdin",
+            PEDERSEN_PARAMS_FILENAME,
+            POSSESSION_SHOWPRICE_PK_FILENAME,
+            &root_str,
+        ],
+        r#"{ "card_purchase_price": "100", "card_serial_num": "5678", "card_com_rand": "1234" }"#,
+    );
+    assert!(output.status.success(), "prove_showprice failed");
+
+    let output = run(
+        &dir,
+        env!("CARGO_BIN_EXE_verify_showprice"),
+        &[
+            POSSESSION_SHOWPRICE_VK_FILENAME,
+            POSSESSION_SHOWPRICE_PROOF_FILENAME,
+            POSSESSION_REVEALED_SERIAL_FILENAME,
+            POSSESSION_REVEALED_PRICE_FILENAME,
+            &root_str,
+        ],
+    );
+    assert!(output.status.success());
+
+    // The revealed price is the one we gave
+    let price_path = dir.join(POSSESSION_REVEALED_PRICE_FILENAME);
+    let price: F = read_from_file(price_path.to_str().unwrap());
+    assert_eq!(price, card.purchase_price);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+// Generates params, proves, and verifies. Then verifies again with the wrong root, which should
+// fail. This makes a real Groth16 CRS, so it's slow. Run it with
+//     cargo test --release -- --ignored
+#[test]
+#[ignore]
+fn showprice_end_to_end() {
+    let dir = fresh_dir("showprice-end-to-end");
+
+    let output = run(&dir, env!("CARGO_BIN_EXE_gen_params_showprice"), &[]);
+    assert!(output.status.success(), "gen_params_showprice failed");
+
+    // Compute the root of the test tree the same way the prover will
+    let params_path = dir.join(PEDERSEN_PARAMS_FILENAME);
+    let (leaf_crh_params, two_to_one_crh_params): (LeafHashParams, TwoToOneHashParams) =
+        read_from_file(params_path.to_str().unwrap());
+    let root = gen_test_tree(&leaf_crh_params, &two_to_one_crh_params).root();
+    let root_str = root_to_zbase32(&root);
+
+    let output = run(
+        &dir,
+        env!("CARGO_BIN_EXE_prove_showprice"),
+        &[
+            PEDERSEN_PARAMS_FILENAME,
+            POSSESSION_SHOWPRICE_PK_FILENAME,
+            &root_str,
+        ],
+    );
+    assert!(output.status.success(), "prove_showprice failed");
+
+    let verify = |root_str: &str| {
+        run(
+            &dir,
+            env!("CARGO_BIN_EXE_verify_showprice"),
+            &[
+                POSSESSION_SHOWPRICE_VK_FILENAME,
+                POSSESSION_SHOWPRICE_PROOF_FILENAME,
+                POSSESSION_REVEALED_SERIAL_FILENAME,
+                POSSESSION_REVEALED_PRICE_FILENAME,
+                root_str,
+            ],
+        )
+    };
+
+    let output = verify(&root_str);
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Proof verified successfully"));
+
+    // A different root fails, with an explanation
+    let wrong_root_str = root_to_zbase32(&MerkleRoot::rand(&mut ark_std::test_rng()));
+    let output = verify(&wrong_root_str);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("proof failed to verify"));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,214 @@
+// Checks that the shapes of our circuits don't change by accident. If you change a circuit on
+// purpose, update the numbers here.
+
+use arkworks_merkle_tree_example::{
+    card::Card,
+    constraints::PossessionCircuit,
+    constraints_showprice::PossessionShowPriceCircuit,
+    hash::{LeafHash, TwoToOneHash},
+    util::{
+        analyze_circuit, count_constraints, diff_circuits, gen_test_tree, get_test_card,
+        report_circuit, ConstraintSystemReport,
+    },
+};
+
+use ark_crypto_primitives::crh::{CRHScheme, TwoToOneCRHScheme};
+use ark_ff::ToConstraintField;
+
+// Makes a valid possession circuit and the equivalent show-price circuit
+fn setup() -> (PossessionCircuit, PossessionShowPriceCircuit) {
+    let mut rng = ark_std::test_rng();
+    let leaf_crh_params = <LeafHash as CRHScheme>::setup(&mut rng).unwrap();
+    let two_to_one_crh_params = <TwoToOneHash as TwoToOneCRHScheme>::setup(&mut rng).unwrap();
+
+    let tree = gen_test_tree(&leaf_crh_params, &two_to_one_crh_params);
+    let our_idx = 7;
+    let (card, card_com_rand) = get_test_card(our_idx);
+    let leaf = tree.leaves()[our_idx].to_vec();
+    let auth_path = tree.generate_proof(our_idx).unwrap();
+
+    let possession = PossessionCircuit {
+        leaf_crh_params: leaf_crh_params.clone(),
+        two_to_one_crh_params: two_to_one_crh_params.clone(),
+        enforce_nonzero_price: false,
+        root: tree.root(),
+        leaf: leaf.clone(),
+        card_serial_num: card.serial_num,
+        auth_path: auth_path.clone(),
+        card_purchase_price: card.purchase_price,
+        card_com_rand,
+    };
+    let showprice = PossessionShowPriceCircuit {
+        leaf_crh_params,
+        two_to_one_crh_params,
+        check_price_range: false,
+        root: tree.root(),
+        leaf,
+        card_serial_num: card.serial_num,
+        auth_path,
+        card_purchase_price: card.purchase_price,
+        card_com_rand,
+    };
+
+    (possession, showprice)
+}
+
+#[test]
+fn possession_public_inputs() {
+    let (possession, _) = setup();
+    let (num_instance_vars, _, _) = count_constraints(possession);
+
+    // The constant 1, the Merkle root (2 field elements), and the serial number
+    assert_eq!(num_instance_vars, 4);
+}
+
+#[test]
+fn showprice_public_inputs() {
+    let (_, showprice) = setup();
+    let (num_instance_vars, _, _) = count_constraints(showprice);
+
+    // Same as above, plus the purchase price
+    assert_eq!(num_instance_vars, 5);
+}
+
+// The exact sizes of the circuits, as (instance variables, witness variables, constraints). A change
+// here means a circuit changed shape. If that was on purpose, update the numbers.
+#[test]
+fn pinned_counts() {
+    let (possession, showprice) = setup();
+    let mut nonzero = possession.clone();
+    nonzero.enforce_nonzero_price = true;
+
+    assert_eq!(count_constraints(possession), (4, 32935, 35539));
+    assert_eq!(count_constraints(nonzero), (4, 32937, 35543));
+    assert_eq!(count_constraints(showprice), (5, 32934, 35539));
+}
+
+// Revealing the price just moves one variable from the witness to the instance. Nothing else about
+// the circuit should change.
+#[test]
+fn showprice_matches_possession() {
+    let (possession, showprice) = setup();
+    let (p_instance, p_witness, p_constraints) = count_constraints(possession);
+    let (s_instance, s_witness, s_constraints) = count_constraints(showprice);
+
+    assert_eq!(s_instance, p_instance + 1);
+    assert_eq!(s_witness + 1, p_witness);
+    assert_eq!(s_constraints, p_constraints);
+}
+
+// The show-price circuit reveals exactly the root and the whole card, so the field elements of
+// those should account for every public input
+#[test]
+fn showprice_public_inputs_match_card() {
+    let (_, showprice) = setup();
+    let card = Card {
+        purchase_price: showprice.card_purchase_price,
+        serial_num: showprice.card_serial_num,
+    };
+    let num_root_elems = showprice.root.to_field_elements().unwrap().len();
+    let num_card_elems = card.to_field_elements().unwrap().len();
+    assert_eq!(num_card_elems, 2);
+
+    // Subtract 1 for the constant 1 variable
+    let (num_instance_vars, _, _) = count_constraints(showprice);
+    assert_eq!(num_instance_vars - 1, num_root_elems + num_card_elems);
+}
+
+// Compares the stats of the two circuits. Exposing the price doesn't cost any constraints, it just
+// makes one more public input. The total number of variables, and hence the density, stays the
+// same.
+#[test]
+fn showprice_stats() {
+    let (possession, showprice) = setup();
+    let p_stats = analyze_circuit(possession);
+    let s_stats = analyze_circuit(showprice);
+
+    assert_eq!(p_stats.pub_inputs, 3);
+    assert!(s_stats.pub_inputs > p_stats.pub_inputs);
+    assert_eq!(s_stats.constraints, p_stats.constraints);
+    assert_eq!(s_stats.density, p_stats.density);
+}
+
+// Proving a different card changes the witness, not the circuit. Turning on the nonzero-price
+// check only adds constraints.
+#[test]
+fn possession_diff() {
+    let (possession, _) = setup();
+
+    // Swap in a different card from the same tree
+    let mut other_card = possession.clone();
+    let (card, card_com_rand) = get_test_card(3);
+    other_card.card_serial_num = card.serial_num;
+    other_card.card_purchase_price = card.purchase_price;
+    other_card.card_com_rand = card_com_rand;
+
+    let diff = diff_circuits(possession.clone(), other_card);
+    assert_eq!(diff.added, 0);
+    assert_eq!(diff.removed, 0);
+    assert_eq!(diff.delta_density, 0.0);
+
+    let mut nonzero = possession.clone();
+    nonzero.enforce_nonzero_price = true;
+    let diff = diff_circuits(possession, nonzero);
+    assert!(diff.added > 0);
+    assert_eq!(diff.removed, 0);
+}
+
+// The report should agree with the plain constraint count, and the matrices shouldn't change
+// density. Like in `pinned_counts`, update the numbers if you change the circuit on purpose.
+#[test]
+fn possession_report() {
+    let (possession, _) = setup();
+    let (_, _, num_constraints) = count_constraints(possession.clone());
+    let report = report_circuit(possession);
+
+    assert_eq!(report.num_constraints, num_constraints);
+    assert_eq!(
+        report,
+        ConstraintSystemReport {
+            num_constraints: 35539,
+            non_zeros_a: 51990,
+            non_zeros_b: 44020,
+            non_zeros_c: 50614,
+        }
+    );
+}
+
+// The public input counts should match what the prove binaries build, and what the circuits
+// actually allocate
+#[test]
+fn num_public_inputs() {
+    let (possession, showprice) = setup();
+
+    let possession_inputs = [
+        possession.root.to_field_elements().unwrap(),
+        possession.card_serial_num.to_field_elements().unwrap(),
+    ]
+    .concat();
+    assert_eq!(
+        possession_inputs.len(),
+        <PossessionCircuit>::NUM_PUBLIC_INPUTS
+    );
+    let (num_instance_vars, _, _) = count_constraints(possession);
+    assert_eq!(
+        num_instance_vars - 1,
+        <PossessionCircuit>::NUM_PUBLIC_INPUTS
+    );
+
+    let showprice_inputs = [
+        showprice.root.to_field_elements().unwrap(),
+        showprice.card_serial_num.to_field_elements().unwrap(),
+        showprice.card_purchase_price.to_field_elements().unwrap(),
+    ]
+    .concat();
+    assert_eq!(
+        showprice_inputs.len(),
+        PossessionShowPriceCircuit::NUM_PUBLIC_INPUTS
+    );
+    let (num_instance_vars, _, _) = count_constraints(showprice);
+    assert_eq!(
+        num_instance_vars - 1,
+        PossessionShowPriceCircuit::NUM_PUBLIC_INPUTS
+    );
+}
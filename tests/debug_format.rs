@@ -0,0 +1,47 @@
+// Checks that our types print something readable with `{:?}`
+
+use arkworks_merkle_tree_example::{card::Card, card::CardVar, F, FV};
+
+use ark_r1cs_std::alloc::AllocVar;
+use ark_relations::r1cs::ConstraintSystem;
+
+// Field elements are printed as the hex of their little-endian canonical bytes
+fn le_hex(x: u8) -> String {
+    format!("{x:02x}{}", "00".repeat(31))
+}
+
+#[test]
+fn card_debug() {
+    let card = Card {
+        purchase_price: F::from(1u64),
+        serial_num: F::from(0xabu64),
+    };
+
+    assert_eq!(
+        format!("{card:?}"),
+        format!(
+            "Card {{ purchase_price: {}, serial_num: {} }}",
+            le_hex(0x01),
+            le_hex(0xab)
+        )
+    );
+}
+
+#[test]
+fn card_var_debug() {
+    let cs = ConstraintSystem::<F>::new_ref();
+
+    // Constants get printed like the native card. Witnesses don't get printed at all.
+    let card_var: CardVar = CardVar {
+        amount: FV::new_constant(cs.clone(), F::from(1u64)).unwrap(),
+        serial_num: FV::new_witness(cs, || Ok(F::from(0xabu64))).unwrap(),
+    };
+
+    assert_eq!(
+        format!("{card_var:?}"),
+        format!(
+            "CardVar {{ amount: {}, serial_num: <witness> }}",
+            le_hex(0x01)
+        )
+    );
+}
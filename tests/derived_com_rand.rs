@@ -0,0 +1,54 @@
+// Checks that a card committed with derived randomness can be shown without storing the randomness
+
+use arkworks_merkle_tree_example::{
+    card::{derive_com_rand, Card},
+    constraints::PossessionCircuit,
+    hash::{LeafHash, TwoToOneHash},
+    merkle::SimpleMerkleTree,
+    util::gen_test_tree,
+    F,
+};
+
+use ark_crypto_primitives::crh::{CRHScheme, TwoToOneCRHScheme};
+use ark_ff::UniformRand;
+use ark_relations::r1cs::{ConstraintSynthesizer, ConstraintSystem};
+
+#[test]
+fn derived_com_rand_possession() {
+    let mut rng = ark_std::test_rng();
+    let leaf_crh_params = <LeafHash as CRHScheme>::setup(&mut rng).unwrap();
+    let two_to_one_crh_params = <TwoToOneHash as TwoToOneCRHScheme>::setup(&mut rng).unwrap();
+
+    // Commit to a fresh card with randomness derived from our master key, and put it in the tree
+    let master_key = F::rand(&mut rng);
+    let card = Card::rand(&mut rng);
+    let our_idx = 7;
+    let mut leaves = gen_test_tree(&leaf_crh_params, &two_to_one_crh_params)
+        .leaves()
+        .to_vec();
+    leaves[our_idx] = card.commit(&leaf_crh_params, &derive_com_rand(&card, &master_key));
+    let tree = SimpleMerkleTree::new(&leaf_crh_params, &two_to_one_crh_params, leaves).unwrap();
+
+    // Later, we show the card. All we need is the card and the master key.
+    let circuit: PossessionCircuit = PossessionCircuit {
+        leaf_crh_params,
+        two_to_one_crh_params,
+        enforce_nonzero_price: false,
+        root: tree.root(),
+        leaf: tree.leaves()[our_idx].to_vec(),
+        card_serial_num: card.serial_num,
+        auth_path: tree.generate_proof(our_idx).unwrap(),
+        card_purchase_price: card.purchase_price,
+        card_com_rand: derive_com_rand(&card, &master_key),
+    };
+
+    let cs = ConstraintSystem::new_ref();
+    circuit.generate_constraints(cs.clone()).unwrap();
+    assert!(cs.is_satisfied().unwrap());
+
+    // A different master key derives different randomness
+    assert_ne!(
+        derive_com_rand(&card, &master_key),
+        derive_com_rand(&card, &F::rand(&mut rng))
+    );
+}
@@ -0,0 +1,90 @@
+// Issues and revokes cards, and checks that only unrevoked cards can be shown
+
+use arkworks_merkle_tree_example::{
+    card::Card,
+    hash::{LeafHash, TwoToOneHash},
+    registry::{CardRegistry, RegistryError},
+};
+
+use ark_crypto_primitives::crh::{CRHScheme, TwoToOneCRHScheme};
+use ark_ff::UniformRand;
+use ark_relations::r1cs::{ConstraintSynthesizer, ConstraintSystem};
+
+#[test]
+fn issue_and_revoke() {
+    let mut rng = ark_std::test_rng();
+    let leaf_crh_params = <LeafHash as CRHScheme>::setup(&mut rng).unwrap();
+    let two_to_one_crh_params = <TwoToOneHash as TwoToOneCRHScheme>::setup(&mut rng).unwrap();
+    let mut registry = CardRegistry::new(&leaf_crh_params, &two_to_one_crh_params, 4).unwrap();
+
+    // Issue two cards
+    let card_a = Card::rand(&mut rng);
+    let card_b = Card::rand(&mut rng);
+    let (idx_a, _) = registry.issue_card(card_a, &mut rng).unwrap();
+    let (idx_b, _) = registry.issue_card(card_b.clone(), &mut rng).unwrap();
+    assert_eq!((idx_a, idx_b), (0, 1));
+
+    // Card a can be shown
+    let inputs_a = registry.generate_possession_proof_inputs(idx_a).unwrap();
+    let circuit = inputs_a
+        .clone()
+        .into_circuit(leaf_crh_params.clone(), two_to_one_crh_params.clone());
+    let cs = ConstraintSystem::new_ref();
+    circuit.generate_constraints(cs.clone()).unwrap();
+    assert!(cs.is_satisfied().unwrap());
+
+    // Revoke card a. Now it can't be shown, and revoking it again is an error.
+    registry.revoke_card(idx_a).unwrap();
+    assert!(registry.is_revoked(&inputs_a.card.serial_num));
+    assert!(!registry.is_revoked(&card_b.serial_num));
+    assert!(matches!(
+        registry.generate_possession_proof_inputs(idx_a),
+        Err(RegistryError::Revoked { idx: 0 })
+    ));
+    assert!(matches!(
+        registry.revoke_card(idx_a),
+        Err(RegistryError::Revoked { idx: 0 })
+    ));
+
+    // Old inputs for the revoked card don't work under the new root
+    let mut stale_inputs = inputs_a;
+    stale_inputs.root = registry.root();
+    let circuit = stale_inputs.into_circuit(leaf_crh_params.clone(), two_to_one_crh_params.clone());
+    let cs = ConstraintSystem::new_ref();
+    circuit.generate_constraints(cs.clone()).unwrap();
+    assert!(!cs.is_satisfied().unwrap());
+
+    // Card b is unaffected
+    let circuit = registry
+        .generate_possession_proof_inputs(idx_b)
+        .unwrap()
+        .into_circuit(leaf_crh_params, two_to_one_crh_params);
+    let cs = ConstraintSystem::new_ref();
+    circuit.generate_constraints(cs.clone()).unwrap();
+    assert!(cs.is_satisfied().unwrap());
+
+    // Unissued slots can't be shown or revoked
+    assert!(matches!(
+        registry.generate_possession_proof_inputs(2),
+        Err(RegistryError::NotIssued { idx: 2 })
+    ));
+    assert!(matches!(
+        registry.revoke_card(2),
+        Err(RegistryError::NotIssued { idx: 2 })
+    ));
+}
+
+#[test]
+fn registry_full() {
+    let mut rng = ark_std::test_rng();
+    let leaf_crh_params = <LeafHash as CRHScheme>::setup(&mut rng).unwrap();
+    let two_to_one_crh_params = <TwoToOneHash as TwoToOneCRHScheme>::setup(&mut rng).unwrap();
+    let mut registry = CardRegistry::new(&leaf_crh_params, &two_to_one_crh_params, 2).unwrap();
+
+    registry.issue_card(Card::rand(&mut rng), &mut rng).unwrap();
+    registry.issue_card(Card::rand(&mut rng), &mut rng).unwrap();
+    assert!(matches!(
+        registry.issue_card(Card::rand(&mut rng), &mut rng),
+        Err(RegistryError::Full { capacity: 2 })
+    ));
+}
@@ -0,0 +1,86 @@
+// Round-trips our major types through compressed and uncompressed serialization
+
+use arkworks_merkle_tree_example::{
+    card::Card,
+    hash::{LeafHash, TwoToOneHash},
+    merkle::MerkleRoot,
+    util::{gen_test_tree, SquareCircuit},
+    E, F,
+};
+
+use ark_crypto_primitives::crh::{CRHScheme, TwoToOneCRHScheme};
+use ark_ff::UniformRand;
+use ark_groth16::{
+    create_random_proof, generate_random_parameters, prepare_verifying_key, PreparedVerifyingKey,
+    Proof, ProvingKey,
+};
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+
+// Serializes `x`, deserializes it, and serializes it again, in both modes. Checks that the bytes
+// match both times, and returns the (compressed, uncompressed) lengths.
+fn round_trip<T: CanonicalSerialize + CanonicalDeserialize>(x: &T) -> (usize, usize) {
+    let mut compressed = Vec::new();
+    x.serialize_compressed(&mut compressed).unwrap();
+    let y = T::deserialize_compressed(compressed.as_slice()).unwrap();
+    let mut recompressed = Vec::new();
+    y.serialize_compressed(&mut recompressed).unwrap();
+    assert_eq!(compressed, recompressed);
+
+    let mut uncompressed = Vec::new();
+    x.serialize_uncompressed(&mut uncompressed).unwrap();
+    let y = T::deserialize_uncompressed(uncompressed.as_slice()).unwrap();
+    let mut reuncompressed = Vec::new();
+    y.serialize_uncompressed(&mut reuncompressed).unwrap();
+    assert_eq!(uncompressed, reuncompressed);
+
+    (compressed.len(), uncompressed.len())
+}
+
+#[test]
+fn card_round_trip() {
+    let mut rng = ark_std::test_rng();
+    let card = Card::rand(&mut rng);
+    round_trip(&card);
+
+    let mut buf = Vec::new();
+    card.serialize_compressed(&mut buf).unwrap();
+    assert_eq!(Card::deserialize_compressed(buf.as_slice()).unwrap(), card);
+}
+
+#[test]
+fn merkle_round_trip() {
+    let mut rng = ark_std::test_rng();
+    let leaf_crh_params = <LeafHash as CRHScheme>::setup(&mut rng).unwrap();
+    let two_to_one_crh_params = <TwoToOneHash as TwoToOneCRHScheme>::setup(&mut rng).unwrap();
+    let tree = gen_test_tree(&leaf_crh_params, &two_to_one_crh_params);
+
+    // Roots are curve points, so compression should make them smaller
+    let root: MerkleRoot = tree.root();
+    let (compressed_len, uncompressed_len) = round_trip(&root);
+    assert!(compressed_len < uncompressed_len);
+
+    // Same for paths, which are made of curve points
+    let path = tree.generate_proof(7).unwrap();
+    let (compressed_len, uncompressed_len) = round_trip(&path);
+    assert!(compressed_len < uncompressed_len);
+}
+
+#[test]
+fn groth16_round_trip() {
+    let mut rng = ark_std::test_rng();
+    let root = F::rand(&mut rng);
+    let circuit = SquareCircuit {
+        root: Some(root),
+        square: root * root,
+    };
+    let pk: ProvingKey<E> = generate_random_parameters(circuit.clone(), &mut rng).unwrap();
+    let pvk: PreparedVerifyingKey<E> = prepare_verifying_key(&pk.vk);
+    let proof: Proof<E> = create_random_proof(circuit, &pk, &mut rng).unwrap();
+
+    // All of these contain curve points, so compression should make them smaller
+    for (compressed_len, uncompressed_len) in
+        [round_trip(&proof), round_trip(&pk), round_trip(&pvk)]
+    {
+        assert!(compressed_len < uncompressed_len);
+    }
+}